@@ -3,13 +3,20 @@
 
 use std::env;
 use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use env_logger::{Builder, Env, Target};
-use slint::{ComponentHandle, SharedString};
+use slint::{ComponentHandle, ModelRc, SharedString, StandardListViewItem, VecModel};
 
+use chess::book::PolyglotBook;
+use chess::engine::{
+    self, ClassicalEvaluator, EvalTerm, EvalTrace, PvLine, SearchInfo, SearchLimits, SearchOptions,
+};
 use chess::fen::FEN;
-use chess::pgn::PGN;
+use chess::pgn::{ParseMode, PGN};
+use chess::tablebase::Tablebase;
 use chess::{eval_to_string, hash_to_string, PieceColour};
 
 slint::include_modules!();
@@ -48,6 +55,82 @@ fn ui_convert_piece(piece: chess::Piece) -> PieceUI {
     }
 }
 
+// pv in SAN with move numbers, e.g. "12... Nf6 13. Nc3"
+fn pv_to_string(root: &chess::BoardState, line: &PvLine) -> String {
+    let mut pv = String::new();
+    let mut move_count = root.move_count();
+    let mut side = root.side_to_move;
+    for (i, notation) in line.pv_notation(root).iter().enumerate() {
+        if side == PieceColour::White {
+            pv.push_str(&format!("{}. ", move_count));
+        } else if i == 0 {
+            pv.push_str(&format!("{}... ", move_count));
+        }
+        pv.push_str(&format!("{} ", notation));
+        if side == PieceColour::Black {
+            move_count += 1;
+        }
+        side = !side;
+    }
+    pv.trim_end().to_string()
+}
+
+// one line per multipv line, with the eval of each
+fn lines_to_string(root: &chess::BoardState, info: &SearchInfo) -> String {
+    if info.from_book {
+        return format!("(book) {}", pv_to_string(root, &info.lines[0]));
+    }
+    info.lines
+        .iter()
+        .map(|line| {
+            format!(
+                "({}) {}",
+                eval_to_string(line.eval),
+                pv_to_string(root, line)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// rows of the eval trace table: term, white mg/eg, black mg/eg and total mg/eg in pawns, then the total
+fn eval_trace_rows(trace: &EvalTrace) -> ModelRc<ModelRc<StandardListViewItem>> {
+    let cell = |text: String| StandardListViewItem::from(SharedString::from(text));
+    let pawns = |cp: i32| format!("{:.2}", cp as f64 / 100.0);
+    let mut rows: Vec<ModelRc<StandardListViewItem>> = EvalTerm::ALL
+        .iter()
+        .map(|term| {
+            let white = trace.score(*term, PieceColour::White);
+            let black = trace.score(*term, PieceColour::Black);
+            let total = trace.term(*term);
+            let row = vec![
+                cell(term.name().to_string()),
+                cell(pawns(white.mg)),
+                cell(pawns(white.eg)),
+                cell(pawns(black.mg)),
+                cell(pawns(black.eg)),
+                cell(pawns(total.mg)),
+                cell(pawns(total.eg)),
+            ];
+            ModelRc::new(VecModel::from(row))
+        })
+        .collect();
+    let total = trace.total();
+    let mut total_row = vec![cell("Total".to_string())];
+    total_row.extend((0..4).map(|_| cell(String::new())));
+    total_row.push(cell(pawns(total.mg)));
+    total_row.push(cell(pawns(total.eg)));
+    rows.push(ModelRc::new(VecModel::from(total_row)));
+    ModelRc::new(VecModel::from(rows))
+}
+
+// stop the running engine search, its move won't be made as the board will have changed
+fn stop_engine_search(search_stop: &Mutex<Option<Arc<AtomicBool>>>) {
+    if let Some(stop) = search_stop.lock().unwrap().take() {
+        stop.store(true, Ordering::Relaxed);
+    }
+}
+
 fn main() -> Result<(), slint::PlatformError> {
     // initialise logger
     let mut builder = if cfg!(debug_assertions) {
@@ -59,11 +142,20 @@ fn main() -> Result<(), slint::PlatformError> {
     builder.init();
 
     let board = Arc::new(Mutex::new(chess::Board::new()));
+    // stop flag of the running engine search, if any
+    let search_stop: Arc<Mutex<Option<Arc<AtomicBool>>>> = Arc::new(Mutex::new(None));
+    // opening book loaded from the settings dialog, used by the engine when use-book is set
+    let opening_book: Arc<Mutex<Option<Arc<PolyglotBook>>>> = Arc::new(Mutex::new(None));
+    // endgame tablebase directory loaded from the settings dialog
+    let tablebase: Arc<Mutex<Option<Arc<Tablebase>>>> = Arc::new(Mutex::new(None));
+    // evaluator used by the engine, the eval breakdown dialog traces the same one
+    let evaluator = ClassicalEvaluator::default();
 
     let ui = Board_UI::new()?;
     let settings_dialog = SettingsDialog_UI::new()?;
     let import_dialog = Import_UI::new()?;
     let export_dialog = Export_UI::new()?;
+    let eval_trace_dialog = EvalTrace_UI::new()?;
 
     let ui_weak_get_gamestate = ui.as_weak();
     let board_get_gamestate = board.clone();
@@ -105,16 +197,20 @@ fn main() -> Result<(), slint::PlatformError> {
 
     let ui_weak_new_game = ui.as_weak();
     let board_new_game = board.clone();
+    let search_stop_new_game = search_stop.clone();
     ui.on_new_game(move || {
         let ui = ui_weak_new_game.upgrade().unwrap();
+        stop_engine_search(&search_stop_new_game);
         *board_new_game.lock().unwrap() = chess::board::Board::new();
         ui.invoke_refresh_position();
     });
 
     let ui_weak_new_chess960_game = ui.as_weak();
     let board_new_chess960_game = board.clone();
+    let search_stop_new_chess960_game = search_stop.clone();
     ui.on_new_chess960_game(move || {
         let ui = ui_weak_new_chess960_game.upgrade().unwrap();
+        stop_engine_search(&search_stop_new_chess960_game);
         *board_new_chess960_game.lock().unwrap() = chess::board::Board::new_chess960();
         ui.invoke_refresh_position();
     });
@@ -125,14 +221,21 @@ fn main() -> Result<(), slint::PlatformError> {
         let ui = ui_select_legal_moves.upgrade().unwrap();
         let board = board_select_legal_moves.lock().unwrap();
         let mut legal_moves = [false; 64];
-        for mv in board.get_current_state().get_legal_moves().unwrap() {
+        // moves losing material to the exchanges on the target square are marked as unsafe
+        let mut unsafe_moves = [false; 64];
+        let state = board.get_current_state();
+        for mv in state.get_legal_moves().unwrap() {
             if mv.from as i32 == from_square {
-                legal_moves[mv.to as usize] = true;
+                legal_moves[mv.to] = true;
+                unsafe_moves[mv.to] |= state.see(mv) < 0;
             }
         }
         ui.set_selected_legal_moves(
             std::rc::Rc::new(slint::VecModel::from(legal_moves.to_vec())).into(),
         );
+        ui.set_selected_unsafe_moves(
+            std::rc::Rc::new(slint::VecModel::from(unsafe_moves.to_vec())).into(),
+        );
     });
 
     let ui_weak_latest_state = ui.as_weak();
@@ -268,11 +371,14 @@ fn main() -> Result<(), slint::PlatformError> {
 
     let ui_weak_refresh_position = ui.as_weak();
     let export_dialog_weak_refresh_position = export_dialog.as_weak();
+    let eval_trace_dialog_weak_refresh_position = eval_trace_dialog.as_weak();
     let board_refresh_position = board.clone();
+    let evaluator_refresh_position = evaluator.clone();
     ui.on_refresh_position(move || {
         log::debug!("Refreshing position...");
         let ui = ui_weak_refresh_position.upgrade().unwrap();
         let export_dialog = export_dialog_weak_refresh_position.upgrade().unwrap();
+        let eval_trace_dialog = eval_trace_dialog_weak_refresh_position.upgrade().unwrap();
         let mut ui_position: Vec<PieceUI> = vec![];
         for s in board_refresh_position
             .lock()
@@ -353,6 +459,22 @@ fn main() -> Result<(), slint::PlatformError> {
             )
         );
 
+        // breakdown of the static eval of the current BoardState
+        let trace = engine::eval_trace_with(
+            board_refresh_position.lock().unwrap().get_current_state(),
+            &evaluator_refresh_position,
+        );
+        eval_trace_dialog.set_rows(eval_trace_rows(&trace));
+        eval_trace_dialog.set_summary(
+            format!(
+                "Phase: {}/{}    Eval: {} (white side)",
+                trace.phase,
+                engine::MAX_PHASE,
+                eval_to_string(trace.eval())
+            )
+            .into(),
+        );
+
         export_dialog.set_pgn(
             PGN::from(board_refresh_position.lock().unwrap().deref())
                 .to_string()
@@ -455,6 +577,10 @@ fn main() -> Result<(), slint::PlatformError> {
 
     let ui_weak_engine_make_move = ui.as_weak();
     let board_engine_make_move = board.clone();
+    let search_stop_engine_make_move = search_stop.clone();
+    let opening_book_engine_make_move = opening_book.clone();
+    let tablebase_engine_make_move = tablebase.clone();
+    let evaluator_engine_make_move = evaluator.clone();
     ui.on_engine_make_move(move || {
         let ui = ui_weak_engine_make_move.clone();
         let bmem: Arc<Mutex<chess::Board>> = board_engine_make_move.clone();
@@ -465,21 +591,84 @@ fn main() -> Result<(), slint::PlatformError> {
             .to_string()
             .parse::<i32>()
             .unwrap();
-        std::thread::spawn(
-            move || match bmem.lock().unwrap().make_engine_move(depth as u8) {
+        // "None" or e.g. "5s"
+        let move_time = ui
+            .upgrade()
+            .unwrap()
+            .get_move_time()
+            .trim_end_matches('s')
+            .parse::<u64>()
+            .ok()
+            .map(Duration::from_secs);
+        let limits = SearchLimits {
+            depth: Some(depth as u8),
+            movetime: move_time,
+            ..Default::default()
+        };
+        let multipv = ui
+            .upgrade()
+            .unwrap()
+            .get_multipv()
+            .parse::<u8>()
+            .unwrap_or(1);
+        let book = if ui.upgrade().unwrap().get_use_book() {
+            opening_book_engine_make_move.lock().unwrap().clone()
+        } else {
+            None
+        };
+        let options = SearchOptions {
+            book,
+            tablebase: tablebase_engine_make_move.lock().unwrap().clone(),
+            evaluator: evaluator_engine_make_move.clone(),
+            ..SearchOptions::multipv(multipv)
+        };
+        // board is only locked to start and finish the search, so the ui stays responsive while the engine thinks
+        let (root, handle) = {
+            let mut board = bmem.lock().unwrap();
+            let root = board.get_current_state().clone();
+            match board.start_engine_search(limits, options) {
+                Ok(handle) => (root, handle),
+                Err(e) => {
+                    log::error!("BoardStateError on starting engine search: {e}");
+                    return;
+                }
+            }
+        };
+        *search_stop_engine_make_move.lock().unwrap() = Some(handle.stop_flag());
+        std::thread::spawn(move || {
+            let mut from_book = false;
+            while let Some(info) = handle.recv_progress() {
+                let ui = ui.clone();
+                let pv = lines_to_string(&root, &info);
+                from_book = info.from_book;
+                slint::invoke_from_event_loop(move || {
+                    let ui = ui.upgrade().unwrap();
+                    if info.from_book {
+                        ui.set_eval("Book".into());
+                    } else {
+                        ui.set_eval(eval_to_string(info.eval).into());
+                    }
+                    ui.set_pv(pv.into());
+                })
+                .unwrap();
+            }
+            let result = bmem.lock().unwrap().finish_engine_search(handle);
+            match result {
                 Ok((_, eval)) => {
                     slint::invoke_from_event_loop(move || {
                         ui.upgrade().unwrap().invoke_refresh_position();
                         ui.upgrade().unwrap().set_engine_made_move(true);
-                        ui.upgrade().unwrap().set_eval(eval_to_string(eval).into())
+                        if !from_book {
+                            ui.upgrade().unwrap().set_eval(eval_to_string(eval).into())
+                        }
                     })
                     .unwrap();
                 }
                 Err(e) => {
                     log::error!("BoardStateError on making engine move: {e}");
                 }
-            },
-        );
+            }
+        });
     });
 
     let import_dialog_weak_run = import_dialog.as_weak();
@@ -493,24 +682,37 @@ fn main() -> Result<(), slint::PlatformError> {
         export_dialog_weak_run.upgrade().unwrap().show().unwrap();
     });
 
+    let eval_trace_dialog_weak_run = eval_trace_dialog.as_weak();
+    ui.on_eval_trace_dialog(move || {
+        eval_trace_dialog_weak_run
+            .upgrade()
+            .unwrap()
+            .show()
+            .unwrap();
+    });
+
     // close all child dialogs/windows on main window close
     let import_dialog_weak_close = import_dialog.as_weak();
     let export_dialog_weak_close = export_dialog.as_weak();
+    let eval_trace_dialog_weak_close = eval_trace_dialog.as_weak();
     let settings_dialog_weak_close = settings_dialog.as_weak();
     ui.window()
         .on_close_requested(move || -> slint::CloseRequestResponse {
             let import_dialog = import_dialog_weak_close.upgrade().unwrap();
             let export_dialog = export_dialog_weak_close.upgrade().unwrap();
+            let eval_trace_dialog = eval_trace_dialog_weak_close.upgrade().unwrap();
             let settings_dialog = settings_dialog_weak_close.upgrade().unwrap();
             import_dialog.hide().unwrap();
             settings_dialog.hide().unwrap();
             export_dialog.hide().unwrap();
+            eval_trace_dialog.hide().unwrap();
             slint::CloseRequestResponse::HideWindow
         });
 
     let ui_weak_import_fen = ui.as_weak();
     let import_dialog_weak_import_fen = import_dialog.as_weak();
     let board_import_fen = board.clone();
+    let search_stop_import_fen = search_stop.clone();
     import_dialog.on_import_fen(move |fen: SharedString| {
         let import_dialog = import_dialog_weak_import_fen.upgrade().unwrap();
         let ui = ui_weak_import_fen.upgrade().unwrap();
@@ -535,6 +737,7 @@ fn main() -> Result<(), slint::PlatformError> {
             PieceColour_UI::Black
         };
 
+        stop_engine_search(&search_stop_import_fen);
         *board_import_fen.lock().unwrap() = new_board;
 
         ui.invoke_reset_properties(player_side, side_to_move);
@@ -567,16 +770,21 @@ fn main() -> Result<(), slint::PlatformError> {
     let ui_weak_import_pgn = ui.as_weak();
     let import_dialog_weak_import_pgn = import_dialog.as_weak();
     let board_import_pgn = board.clone();
+    let search_stop_import_pgn = search_stop.clone();
     import_dialog.on_import_pgn(move |pgn: SharedString| {
         let import_dialog = import_dialog_weak_import_pgn.upgrade().unwrap();
         let ui = ui_weak_import_pgn.upgrade().unwrap();
 
         log::debug!("Importing PGN: \n{}", pgn);
 
-        let pgn_import = pgn.as_str().parse::<PGN>();
+        // pasted games are often exported by other tools, their quirks are accepted and logged
+        let pgn_import = PGN::parse(pgn.as_str(), ParseMode::Lenient);
         match pgn_import {
             Ok(p) => {
-                log::debug!("Successfully parsed PGN");
+                log::debug!(
+                    "Successfully parsed PGN with {} warnings",
+                    p.warnings().len()
+                );
                 let new_board = chess::Board::try_from(p);
                 match new_board {
                     Ok(b) => {
@@ -585,6 +793,7 @@ fn main() -> Result<(), slint::PlatformError> {
                         import_dialog.set_pgn_error_message("".into());
                         log::debug!("Resetting UI properties and refreshing position");
                         let side = b.get_side_to_move();
+                        stop_engine_search(&search_stop_import_pgn);
                         *board_import_pgn.lock().unwrap() = b;
                         // TODO for now set both to sidetomove so engine doesnt make move
                         ui.invoke_reset_properties(
@@ -656,6 +865,12 @@ fn main() -> Result<(), slint::PlatformError> {
         export_dialog.hide().unwrap();
     });
 
+    let eval_trace_dialog_weak_close = eval_trace_dialog.as_weak();
+    eval_trace_dialog.on_close(move || {
+        let eval_trace_dialog = eval_trace_dialog_weak_close.upgrade().unwrap();
+        eval_trace_dialog.hide().unwrap();
+    });
+
     let settings_dialog_weak_run = settings_dialog.as_weak();
     ui.on_settings_dialog(move || {
         let settings_dialog = settings_dialog_weak_run.upgrade().unwrap();
@@ -674,6 +889,87 @@ fn main() -> Result<(), slint::PlatformError> {
         ui.set_depth(depth);
     });
 
+    let ui_weak_set_multipv = ui.as_weak();
+    settings_dialog.on_set_multipv(move |multipv| {
+        let ui = ui_weak_set_multipv.upgrade().unwrap();
+        ui.set_multipv(multipv);
+    });
+
+    let ui_weak_set_move_time = ui.as_weak();
+    settings_dialog.on_set_move_time(move |move_time| {
+        let ui = ui_weak_set_move_time.upgrade().unwrap();
+        ui.set_move_time(move_time);
+    });
+
+    let settings_dialog_weak_load_book = settings_dialog.as_weak();
+    let opening_book_load_book = opening_book.clone();
+    settings_dialog.on_load_book(move || -> SharedString {
+        let settings_dialog = settings_dialog_weak_load_book.upgrade().unwrap();
+        let path = match native_dialog::FileDialog::new()
+            .set_location("~/Desktop")
+            .add_filter("Polyglot Book", &["bin"])
+            .show_open_single_file()
+        {
+            Ok(Some(path)) => path,
+            Ok(None) => {
+                log::warn!("No file selected");
+                return settings_dialog.get_book_name();
+            }
+            Err(e) => {
+                log::error!("Error opening file dialog: {}", e);
+                return settings_dialog.get_book_name();
+            }
+        };
+        match PolyglotBook::from_file(&path) {
+            Ok(book) => {
+                *opening_book_load_book.lock().unwrap() = Some(Arc::new(book));
+                path.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+                    .into()
+            }
+            // error is logged by the book loader, keep the previous book
+            Err(_) => settings_dialog.get_book_name(),
+        }
+    });
+
+    let settings_dialog_weak_load_tablebase = settings_dialog.as_weak();
+    let tablebase_load_tablebase = tablebase.clone();
+    settings_dialog.on_load_tablebase(move || -> SharedString {
+        let settings_dialog = settings_dialog_weak_load_tablebase.upgrade().unwrap();
+        let path = match native_dialog::FileDialog::new()
+            .set_location("~/Desktop")
+            .show_open_single_dir()
+        {
+            Ok(Some(path)) => path,
+            Ok(None) => {
+                log::warn!("No directory selected");
+                return settings_dialog.get_tablebase_name();
+            }
+            Err(e) => {
+                log::error!("Error opening directory dialog: {}", e);
+                return settings_dialog.get_tablebase_name();
+            }
+        };
+        match Tablebase::open(&path) {
+            Ok(tb) => {
+                *tablebase_load_tablebase.lock().unwrap() = Some(Arc::new(tb));
+                path.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+                    .into()
+            }
+            // error is logged by the tablebase loader, keep the previous tablebase
+            Err(_) => settings_dialog.get_tablebase_name(),
+        }
+    });
+
+    let ui_weak_set_use_book = ui.as_weak();
+    settings_dialog.on_set_use_book(move |use_book| {
+        let ui = ui_weak_set_use_book.upgrade().unwrap();
+        ui.set_use_book(use_book);
+    });
+
     let ui_weak_set_piece_theme = ui.as_weak();
     settings_dialog.on_set_piece_theme(move |theme| {
         let ui = ui_weak_set_piece_theme.upgrade().unwrap();
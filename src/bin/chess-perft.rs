@@ -1,11 +1,219 @@
+use std::fs;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
 use env_logger::{Builder, Env, Target};
 
-use chess::perft;
+use chess::fen::FEN;
+use chess::{engine_smp_perft, perft, perft_divide, perft_nodes, BoardState, PerftEpd, PerftNodes};
+
+const DEFAULT_DEPTH: u8 = 5;
+const USAGE: &str = "Usage:
+  chess-perft [--fen <FEN>] [--depth <N>] [--divide]   perft from a position, the starting position by default
+  chess-perft --epd <FILE> [--max-depth <N>]          run an EPD perft suite, e.g. <FEN> ;D1 20 ;D2 400
+  chess-perft --bench                                  move generation and engine benchmark (default with no arguments)";
+
+#[derive(Debug, Default)]
+struct Args {
+    fen: Option<String>,
+    depth: Option<u8>,
+    divide: bool,
+    epd: Option<String>,
+    max_depth: Option<u8>,
+    bench: bool,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut parsed = Args {
+            bench: args.is_empty(),
+            ..Default::default()
+        };
+        let mut i = 0;
+        while i < args.len() {
+            let arg = args[i].as_str();
+            i += 1;
+            // all values up to the next flag, so a FEN can be given without quotes
+            let mut value = || {
+                let start = i;
+                while i < args.len() && !args[i].starts_with("--") {
+                    i += 1;
+                }
+                if start == i {
+                    Err(format!("Missing value for {}", arg))
+                } else {
+                    Ok(args[start..i].join(" "))
+                }
+            };
+            match arg {
+                "--fen" => parsed.fen = Some(value()?),
+                "--depth" => parsed.depth = Some(parse_depth(&value()?)?),
+                "--divide" => parsed.divide = true,
+                "--epd" => parsed.epd = Some(value()?),
+                "--max-depth" => parsed.max_depth = Some(parse_depth(&value()?)?),
+                "--bench" => parsed.bench = true,
+                "--help" | "-h" => return Err(String::new()),
+                other => return Err(format!("Unknown argument: {}", other)),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+fn parse_depth(s: &str) -> Result<u8, String> {
+    s.parse::<u8>().map_err(|_| format!("Invalid depth: {}", s))
+}
+
+fn nps(nodes: u64, time: Duration) -> u64 {
+    (nodes as f64 / time.as_secs_f64().max(1e-9)) as u64
+}
 
-fn main() {
+fn print_nodes(nodes: &PerftNodes, time: Duration) {
+    println!(" - Nodes: {}", nodes.nodes);
+    println!(" - Captures: {}", nodes.captures);
+    println!(" - En Passant: {}", nodes.en_passant);
+    println!(" - Castles: {}", nodes.castles);
+    println!(" - Promotions: {}", nodes.promotions);
+    println!(" - Time: {:?} ({} nps)", time, nps(nodes.nodes, time));
+}
+
+fn run_perft(fen: Option<&str>, depth: u8, divide: bool) -> Result<(), String> {
+    let bs = match fen {
+        Some(fen) => BoardState::from(FEN::from_str(fen).map_err(|e| e.to_string())?),
+        None => BoardState::new_starting(),
+    };
+    let start = Instant::now();
+    let nodes = if divide {
+        let mut divide = perft_divide(&bs, depth);
+        divide.sort_by_key(|(mv, _)| mv.to_uci_string());
+        let mut total = PerftNodes::default();
+        for (mv, nodes) in divide {
+            println!("{}: {}", mv.to_uci_string(), nodes.nodes);
+            total += nodes;
+        }
+        println!();
+        total
+    } else {
+        perft_nodes(&bs, depth)
+    };
+    let time = start.elapsed();
+    println!("Perft at depth {}:", depth);
+    print_nodes(&nodes, time);
+    Ok(())
+}
+
+// returns the number of positions that passed and the total number of positions
+fn run_epd(path: &str, max_depth: Option<u8>) -> Result<(usize, usize), String> {
+    let suite = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let (mut passed, mut total) = (0, 0);
+    let suite_start = Instant::now();
+    let mut suite_nodes = 0;
+    for line in suite.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        total += 1;
+        println!(
+            "Position {}: {}",
+            total,
+            line.split(';').next().unwrap_or_default().trim()
+        );
+        let epd = match line.parse::<PerftEpd>() {
+            Ok(epd) => epd,
+            Err(e) => {
+                println!(" - FAIL: {}", e);
+                continue;
+            }
+        };
+        let bs = BoardState::from(epd.fen);
+        let mut position_passed = true;
+        for (depth, expected) in epd.depths {
+            if max_depth.is_some_and(|max| depth > max) {
+                continue;
+            }
+            let start = Instant::now();
+            let nodes = perft_nodes(&bs, depth);
+            let time = start.elapsed();
+            suite_nodes += nodes.nodes;
+            let result = if nodes.nodes == expected {
+                "PASS"
+            } else {
+                position_passed = false;
+                "FAIL"
+            };
+            println!(
+                " - D{} {}: {} nodes (expected {}), captures {}, en passant {}, castles {}, promotions {}, {:?} ({} nps)",
+                depth,
+                result,
+                nodes.nodes,
+                expected,
+                nodes.captures,
+                nodes.en_passant,
+                nodes.castles,
+                nodes.promotions,
+                time,
+                nps(nodes.nodes, time)
+            );
+        }
+        if position_passed {
+            passed += 1;
+        }
+    }
+    let time = suite_start.elapsed();
+    println!();
+    println!(
+        "{}/{} positions passed, {} nodes in {:?} ({} nps)",
+        passed,
+        total,
+        suite_nodes,
+        time,
+        nps(suite_nodes, time)
+    );
+    Ok((passed, total))
+}
+
+fn main() -> ExitCode {
     // initialise logger
     let mut builder = Builder::from_env(Env::default().default_filter_or("off"));
     builder.target(Target::Stdout);
     builder.init();
-    perft(10, 5);
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args = match Args::parse(&args) {
+        Ok(args) => args,
+        Err(e) => {
+            if !e.is_empty() {
+                eprintln!("{}\n", e);
+            }
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if args.bench {
+        perft(10, 5);
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        engine_smp_perft(7, threads);
+        return ExitCode::SUCCESS;
+    }
+
+    let result = match &args.epd {
+        Some(path) => run_epd(path, args.max_depth).map(|(passed, total)| passed == total),
+        None => run_perft(
+            args.fen.as_deref(),
+            args.depth.unwrap_or(DEFAULT_DEPTH),
+            args.divide,
+        )
+        .map(|_| true),
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
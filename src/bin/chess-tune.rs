@@ -0,0 +1,139 @@
+use std::fs;
+use std::process::ExitCode;
+use std::time::Instant;
+
+use env_logger::{Builder, Env, Target};
+
+use chess::engine::EvalParams;
+use chess::tune::{self, Tuner};
+
+const DEFAULT_OUTPUT: &str = "eval-params.txt";
+const DEFAULT_ITERATIONS: usize = 100;
+const USAGE: &str = "Usage:
+  chess-tune (--epd <FILE> | --pgn <FILE>)... [options]   tune the evaluation parameters on labelled positions
+
+  --epd <FILE>          positions with the game result, e.g. <FEN> c9 \"1-0\"; or <FEN> [0.5]
+  --pgn <FILE>          quiet positions from finished games, labelled with the game result
+  --params <FILE>       parameters to start from, the engine defaults otherwise
+  --output <FILE>       where the tuned parameters are written after each pass (default eval-params.txt)
+  --iterations <N>      maximum number of passes over the parameters (default 100)
+  --threads <N>         threads used to evaluate the positions (default all cores)";
+
+#[derive(Debug, Default)]
+struct Args {
+    epd: Vec<String>,
+    pgn: Vec<String>,
+    params: Option<String>,
+    output: Option<String>,
+    iterations: Option<usize>,
+    threads: Option<usize>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        let mut i = 0;
+        while i < args.len() {
+            let arg = args[i].as_str();
+            i += 1;
+            let mut value = || match args.get(i) {
+                Some(value) if !value.starts_with("--") => {
+                    i += 1;
+                    Ok(value.clone())
+                }
+                _ => Err(format!("Missing value for {}", arg)),
+            };
+            match arg {
+                "--epd" => parsed.epd.push(value()?),
+                "--pgn" => parsed.pgn.push(value()?),
+                "--params" => parsed.params = Some(value()?),
+                "--output" => parsed.output = Some(value()?),
+                "--iterations" => parsed.iterations = Some(parse_number(&value()?)?),
+                "--threads" => parsed.threads = Some(parse_number(&value()?)?),
+                "--help" | "-h" => return Err(String::new()),
+                other => return Err(format!("Unknown argument: {}", other)),
+            }
+        }
+        if parsed.epd.is_empty() && parsed.pgn.is_empty() {
+            return Err("No positions given, use --epd or --pgn".to_string());
+        }
+        Ok(parsed)
+    }
+}
+
+fn parse_number(s: &str) -> Result<usize, String> {
+    s.parse::<usize>()
+        .map_err(|_| format!("Invalid number: {}", s))
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let mut positions = Vec::new();
+    for path in &args.epd {
+        positions.extend(tune::read_epd_file(path).map_err(|e| e.to_string())?);
+    }
+    for path in &args.pgn {
+        positions.extend(tune::read_pgn_file(path).map_err(|e| e.to_string())?);
+    }
+    if positions.is_empty() {
+        return Err("No positions found".to_string());
+    }
+    println!("Loaded {} positions", positions.len());
+
+    let params = match &args.params {
+        Some(path) => EvalParams::from_file(path).map_err(|e| e.to_string())?,
+        None => EvalParams::default(),
+    };
+    let threads = args
+        .threads
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+    let output = args.output.as_deref().unwrap_or(DEFAULT_OUTPUT);
+    let mut tuner = Tuner::new(positions, params, threads);
+
+    let start = Instant::now();
+    let k = tuner.fit_k();
+    println!("K: {:.4}, error: {:.6}", k, tuner.error());
+    for iteration in 1..=args.iterations.unwrap_or(DEFAULT_ITERATIONS) {
+        let (error, changed) = tuner.pass();
+        fs::write(output, tuner.params().to_string()).map_err(|e| format!("{}: {}", output, e))?;
+        println!(
+            "Pass {}: error {:.6}, {} values changed, {:?}",
+            iteration,
+            error,
+            changed,
+            start.elapsed()
+        );
+        // a local minimum, no single value can be moved to lower the error
+        if changed == 0 {
+            break;
+        }
+    }
+    println!("Tuned parameters written to {}", output);
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    // initialise logger
+    let mut builder = Builder::from_env(Env::default().default_filter_or("off"));
+    builder.target(Target::Stdout);
+    builder.init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args = match Args::parse(&args) {
+        Ok(args) => args,
+        Err(e) => {
+            if !e.is_empty() {
+                eprintln!("{}\n", e);
+            }
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,522 @@
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use env_logger::{Builder, Env};
+
+use chess::book::PolyglotBook;
+use chess::engine::{
+    self, ClassicalEvaluator, EvalParams, Evaluator, NnueEvaluator, NnueNetwork, SearchHandle,
+    SearchHistory, SearchInfo, SearchLimits, SearchOptions, MAX_MULTIPV, MAX_SEARCH_DEPTH,
+    MAX_THREADS,
+};
+use chess::fen::FEN;
+use chess::tablebase::Tablebase;
+use chess::{BoardState, PieceColour, TranspositionTable};
+
+const ENGINE_NAME: &str = "chess-oxide";
+const ENGINE_AUTHOR: &str = "Gavin Shaughnessy";
+const FEATURE_OPTIONS: [&str; 6] = [
+    "NullMove",
+    "LateMoveReductions",
+    "PVS",
+    "Futility",
+    "ReverseFutility",
+    "CheckExtensions",
+];
+const DEFAULT_HASH_MB: usize = 200;
+const MAX_HASH_MB: usize = 4096;
+const DEFAULT_DEPTH: u8 = 5; // used when go is sent with no limits at all, same as the gui default
+
+#[derive(Debug, Default, Clone, Copy)]
+struct GoCommand {
+    limits: SearchLimits,
+    infinite: bool,
+}
+
+impl GoCommand {
+    fn parse(args: &[&str]) -> Self {
+        let mut go = GoCommand::default();
+        let limits = &mut go.limits;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut next_num = || iter.next().and_then(|v| v.parse::<i64>().ok());
+            let mut next_ms = || next_num().map(|v| Duration::from_millis(v.max(0) as u64));
+            match *arg {
+                "depth" => {
+                    limits.depth = next_num().map(|v| v.clamp(1, MAX_SEARCH_DEPTH as i64) as u8)
+                }
+                "nodes" => limits.nodes = next_num().map(|v| v.max(0) as u64),
+                "movetime" => limits.movetime = next_ms(),
+                "wtime" => limits.wtime = next_ms(),
+                "btime" => limits.btime = next_ms(),
+                "winc" => limits.winc = next_ms(),
+                "binc" => limits.binc = next_ms(),
+                "movestogo" => limits.movestogo = next_num().map(|v| v.max(1) as u32),
+                "infinite" => go.infinite = true,
+                _ => {}
+            }
+        }
+        if !go.infinite && go.limits == SearchLimits::default() {
+            go.limits.depth = Some(DEFAULT_DEPTH);
+        }
+        go
+    }
+}
+
+struct Search {
+    stop: Arc<AtomicBool>,
+    stopped: mpsc::Sender<()>, // wakes the reporter of an infinite search once stop is received
+    reporter: thread::JoinHandle<(TranspositionTable, SearchHistory)>,
+}
+
+struct Uci {
+    state: BoardState,
+    tt: Option<TranspositionTable>,
+    history: Option<SearchHistory>,
+    hash_mb: usize,
+    options: SearchOptions,
+    book: Option<Arc<PolyglotBook>>,
+    own_book: bool,
+    network: Option<Arc<NnueNetwork>>, // evaluates with the network instead of the default evaluator
+    search: Option<Search>,
+}
+
+impl Uci {
+    fn new() -> Self {
+        Uci {
+            state: BoardState::new_starting(),
+            tt: Some(TranspositionTable::with_size(DEFAULT_HASH_MB)),
+            history: Some(SearchHistory::new()),
+            hash_mb: DEFAULT_HASH_MB,
+            options: SearchOptions::default(),
+            book: None,
+            own_book: false,
+            network: None,
+            search: None,
+        }
+    }
+
+    // returns false when the engine should exit
+    fn handle_command(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = tokens.split_first() else {
+            return true;
+        };
+        match command {
+            "uci" => {
+                println!("id name {} {}", ENGINE_NAME, env!("CARGO_PKG_VERSION"));
+                println!("id author {}", ENGINE_AUTHOR);
+                println!(
+                    "option name Hash type spin default {} min 1 max {}",
+                    DEFAULT_HASH_MB, MAX_HASH_MB
+                );
+                println!("option name Clear Hash type button");
+                println!(
+                    "option name Threads type spin default 1 min 1 max {}",
+                    MAX_THREADS
+                );
+                println!(
+                    "option name MultiPV type spin default 1 min 1 max {}",
+                    MAX_MULTIPV
+                );
+                println!("option name OwnBook type check default false");
+                println!("option name BookFile type string default <empty>");
+                println!("option name SyzygyPath type string default <empty>");
+                println!("option name EvalFile type string default <empty>");
+                println!("option name EvalParams type string default <empty>");
+                // search features, to test each one on its own
+                for name in FEATURE_OPTIONS {
+                    println!("option name {} type check default true", name);
+                }
+                println!("uciok");
+            }
+            "isready" => println!("readyok"),
+            "ucinewgame" => {
+                self.stop_search();
+                self.state = BoardState::new_starting();
+                if let Some(tt) = self.tt.as_mut() {
+                    tt.clear();
+                }
+                if let Some(history) = self.history.as_mut() {
+                    history.clear();
+                }
+            }
+            "position" => {
+                self.stop_search();
+                self.set_position(args);
+            }
+            "go" => {
+                self.stop_search();
+                self.go(GoCommand::parse(args));
+            }
+            "stop" => self.stop_search(),
+            "setoption" => {
+                self.stop_search();
+                self.set_option(args);
+            }
+            "quit" => {
+                self.stop_search();
+                return false;
+            }
+            // debug and ponderhit are accepted but have no effect, as the engine does not ponder
+            "debug" | "ponderhit" => {}
+            // not part of uci, prints the classical eval of the current position broken down by term
+            "eval" => self.print_eval(),
+            _ => log::warn!("Unknown UCI command: {}", line),
+        }
+        true
+    }
+
+    fn set_position(&mut self, args: &[&str]) {
+        let moves_idx = args.iter().position(|a| *a == "moves");
+        let (pos_args, moves) = match moves_idx {
+            Some(i) => (&args[..i], &args[i + 1..]),
+            None => (args, &[][..]),
+        };
+        let mut state = match pos_args.split_first() {
+            Some((&"startpos", _)) => BoardState::new_starting(),
+            Some((&"fen", fen_args)) => match FEN::from_str(&fen_args.join(" ")) {
+                Ok(fen) => BoardState::from(fen),
+                Err(e) => {
+                    log::error!("Invalid FEN in position command: {}", e);
+                    return;
+                }
+            },
+            _ => {
+                log::error!("Invalid position command: {:?}", args);
+                return;
+            }
+        };
+        for uci_mv in moves {
+            let next = state
+                .move_from_uci(uci_mv)
+                .and_then(|mv| state.next_state(&mv));
+            match next {
+                Ok(next) => state = next,
+                Err(e) => {
+                    log::error!("Invalid move in position command: {}", e);
+                    return;
+                }
+            }
+        }
+        self.state = state;
+    }
+
+    fn set_option(&mut self, args: &[&str]) {
+        // setoption name <id> [value <x>], option names can contain spaces
+        let value_idx = args.iter().position(|a| *a == "value");
+        let name_end = value_idx.unwrap_or(args.len());
+        let name = args.get(1..name_end).unwrap_or_default().join(" ");
+        let value = value_idx
+            .map(|i| args[i + 1..].join(" "))
+            .unwrap_or_default();
+        match name.to_lowercase().as_str() {
+            "hash" => match value.parse::<usize>() {
+                Ok(mb) => {
+                    self.hash_mb = mb.clamp(1, MAX_HASH_MB);
+                    self.tt = Some(TranspositionTable::with_size(self.hash_mb));
+                }
+                Err(_) => log::error!("Invalid Hash value: {}", value),
+            },
+            "multipv" => match value.parse::<u8>() {
+                Ok(multipv) => self.options.multipv = multipv.clamp(1, MAX_MULTIPV),
+                Err(_) => log::error!("Invalid MultiPV value: {}", value),
+            },
+            "threads" => match value.parse::<usize>() {
+                Ok(threads) => self.options.threads = threads.clamp(1, MAX_THREADS),
+                Err(_) => log::error!("Invalid Threads value: {}", value),
+            },
+            "ownbook" => match value.parse::<bool>() {
+                Ok(own_book) => self.own_book = own_book,
+                Err(_) => log::error!("Invalid OwnBook value: {}", value),
+            },
+            "bookfile" => {
+                self.book = if value.is_empty() || value == "<empty>" {
+                    None
+                } else {
+                    // error is logged by the book loader
+                    PolyglotBook::from_file(&value).ok().map(Arc::new)
+                };
+            }
+            "syzygypath" => {
+                self.options.tablebase = if value.is_empty() || value == "<empty>" {
+                    None
+                } else {
+                    // error is logged by the tablebase loader
+                    Tablebase::open(&value).ok().map(Arc::new)
+                };
+            }
+            "evalfile" => {
+                self.network = if value.is_empty() || value == "<empty>" {
+                    None
+                } else {
+                    // error is logged by the network loader
+                    NnueNetwork::from_file(&value).ok().map(Arc::new)
+                };
+            }
+            "evalparams" => {
+                self.options.evaluator = if value.is_empty() || value == "<empty>" {
+                    ClassicalEvaluator::default()
+                } else {
+                    // error is logged by the parameters loader, the defaults are kept
+                    match EvalParams::from_file(&value) {
+                        Ok(params) => ClassicalEvaluator::with_params(Arc::new(params)),
+                        Err(_) => ClassicalEvaluator::default(),
+                    }
+                };
+            }
+            "nullmove" | "latemovereductions" | "pvs" | "futility" | "reversefutility"
+            | "checkextensions" => {
+                let Ok(enabled) = value.parse::<bool>() else {
+                    log::error!("Invalid {} value: {}", name, value);
+                    return;
+                };
+                let features = &mut self.options.features;
+                let feature = match name.to_lowercase().as_str() {
+                    "nullmove" => &mut features.null_move,
+                    "latemovereductions" => &mut features.late_move_reductions,
+                    "pvs" => &mut features.pvs,
+                    "futility" => &mut features.futility,
+                    "reversefutility" => &mut features.reverse_futility,
+                    _ => &mut features.check_extensions,
+                };
+                *feature = enabled;
+            }
+            "clear hash" => {
+                if let Some(tt) = self.tt.as_mut() {
+                    tt.clear();
+                }
+            }
+            _ => log::warn!("Unknown option: {}", name),
+        }
+    }
+
+    fn go(&mut self, go: GoCommand) {
+        let tt = self
+            .tt
+            .take()
+            .unwrap_or_else(|| TranspositionTable::with_size(self.hash_mb));
+        let history = self.history.take().unwrap_or_default();
+        let options = SearchOptions {
+            book: self.book.clone().filter(|_| self.own_book),
+            ..self.options.clone()
+        };
+        let state = self.state.clone();
+        let handle = match &self.network {
+            Some(network) => {
+                let options = options.with_evaluator(NnueEvaluator::new(network.clone()));
+                SearchHandle::spawn(state, go.limits, options, tt, history)
+            }
+            None => SearchHandle::spawn(state, go.limits, options, tt, history),
+        };
+        let stop = handle.stop_flag();
+        let (stopped, stop_received) = mpsc::channel();
+        // reports progress while the search runs, then sends bestmove and gives back the transposition table
+        // and move ordering history
+        let reporter = thread::spawn(move || {
+            while let Some(info) = handle.recv_progress() {
+                print_info(&info);
+            }
+            let (info, tt, history) = handle.join();
+            // in infinite mode bestmove must not be sent before stop
+            if go.infinite {
+                let _ = stop_received.recv();
+            }
+            println!("bestmove {}", info.best_move.to_uci_string());
+            io::stdout().flush().ok();
+            (tt, history)
+        });
+        self.search = Some(Search {
+            stop,
+            stopped,
+            reporter,
+        });
+    }
+
+    // the trace is always of the classical eval, so with a network loaded its eval is printed as well
+    fn print_eval(&self) {
+        println!(
+            "{}",
+            engine::eval_trace_with(&self.state, &self.options.evaluator)
+        );
+        if let Some(network) = &self.network {
+            let pos = self.state.get_pos64();
+            let mut evaluator = NnueEvaluator::new(network.clone());
+            evaluator.reset(pos);
+            let side = self.state.side_to_move;
+            let eval = evaluator.evaluate(pos, side);
+            let eval = if side == PieceColour::White {
+                eval
+            } else {
+                -eval
+            };
+            println!(
+                "Classical trace, network active: network eval {:+.2} (white side)",
+                eval as f64 / 100.0
+            );
+        }
+    }
+
+    // signals the running search to stop and waits for it to print bestmove
+    fn stop_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.stop.store(true, Ordering::Relaxed);
+            let _ = search.stopped.send(());
+            match search.reporter.join() {
+                Ok((tt, history)) => {
+                    self.tt = Some(tt);
+                    self.history = Some(history);
+                }
+                Err(_) => log::error!("Search thread panicked"),
+            }
+        }
+    }
+}
+
+fn print_info(info: &SearchInfo) {
+    if info.from_book {
+        println!("info string book move {}", info.best_move.to_uci_string());
+        io::stdout().flush().ok();
+        return;
+    }
+    let ms = info.time.as_millis() as u64;
+    let nps = info.nodes * 1000 / ms.max(1);
+    for (i, line) in info.lines.iter().enumerate() {
+        let score = if engine::is_eval_checkmate(line.eval) {
+            let moves = (engine::get_checkmate_ply(line.eval) as i32 + 1) / 2;
+            format!("mate {}", if line.eval > 0 { moves } else { -moves })
+        } else {
+            format!("cp {}", line.eval)
+        };
+        let pv: Vec<String> = line.pv.iter().map(|mv| mv.to_uci_string()).collect();
+        println!(
+            "info depth {} multipv {} score {} nodes {} nps {} tbhits {} time {} pv {}",
+            info.depth,
+            i + 1,
+            score,
+            info.nodes,
+            nps,
+            info.tb_hits,
+            ms,
+            pv.join(" ")
+        );
+    }
+    io::stdout().flush().ok();
+}
+
+fn main() {
+    // stdout is reserved for the protocol, so logs go to stderr
+    let mut builder = Builder::from_env(Env::default().default_filter_or("off"));
+    builder.init();
+
+    let mut uci = Uci::new();
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if !uci.handle_command(&line) {
+            break;
+        }
+        io::stdout().flush().ok();
+    }
+    uci.stop_search();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fen(uci: &Uci) -> String {
+        FEN::from(&uci.state).to_string()
+    }
+
+    #[test]
+    fn test_go_command_parse() {
+        let go = GoCommand::parse(&[
+            "wtime",
+            "60000",
+            "btime",
+            "-5",
+            "winc",
+            "1000",
+            "movestogo",
+            "20",
+        ]);
+        assert!(!go.infinite);
+        assert_eq!(go.limits.wtime, Some(Duration::from_secs(60)));
+        assert_eq!(go.limits.btime, Some(Duration::ZERO));
+        assert_eq!(go.limits.winc, Some(Duration::from_secs(1)));
+        assert_eq!(go.limits.binc, None);
+        assert_eq!(go.limits.movestogo, Some(20));
+        assert_eq!(go.limits.depth, None);
+
+        let go = GoCommand::parse(&["depth", "0", "nodes", "5000", "movetime", "250"]);
+        assert_eq!(go.limits.depth, Some(1));
+        assert_eq!(go.limits.nodes, Some(5000));
+        assert_eq!(go.limits.movetime, Some(Duration::from_millis(250)));
+        assert_eq!(
+            GoCommand::parse(&["depth", "1000"]).limits.depth,
+            Some(MAX_SEARCH_DEPTH)
+        );
+
+        let go = GoCommand::parse(&["infinite"]);
+        assert!(go.infinite);
+        assert_eq!(go.limits, SearchLimits::default());
+        // no limits, or only ones that can't be read, search to the default depth
+        for args in [&[][..], &["depth", "x"], &["ponder"]] {
+            let go = GoCommand::parse(args);
+            assert!(!go.infinite);
+            assert_eq!(go.limits, SearchLimits::depth(DEFAULT_DEPTH));
+        }
+    }
+
+    #[test]
+    fn test_set_position() {
+        let mut uci = Uci::new();
+        uci.set_position(&["startpos", "moves", "e2e4", "c7c5", "g1f3"]);
+        assert_eq!(
+            fen(&uci),
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+
+        // castling can be sent as the king move or, for chess960, as the king taking the rook
+        let castling_fen = ["fen", "r3k2r/8/8/8/8/8/8/R3K2R", "w", "KQkq", "-", "0", "1"];
+        uci.set_position(&[&castling_fen[..], &["moves", "e1h1", "e8c8"]].concat());
+        assert_eq!(fen(&uci), "2kr3r/8/8/8/8/8/8/R4RK1 w - - 2 2");
+        uci.set_position(&[&castling_fen[..], &["moves", "e1g1", "e8a8"]].concat());
+        assert_eq!(fen(&uci), "2kr3r/8/8/8/8/8/8/R4RK1 w - - 2 2");
+        uci.set_position(&[
+            "fen",
+            "7k/P7/8/8/8/8/8/K7",
+            "w",
+            "-",
+            "-",
+            "0",
+            "1",
+            "moves",
+            "a7a8n",
+        ]);
+        assert!(fen(&uci).starts_with("N6k/8/8/8/8/8/8/K7 b - -"));
+
+        // a position command with an illegal move, invalid FEN or missing arguments keeps the old position
+        // rather than applying some of its moves
+        uci.set_position(&["startpos", "moves", "e2e4"]);
+        let before = fen(&uci);
+        for args in [
+            &["startpos", "moves", "d2d4", "d2d4"][..],
+            &["startpos", "moves", "e7e5"],
+            &["startpos", "moves", "e2e4", "e7e8q"],
+            &["startpos", "moves", "e2e4", "xx"],
+            &["fen", "8/8/8", "w", "-", "-", "0", "1"],
+            &["moves", "e2e4"],
+            &[],
+        ] {
+            uci.set_position(args);
+            assert_eq!(fen(&uci), before, "{:?}", args);
+        }
+    }
+}
@@ -0,0 +1,499 @@
+// bitboard attack generation. bit i of a bitboard is square index i, so bit 0 is a8 and bit 63 is h1
+use static_init::dynamic;
+
+use crate::movegen::{Piece, PieceColour, PieceType, Square};
+use crate::position::Pos64;
+
+pub type Bitboard = u64;
+
+pub const FILE_A: Bitboard = 0x0101_0101_0101_0101;
+pub const FILE_H: Bitboard = FILE_A << 7;
+const ROOK_DIRECTIONS: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+const BISHOP_DIRECTIONS: [(i32, i32); 4] = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+const KNIGHT_JUMPS: [(i32, i32); 8] = [
+    (-2, -1),
+    (-2, 1),
+    (-1, -2),
+    (-1, 2),
+    (1, -2),
+    (1, 2),
+    (2, -1),
+    (2, 1),
+];
+const KING_STEPS: [(i32, i32); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+
+// magic numbers found offline for this square layout, with one shift per square (64 - mask bits)
+const ROOK_MAGICS: [u64; 64] = [
+    0x2080002080400010,
+    0x00c0002001401000,
+    0x2100110008402002,
+    0x0880080081041000,
+    0x0200020020041008,
+    0x2300040008010012,
+    0x0c00283004008201,
+    0x0180010000407a80,
+    0x0168800080400020,
+    0x0010400040201000,
+    0x1001002001001048,
+    0x1001002408100100,
+    0x0801000408010012,
+    0x4001000209000400,
+    0x08a20004c8020001,
+    0x2002801145002280,
+    0x0080860021004200,
+    0x001000c009402002,
+    0x00b0002004002800,
+    0x100a808010020800,
+    0x8101010008000410,
+    0x0244008002000480,
+    0x0000040010810208,
+    0x2000020000448534,
+    0x4104400480008033,
+    0x0000810100204000,
+    0x0440430900200010,
+    0x4600240900100100,
+    0x0060080080040080,
+    0x0001000300080400,
+    0x0004084400011002,
+    0x0023040200008041,
+    0x0580050043002080,
+    0x0400804002802008,
+    0x0001002001004010,
+    0x1000200901001000,
+    0x4410800801800c00,
+    0xa012003806001004,
+    0x0020100104008802,
+    0x0004808402000041,
+    0x0010400170898000,
+    0x0080500020004004,
+    0x1040408012020020,
+    0x8010040008004040,
+    0x2001080100110004,
+    0x0000020004008080,
+    0x0021010810040002,
+    0x0800008c43020024,
+    0x0000800021005100,
+    0x0070201040008080,
+    0x0000d04282006a00,
+    0x0010014400080240,
+    0x0001080110050100,
+    0x0012000810240600,
+    0x0402000801040200,
+    0x028100108a004100,
+    0x0050800300102045,
+    0x8208210040120882,
+    0x8010600101183441,
+    0x020b000910006045,
+    0x0241001002480005,
+    0x0081000400880241,
+    0x0000009008024124,
+    0x0048122980410402,
+];
+const BISHOP_MAGICS: [u64; 64] = [
+    0x0848020822040013,
+    0x8010a40085821200,
+    0x0008008430840822,
+    0x0808048108040000,
+    0x1304042100008104,
+    0x5001012010204023,
+    0x81048801b8200420,
+    0x200a008084012000,
+    0x0040102001042084,
+    0x840a505042428020,
+    0x0000700102202920,
+    0x44101c0c10800002,
+    0x0040040422000000,
+    0x0180020802090202,
+    0x4020020811041202,
+    0x000104308c042000,
+    0x4140661002424400,
+    0x0028012008010460,
+    0x0188062102002a00,
+    0x0014004840102008,
+    0x0105000290400002,
+    0x8001022200410400,
+    0x104a041918013446,
+    0x008a000082008238,
+    0x04a0060008100430,
+    0x0008220008820801,
+    0x2508041208005010,
+    0x4008080200202020,
+    0x2441001013004000,
+    0x0030008060407000,
+    0x4008108000420800,
+    0x0012021050290100,
+    0x0210080482200500,
+    0xcc01112048100480,
+    0x0020402806500440,
+    0x00048e0080580080,
+    0x0040102020020080,
+    0x0028010440080807,
+    0x4601041108008800,
+    0x8040810e04104200,
+    0x901210110400088a,
+    0xa003080212081050,
+    0x00c1004048401004,
+    0x900000a014400800,
+    0x0008021040405401,
+    0x4020008206002090,
+    0x0004190424030100,
+    0x0424008a02026250,
+    0x8004088250900040,
+    0x1c00430088a04200,
+    0x0001020094040001,
+    0x8040210020880061,
+    0x2010040450442032,
+    0x0800840850044001,
+    0x0004040802140004,
+    0x0004080a04222020,
+    0x8088802110022000,
+    0x1081a10416114400,
+    0x0205010a24060820,
+    0x0000000720411080,
+    0x1008000208430400,
+    0x580c026028810840,
+    0x802020441020a110,
+    0x12c0022401020018,
+];
+pub const KNIGHT_ATTACKS: [Bitboard; 64] = step_attacks(&KNIGHT_JUMPS);
+pub const KING_ATTACKS: [Bitboard; 64] = step_attacks(&KING_STEPS);
+// squares attacked by a pawn of each colour, indexed by PieceColour as usize
+pub const PAWN_ATTACKS: [[Bitboard; 64]; 2] = [
+    step_attacks(&[(-1, -1), (-1, 1)]),
+    step_attacks(&[(1, -1), (1, 1)]),
+];
+
+#[dynamic]
+static ROOK_TABLE: MagicTable = MagicTable::new(&ROOK_MAGICS, &ROOK_DIRECTIONS);
+#[dynamic]
+static BISHOP_TABLE: MagicTable = MagicTable::new(&BISHOP_MAGICS, &BISHOP_DIRECTIONS);
+
+const fn step_attacks(steps: &[(i32, i32)]) -> [Bitboard; 64] {
+    let mut table = [0; 64];
+    let mut sq = 0;
+    while sq < 64 {
+        let (row, col) = ((sq / 8) as i32, (sq % 8) as i32);
+        let mut j = 0;
+        while j < steps.len() {
+            let (r, c) = (row + steps[j].0, col + steps[j].1);
+            if r >= 0 && r < 8 && c >= 0 && c < 8 {
+                table[sq] |= 1 << (r * 8 + c);
+            }
+            j += 1;
+        }
+        sq += 1;
+    }
+    table
+}
+
+// slow ray walk, only used to fill the magic tables. with edges false the last square of each ray is
+// left out, giving the relevant occupancy mask
+fn ray_attacks(sq: usize, occupied: Bitboard, directions: &[(i32, i32)], edges: bool) -> Bitboard {
+    let mut attacks = 0;
+    let (row, col) = ((sq / 8) as i32, (sq % 8) as i32);
+    for (dr, dc) in directions {
+        let (mut r, mut c) = (row + dr, col + dc);
+        while (0..8).contains(&r) && (0..8).contains(&c) {
+            let on_edge = !((0..8).contains(&(r + dr)) && (0..8).contains(&(c + dc)));
+            if on_edge && !edges {
+                break;
+            }
+            let bit = 1 << (r * 8 + c);
+            attacks |= bit;
+            if occupied & bit != 0 {
+                break;
+            }
+            r += dr;
+            c += dc;
+        }
+    }
+    attacks
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct MagicEntry {
+    mask: Bitboard,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+impl MagicEntry {
+    #[inline(always)]
+    fn index(&self, occupied: Bitboard) -> usize {
+        self.offset + ((occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+struct MagicTable {
+    entries: [MagicEntry; 64],
+    attacks: Vec<Bitboard>,
+}
+
+impl MagicTable {
+    fn new(magics: &[u64; 64], directions: &[(i32, i32)]) -> Self {
+        let mut entries = [MagicEntry::default(); 64];
+        let mut attacks = Vec::new();
+        for (sq, entry) in entries.iter_mut().enumerate() {
+            let mask = ray_attacks(sq, 0, directions, false);
+            *entry = MagicEntry {
+                mask,
+                magic: magics[sq],
+                shift: 64 - mask.count_ones(),
+                offset: attacks.len(),
+            };
+            attacks.resize(attacks.len() + (1 << mask.count_ones()), 0);
+            // carry rippler, enumerates every subset of the mask
+            let mut subset: Bitboard = 0;
+            loop {
+                attacks[entry.index(subset)] = ray_attacks(sq, subset, directions, true);
+                subset = subset.wrapping_sub(mask) & mask;
+                if subset == 0 {
+                    break;
+                }
+            }
+        }
+        Self { entries, attacks }
+    }
+
+    #[inline(always)]
+    fn get(&self, sq: usize, occupied: Bitboard) -> Bitboard {
+        unsafe {
+            let entry = self.entries.get_unchecked(sq);
+            *self.attacks.get_unchecked(entry.index(occupied))
+        }
+    }
+}
+
+#[inline(always)]
+pub fn rook_attacks(sq: usize, occupied: Bitboard) -> Bitboard {
+    ROOK_TABLE.get(sq, occupied)
+}
+
+#[inline(always)]
+pub fn bishop_attacks(sq: usize, occupied: Bitboard) -> Bitboard {
+    BISHOP_TABLE.get(sq, occupied)
+}
+
+#[inline(always)]
+pub fn queen_attacks(sq: usize, occupied: Bitboard) -> Bitboard {
+    rook_attacks(sq, occupied) | bishop_attacks(sq, occupied)
+}
+
+// squares attacked by a piece of type ptype on sq, for pawns only the capture squares
+#[inline(always)]
+pub fn piece_attacks(piece: Piece, sq: usize, occupied: Bitboard) -> Bitboard {
+    match piece.ptype {
+        PieceType::Pawn => PAWN_ATTACKS[piece.pcolour as usize][sq],
+        PieceType::Knight => KNIGHT_ATTACKS[sq],
+        PieceType::Bishop => bishop_attacks(sq, occupied),
+        PieceType::Rook => rook_attacks(sq, occupied),
+        PieceType::Queen => queen_attacks(sq, occupied),
+        PieceType::King => KING_ATTACKS[sq],
+    }
+}
+
+#[inline(always)]
+pub const fn square_bb(sq: usize) -> Bitboard {
+    1 << sq
+}
+
+// squares directly left and right of sq on the same rank
+#[inline(always)]
+pub const fn adjacent_bb(sq: usize) -> Bitboard {
+    let bb = square_bb(sq);
+    ((bb << 1) & !FILE_A) | ((bb >> 1) & !FILE_H)
+}
+
+// iterator over the set squares of a bitboard, lowest index first
+pub struct Squares(Bitboard);
+
+impl Iterator for Squares {
+    type Item = usize;
+
+    #[inline(always)]
+    fn next(&mut self) -> Option<usize> {
+        if self.0 == 0 {
+            return None;
+        }
+        let sq = self.0.trailing_zeros() as usize;
+        self.0 &= self.0 - 1;
+        Some(sq)
+    }
+}
+
+#[inline(always)]
+pub const fn squares(bb: Bitboard) -> Squares {
+    Squares(bb)
+}
+
+// piece placement as bitboards, kept in sync with the Pos64 mailbox by Position
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Bitboards {
+    colours: [Bitboard; 2],
+    pieces: [Bitboard; 6],
+}
+
+impl Bitboards {
+    #[inline(always)]
+    pub fn put(&mut self, sq: usize, piece: Piece) {
+        self.colours[piece.pcolour as usize] |= square_bb(sq);
+        self.pieces[piece.ptype as usize] |= square_bb(sq);
+    }
+
+    // removes whatever piece is on sq, if any
+    #[inline(always)]
+    pub fn clear(&mut self, sq: usize) {
+        let mask = !square_bb(sq);
+        for bb in self.colours.iter_mut().chain(self.pieces.iter_mut()) {
+            *bb &= mask;
+        }
+    }
+
+    #[inline(always)]
+    pub fn set(&mut self, sq: usize, square: Square) {
+        self.clear(sq);
+        if let Square::Piece(p) = square {
+            self.put(sq, p);
+        }
+    }
+
+    #[inline(always)]
+    pub fn occupied(&self) -> Bitboard {
+        self.colours[0] | self.colours[1]
+    }
+
+    #[inline(always)]
+    pub fn colour(&self, colour: PieceColour) -> Bitboard {
+        self.colours[colour as usize]
+    }
+
+    #[inline(always)]
+    pub fn ptype(&self, ptype: PieceType) -> Bitboard {
+        self.pieces[ptype as usize]
+    }
+
+    // is sq attacked by any piece of colour 'by'
+    #[inline(always)]
+    pub fn is_attacked(&self, sq: usize, by: PieceColour) -> bool {
+        let them = self.colour(by);
+        let occupied = self.occupied();
+        // cheap leapers first, sliders need the magic lookups
+        if PAWN_ATTACKS[!by as usize][sq] & self.ptype(PieceType::Pawn) & them != 0
+            || KNIGHT_ATTACKS[sq] & self.ptype(PieceType::Knight) & them != 0
+            || KING_ATTACKS[sq] & self.ptype(PieceType::King) & them != 0
+        {
+            return true;
+        }
+        let queens = self.ptype(PieceType::Queen);
+        bishop_attacks(sq, occupied) & (self.ptype(PieceType::Bishop) | queens) & them != 0
+            || rook_attacks(sq, occupied) & (self.ptype(PieceType::Rook) | queens) & them != 0
+    }
+
+    // pieces of both colours attacking sq, with sliders blocked by occupied instead of the real occupancy.
+    // pieces removed from occupied are still included, mask them out if they shouldn't be
+    #[inline(always)]
+    pub fn attackers(&self, sq: usize, occupied: Bitboard) -> Bitboard {
+        let pawns = self.ptype(PieceType::Pawn);
+        let queens = self.ptype(PieceType::Queen);
+        PAWN_ATTACKS[PieceColour::Black as usize][sq] & pawns & self.colour(PieceColour::White)
+            | PAWN_ATTACKS[PieceColour::White as usize][sq]
+                & pawns
+                & self.colour(PieceColour::Black)
+            | KNIGHT_ATTACKS[sq] & self.ptype(PieceType::Knight)
+            | KING_ATTACKS[sq] & self.ptype(PieceType::King)
+            | bishop_attacks(sq, occupied) & (self.ptype(PieceType::Bishop) | queens)
+            | rook_attacks(sq, occupied) & (self.ptype(PieceType::Rook) | queens)
+    }
+}
+
+impl From<&Pos64> for Bitboards {
+    fn from(pos64: &Pos64) -> Self {
+        let mut bitboards = Self::default();
+        for (sq, s) in pos64.iter().enumerate() {
+            if let Square::Piece(p) = s {
+                bitboards.put(sq, *p);
+            }
+        }
+        bitboards
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_magic_attacks_match_ray_walk() {
+        let mut occupied: Bitboard = 0x9E37_79B9_7F4A_7C15;
+        for _ in 0..64 {
+            // xorshift, just to get varied occupancies
+            occupied ^= occupied << 13;
+            occupied ^= occupied >> 7;
+            occupied ^= occupied << 17;
+            for sq in 0..64 {
+                assert_eq!(
+                    rook_attacks(sq, occupied),
+                    ray_attacks(sq, occupied, &ROOK_DIRECTIONS, true)
+                );
+                assert_eq!(
+                    bishop_attacks(sq, occupied),
+                    ray_attacks(sq, occupied, &BISHOP_DIRECTIONS, true)
+                );
+            }
+        }
+    }
+
+    // a magic that maps two blocker sets with different attacks to the same index overwrites one of them
+    // while the table is filled, so every subset of every mask is looked up again
+    #[test]
+    fn test_magics_no_destructive_collisions() {
+        for (table, directions) in [
+            (&*ROOK_TABLE, &ROOK_DIRECTIONS),
+            (&*BISHOP_TABLE, &BISHOP_DIRECTIONS),
+        ] {
+            for sq in 0..64 {
+                let entry = table.entries[sq];
+                let size = 1 << entry.mask.count_ones();
+                let mut subset: Bitboard = 0;
+                loop {
+                    let index = entry.index(subset);
+                    assert!((entry.offset..entry.offset + size).contains(&index));
+                    let expected = ray_attacks(sq, subset, directions, true);
+                    assert_eq!(table.get(sq, subset), expected, "{} {:#x}", sq, subset);
+                    // blockers outside the mask, including the edges, don't change the attacks
+                    assert_eq!(table.get(sq, subset | !entry.mask), expected);
+                    subset = subset.wrapping_sub(entry.mask) & entry.mask;
+                    if subset == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_leaper_attacks() {
+        // knight on a8 attacks b6 and c7
+        assert_eq!(KNIGHT_ATTACKS[0], square_bb(17) | square_bb(10));
+        // king on h1 attacks g1, g2 and h2
+        assert_eq!(
+            KING_ATTACKS[63],
+            square_bb(62) | square_bb(54) | square_bb(55)
+        );
+        // white pawn on e2 attacks d3 and f3, black pawn on e7 attacks d6 and f6
+        assert_eq!(PAWN_ATTACKS[0][52], square_bb(43) | square_bb(45));
+        assert_eq!(PAWN_ATTACKS[1][12], square_bb(19) | square_bb(21));
+        assert_eq!(adjacent_bb(24), square_bb(25));
+        assert_eq!(adjacent_bb(31), square_bb(30));
+    }
+}
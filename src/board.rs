@@ -5,11 +5,12 @@ use log;
 
 use crate::engine;
 use crate::errors::BoardStateError;
-use crate::errors::PGNParseError;
+use crate::errors::{PGNParseError, PGNParseErrorKind};
 use crate::fen::FEN;
 use crate::log_and_return_error;
 use crate::movegen::*;
 use crate::pgn;
+use crate::pgn::command::MoveCommands;
 use crate::pgn::notation::Notation;
 use crate::pgn::tag::Tag;
 use crate::position::*;
@@ -160,6 +161,10 @@ impl BoardState {
         &self.position
     }
 
+    pub(crate) fn position_occurences(&self) -> &ahash::AHashMap<PositionHash, u8> {
+        &self.position_occurences
+    }
+
     pub fn halfmove_count(&self) -> u32 {
         self.halfmove_count
     }
@@ -177,6 +182,12 @@ impl BoardState {
         self.position.is_move_legal(mv)
     }
 
+    // material won or lost by mv in centipawns after the exchanges on its target square, see Position::see.
+    // negative if the moved piece can be won, e.g. to show a capture isn't safe
+    pub fn see(&self, mv: &Move) -> i32 {
+        self.position.see(mv)
+    }
+
     // lazily do legality check on pseudo legal moves as the iterator is used
     pub fn lazy_get_legal_moves(&self) -> impl Iterator<Item = &Move> {
         self.position
@@ -344,6 +355,53 @@ impl BoardState {
         Ok(&self.legal_moves)
     }
 
+    // find the legal move matching a UCI long algebraic move string (e.g. e2e4, e7e8q)
+    // castling is accepted both as the king move (e1g1) and as king takes rook (e1h1) as used for chess960
+    pub fn move_from_uci(&self, uci: &str) -> Result<Move, BoardStateError> {
+        let uci = uci.trim();
+        if !uci.is_ascii() || !(4..=5).contains(&uci.len()) {
+            let err = BoardStateError::InvalidInput(format!("Invalid UCI move: {}", uci));
+            log_and_return_error!(err)
+        }
+        let (Ok(from), Ok(to)) = (
+            crate::fen::notation_to_index(&uci[0..2]),
+            crate::fen::notation_to_index(&uci[2..4]),
+        ) else {
+            let err = BoardStateError::InvalidInput(format!("Invalid UCI move: {}", uci));
+            log_and_return_error!(err)
+        };
+        let promotion_ptype = match uci.get(4..5) {
+            None => None,
+            Some("q") => Some(PieceType::Queen),
+            Some("r") => Some(PieceType::Rook),
+            Some("b") => Some(PieceType::Bishop),
+            Some("n") => Some(PieceType::Knight),
+            Some(_) => {
+                let err = BoardStateError::InvalidInput(format!("Invalid UCI move: {}", uci));
+                log_and_return_error!(err)
+            }
+        };
+        let found = self.lazy_get_legal_moves().find(|mv| {
+            if mv.from != from {
+                return false;
+            }
+            match mv.move_type {
+                MoveType::Promotion(ptype, _) => mv.to == to && Some(ptype) == promotion_ptype,
+                MoveType::Castle(castle_mv) => {
+                    promotion_ptype.is_none() && (mv.to == to || castle_mv.rook_from == to)
+                }
+                _ => mv.to == to && promotion_ptype.is_none(),
+            }
+        });
+        match found {
+            Some(mv) => Ok(*mv),
+            None => {
+                let err = BoardStateError::IllegalMove(format!("{} is not a legal move", uci));
+                log_and_return_error!(err)
+            }
+        }
+    }
+
     pub fn get_occurences_of_current_position(&self) -> u8 {
         *self
             .position_occurences
@@ -445,21 +503,12 @@ impl fmt::Display for Variant {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct PlayerData {
     pub name: Option<String>,
     pub elo: Option<u16>,
 }
 
-impl Default for PlayerData {
-    fn default() -> Self {
-        Self {
-            name: None,
-            elo: None,
-        }
-    }
-}
-
 #[derive(Debug, Clone)]
 pub struct Board {
     variant: Variant,
@@ -468,8 +517,12 @@ pub struct Board {
     current_state: BoardState,
     state_history: Vec<BoardState>,
     move_history: Vec<Move>,
+    move_commands: Vec<MoveCommands>, // clock, eval and drawing commands of each move, written in the PGN
     game_over_state: Option<GameOverState>,
-    transposition_table: transposition::TranspositionTable,
+    transposition_table: Option<transposition::TranspositionTable>, // None while lent to a SearchHandle
+    search_history: Option<engine::SearchHistory>, // move ordering history kept between searches, lent with the tt
+    engine_search_id: Option<u64>, // id of the SearchHandle the transposition table is lent to
+    returned_tables: engine::ReturnedTables, // filled when that SearchHandle is dropped instead of finished
     detatched_idx: Option<usize>,
 }
 
@@ -483,9 +536,9 @@ impl From<FEN> for Board {
     fn from(fen: FEN) -> Self {
         let current_state = BoardState::from(fen);
         let state_history: Vec<BoardState> = vec![current_state.clone()];
-        let transposition_table = transposition::TranspositionTable::new();
+        let transposition_table = Some(transposition::TranspositionTable::new());
         // TODO gos
-        log::info!("New Board created from FEN: {}", fen.to_string());
+        log::info!("New Board created from FEN: {}", fen);
         Board {
             variant: Variant::FromPosition,
             white_player: PlayerData::default(),
@@ -493,8 +546,12 @@ impl From<FEN> for Board {
             current_state,
             state_history,
             move_history: Vec::new(),
+            move_commands: Vec::new(),
             game_over_state: None,
             transposition_table,
+            search_history: Some(engine::SearchHistory::new()),
+            engine_search_id: None,
+            returned_tables: engine::ReturnedTables::default(),
             detatched_idx: None,
         }
     }
@@ -521,7 +578,10 @@ impl TryFrom<pgn::PGN> for Board {
                         board
                     }
                     Err(e) => {
-                        log_and_return_error!(PGNParseError::NotationParseError(e.to_string()))
+                        let err = PGNParseError::new(PGNParseErrorKind::InvalidFen, e.to_string())
+                            .with_token(fen_str);
+                        let err = pgn.locate_error(err, None);
+                        log_and_return_error!(err)
                     }
                 }
             }
@@ -565,12 +625,22 @@ impl TryFrom<pgn::PGN> for Board {
             }
         }
 
-        for notation in pgn.moves() {
-            let mv = notation.to_move_with_context(board.get_current_state())?;
+        // the main line is played, variations are left out of the game
+        for (i, node) in pgn.moves().iter().enumerate() {
+            let mv = node
+                .notation
+                .to_move_with_context(board.get_current_state())
+                .map_err(|e| pgn.locate_error(e, Some(i)))?;
             match board.make_move(&mv) {
                 Ok(_) => {}
-                Err(e) => log_and_return_error!(PGNParseError::NotationParseError(e.to_string())),
+                Err(e) => {
+                    let err = PGNParseError::new(PGNParseErrorKind::IllegalMove, e.to_string());
+                    let err = pgn.locate_error(err, Some(i));
+                    log_and_return_error!(err)
+                }
             }
+            // make_move pushed the default commands for the move
+            *board.move_commands.last_mut().unwrap() = node.commands.clone();
         }
 
         for tag in pgn.tags() {
@@ -595,7 +665,7 @@ impl Board {
         log::info!("State history created");
         state_history.push(current_state.clone());
 
-        let transposition_table = transposition::TranspositionTable::new();
+        let transposition_table = Some(transposition::TranspositionTable::new());
         log::info!("Transposition table created");
         log::info!("New Board created");
         Board {
@@ -605,8 +675,12 @@ impl Board {
             current_state,
             state_history,
             move_history: Vec::new(),
+            move_commands: Vec::new(),
             game_over_state: None,
             transposition_table,
+            search_history: Some(engine::SearchHistory::new()),
+            engine_search_id: None,
+            returned_tables: engine::ReturnedTables::default(),
             detatched_idx: None,
         }
     }
@@ -617,7 +691,7 @@ impl Board {
         log::info!("State history created");
         state_history.push(current_state.clone());
 
-        let transposition_table = transposition::TranspositionTable::new();
+        let transposition_table = Some(transposition::TranspositionTable::new());
         log::info!("Transposition table created");
         log::info!("New Chess960 variant Board created");
         Board {
@@ -627,8 +701,12 @@ impl Board {
             current_state,
             state_history,
             move_history: Vec::new(),
+            move_commands: Vec::new(),
             game_over_state: None,
             transposition_table,
+            search_history: Some(engine::SearchHistory::new()),
+            engine_search_id: None,
+            returned_tables: engine::ReturnedTables::default(),
             detatched_idx: None,
         }
     }
@@ -639,7 +717,7 @@ impl Board {
         log::info!("State history created");
         state_history.push(current_state.clone());
 
-        let transposition_table = transposition::TranspositionTable::new();
+        let transposition_table = Some(transposition::TranspositionTable::new());
         log::info!("Transposition table created");
         log::info!(
             "New Chess960 variant Board created from position number: {}",
@@ -652,8 +730,12 @@ impl Board {
             current_state,
             state_history,
             move_history: Vec::new(),
+            move_commands: Vec::new(),
             game_over_state: None,
             transposition_table,
+            search_history: Some(engine::SearchHistory::new()),
+            engine_search_id: None,
+            returned_tables: engine::ReturnedTables::default(),
             detatched_idx: None,
         })
     }
@@ -705,7 +787,7 @@ impl Board {
 
     pub fn get_game_over_state(&self) -> Option<GameOverState> {
         if self.is_detatched() {
-            return None;
+            None
         } else {
             self.game_over_state
         }
@@ -747,6 +829,7 @@ impl Board {
         self.current_state = next_state;
         self.state_history.push(self.current_state.clone());
         self.move_history.push(*mv);
+        self.move_commands.push(MoveCommands::default());
 
         let game_state = self.current_state.get_gamestate();
         if game_state.is_game_over() {
@@ -757,6 +840,14 @@ impl Board {
     }
 
     pub fn make_engine_move(&mut self, depth: u8) -> Result<(GameState, i32), BoardStateError> {
+        self.make_engine_move_with_limits(&engine::SearchLimits::depth(depth))
+    }
+
+    // iterative deepening engine move, stopping at whichever limit is reached first
+    pub fn make_engine_move_with_limits(
+        &mut self,
+        limits: &engine::SearchLimits,
+    ) -> Result<(GameState, i32), BoardStateError> {
         if let Some(idx) = self.detatched_idx {
             let err = BoardStateError::Detatched(format!(
                 "Detatched from current boardstate at index {}. Cannot make engine move",
@@ -768,11 +859,101 @@ impl Board {
             let err = BoardStateError::GameOver(gos);
             log_and_return_error!(err)
         }
-        let (eval, engine_move) =
-            engine::choose_move(&self.current_state, depth, &mut self.transposition_table);
-        let mv = *engine_move;
-        match self.make_move(&mv) {
-            Ok(gs) => Ok((gs, eval)),
+        self.recover_search_tables();
+        let (Some(tt), Some(history)) = (
+            self.transposition_table.as_ref(),
+            self.search_history.as_mut(),
+        ) else {
+            let err = BoardStateError::EngineSearch(
+                "Search already in progress. Cannot make engine move".to_string(),
+            );
+            log_and_return_error!(err)
+        };
+        let info = engine::choose_move_with_limits(&self.current_state, limits, tt, history);
+        match self.make_move(&info.best_move) {
+            Ok(gs) => Ok((gs, info.eval)),
+            Err(e) => Err(e),
+        }
+    }
+
+    // start an engine search on a worker thread, the board is not borrowed while it runs.
+    // the transposition table is lent to the search, finish_engine_search must be called with the handle to get it back
+    pub fn start_engine_search<E: engine::Evaluator + 'static>(
+        &mut self,
+        limits: engine::SearchLimits,
+        options: engine::SearchOptions<E>,
+    ) -> Result<engine::SearchHandle, BoardStateError> {
+        if let Some(idx) = self.detatched_idx {
+            let err = BoardStateError::Detatched(format!(
+                "Detatched from current boardstate at index {}. Cannot start engine search",
+                idx
+            ));
+            log_and_return_error!(err)
+        }
+        if let Some(gos) = self.game_over_state {
+            let err = BoardStateError::GameOver(gos);
+            log_and_return_error!(err)
+        }
+        self.recover_search_tables();
+        let (Some(tt), Some(history)) =
+            (self.transposition_table.take(), self.search_history.take())
+        else {
+            let err = BoardStateError::EngineSearch(
+                "Search already in progress. Cannot start another".to_string(),
+            );
+            log_and_return_error!(err)
+        };
+        let mut handle =
+            engine::SearchHandle::spawn(self.current_state.clone(), limits, options, tt, history);
+        handle.return_tables_to(self.returned_tables.clone());
+        self.engine_search_id = Some(handle.id());
+        Ok(handle)
+    }
+
+    // takes back the tables of a search whose handle was dropped instead of passed to finish_engine_search
+    fn recover_search_tables(&mut self) {
+        if self.transposition_table.is_some() {
+            return;
+        }
+        let returned = self
+            .returned_tables
+            .lock()
+            .ok()
+            .and_then(|mut tables| tables.take());
+        if let Some((tt, history)) = returned {
+            self.transposition_table = Some(tt);
+            self.search_history = Some(history);
+            self.engine_search_id = None;
+        }
+    }
+
+    // waits for the search to finish (call stop on the handle first to cut it short) and makes the chosen move.
+    // the move is only made if the board is still at the position the search was started from
+    pub fn finish_engine_search(
+        &mut self,
+        handle: engine::SearchHandle,
+    ) -> Result<(GameState, i32), BoardStateError> {
+        if self.engine_search_id != Some(handle.id()) {
+            // dropping stops the search and gives the tables back to the board it was started from
+            drop(handle);
+            let err = BoardStateError::EngineSearch(
+                "Search was not started from this board, engine move not made".to_string(),
+            );
+            log_and_return_error!(err)
+        }
+        let root_hash = handle.root_hash();
+        let (info, tt, history) = handle.join();
+        self.transposition_table = Some(tt);
+        self.search_history = Some(history);
+        self.engine_search_id = None;
+        if root_hash != self.current_state.board_hash || self.detatched_idx.is_some() {
+            let err = BoardStateError::EngineSearch(
+                "Board has changed since the search was started, engine move not made".to_string(),
+            );
+            log_and_return_error!(err)
+        }
+        match self.make_move(&info.best_move) {
+            Ok(gs) => Ok((gs, info.eval)),
             Err(e) => Err(e),
         }
     }
@@ -796,6 +977,28 @@ impl Board {
         notations
     }
 
+    // commands of each move, in the order the moves were made
+    pub fn get_move_commands(&self) -> &[MoveCommands] {
+        &self.move_commands
+    }
+
+    // sets the clock, eval and drawing commands of the move at index idx of the move history
+    pub fn set_move_commands(
+        &mut self,
+        idx: usize,
+        commands: MoveCommands,
+    ) -> Result<(), BoardStateError> {
+        let Some(move_commands) = self.move_commands.get_mut(idx) else {
+            let err = BoardStateError::InvalidInput(format!(
+                "No move at index {} to set commands for",
+                idx
+            ));
+            log_and_return_error!(err)
+        };
+        *move_commands = commands;
+        Ok(())
+    }
+
     pub fn last_move_notation(&self) -> Option<Notation> {
         if let Some(idx) = self.detatched_idx {
             if idx == 0 {
@@ -897,3 +1100,132 @@ impl Board {
         self.current_state.get_gamestate()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board_state(fen: &str) -> BoardState {
+        BoardState::from(fen.parse::<FEN>().unwrap())
+    }
+
+    #[test]
+    fn test_engine_search_handle_dropped() {
+        let mut board = Board::new();
+        let handle = board
+            .start_engine_search(
+                engine::SearchLimits::default(),
+                engine::SearchOptions::default(),
+            )
+            .unwrap();
+        // the tables are lent to the search until the handle is finished or dropped
+        assert!(matches!(
+            board.make_engine_move(1),
+            Err(BoardStateError::EngineSearch(_))
+        ));
+        drop(handle);
+        let handle = board
+            .start_engine_search(
+                engine::SearchLimits::depth(2),
+                engine::SearchOptions::default(),
+            )
+            .unwrap();
+        board.finish_engine_search(handle).unwrap();
+        board.make_engine_move(2).unwrap();
+        assert_eq!(board.move_history.len(), 2);
+    }
+
+    #[test]
+    fn test_uci_move_round_trip() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        ] {
+            let bs = board_state(fen);
+            for mv in bs.get_legal_moves().unwrap() {
+                assert_eq!(
+                    bs.move_from_uci(&mv.to_uci_string()).unwrap(),
+                    *mv,
+                    "{}",
+                    fen
+                );
+            }
+        }
+        assert_eq!(NULL_MOVE.to_uci_string(), "0000");
+    }
+
+    #[test]
+    fn test_uci_castling() {
+        let bs = board_state("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        let short = bs.move_from_uci("e1g1").unwrap();
+        let long = bs.move_from_uci("e1c1").unwrap();
+        assert!(
+            matches!(short.move_type, MoveType::Castle(c) if c.get_castle_side() == CastleSide::Short)
+        );
+        assert!(
+            matches!(long.move_type, MoveType::Castle(c) if c.get_castle_side() == CastleSide::Long)
+        );
+        assert_eq!(short.to_uci_string(), "e1g1");
+        assert_eq!(long.to_uci_string(), "e1c1");
+        // chess960 writes castling as the king taking its own rook
+        assert_eq!(bs.move_from_uci("e1h1").unwrap(), short);
+        assert_eq!(bs.move_from_uci("e1a1").unwrap(), long);
+
+        let bs = board_state("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1");
+        assert_eq!(
+            bs.move_from_uci("e8h8").unwrap(),
+            bs.move_from_uci("e8g8").unwrap()
+        );
+        assert_eq!(
+            bs.move_from_uci("e8a8").unwrap(),
+            bs.move_from_uci("e8c8").unwrap()
+        );
+        // without the castling right neither form is legal
+        let bs = board_state("r3k2r/8/8/8/8/8/8/R3K2R w k - 0 1");
+        assert!(bs.move_from_uci("e1g1").is_err());
+        assert!(bs.move_from_uci("e1h1").is_err());
+    }
+
+    #[test]
+    fn test_uci_promotion() {
+        let bs = board_state("1r5k/P7/8/8/8/8/8/K7 w - - 0 1");
+        for (uci, ptype) in [
+            ("a7a8q", PieceType::Queen),
+            ("a7a8r", PieceType::Rook),
+            ("a7a8b", PieceType::Bishop),
+            ("a7a8n", PieceType::Knight),
+            ("a7b8q", PieceType::Queen),
+            ("a7b8n", PieceType::Knight),
+        ] {
+            let mv = bs.move_from_uci(uci).unwrap();
+            assert!(
+                matches!(mv.move_type, MoveType::Promotion(p, _) if p == ptype),
+                "{}",
+                uci
+            );
+            assert_eq!(mv.to_uci_string(), uci);
+        }
+        // the promotion piece is required, and only for promotions
+        assert!(matches!(
+            bs.move_from_uci("a7a8"),
+            Err(BoardStateError::IllegalMove(_))
+        ));
+        assert!(matches!(
+            bs.move_from_uci("a1a2q"),
+            Err(BoardStateError::IllegalMove(_))
+        ));
+        for invalid in ["a7a8k", "a7a9q", "i1a2", "e2", "e2e4e5", "é2e4"] {
+            assert!(
+                matches!(
+                    bs.move_from_uci(invalid),
+                    Err(BoardStateError::InvalidInput(_))
+                ),
+                "{}",
+                invalid
+            );
+        }
+    }
+}
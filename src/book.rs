@@ -0,0 +1,296 @@
+use std::fs;
+use std::path::Path;
+
+use rand::Rng;
+
+use crate::board::BoardState;
+use crate::errors::BookError;
+use crate::log_and_return_error;
+use crate::movegen::*;
+
+const ENTRY_SIZE: usize = 16;
+
+// how a move is picked when the book has more than one move for a position
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BookMoveSelection {
+    // always the move with the highest weight
+    Best,
+    // random move, with the chance of each move proportional to its weight
+    #[default]
+    WeightedRandom,
+}
+
+// book move for a position, decoded into a legal move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookEntry {
+    pub mv: Move,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+// entry as stored in the file, 16 bytes big endian: key u64, move u16, weight u16, learn u32
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RawEntry {
+    key: u64,
+    mv: u16,
+    weight: u16,
+    learn: u32,
+}
+
+impl RawEntry {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        RawEntry {
+            key: u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            mv: u16::from_be_bytes(bytes[8..10].try_into().unwrap()),
+            weight: u16::from_be_bytes(bytes[10..12].try_into().unwrap()),
+            learn: u32::from_be_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+}
+
+// Polyglot opening book (.bin), looked up with the polyglot compatible zobrist key of the position.
+// http://hgm.nubati.net/book_format.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolyglotBook {
+    entries: Vec<RawEntry>, // sorted by key
+}
+
+impl PolyglotBook {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, BookError> {
+        let path = path.as_ref();
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                let err = BookError::FileError(format!("{}: {}", path.display(), e));
+                log_and_return_error!(err)
+            }
+        };
+        let book = Self::from_bytes(&bytes)?;
+        log::info!(
+            "Opening book loaded from {} with {} entries",
+            path.display(),
+            book.len()
+        );
+        Ok(book)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BookError> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            let err = BookError::InvalidFormat(format!(
+                "Book size of {} bytes is not a multiple of the {} byte entry size",
+                bytes.len(),
+                ENTRY_SIZE
+            ));
+            log_and_return_error!(err)
+        }
+        let mut entries: Vec<RawEntry> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(RawEntry::from_bytes)
+            .collect();
+        // books should already be sorted, but lookup depends on it. stable so the file order of moves is kept
+        entries.sort_by_key(|entry| entry.key);
+        Ok(PolyglotBook { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // book moves for the position that are legal in it, highest weight first
+    pub fn entries(&self, bs: &BoardState) -> Vec<BookEntry> {
+        let key = bs.position_hash;
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let mut entries: Vec<BookEntry> = self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| {
+                let mv = decode_move(bs, entry.mv)?;
+                Some(BookEntry {
+                    mv,
+                    weight: entry.weight,
+                    learn: entry.learn,
+                })
+            })
+            .collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.weight));
+        entries
+    }
+
+    // book move for the position, None if the position is not in the book.
+    // moves with a weight of 0 are never played
+    pub fn choose_move(&self, bs: &BoardState, selection: BookMoveSelection) -> Option<Move> {
+        let entries: Vec<BookEntry> = self
+            .entries(bs)
+            .into_iter()
+            .filter(|entry| entry.weight > 0)
+            .collect();
+        let mv = match selection {
+            BookMoveSelection::Best => entries.first()?.mv,
+            BookMoveSelection::WeightedRandom => {
+                let total: u32 = entries.iter().map(|entry| entry.weight as u32).sum();
+                if total == 0 {
+                    return None;
+                }
+                let mut pick = rand::thread_rng().gen_range(0..total);
+                let mut chosen = entries[0].mv;
+                for entry in &entries {
+                    if pick < entry.weight as u32 {
+                        chosen = entry.mv;
+                        break;
+                    }
+                    pick -= entry.weight as u32;
+                }
+                chosen
+            }
+        };
+        log::info!("Book move found: {}", mv.to_uci_string());
+        Some(mv)
+    }
+}
+
+// polyglot square, file 0-7 from a and row 0-7 from rank 1, to the board index where 0 is a8
+const fn polyglot_square_to_index(file: u16, row: u16) -> usize {
+    ((7 - row) * 8 + file) as usize
+}
+
+// packed move: bits 0-2 to file, 3-5 to row, 6-8 from file, 9-11 from row, 12-14 promotion piece.
+// castling is encoded as the king taking its own rook, in both standard chess and chess960
+fn decode_move(bs: &BoardState, packed: u16) -> Option<Move> {
+    let to = polyglot_square_to_index(packed & 7, (packed >> 3) & 7);
+    let from = polyglot_square_to_index((packed >> 6) & 7, (packed >> 9) & 7);
+    let promotion = match (packed >> 12) & 7 {
+        0 => None,
+        1 => Some(PieceType::Knight),
+        2 => Some(PieceType::Bishop),
+        3 => Some(PieceType::Rook),
+        4 => Some(PieceType::Queen),
+        _ => return None,
+    };
+    bs.lazy_get_legal_moves()
+        .find(|mv| {
+            if mv.from != from {
+                return false;
+            }
+            match mv.move_type {
+                MoveType::Promotion(ptype, _) => mv.to == to && Some(ptype) == promotion,
+                MoveType::Castle(castle_mv) => promotion.is_none() && castle_mv.rook_from == to,
+                _ => mv.to == to && promotion.is_none(),
+            }
+        })
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::FEN;
+
+    fn pack_move(from: &str, to: &str, promotion: u16) -> u16 {
+        let square = |s: &str| {
+            let s = s.as_bytes();
+            ((s[1] - b'1') as u16) << 3 | (s[0] - b'a') as u16
+        };
+        promotion << 12 | square(from) << 6 | square(to)
+    }
+
+    fn entry_bytes(key: u64, mv: u16, weight: u16) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(ENTRY_SIZE);
+        bytes.extend_from_slice(&key.to_be_bytes());
+        bytes.extend_from_slice(&mv.to_be_bytes());
+        bytes.extend_from_slice(&weight.to_be_bytes());
+        bytes.extend_from_slice(&0u32.to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_book_lookup() {
+        let bs = BoardState::new_starting();
+        // key of the starting position from the polyglot book format specification
+        assert_eq!(bs.position_hash, 0x463b96181691fc9c);
+        let mut bytes = Vec::new();
+        bytes.extend(entry_bytes(1, pack_move("a2", "a3", 0), 100));
+        bytes.extend(entry_bytes(bs.position_hash, pack_move("d2", "d4", 5), 1));
+        bytes.extend(entry_bytes(bs.position_hash, pack_move("d2", "d4", 0), 5));
+        bytes.extend(entry_bytes(bs.position_hash, pack_move("e2", "e4", 0), 10));
+        bytes.extend(entry_bytes(bs.position_hash, pack_move("g1", "f3", 0), 0));
+        bytes.extend(entry_bytes(u64::MAX, pack_move("a2", "a4", 0), 100));
+        let book = PolyglotBook::from_bytes(&bytes).unwrap();
+        assert_eq!(book.len(), 6);
+
+        // d2d4 with a promotion piece is not a legal move and is skipped
+        let entries: Vec<(String, u16)> = book
+            .entries(&bs)
+            .iter()
+            .map(|entry| (entry.mv.to_uci_string(), entry.weight))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("e2e4".to_string(), 10),
+                ("d2d4".to_string(), 5),
+                ("g1f3".to_string(), 0)
+            ]
+        );
+        assert_eq!(
+            book.choose_move(&bs, BookMoveSelection::Best)
+                .unwrap()
+                .to_uci_string(),
+            "e2e4"
+        );
+        for _ in 0..20 {
+            let mv = book
+                .choose_move(&bs, BookMoveSelection::WeightedRandom)
+                .unwrap()
+                .to_uci_string();
+            assert!(mv == "e2e4" || mv == "d2d4");
+        }
+
+        let bs = bs.next_state(&bs.move_from_uci("e2e4").unwrap()).unwrap();
+        assert_eq!(bs.position_hash, 0x823c9b50fd114196);
+        assert_eq!(book.choose_move(&bs, BookMoveSelection::Best), None);
+    }
+
+    #[test]
+    fn test_book_castling_and_promotion() {
+        let bs = BoardState::from(
+            "r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1"
+                .parse::<FEN>()
+                .unwrap(),
+        );
+        let mut bytes = Vec::new();
+        bytes.extend(entry_bytes(bs.position_hash, pack_move("e1", "h1", 0), 3));
+        bytes.extend(entry_bytes(bs.position_hash, pack_move("e1", "a1", 0), 2));
+        bytes.extend(entry_bytes(bs.position_hash, pack_move("b7", "a8", 1), 1));
+        let book = PolyglotBook::from_bytes(&bytes).unwrap();
+        let entries = book.entries(&bs);
+        assert_eq!(entries.len(), 3);
+        assert!(matches!(
+            entries[0].mv.move_type,
+            MoveType::Castle(CastleMove {
+                side: CastleSide::Short,
+                ..
+            })
+        ));
+        assert!(matches!(
+            entries[1].mv.move_type,
+            MoveType::Castle(CastleMove {
+                side: CastleSide::Long,
+                ..
+            })
+        ));
+        assert_eq!(
+            entries[2].mv.move_type,
+            MoveType::Promotion(PieceType::Knight, Some(PieceType::Rook))
+        );
+    }
+
+    #[test]
+    fn test_book_invalid_size() {
+        assert!(PolyglotBook::from_bytes(&[0; ENTRY_SIZE + 1]).is_err());
+        assert!(PolyglotBook::from_bytes(&[]).unwrap().is_empty());
+    }
+}
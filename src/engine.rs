@@ -1,9 +1,38 @@
+mod eval;
+mod handle;
+mod history;
+mod limits;
+mod nnue;
+mod options;
+mod params;
+mod pawns;
+mod pieces;
+
 use std::cmp;
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use crate::board::*;
 use crate::movegen::*;
+use crate::pgn::notation::Notation;
+use crate::search_position::{SearchPosition, MOVE_LIST_START_SIZE};
+use crate::tablebase::{Tablebase, TablebaseMove, Wdl};
 use crate::transposition::*;
 use crate::util;
+use eval::get_piece_value;
+pub use eval::{
+    eval_trace, eval_trace_with, game_phase, ClassicalEvaluator, EvalTerm, EvalTrace, Evaluator,
+    Score, MAX_PHASE,
+};
+pub(crate) use handle::ReturnedTables;
+pub use handle::SearchHandle;
+pub use history::SearchHistory;
+pub use limits::{SearchLimits, MAX_SEARCH_DEPTH};
+pub use nnue::{NnueEvaluator, NnueNetwork};
+pub use options::{SearchFeatures, SearchOptions, MAX_MULTIPV, MAX_THREADS};
+pub use params::EvalParams;
 
 // avoid int overflows when operating on these values i.e. negating, +/- checkmate depth etc.
 const MIN: i32 = i32::MIN + 1000;
@@ -11,8 +40,25 @@ const MAX: i32 = i32::MAX - 1000;
 const CHECKMATE_VALUE: i32 = 100_000_000;
 const CHECKMATE_THRESHOLD: i32 = CHECKMATE_VALUE - 1000;
 const DRAW_VALUE: i32 = 0;
-// max depth for quiescence search, best case it should be unlimited (only stopping when there are no more captures), but in practice it takes too long
-const QUIECENCE_DEPTH: u8 = 4;
+// tablebase wins are below checkmates, but above any material eval
+const TABLEBASE_WIN_VALUE: i32 = 20_000;
+const TABLEBASE_WIN_THRESHOLD: i32 = TABLEBASE_WIN_VALUE - 1000;
+// move ordering scores, above the range of the quiet move history scores
+const CAPTURE_SCORE: i32 = 1 << 20;
+const KILLER_SCORES: [i32; 2] = [(1 << 19) + 1, 1 << 19];
+const COUNTERMOVE_SCORE: i32 = 1 << 18;
+// captures losing material by static exchange evaluation come after the quiet moves
+const LOSING_CAPTURE_SCORE: i32 = -(1 << 18);
+// null move pruning is only tried with at least this much depth left, the null move is searched 2 + depth / 4 plies shallower
+const NULL_MOVE_MIN_DEPTH: u8 = 3;
+// late move reductions start after this many moves have been searched, with at least LMR_MIN_DEPTH left
+const LMR_MIN_DEPTH: u8 = 3;
+const LMR_FULL_DEPTH_MOVES: usize = 3;
+// margin added to the static eval for each depth left in futility pruning, it isn't used from depth 4
+const FUTILITY_MARGINS: [i32; 4] = [0, 125, 250, 400];
+// reverse futility pruning margin per ply of depth left
+const REVERSE_FUTILITY_MAX_DEPTH: u8 = 6;
+const REVERSE_FUTILITY_MARGIN: i32 = 80;
 
 // TODO for tt, to make sure checkmate eval is relative to the ply it was found at, maybe have a checkmate flag in the tt entry or an enum here for evals i dont know
 #[inline(always)]
@@ -20,6 +66,12 @@ pub const fn is_eval_checkmate(eval: i32) -> bool {
     eval.abs() >= CHECKMATE_THRESHOLD
 }
 
+// won or lost according to the endgame tablebase, but no checkmate has been found
+#[inline(always)]
+pub const fn is_eval_tablebase(eval: i32) -> bool {
+    eval.abs() >= TABLEBASE_WIN_THRESHOLD && !is_eval_checkmate(eval)
+}
+
 // amount of plys until checkmate
 #[inline(always)]
 pub const fn get_checkmate_ply(eval: i32) -> u8 {
@@ -32,6 +84,7 @@ struct Nodes {
     quiescence_nodes: u64,
     quiescence_prunes: u64,
     transposition_table_hits: u64,
+    tablebase_hits: u64,
 }
 impl Nodes {
     fn new() -> Self {
@@ -41,6 +94,7 @@ impl Nodes {
             quiescence_nodes: 0,
             quiescence_prunes: 0,
             transposition_table_hits: 0,
+            tablebase_hits: 0,
         }
     }
 
@@ -53,26 +107,507 @@ impl Nodes {
     }
 }
 
-pub fn choose_move<'a>(
+// how often (in nodes) the search checks the clock, node limit and stop flag
+const STOP_CHECK_INTERVAL: u64 = 1024;
+
+// state shared by the whole search tree, including the limits that can abort it
+struct SearchContext<'a, E: Evaluator> {
+    nodes: Nodes,
+    start: Instant,
+    hard_limit: Option<Duration>,
+    node_limit: Option<u64>,
+    stop: Option<&'a AtomicBool>,
+    stopped: bool,
+    can_stop: bool,
+    checks: u64,
+    pv: Vec<Vec<Move>>, // triangular pv table, pv[ply] is the best line found from that ply
+    move_lists: Vec<Vec<Move>>, // pseudo legal moves of the node at each ply, reused by every node at that ply
+    helper_nodes: Option<&'a AtomicU64>, // nodes searched by lazy smp helper threads
+    tablebase: Option<&'a Tablebase>,
+    evaluator: E,
+    features: SearchFeatures,
+    history: &'a mut SearchHistory, // move ordering heuristics, kept by the caller between searches
+}
+impl<'a, E: Evaluator> SearchContext<'a, E> {
+    fn new(evaluator: E, history: &'a mut SearchHistory) -> Self {
+        SearchContext {
+            nodes: Nodes::new(),
+            start: Instant::now(),
+            hard_limit: None,
+            node_limit: None,
+            stop: None,
+            stopped: false,
+            can_stop: false,
+            checks: 0,
+            pv: Vec::new(),
+            move_lists: Vec::new(),
+            helper_nodes: None,
+            tablebase: None,
+            evaluator,
+            features: SearchFeatures::default(),
+            history,
+        }
+    }
+
+    fn with_limits(
+        bs: &BoardState,
+        limits: &SearchLimits,
+        options: &'a SearchOptions<E>,
+        history: &'a mut SearchHistory,
+        stop: Option<&'a AtomicBool>,
+    ) -> Self {
+        SearchContext {
+            hard_limit: limits.time_limits(bs.side_to_move).map(|(_, hard)| hard),
+            node_limit: limits.nodes,
+            stop,
+            tablebase: options.tablebase.as_deref(),
+            features: options.features,
+            ..Self::new(options.evaluator.clone(), history)
+        }
+    }
+
+    // called on every node, once stopped the rest of the tree returns immediately and its results are discarded
+    #[inline]
+    fn should_stop(&mut self) -> bool {
+        if self.stopped {
+            return true;
+        }
+        self.checks += 1;
+        if !self.can_stop || !self.checks.is_multiple_of(STOP_CHECK_INTERVAL) {
+            return false;
+        }
+        self.stopped = self.stop.is_some_and(|s| s.load(Ordering::Relaxed))
+            || self.node_limit.is_some_and(|n| self.total_nodes() >= n)
+            || self.hard_limit.is_some_and(|t| self.start.elapsed() >= t);
+        self.stopped
+    }
+
+    // nodes searched by this thread and any helper threads
+    fn total_nodes(&self) -> u64 {
+        self.nodes.total_nodes() + self.helper_nodes.map_or(0, |n| n.load(Ordering::Relaxed))
+    }
+
+    #[inline]
+    fn clear_pv(&mut self, ply: u8) {
+        let ply = ply as usize;
+        if self.pv.len() < ply + 2 {
+            self.pv.resize(ply + 2, Vec::new());
+        }
+        self.pv[ply].clear();
+    }
+
+    // new best move at ply, the line is the move followed by the child's line
+    #[inline]
+    fn update_pv(&mut self, ply: u8, mv: Move) {
+        let ply = ply as usize;
+        let (line, child_lines) = self.pv.split_at_mut(ply + 1);
+        line[ply].clear();
+        line[ply].push(mv);
+        line[ply].extend_from_slice(&child_lines[0]);
+    }
+
+    // fills the move list of ply with the pseudo legal moves of sp, so move generation doesn't allocate
+    #[inline]
+    fn gen_moves(&mut self, sp: &SearchPosition, ply: u8) -> &[Move] {
+        let ply = ply as usize;
+        if self.move_lists.len() <= ply {
+            self.move_lists
+                .resize_with(ply + 1, || Vec::with_capacity(MOVE_LIST_START_SIZE));
+        }
+        sp.pseudo_legal_moves(&mut self.move_lists[ply]);
+        &self.move_lists[ply]
+    }
+
+    // the list is taken out while its moves are searched, as the children need the context too.
+    // put_moves gives it back for the next node at the same ply
+    #[inline]
+    fn take_moves(&mut self, ply: u8) -> Vec<Move> {
+        mem::take(&mut self.move_lists[ply as usize])
+    }
+
+    #[inline]
+    fn put_moves(&mut self, ply: u8, moves: Vec<Move>) {
+        self.move_lists[ply as usize] = moves;
+    }
+
+    // makes the move on the position and tells the evaluator about it
+    #[inline(always)]
+    fn make_move(&mut self, sp: &mut SearchPosition, mv: &Move) {
+        self.evaluator.make_move(sp.pos64(), mv);
+        sp.make_move(mv);
+    }
+
+    #[inline(always)]
+    fn unmake_move(&mut self, sp: &mut SearchPosition) {
+        sp.unmake_move();
+        self.evaluator.unmake_move();
+    }
+
+    // no pieces move, so the evaluator isn't told about null moves
+    #[inline(always)]
+    fn make_null_move(&mut self, sp: &mut SearchPosition) {
+        sp.make_null_move();
+    }
+
+    #[inline(always)]
+    fn unmake_null_move(&mut self, sp: &mut SearchPosition) {
+        sp.unmake_null_move();
+    }
+}
+
+// one root move with its eval and principal variation
+#[derive(Debug, Clone)]
+pub struct PvLine {
+    pub eval: i32,
+    pub mv: Move,
+    pub pv: Vec<Move>,
+}
+
+impl PvLine {
+    // pv in standard algebraic notation, bs must be the position that was searched
+    pub fn pv_notation(&self, bs: &BoardState) -> Vec<Notation> {
+        pv_notation(bs, &self.pv)
+    }
+}
+
+// result of a completed search iteration
+#[derive(Debug, Clone)]
+pub struct SearchInfo {
+    pub depth: u8,
+    pub eval: i32,
+    pub nodes: u64, // total nodes searched, including previous iterations
+    pub time: Duration,
+    pub best_move: Move,
+    pub pv: Vec<Move>,
+    pub lines: Vec<PvLine>, // ranked best first, one per multipv line. lines[0] is the same as the fields above
+    pub from_book: bool, // move was taken from the opening book without searching, depth and eval are 0
+    pub from_tablebase: bool, // move was taken from the endgame tablebase without searching, depth is 0
+    pub tb_hits: u64, // tablebase probes in the search, not counting lazy smp helper threads
+}
+
+impl SearchInfo {
+    fn from_lines<E: Evaluator>(depth: u8, lines: Vec<PvLine>, ctx: &SearchContext<E>) -> Self {
+        let (eval, best_move, pv) = match lines.first() {
+            Some(line) => (line.eval, line.mv, line.pv.clone()),
+            None => (0, NULL_MOVE, Vec::new()),
+        };
+        SearchInfo {
+            depth,
+            eval,
+            nodes: ctx.total_nodes(),
+            time: ctx.start.elapsed(),
+            best_move,
+            pv,
+            lines,
+            from_book: false,
+            from_tablebase: false,
+            tb_hits: ctx.nodes.tablebase_hits,
+        }
+    }
+
+    // root move played without a search
+    fn from_root_move(mv: Move, eval: i32) -> Self {
+        SearchInfo {
+            depth: 0,
+            eval,
+            nodes: 0,
+            time: Duration::ZERO,
+            best_move: mv,
+            pv: vec![mv],
+            lines: vec![PvLine {
+                eval,
+                mv,
+                pv: vec![mv],
+            }],
+            from_book: false,
+            from_tablebase: false,
+            tb_hits: 0,
+        }
+    }
+
+    fn from_book_move(mv: Move) -> Self {
+        SearchInfo {
+            from_book: true,
+            ..Self::from_root_move(mv, 0)
+        }
+    }
+
+    // eval is a tablebase win or loss, shorter wins and longer losses are better
+    fn from_tablebase_move(tb_move: TablebaseMove) -> Self {
+        let eval = match tb_move.wdl {
+            Wdl::Win => TABLEBASE_WIN_VALUE - tb_move.dtz.abs(),
+            Wdl::Loss => -TABLEBASE_WIN_VALUE + tb_move.dtz.abs(),
+            _ => DRAW_VALUE,
+        };
+        SearchInfo {
+            from_tablebase: true,
+            tb_hits: 1,
+            ..Self::from_root_move(tb_move.mv, eval)
+        }
+    }
+
+    // pv in standard algebraic notation, bs must be the position that was searched
+    pub fn pv_notation(&self, bs: &BoardState) -> Vec<Notation> {
+        pv_notation(bs, &self.pv)
+    }
+}
+
+fn pv_notation(bs: &BoardState, pv: &[Move]) -> Vec<Notation> {
+    let mut notations = Vec::with_capacity(pv.len());
+    let mut state = bs.clone();
+    for mv in pv {
+        let Ok(notation) = Notation::from_mv_with_context(&state, mv) else {
+            break;
+        };
+        let Ok(next_state) = state.next_state(mv) else {
+            break;
+        };
+        notations.push(notation);
+        state = next_state;
+    }
+    notations
+}
+
+// fixed depth search with the given evaluator, ClassicalEvaluator is the default used by the other searches
+pub fn choose_move<'a, E: Evaluator>(
     bs: &'a BoardState,
     depth: u8,
-    tt: &mut TranspositionTable,
+    tt: &TranspositionTable,
+    evaluator: &E,
 ) -> (i32, &'a Move) {
-    let mut nodes = Nodes::new();
-    // TODO add check if position is in endgame, for different evaluation
-    let (eval, mv) = negamax_root(bs, depth, tt, &mut nodes);
+    let mut history = SearchHistory::new();
+    let mut ctx = SearchContext::new(evaluator.clone(), &mut history);
+    search_root(bs, depth, NULL_SHORT_MOVE, &[], tt, &mut ctx)
+}
+
+// same as choose_move, but also returns the node count and the principal variation
+pub fn search_depth(bs: &BoardState, depth: u8, tt: &TranspositionTable) -> SearchInfo {
+    let mut history = SearchHistory::new();
+    let mut ctx = SearchContext::new(ClassicalEvaluator::default(), &mut history);
+    let lines = search_lines(bs, depth, 1, &[], tt, &mut ctx);
+    SearchInfo::from_lines(depth, lines, &ctx)
+}
+
+// iterative deepening search within the given limits, returns the result of the last completed iteration
+pub fn choose_move_with_limits(
+    bs: &BoardState,
+    limits: &SearchLimits,
+    tt: &TranspositionTable,
+    history: &mut SearchHistory,
+) -> SearchInfo {
+    iterative_deepening(
+        bs,
+        limits,
+        &SearchOptions::default(),
+        tt,
+        history,
+        None,
+        |_| {},
+    )
+}
+
+// iterative deepening search, searching depth 1, 2, 3... reusing the transposition table so each iteration has better move ordering than the last.
+// on_iteration is called with the result of every completed iteration. the search stops when a limit is reached or the stop flag is set,
+// and an iteration that is cut short is thrown away.
+// with more than one thread this is a lazy smp search, helper threads search the same position and only share results through the tt.
+// history is aged and then kept for the whole search, helper threads start with their own empty history.
+// if the position is in the opening book or the endgame tablebase, the book or tablebase move is returned straight away
+pub fn iterative_deepening<E: Evaluator>(
+    bs: &BoardState,
+    limits: &SearchLimits,
+    options: &SearchOptions<E>,
+    tt: &TranspositionTable,
+    history: &mut SearchHistory,
+    stop: Option<&AtomicBool>,
+    mut on_iteration: impl FnMut(&SearchInfo),
+) -> SearchInfo {
+    if let Some(mv) = options
+        .book
+        .as_ref()
+        .and_then(|book| book.choose_move(bs, options.book_selection))
+    {
+        let info = SearchInfo::from_book_move(mv);
+        on_iteration(&info);
+        return info;
+    }
+    if let Some(tb_move) = options.tablebase.as_ref().and_then(|tb| tb.best_move(bs)) {
+        log::info!(
+            "Tablebase move found: {} ({:?}, dtz {})",
+            tb_move.mv.to_uci_string(),
+            tb_move.wdl,
+            tb_move.dtz
+        );
+        let info = SearchInfo::from_tablebase_move(tb_move);
+        on_iteration(&info);
+        return info;
+    }
+
+    history.age();
+    if options.threads <= 1 {
+        let ctx = SearchContext::with_limits(bs, limits, options, history, stop);
+        return main_search(bs, limits, options, tt, ctx, on_iteration);
+    }
+
+    let helpers_stop = AtomicBool::new(false);
+    let helper_nodes = AtomicU64::new(0);
+    thread::scope(|scope| {
+        for id in 1..options.threads {
+            let (helpers_stop, helper_nodes) = (&helpers_stop, &helper_nodes);
+            scope.spawn(move || {
+                helper_search(bs, limits, options, id, tt, helpers_stop, helper_nodes)
+            });
+        }
+        let ctx = SearchContext {
+            helper_nodes: Some(&helper_nodes),
+            ..SearchContext::with_limits(bs, limits, options, history, stop)
+        };
+        let info = main_search(bs, limits, options, tt, ctx, on_iteration);
+        helpers_stop.store(true, Ordering::Relaxed);
+        info
+    })
+}
+
+fn main_search<E: Evaluator>(
+    bs: &BoardState,
+    limits: &SearchLimits,
+    options: &SearchOptions<E>,
+    tt: &TranspositionTable,
+    mut ctx: SearchContext<E>,
+    mut on_iteration: impl FnMut(&SearchInfo),
+) -> SearchInfo {
+    let soft_limit = limits.time_limits(bs.side_to_move).map(|(soft, _)| soft);
+    let mut best = SearchInfo::from_lines(0, Vec::new(), &ctx);
+
+    for depth in 1..=limits.max_depth() {
+        if depth > 1 {
+            // the next iteration will probably take longer than all the previous ones combined
+            if soft_limit.is_some_and(|t| ctx.start.elapsed() * 2 >= t)
+                || ctx.stop.is_some_and(|s| s.load(Ordering::Relaxed))
+            {
+                break;
+            }
+        }
+        // depth 1 is always completed so there is a move to return
+        ctx.can_stop = depth > 1;
+        let lines = search_lines(bs, depth, options.multipv, &best.lines, tt, &mut ctx);
+        if ctx.stopped {
+            break;
+        }
+        best = SearchInfo::from_lines(depth, lines, &ctx);
+        on_iteration(&best);
+
+        // game over at the root, or a forced mate has been found within the search depth
+        if best.best_move == NULL_MOVE
+            || (options.multipv <= 1
+                && is_eval_checkmate(best.eval)
+                && get_checkmate_ply(best.eval) <= depth)
+        {
+            break;
+        }
+    }
+    best
+}
+
+// lazy smp helper, its results are never used directly. odd helpers start one depth ahead so the threads
+// spread out over different depths and fill the tt with entries the main thread hasn't searched yet
+fn helper_search<E: Evaluator>(
+    bs: &BoardState,
+    limits: &SearchLimits,
+    options: &SearchOptions<E>,
+    id: usize,
+    tt: &TranspositionTable,
+    stop: &AtomicBool,
+    helper_nodes: &AtomicU64,
+) {
+    let mut history = SearchHistory::new();
+    let mut ctx = SearchContext {
+        stop: Some(stop),
+        can_stop: true,
+        tablebase: options.tablebase.as_deref(),
+        features: options.features,
+        ..SearchContext::new(options.evaluator.clone(), &mut history)
+    };
+    let mut prev_best = NULL_SHORT_MOVE;
+    let mut reported_nodes = 0;
+    for depth in (1 + (id % 2) as u8)..=limits.max_depth() {
+        let (_, mv) = negamax_root(bs, depth, prev_best, &[], tt, &mut ctx);
+        let nodes = ctx.nodes.total_nodes();
+        helper_nodes.fetch_add(nodes - reported_nodes, Ordering::Relaxed);
+        reported_nodes = nodes;
+        if ctx.stopped || *mv == NULL_MOVE {
+            break;
+        }
+        prev_best = mv.short_move();
+    }
+    log::debug!(
+        "Lazy SMP helper {} finished after {} nodes",
+        id,
+        reported_nodes
+    );
+}
+
+// searches the root once per multipv line, each time excluding the moves of the lines already found,
+// so every line gets an exact eval. prev_lines from the last iteration are used for move ordering
+fn search_lines<E: Evaluator>(
+    bs: &BoardState,
+    depth: u8,
+    multipv: u8,
+    prev_lines: &[PvLine],
+    tt: &TranspositionTable,
+    ctx: &mut SearchContext<E>,
+) -> Vec<PvLine> {
+    // the options can be built by hand, so 0 lines still searches the best move
+    let multipv = multipv.clamp(1, MAX_MULTIPV) as usize;
+    let mut lines: Vec<PvLine> = Vec::with_capacity(multipv);
+    let mut excluded: Vec<Move> = Vec::with_capacity(multipv);
+    for i in 0..multipv {
+        let prev_best = prev_lines
+            .get(i)
+            .map_or(NULL_SHORT_MOVE, |line| line.mv.short_move());
+        let (eval, mv) = search_root(bs, depth, prev_best, &excluded, tt, ctx);
+        // no moves left to search, or the search was stopped
+        if *mv == NULL_MOVE || ctx.stopped {
+            break;
+        }
+        let mv = *mv;
+        excluded.push(mv);
+        lines.push(PvLine {
+            eval,
+            mv,
+            pv: principal_variation(bs, &ctx.pv[0], depth, tt),
+        });
+    }
+    // later lines are searched with better ordering, so they can rarely beat an earlier one
+    lines.sort_by_key(|line| cmp::Reverse(line.eval));
+    lines
+}
+
+fn search_root<'a, E: Evaluator>(
+    bs: &'a BoardState,
+    depth: u8,
+    prev_best: ShortMove,
+    excluded: &[Move],
+    tt: &TranspositionTable,
+    ctx: &mut SearchContext<E>,
+) -> (i32, &'a Move) {
+    let (eval, mv) = negamax_root(bs, depth, prev_best, excluded, tt, ctx);
+    if ctx.stopped {
+        log::info!("Search stopped during depth {}", depth);
+        return (eval, mv);
+    }
 
     if cfg!(feature = "debug_engine_logging") {
-        log::info!("Nodes searched: {}", nodes.total_nodes());
-        log::info!("Branches pruned: {}", nodes.total_prunes());
-        log::info!("Negamax nodes: {}", nodes.negamax_nodes);
-        log::info!("Negamax prunes: {}", nodes.negamax_prunes);
-        log::info!("Quiescence nodes: {}", nodes.quiescence_nodes);
-        log::info!("Quiescence prunes: {}", nodes.quiescence_prunes);
+        log::info!("Nodes searched: {}", ctx.nodes.total_nodes());
+        log::info!("Branches pruned: {}", ctx.nodes.total_prunes());
+        log::info!("Negamax nodes: {}", ctx.nodes.negamax_nodes);
+        log::info!("Negamax prunes: {}", ctx.nodes.negamax_prunes);
+        log::info!("Quiescence nodes: {}", ctx.nodes.quiescence_nodes);
+        log::info!("Quiescence prunes: {}", ctx.nodes.quiescence_prunes);
         log::info!(
             "Transposition table hits: {}",
-            nodes.transposition_table_hits
+            ctx.nodes.transposition_table_hits
         );
+        log::info!("Tablebase hits: {}", ctx.nodes.tablebase_hits);
     }
     log::debug!(
         "Transposition table: Entries -> {}/{}, Size on heap -> {}",
@@ -98,22 +633,61 @@ pub fn choose_move<'a>(
     (eval, mv)
 }
 
-// TODO add checks (and maybe promotions) to quiescence search
-fn quiescence(
+// principal variation from the pv table, extended with best moves stored in the transposition table.
+// the pv table line is cut short by tt cutoffs, so the tt is used to fill in the rest of the line where possible.
+// lines extended past the search depth make the pv table line longer, so both are cut to max_len
+fn principal_variation(
     bs: &BoardState,
-    depth: u8,
+    line: &[Move],
+    max_len: u8,
+    tt: &TranspositionTable,
+) -> Vec<Move> {
+    let mut pv = Vec::new();
+    let mut state = bs.clone();
+    for mv in line.iter().take(max_len as usize) {
+        state = state.next_state_unchecked(mv);
+        pv.push(*mv);
+    }
+    if pv.is_empty() {
+        return pv;
+    }
+    while pv.len() < max_len as usize {
+        if state.get_gamestate().is_game_over() {
+            break;
+        }
+        let Some(entry) = tt.get(state.board_hash) else {
+            break;
+        };
+        let Some(mv) = state
+            .get_pseudo_legal_moves()
+            .iter()
+            .find(|mv| **mv == entry.mv && state.is_move_legal_position(mv))
+            .copied()
+        else {
+            break;
+        };
+        pv.push(mv);
+        state = state.next_state_unchecked(&mv);
+    }
+    pv
+}
+
+// searches captures and queen promotions that don't lose material until the position is quiet, and every
+// evasion when in check. there is no depth limit, the search ends as the pieces left to capture run out
+fn quiescence<E: Evaluator>(
+    sp: &mut SearchPosition,
     ply: u8,
     mut alpha: i32,
     beta: i32,
-    nodes: &mut Nodes,
+    ctx: &mut SearchContext<E>,
 ) -> i32 {
-    let pseudo_legal_moves = bs.get_pseudo_legal_moves();
+    if ctx.should_stop() {
+        return 0;
+    }
     // check game over conditions returning immediately, or begin quiescence search
-    match bs.get_gamestate() {
+    match sp.get_gamestate(ctx.gen_moves(sp, ply)) {
         GameState::Checkmate => {
-            if cfg!(feature = "debug_engine_logging") {
-                nodes.quiescence_nodes += 1;
-            }
+            ctx.nodes.quiescence_nodes += 1;
             return -CHECKMATE_VALUE + ply as i32;
         }
         // draw states
@@ -121,57 +695,72 @@ fn quiescence(
         | GameState::Repetition
         | GameState::FiftyMove
         | GameState::InsufficientMaterial => {
-            if cfg!(feature = "debug_engine_logging") {
-                nodes.quiescence_nodes += 1;
-            }
+            ctx.nodes.quiescence_nodes += 1;
             return DRAW_VALUE;
         }
         _ => {}
     }
 
-    let mut max_eval = evaluate(bs);
-    if max_eval >= beta || depth == 0 {
+    // standing pat isn't possible in check, any legal evasion may be the only one
+    let in_check = sp.is_in_check();
+    let mut max_eval = if in_check && ply < u8::MAX {
+        MIN
+    } else {
+        ctx.evaluator.evaluate(sp.pos64(), sp.side_to_move())
+    };
+    if max_eval >= beta || ply == u8::MAX {
         return max_eval;
     }
     alpha = cmp::max(alpha, max_eval);
 
-    for i in sorted_move_indexes(pseudo_legal_moves, true, NULL_SHORT_MOVE, &bs.last_move) {
+    let pseudo_legal_moves = ctx.take_moves(ply);
+    let moves = sorted_move_indexes(
+        &pseudo_legal_moves,
+        !in_check,
+        NULL_SHORT_MOVE,
+        &sp.last_move(),
+        ctx.history,
+        ply,
+        |mv| sp.see(mv),
+    );
+    for i in moves {
         let mv = &pseudo_legal_moves[i];
-        if !bs.is_move_legal_position(mv) {
+        if !sp.is_move_legal(mv) {
             continue; // skip illegal moves
         }
-        let child_bs = bs.next_state_unchecked(mv);
-        let eval = -quiescence(&child_bs, depth - 1, ply + 1, -beta, -alpha, nodes);
+        ctx.make_move(sp, mv);
+        let eval = -quiescence(sp, ply + 1, -beta, -alpha, ctx);
+        ctx.unmake_move(sp);
         max_eval = cmp::max(max_eval, eval);
         alpha = cmp::max(alpha, max_eval);
 
-        if cfg!(feature = "debug_engine_logging") {
-            nodes.quiescence_nodes += 1;
-        }
+        ctx.nodes.quiescence_nodes += 1;
 
         if beta <= alpha {
             if cfg!(feature = "debug_engine_logging") {
-                nodes.quiescence_prunes += 1;
+                ctx.nodes.quiescence_prunes += 1;
             }
             break;
         }
     }
+    ctx.put_moves(ply, pseudo_legal_moves);
     max_eval
 }
 
-fn negamax_root<'a>(
+fn negamax_root<'a, E: Evaluator>(
     bs: &'a BoardState,
     depth: u8,
-    tt: &mut TranspositionTable,
-    nodes: &mut Nodes,
+    prev_best: ShortMove,
+    excluded: &[Move],
+    tt: &TranspositionTable,
+    ctx: &mut SearchContext<E>,
 ) -> (i32, &'a Move) {
+    ctx.clear_pv(0);
     let pseudo_legal_moves = bs.get_pseudo_legal_moves();
     // check game over conditions returning immediately, or begin quiescence search
     match bs.get_gamestate() {
         GameState::Checkmate => {
-            if cfg!(feature = "debug_engine_logging") {
-                nodes.negamax_nodes += 1;
-            }
+            ctx.nodes.negamax_nodes += 1;
             return (-CHECKMATE_VALUE, &NULL_MOVE);
         }
         // draw states
@@ -179,37 +768,62 @@ fn negamax_root<'a>(
         | GameState::Repetition
         | GameState::FiftyMove
         | GameState::InsufficientMaterial => {
-            if cfg!(feature = "debug_engine_logging") {
-                nodes.negamax_nodes += 1;
-            }
+            ctx.nodes.negamax_nodes += 1;
             return (DRAW_VALUE, &NULL_MOVE); // stalemate
         }
         _ => {}
     }
+    let mut sp = SearchPosition::from(bs);
+    ctx.evaluator.reset(sp.pos64());
     let mut alpha = MIN;
     let beta = MAX;
     let mut best_move = &NULL_MOVE;
     let mut max_eval = MIN;
-    for i in sorted_move_indexes(pseudo_legal_moves, false, NULL_SHORT_MOVE, &bs.last_move) {
+    let mut searched = 0;
+    // best move from the previous iteration is searched first
+    let moves = sorted_move_indexes(
+        pseudo_legal_moves,
+        false,
+        prev_best,
+        &bs.last_move,
+        ctx.history,
+        0,
+        |mv| bs.see(mv),
+    );
+    for i in moves {
         let mv = &pseudo_legal_moves[i];
-        if !bs.is_move_legal_position(mv) {
-            continue; // skip illegal moves
+        if !bs.is_move_legal_position(mv) || excluded.contains(mv) {
+            continue; // skip illegal moves, and moves already searched as another multipv line
+        }
+        ctx.make_move(&mut sp, mv);
+        // the root is on the pv, so every full window search of its moves is too
+        let eval = if searched == 0 || !ctx.features.pvs {
+            -negamax(&mut sp, depth - 1, 1, -beta, -alpha, true, tt, ctx)
+        } else {
+            let eval = -negamax(&mut sp, depth - 1, 1, -alpha - 1, -alpha, false, tt, ctx);
+            if eval > alpha && eval < beta {
+                -negamax(&mut sp, depth - 1, 1, -beta, -alpha, true, tt, ctx)
+            } else {
+                eval
+            }
+        };
+        searched += 1;
+        ctx.unmake_move(&mut sp);
+        if ctx.stopped {
+            break;
         }
-        let child_bs = bs.next_state_unchecked(mv);
-        let eval = -negamax(&child_bs, depth - 1, 1, -beta, -alpha, tt, nodes);
 
         if eval > max_eval {
             max_eval = eval;
             best_move = mv;
+            ctx.update_pv(0, *mv);
         }
         alpha = cmp::max(alpha, max_eval);
 
-        if cfg!(feature = "debug_engine_logging") {
-            nodes.negamax_nodes += 1;
-        }
+        ctx.nodes.negamax_nodes += 1;
         if beta <= alpha {
             if cfg!(feature = "debug_engine_logging") {
-                nodes.negamax_prunes += 1;
+                ctx.nodes.negamax_prunes += 1;
             }
             break;
         }
@@ -218,22 +832,34 @@ fn negamax_root<'a>(
     (max_eval, best_move)
 }
 
-fn negamax(
-    bs: &BoardState,
-    depth: u8,
+// pv_node is true on the principal variation, the rest of the tree only has to prove a bound and is pruned harder
+#[allow(clippy::too_many_arguments)]
+fn negamax<E: Evaluator>(
+    sp: &mut SearchPosition,
+    mut depth: u8,
     ply: u8,
     mut alpha: i32,
     mut beta: i32,
-    tt: &mut TranspositionTable,
-    nodes: &mut Nodes,
+    pv_node: bool,
+    tt: &TranspositionTable,
+    ctx: &mut SearchContext<E>,
 ) -> i32 {
+    if ctx.should_stop() {
+        return 0;
+    }
+    ctx.clear_pv(ply);
+    let in_check = sp.is_in_check();
+    // a check at the horizon is always answered, instead of the quiescence search standing pat in check
+    if in_check && ctx.features.check_extensions && ply < MAX_SEARCH_DEPTH {
+        depth += 1;
+    }
     // transposition table lookup
     let alpha_orig = alpha;
     let mut best_move = NULL_SHORT_MOVE; // will be set on tt hit
-    if let Some(entry) = tt.get(bs.board_hash) {
+    if let Some(entry) = tt.get(sp.board_hash()) {
         //TODO does adding halfmove count to the hash make sense? test performance
         if cfg!(feature = "debug_engine_logging") {
-            nodes.transposition_table_hits += 1;
+            ctx.nodes.transposition_table_hits += 1;
         }
         if entry.depth >= depth {
             match entry.bound_type {
@@ -258,13 +884,10 @@ fn negamax(
     }
     // TODO checkmate stored in tt will have wrong eval, so the root depth should be recalculated, might need a checkmate flag in tt entry
 
-    let pseudo_legal_moves = bs.get_pseudo_legal_moves();
     // check game over conditions returning immediately, or begin quiescence search
-    match bs.get_gamestate() {
+    match sp.get_gamestate(ctx.gen_moves(sp, ply)) {
         GameState::Checkmate => {
-            if cfg!(feature = "debug_engine_logging") {
-                nodes.negamax_nodes += 1;
-            }
+            ctx.nodes.negamax_nodes += 1;
             return -CHECKMATE_VALUE + ply as i32;
         }
         // draw states
@@ -272,44 +895,199 @@ fn negamax(
         | GameState::Repetition
         | GameState::FiftyMove
         | GameState::InsufficientMaterial => {
-            if cfg!(feature = "debug_engine_logging") {
-                nodes.negamax_nodes += 1;
-            }
+            ctx.nodes.negamax_nodes += 1;
             return DRAW_VALUE; // stalemate
         }
         _ => {}
     }
 
+    // the tables assume the 50 move counter is 0, so they are only probed after a capture or pawn move.
+    // a null move resets the counter too, but isn't either
+    if let Some(tb) = ctx.tablebase {
+        if sp.halfmove_count() == 0 && sp.last_move() != Some(NULL_MOVE) {
+            if let Some(wdl) = tb.probe_wdl_search(sp) {
+                ctx.nodes.tablebase_hits += 1;
+                ctx.nodes.negamax_nodes += 1;
+                // cursed wins and blessed losses are draws with the 50 move rule
+                return match wdl {
+                    Wdl::Win => TABLEBASE_WIN_VALUE - ply as i32,
+                    Wdl::Loss => -TABLEBASE_WIN_VALUE + ply as i32,
+                    _ => DRAW_VALUE,
+                };
+            }
+        }
+    }
+
     if depth == 0 {
-        return quiescence(bs, QUIECENCE_DEPTH, ply + 1, alpha, beta, nodes);
+        return quiescence(sp, ply + 1, alpha, beta, ctx);
+    }
+
+    // the static eval is only trusted away from the pv, out of check and without a mate or tablebase win to prove
+    let prunable = !pv_node && !in_check && beta.abs() < TABLEBASE_WIN_THRESHOLD;
+    let static_eval = if prunable {
+        ctx.evaluator.evaluate(sp.pos64(), sp.side_to_move())
+    } else {
+        0
+    };
+
+    // reverse futility pruning, too far above beta for the few plies left to bring it back down
+    if prunable
+        && ctx.features.reverse_futility
+        && depth <= REVERSE_FUTILITY_MAX_DEPTH
+        && static_eval - REVERSE_FUTILITY_MARGIN * depth as i32 >= beta
+    {
+        return static_eval;
+    }
+
+    // null move pruning, if passing the turn still fails high a real move almost certainly does too. not tried
+    // twice in a row, or with only pawns left where passing can be better than any move (zugzwang)
+    if prunable
+        && ctx.features.null_move
+        && depth >= NULL_MOVE_MIN_DEPTH
+        && static_eval >= beta
+        && sp.has_non_pawn_material()
+        && sp.last_move() != Some(NULL_MOVE)
+    {
+        let reduction = 2 + depth / 4;
+        ctx.make_null_move(sp);
+        let eval = -negamax(
+            sp,
+            depth.saturating_sub(1 + reduction),
+            ply + 1,
+            -beta,
+            -beta + 1,
+            false,
+            tt,
+            ctx,
+        );
+        ctx.unmake_null_move(sp);
+        if ctx.stopped {
+            return 0;
+        }
+        if eval >= beta {
+            // a mate found after passing the turn isn't proven
+            return if eval >= TABLEBASE_WIN_THRESHOLD {
+                beta
+            } else {
+                eval
+            };
+        }
     }
 
+    // futility pruning, quiet moves are skipped when even a margin above the static eval can't reach alpha
+    let futile = prunable
+        && ctx.features.futility
+        && (depth as usize) < FUTILITY_MARGINS.len()
+        && static_eval + FUTILITY_MARGINS[depth as usize] <= alpha;
+
     let mut max_eval = MIN;
-    let moves = sorted_move_indexes(pseudo_legal_moves, false, best_move, &bs.last_move); // sort pseudo legal moves instead of consuming the lazy iterator
+    let mut searched = 0;
+    let last_move = sp.last_move();
+    let pseudo_legal_moves = ctx.take_moves(ply);
+    let moves = sorted_move_indexes(
+        &pseudo_legal_moves,
+        false,
+        best_move,
+        &last_move,
+        ctx.history,
+        ply,
+        |mv| sp.see(mv),
+    );
+    // quiet moves searched before a cutoff, they get a history malus
+    let mut quiets_tried: Vec<Move> = Vec::new();
     for i in moves {
         let mv = &pseudo_legal_moves[i];
-        if !bs.is_move_legal_position(mv) {
+        if !sp.is_move_legal(mv) {
             continue; // skip illegal moves
         }
+        let quiet = is_quiet(mv);
 
-        let child_bs = bs.next_state_unchecked(mv);
-        let eval = -negamax(&child_bs, depth - 1, ply + 1, -beta, -alpha, tt, nodes);
+        ctx.make_move(sp, mv);
+        let gives_check = sp.is_in_check();
+        // at least one move is searched, so there is always an eval to return
+        if futile && searched > 0 && quiet && !gives_check {
+            ctx.unmake_move(sp);
+            continue;
+        }
+        let eval = if searched == 0 {
+            -negamax(sp, depth - 1, ply + 1, -beta, -alpha, pv_node, tt, ctx)
+        } else {
+            let reduction = if ctx.features.late_move_reductions
+                && depth >= LMR_MIN_DEPTH
+                && searched >= LMR_FULL_DEPTH_MOVES
+                && quiet
+                && !in_check
+                && !gives_check
+            {
+                late_move_reduction(depth, searched, pv_node)
+            } else {
+                0
+            };
+            // with pvs the later moves only have to be shown to be no better than alpha, with a zero window.
+            // a move that beats alpha is searched again at full depth, then with the full window. without pvs
+            // they get the full window straight away, and stay pv nodes so they aren't pruned any harder
+            let (child_alpha, child_beta, child_pv) = if ctx.features.pvs {
+                (-alpha - 1, -alpha, false)
+            } else {
+                (-beta, -alpha, pv_node)
+            };
+            let mut eval = -negamax(
+                sp,
+                depth - 1 - reduction,
+                ply + 1,
+                child_alpha,
+                child_beta,
+                child_pv,
+                tt,
+                ctx,
+            );
+            if reduction > 0 && eval > alpha {
+                eval = -negamax(
+                    sp,
+                    depth - 1,
+                    ply + 1,
+                    child_alpha,
+                    child_beta,
+                    child_pv,
+                    tt,
+                    ctx,
+                );
+            }
+            if ctx.features.pvs && eval > alpha && eval < beta {
+                eval = -negamax(sp, depth - 1, ply + 1, -beta, -alpha, pv_node, tt, ctx);
+            }
+            eval
+        };
+        searched += 1;
+        ctx.unmake_move(sp);
+        if ctx.stopped {
+            return 0; // results of an unfinished search can't be stored in the tt
+        }
         if eval > max_eval {
             max_eval = eval;
             best_move = mv.short_move();
         }
+        if eval > alpha {
+            ctx.update_pv(ply, *mv);
+        }
         alpha = cmp::max(alpha, max_eval);
 
-        if cfg!(feature = "debug_engine_logging") {
-            nodes.negamax_nodes += 1;
-        }
+        ctx.nodes.negamax_nodes += 1;
         if beta <= alpha {
             if cfg!(feature = "debug_engine_logging") {
-                nodes.negamax_prunes += 1;
+                ctx.nodes.negamax_prunes += 1;
+            }
+            if quiet {
+                ctx.history
+                    .update_cutoff(ply, depth, mv, &quiets_tried, last_move.as_ref());
             }
             break;
         }
+        if quiet {
+            quiets_tried.push(*mv);
+        }
     }
+    ctx.put_moves(ply, pseudo_legal_moves);
 
     // Insert new entry in transposition table
     let mut entry = TableEntry {
@@ -325,21 +1103,47 @@ fn negamax(
     } else if entry.eval >= beta {
         entry.bound_type = BoundType::Lower;
     }
-    tt.insert(bs.board_hash, entry);
+    tt.insert(sp.board_hash(), entry);
 
     max_eval
 }
 
+// not a capture or promotion, these are the moves reduced and pruned
+#[inline(always)]
+fn is_quiet(mv: &Move) -> bool {
+    !mv.move_type.is_capture() && !matches!(mv.move_type, MoveType::Promotion(..))
+}
+
+// plies taken off a late move, more for later moves and deeper searches, less on the pv. leaves at least one ply
+fn late_move_reduction(depth: u8, searched: usize, pv_node: bool) -> u8 {
+    let reduction = 0.75 + (depth as f32).ln() * (searched as f32).ln() / 2.25;
+    (reduction as u8)
+        .saturating_sub(pv_node as u8)
+        .min(depth - 2)
+}
+
+// tt move first, then captures and promotions, killers, the countermove, the other quiet moves by history and
+// last the captures that lose material. tactical_only leaves just the captures and queen promotions that don't
+// lose material, for the quiescence search
+#[allow(clippy::too_many_arguments)]
 fn sorted_move_indexes(
     moves: &[Move],
-    captures_only: bool,
+    tactical_only: bool,
     tt_mv: ShortMove,
     last_mv: &Option<Move>,
+    history: &SearchHistory,
+    ply: u8,
+    see: impl Fn(&Move) -> i32,
 ) -> Vec<usize> {
     let mut move_scores: Vec<(usize, i32)> = Vec::with_capacity(moves.len());
+    let killers = history.killers(ply);
+    let countermove = history.countermove(last_mv.as_ref());
 
     for (index, mv) in moves.iter().enumerate() {
-        if captures_only && !matches!(mv.move_type, MoveType::Capture(_)) {
+        if tactical_only
+            && (is_quiet(mv)
+                || matches!(mv.move_type, MoveType::Promotion(ptype, _) if ptype != PieceType::Queen))
+        {
             continue;
         }
         if mv == &tt_mv {
@@ -350,8 +1154,22 @@ fn sorted_move_indexes(
         let mv_score = match mv.move_type {
             MoveType::Capture(capture_type) => {
                 let mv_ptype_value = get_piece_value(&mv.piece.ptype);
+                // taking a piece worth at least as much can't lose material, only the others need the exchanges
+                let exchange = if get_piece_value(&capture_type) < mv_ptype_value {
+                    see(mv)
+                } else {
+                    0
+                };
+                if exchange < 0 {
+                    if tactical_only {
+                        continue;
+                    }
+                    move_scores.push((index, LOSING_CAPTURE_SCORE + exchange));
+                    continue;
+                }
                 // prioritise captures, even when capturing with a more valuable piece. After trades it could still be good, so min 1
-                cmp::max(get_piece_value(&capture_type) - mv_ptype_value, 1)
+                CAPTURE_SCORE
+                    + cmp::max(get_piece_value(&capture_type) - mv_ptype_value, 1)
                     + if let Some(last_mv) = last_mv {
                         // prioritize recaptures, with least valuable piece
                         if mv.to == last_mv.to {
@@ -363,14 +1181,20 @@ fn sorted_move_indexes(
                         0
                     }
             }
-            MoveType::Promotion(promotion_type, _) => get_piece_value(&promotion_type), // TODO maybe potential capture should be taken into account
-            _ => 0,
+            MoveType::EnPassant(_) => CAPTURE_SCORE + 1,
+            MoveType::Promotion(promotion_type, _) => {
+                CAPTURE_SCORE + get_piece_value(&promotion_type) // TODO maybe potential capture should be taken into account
+            }
+            _ if *mv == killers[0] => KILLER_SCORES[0],
+            _ if *mv == killers[1] => KILLER_SCORES[1],
+            _ if *mv == countermove => COUNTERMOVE_SCORE,
+            _ => history.quiet_score(mv, last_mv.as_ref()),
         };
 
         move_scores.push((index, mv_score));
     }
 
-    move_scores.sort_by(|a, b| b.1.cmp(&a.1));
+    move_scores.sort_by_key(|a| cmp::Reverse(a.1));
 
     move_scores
         .into_iter()
@@ -378,105 +1202,201 @@ fn sorted_move_indexes(
         .0
 }
 
-// values in centipawns
-#[inline(always)]
-const fn get_piece_value(ptype: &PieceType) -> i32 {
-    match ptype {
-        PieceType::Pawn => 100,
-        PieceType::Knight => 320,
-        PieceType::Bishop => 330,
-        PieceType::Rook => 500,
-        PieceType::Queen => 900,
-        PieceType::King => 20000,
-    }
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::FEN;
 
-#[inline(always)]
-const fn get_piece_pos_value(i: usize, piece: &Piece, is_endgame: bool) -> i32 {
-    // all pos values are from whites perspective (a8 = index 0, h1 = index 63)
-    const PAWN_POS_VALUES: [i32; 64] = [
-        0, 0, 0, 0, 0, 0, 0, 0, 50, 50, 50, 50, 50, 50, 50, 50, 10, 10, 20, 30, 30, 20, 10, 10, 5,
-        5, 10, 25, 25, 10, 5, 5, 0, 0, 0, 20, 20, 0, 0, 0, 5, -5, -10, 0, 0, -10, -5, 5, 5, 10, 10,
-        -20, -20, 10, 10, 5, 0, 0, 0, 0, 0, 0, 0, 0,
-    ];
-    const KNIGHT_POS_VALUES: [i32; 64] = [
-        -50, -40, -30, -30, -30, -30, -40, -50, -40, -20, 0, 0, 0, 0, -20, -40, -30, 0, 10, 15, 15,
-        10, 0, -30, -30, 5, 15, 20, 20, 15, 5, -30, -30, 0, 15, 20, 20, 15, 0, -30, -30, 5, 10, 15,
-        15, 10, 5, -30, -40, -20, 0, 5, 5, 0, -20, -40, -50, -40, -30, -30, -30, -30, -40, -50,
-    ];
-    const BISHOP_POS_VALUES: [i32; 64] = [
-        -20, -10, -10, -10, -10, -10, -10, -20, -10, 0, 0, 0, 0, 0, 0, -10, -10, 0, 5, 10, 10, 5,
-        0, -10, -10, 5, 5, 10, 10, 5, 5, -10, -10, 0, 10, 10, 10, 10, 0, -10, -10, 10, 10, 10, 10,
-        10, 10, -10, -10, 5, 0, 0, 0, 0, 5, -10, -20, -10, -10, -10, -10, -10, -10, -20,
-    ];
-    const ROOK_POS_VALUES: [i32; 64] = [
-        0, 0, 0, 0, 0, 0, 0, 0, 5, 10, 10, 10, 10, 10, 10, 5, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0,
-        0, 0, 0, 0, -5, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0, 0, 0, 0, 0,
-        -5, 0, 0, 0, 5, 5, 0, 0, 0,
-    ];
-    const QUEEN_POS_VALUES: [i32; 64] = [
-        -20, -10, -10, -5, -5, -10, -10, -20, -10, 0, 0, 0, 0, 0, 0, -10, -10, 0, 5, 5, 5, 5, 0,
-        -10, -5, 0, 5, 5, 5, 5, 0, -5, 0, 0, 5, 5, 5, 5, 0, -5, -10, 5, 5, 5, 5, 5, 0, -10, -10, 0,
-        5, 0, 0, 0, 0, -10, -20, -10, -10, -5, -5, -10, -10, -20,
-    ];
-    const KING_MIDDLE_POS_VALUES: [i32; 64] = [
-        -30, -40, -40, -50, -50, -40, -40, -30, -30, -40, -40, -50, -50, -40, -40, -30, -30, -40,
-        -40, -50, -50, -40, -40, -30, -30, -40, -40, -50, -50, -40, -40, -30, -20, -30, -30, -40,
-        -40, -30, -30, -20, -10, -20, -20, -20, -20, -20, -20, -10, 20, 20, 0, 0, 0, 0, 20, 20, 20,
-        30, 10, 0, 0, 10, 30, 20,
-    ];
-    const KING_END_POS_VALUES: [i32; 64] = [
-        -50, -40, -30, -20, -20, -30, -40, -50, -30, -20, -10, 0, 0, -10, -20, -30, -30, -10, 20,
-        30, 30, 20, -10, -30, -30, -10, 30, 40, 40, 30, -10, -30, -30, -10, 30, 40, 40, 30, -10,
-        -30, -30, -10, 20, 30, 30, 20, -10, -30, -30, -30, 0, 0, 0, 0, -30, -30, -50, -30, -30,
-        -30, -30, -30, -30, -50,
-    ];
-
-    let side_adjusted_idx = match piece.pcolour {
-        PieceColour::White => i,
-        PieceColour::Black => 63 - i,
-    };
+    fn search(fen: &str, depth: u8, features: SearchFeatures) -> SearchInfo {
+        let bs = BoardState::from(fen.parse::<FEN>().unwrap());
+        let tt = TranspositionTable::with_size(1);
+        let options = SearchOptions::features(features);
+        iterative_deepening(
+            &bs,
+            &SearchLimits::depth(depth),
+            &options,
+            &tt,
+            &mut SearchHistory::new(),
+            None,
+            |_| {},
+        )
+    }
 
-    match piece.ptype {
-        PieceType::Pawn => PAWN_POS_VALUES[side_adjusted_idx],
-        PieceType::Knight => KNIGHT_POS_VALUES[side_adjusted_idx],
-        PieceType::Bishop => BISHOP_POS_VALUES[side_adjusted_idx],
-        PieceType::Rook => ROOK_POS_VALUES[side_adjusted_idx],
-        PieceType::Queen => QUEEN_POS_VALUES[side_adjusted_idx],
-        PieceType::King => {
-            if is_endgame {
-                KING_END_POS_VALUES[side_adjusted_idx]
-            } else {
-                KING_MIDDLE_POS_VALUES[side_adjusted_idx]
-            }
+    #[test]
+    fn test_search_features() {
+        // mate in 2 with Nf6+ gxf6 Bxf7#, found with each feature on its own
+        let fen = "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1";
+        let enable: [fn(&mut SearchFeatures); 6] = [
+            |f| f.null_move = true,
+            |f| f.late_move_reductions = true,
+            |f| f.pvs = true,
+            |f| f.futility = true,
+            |f| f.reverse_futility = true,
+            |f| f.check_extensions = true,
+        ];
+        let each = enable.iter().map(|enable| {
+            let mut features = SearchFeatures::none();
+            enable(&mut features);
+            features
+        });
+        for features in each.chain([SearchFeatures::none(), SearchFeatures::default()]) {
+            let info = search(fen, 4, features);
+            assert_eq!(info.best_move.to_uci_string(), "d5f6", "{:?}", features);
+            assert_eq!(info.eval, CHECKMATE_VALUE - 3, "{:?}", features);
         }
     }
-}
 
-// adapted piece eval scores from here -> https://www.chessprogramming.org/Simplified_Evaluation_Function
-fn evaluate(bs: &BoardState) -> i32 {
-    let maxi_colour = bs.side_to_move;
-    let mut w_eval: i32 = 0;
-    let mut b_eval: i32 = 0;
-    for (i, s) in bs.get_pos64().iter().enumerate() {
-        match s {
-            Square::Empty => {
-                continue;
-            }
-            Square::Piece(p) => {
-                let val = get_piece_value(&p.ptype) + get_piece_pos_value(i, p, false);
-                if p.pcolour == PieceColour::White {
-                    w_eval += val;
-                } else {
-                    b_eval += val;
-                }
-            }
+    #[test]
+    fn test_search_without_pvs() {
+        // turning pvs off only widens the windows, the other pruning still only happens away from the pv
+        let no_pvs = SearchFeatures {
+            pvs: false,
+            ..SearchFeatures::default()
+        };
+        for (fen, depth, best_move, eval) in [
+            (
+                "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1",
+                4,
+                "d5f6",
+                CHECKMATE_VALUE - 3,
+            ),
+            (
+                "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+                3,
+                "h5f7",
+                CHECKMATE_VALUE - 1,
+            ),
+        ] {
+            let pvs = search(fen, depth, SearchFeatures::default());
+            let full_window = search(fen, depth, no_pvs);
+            assert_eq!(pvs.best_move.to_uci_string(), best_move);
+            assert_eq!(pvs.eval, eval);
+            assert_eq!(full_window.best_move, pvs.best_move);
+            assert_eq!(full_window.eval, pvs.eval);
         }
     }
-    let eval = w_eval - b_eval;
-    if maxi_colour == PieceColour::White {
-        eval
-    } else {
-        -eval
+
+    #[test]
+    fn test_search_features_prune() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let selective = search(fen, 5, SearchFeatures::default());
+        let full = search(fen, 5, SearchFeatures::none());
+        assert!(selective.nodes * 2 < full.nodes);
+    }
+
+    // plays the pv from the root, each move has to be legal where it is played
+    fn play_pv(bs: &BoardState, pv: &[Move]) -> BoardState {
+        pv.iter().fold(bs.clone(), |state, mv| {
+            assert!(
+                state.get_legal_moves().unwrap().contains(mv),
+                "{:?} in {:?}",
+                mv,
+                pv
+            );
+            state.next_state(mv).unwrap()
+        })
+    }
+
+    #[test]
+    fn test_pv_legal() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let bs = BoardState::from(fen.parse::<FEN>().unwrap());
+        let info = search(fen, 5, SearchFeatures::default());
+        assert_eq!(info.pv.first(), Some(&info.best_move));
+        assert!(info.pv.len() <= 5);
+        play_pv(&bs, &info.pv);
+        assert_eq!(info.pv_notation(&bs).len(), info.pv.len());
+    }
+
+    #[test]
+    fn test_pv_mate() {
+        // Nf6+ gxf6 Bxf7#, the pv stops at the mate without moves left in the pv table by earlier iterations
+        let fen = "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1";
+        let bs = BoardState::from(fen.parse::<FEN>().unwrap());
+        let info = search(fen, 5, SearchFeatures::default());
+        let pv: Vec<String> = info.pv.iter().map(|mv| mv.to_uci_string()).collect();
+        assert_eq!(pv, ["d5f6", "g7f6", "c4f7"]);
+        assert_eq!(play_pv(&bs, &info.pv).get_gamestate(), GameState::Checkmate);
+    }
+
+    #[test]
+    fn test_late_move_reduction() {
+        assert_eq!(late_move_reduction(3, 3, false), 1);
+        assert_eq!(late_move_reduction(3, 3, true), 0);
+        // always leaves at least one ply to search
+        assert_eq!(late_move_reduction(3, 60, false), 1);
+        assert!(late_move_reduction(20, 30, false) > late_move_reduction(6, 4, false));
+    }
+
+    #[test]
+    fn test_losing_captures_ordering() {
+        // Rxe5 loses the exchange to dxe5, Nxe5 and Nxd6 win material
+        let bs = BoardState::from(
+            "4k3/8/3p4/4n3/2N5/8/8/4RK2 w - - 0 1"
+                .parse::<FEN>()
+                .unwrap(),
+        );
+        let moves = bs.get_pseudo_legal_moves();
+        let history = SearchHistory::new();
+        let sorted = |tactical_only| -> Vec<String> {
+            sorted_move_indexes(
+                moves,
+                tactical_only,
+                NULL_SHORT_MOVE,
+                &None,
+                &history,
+                0,
+                |mv| bs.see(mv),
+            )
+            .into_iter()
+            .map(|i| moves[i].to_uci_string())
+            .collect()
+        };
+        let all = sorted(false);
+        assert_eq!(all.len(), moves.len());
+        assert_eq!(all.last().unwrap(), "e1e5");
+        // the quiescence search only gets the captures that don't lose material
+        let mut tactical = sorted(true);
+        tactical.sort();
+        assert_eq!(tactical, ["c4d6", "c4e5"]);
+    }
+
+    // quiescence eval and static eval of a position, both for the side to move
+    fn quiescence_fen(fen: &str) -> (i32, i32) {
+        let bs = BoardState::from(fen.parse::<FEN>().unwrap());
+        let mut sp = SearchPosition::from(&bs);
+        let mut history = SearchHistory::new();
+        let mut ctx = SearchContext::new(ClassicalEvaluator::default(), &mut history);
+        ctx.evaluator.reset(sp.pos64());
+        let static_eval = ctx.evaluator.evaluate(sp.pos64(), sp.side_to_move());
+        (quiescence(&mut sp, 1, MIN, MAX, &mut ctx), static_eval)
+    }
+
+    #[test]
+    fn test_quiescence_check_evasions() {
+        // after Qxa5 Nxf2+ white can't stand pat, both king moves lose the rook to Nxd1
+        let (eval, static_eval) = quiescence_fen("6k1/6p1/8/Q7/8/8/5nP1/3R3K w - - 0 2");
+        assert!(eval < static_eval - 300, "{} {}", eval, static_eval);
+        // so a depth 1 search doesn't take the knight on a5
+        let info = search(
+            "6k1/6p1/8/n7/6n1/8/3Q1PP1/3R3K w - - 0 1",
+            1,
+            SearchFeatures::default(),
+        );
+        assert_ne!(info.best_move.to_uci_string(), "d2a5");
+    }
+
+    #[test]
+    fn test_quiescence_promotions() {
+        // nothing covers b1, the pawn promotes
+        let (eval, static_eval) = quiescence_fen("k7/8/8/8/8/4K3/1p4R1/8 b - - 0 1");
+        assert!(eval > static_eval + 400, "{} {}", eval, static_eval);
+        // Rxg6 wins a knight but lets the pawn queen, at depth 1 only quiescence sees that Rxb2 is better
+        let info = search(
+            "k7/8/6n1/8/8/4K3/1p4R1/8 w - - 0 1",
+            1,
+            SearchFeatures::default(),
+        );
+        assert_eq!(info.best_move.to_uci_string(), "g2b2");
     }
 }
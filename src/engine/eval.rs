@@ -0,0 +1,673 @@
+use std::fmt;
+use std::ops;
+use std::sync::Arc;
+
+use super::params::EvalParams;
+use super::pawns::{self, PawnEntry};
+use super::pieces;
+use crate::bitboard::Bitboards;
+use crate::board::BoardState;
+use crate::movegen::*;
+use crate::transposition::{TTData, TT};
+use crate::zobrist;
+
+// static evaluation used by the search. each search thread has its own clone, which is told about every move
+// made and unmade from the root, so an evaluator can keep incremental state in sync with the position.
+// boards are indexed with 0 = a8 and 63 = h1
+pub trait Evaluator: Clone + Send + Sync {
+    // called with the root position before it is searched
+    fn reset(&mut self, _board: &[Square; 64]) {}
+
+    // called before mv is made, board is the position mv is made from
+    fn make_move(&mut self, _board: &[Square; 64], _mv: &Move) {}
+
+    // called after the last move made has been unmade
+    fn unmake_move(&mut self) {}
+
+    // eval in centipawns from the point of view of the side to move
+    fn evaluate(&mut self, board: &[Square; 64], side_to_move: PieceColour) -> i32;
+}
+
+// middlegame and endgame values of an evaluation term, blended by the game phase
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Score {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl Score {
+    pub const ZERO: Score = Score { mg: 0, eg: 0 };
+
+    pub const fn new(mg: i32, eg: i32) -> Self {
+        Score { mg, eg }
+    }
+
+    // blend of the middlegame and endgame values, phase is MAX_PHASE for the middlegame down to 0 for the endgame
+    #[inline(always)]
+    pub const fn taper(self, phase: i32) -> i32 {
+        (self.mg * phase + self.eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl ops::Add for Score {
+    type Output = Score;
+    fn add(self, rhs: Score) -> Score {
+        Score::new(self.mg + rhs.mg, self.eg + rhs.eg)
+    }
+}
+
+impl ops::AddAssign for Score {
+    fn add_assign(&mut self, rhs: Score) {
+        *self = *self + rhs;
+    }
+}
+
+impl ops::Sub for Score {
+    type Output = Score;
+    fn sub(self, rhs: Score) -> Score {
+        Score::new(self.mg - rhs.mg, self.eg - rhs.eg)
+    }
+}
+
+impl ops::SubAssign for Score {
+    fn sub_assign(&mut self, rhs: Score) {
+        *self = *self - rhs;
+    }
+}
+
+impl ops::Neg for Score {
+    type Output = Score;
+    fn neg(self) -> Score {
+        Score::new(-self.mg, -self.eg)
+    }
+}
+
+impl ops::Mul<i32> for Score {
+    type Output = Score;
+    fn mul(self, rhs: i32) -> Score {
+        Score::new(self.mg * rhs, self.eg * rhs)
+    }
+}
+
+// phase of the starting position, promotions can take the phase above it so it is capped
+pub const MAX_PHASE: i32 = 24;
+// contribution of each piece type to the game phase
+const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+
+// game phase from the non pawn material on the board, MAX_PHASE in the opening down to 0 with only kings and pawns left
+pub fn game_phase(board: &[Square; 64]) -> i32 {
+    let phase = board
+        .iter()
+        .map(|s| match s {
+            Square::Piece(p) => PHASE_WEIGHTS[p.ptype as usize],
+            Square::Empty => 0,
+        })
+        .sum::<i32>();
+    phase.min(MAX_PHASE)
+}
+
+// terms of the classical evaluation, in the order they are shown in an EvalTrace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvalTerm {
+    Material,
+    PieceSquares,
+    PawnStructure,
+    PassedPawns,
+    Mobility,
+    KingSafety,
+    Pieces,
+}
+
+impl EvalTerm {
+    pub const ALL: [EvalTerm; 7] = [
+        EvalTerm::Material,
+        EvalTerm::PieceSquares,
+        EvalTerm::PawnStructure,
+        EvalTerm::PassedPawns,
+        EvalTerm::Mobility,
+        EvalTerm::KingSafety,
+        EvalTerm::Pieces,
+    ];
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            EvalTerm::Material => "Material",
+            EvalTerm::PieceSquares => "Piece squares",
+            EvalTerm::PawnStructure => "Pawn structure",
+            EvalTerm::PassedPawns => "Passed pawns",
+            EvalTerm::Mobility => "Mobility",
+            EvalTerm::KingSafety => "King safety",
+            EvalTerm::Pieces => "Pieces",
+        }
+    }
+}
+
+impl fmt::Display for EvalTerm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// classical evaluation broken down by term and side. the evaluator builds one for every eval, so the
+// breakdown always adds up to what the search sees
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EvalTrace {
+    // game phase the terms are tapered with
+    pub phase: i32,
+    // indexed by EvalTerm and PieceColour as usize, each from that side's point of view
+    scores: [[Score; 2]; EvalTerm::ALL.len()],
+}
+
+impl EvalTrace {
+    pub fn score(&self, term: EvalTerm, pcolour: PieceColour) -> Score {
+        self.scores[term as usize][pcolour as usize]
+    }
+
+    // white minus black
+    pub fn term(&self, term: EvalTerm) -> Score {
+        self.score(term, PieceColour::White) - self.score(term, PieceColour::Black)
+    }
+
+    // sum of all terms, white minus black
+    pub fn total(&self) -> Score {
+        EvalTerm::ALL
+            .iter()
+            .fold(Score::ZERO, |total, term| total + self.term(*term))
+    }
+
+    // eval in centipawns from white's point of view, the same as ClassicalEvaluator::evaluate for white to move
+    pub fn eval(&self) -> i32 {
+        self.total().taper(self.phase)
+    }
+
+    #[inline(always)]
+    fn add(&mut self, term: EvalTerm, pcolour: PieceColour, score: Score) {
+        self.scores[term as usize][pcolour as usize] += score;
+    }
+}
+
+// table of the terms in pawns, with the tapered eval at the bottom
+impl fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pawns = |cp: i32| cp as f64 / 100.0;
+        // blank cells are left out of the total row
+        let row = |name: &str, cells: [Option<Score>; 3]| {
+            let cells: Vec<String> = cells
+                .iter()
+                .map(|cell| match cell {
+                    Some(score) => format!("{:>7.2} {:>7.2}", pawns(score.mg), pawns(score.eg)),
+                    None => String::new(),
+                })
+                .collect();
+            format!(
+                "{:<16}|{:<16}|{:<16}|{}",
+                name, cells[0], cells[1], cells[2]
+            )
+        };
+        let separator = format!("{0:-<16}+{0:-<16}+{0:-<16}+{0:-<16}", "");
+
+        writeln!(
+            f,
+            "{:<16}|{:^16}|{:^16}|{:>10}",
+            "Term", "White", "Black", "Total"
+        )?;
+        writeln!(
+            f,
+            "{:<16}|{:>7} {:>7} |{:>7} {:>7} |{:>7} {:>7}",
+            "", "MG", "EG", "MG", "EG", "MG", "EG"
+        )?;
+        writeln!(f, "{}", separator)?;
+        for term in EvalTerm::ALL {
+            let white = self.score(term, PieceColour::White);
+            let black = self.score(term, PieceColour::Black);
+            let line = row(
+                term.name(),
+                [Some(white), Some(black), Some(self.term(term))],
+            );
+            writeln!(f, "{}", line)?;
+        }
+        writeln!(f, "{}", separator)?;
+        writeln!(f, "{}", row("Total", [None, None, Some(self.total())]))?;
+        writeln!(f)?;
+        writeln!(f, "Phase: {}/{}", self.phase, MAX_PHASE)?;
+        write!(f, "Eval: {:+.2} (white side)", pawns(self.eval()))
+    }
+}
+
+// classical evaluation of the position with a breakdown by term, see EvalTrace
+pub fn eval_trace(bs: &BoardState) -> EvalTrace {
+    eval_trace_with(bs, &ClassicalEvaluator::default())
+}
+
+// eval_trace with the params of evaluator, so the trace has the same scores as a search using it.
+// only its params are used so its pawn table isn't copied
+pub fn eval_trace_with(bs: &BoardState, evaluator: &ClassicalEvaluator) -> EvalTrace {
+    ClassicalEvaluator::with_params(evaluator.params().clone()).trace(bs.get_pos64())
+}
+
+// size of the pawn hash table of each search thread
+const PAWN_TABLE_SIZE_MB: usize = 2;
+
+// material, piece square tables, pawn structure, piece activity and king safety, tapered between the middlegame
+// and endgame. the default evaluator, with the values in EvalParams.
+// adapted piece eval scores from here -> https://www.chessprogramming.org/Simplified_Evaluation_Function
+#[derive(Debug, Clone)]
+pub struct ClassicalEvaluator {
+    params: Arc<EvalParams>,
+    // pawn structure by pawn only zobrist hash, allocated when a search starts
+    pawn_table: TT<PawnEntry>,
+}
+
+impl Default for ClassicalEvaluator {
+    fn default() -> Self {
+        Self::with_params(Arc::new(EvalParams::default()))
+    }
+}
+
+// the pawn table is only a cache, it never changes an eval
+impl PartialEq for ClassicalEvaluator {
+    fn eq(&self, other: &Self) -> bool {
+        self.params == other.params
+    }
+}
+
+impl Eq for ClassicalEvaluator {}
+
+impl Evaluator for ClassicalEvaluator {
+    fn reset(&mut self, _board: &[Square; 64]) {
+        if self.pawn_table.size() == 0 {
+            self.pawn_table = TT::with_size(PAWN_TABLE_SIZE_MB);
+        }
+    }
+
+    fn evaluate(&mut self, board: &[Square; 64], side_to_move: PieceColour) -> i32 {
+        let eval = self.trace(board).eval();
+        if side_to_move == PieceColour::White {
+            eval
+        } else {
+            -eval
+        }
+    }
+}
+
+impl ClassicalEvaluator {
+    // evaluator with tuned parameters, e.g. loaded with EvalParams::from_file
+    pub fn with_params(params: Arc<EvalParams>) -> Self {
+        ClassicalEvaluator {
+            params,
+            pawn_table: TT::with_size(0),
+        }
+    }
+
+    pub fn params(&self) -> &Arc<EvalParams> {
+        &self.params
+    }
+
+    // every term of the evaluation, evaluate is the tapered total
+    pub fn trace(&mut self, board: &[Square; 64]) -> EvalTrace {
+        let mut trace = EvalTrace::default();
+        let mut phase = 0;
+        let mut bitboards = Bitboards::default();
+        for (i, s) in board.iter().enumerate() {
+            let Square::Piece(p) = s else {
+                continue;
+            };
+            bitboards.put(i, *p);
+            phase += PHASE_WEIGHTS[p.ptype as usize];
+            trace.add(
+                EvalTerm::Material,
+                p.pcolour,
+                self.params.material[p.ptype as usize],
+            );
+            trace.add(
+                EvalTerm::PieceSquares,
+                p.pcolour,
+                get_pos_score(&self.params, i, p),
+            );
+        }
+        trace.phase = phase.min(MAX_PHASE);
+
+        let entry = self.pawn_entry(&bitboards);
+        let free_path = pawns::passed_free_path(&self.params, &entry, bitboards.occupied());
+        let piece_terms = pieces::evaluate_pieces(&self.params, &bitboards);
+        for pcolour in [PieceColour::White, PieceColour::Black] {
+            let c = pcolour as usize;
+            trace.add(EvalTerm::PawnStructure, pcolour, entry.structure[c]);
+            trace.add(
+                EvalTerm::PassedPawns,
+                pcolour,
+                entry.passed_score[c] + free_path[c],
+            );
+            trace.add(EvalTerm::Mobility, pcolour, piece_terms[c].mobility);
+            trace.add(EvalTerm::KingSafety, pcolour, piece_terms[c].king_safety);
+            trace.add(EvalTerm::Pieces, pcolour, piece_terms[c].pieces);
+        }
+        trace
+    }
+
+    // pawn structure of both sides, looked up in the pawn table before it is calculated
+    fn pawn_entry(&mut self, bitboards: &Bitboards) -> PawnEntry {
+        let pawns = bitboards.ptype(PieceType::Pawn);
+        let pawns = [
+            pawns & bitboards.colour(PieceColour::White),
+            pawns & bitboards.colour(PieceColour::Black),
+        ];
+        let key = zobrist::pawn_hash(pawns);
+        match self.pawn_table.get(key) {
+            // empty buckets match a key with zeroed low bits, so check the entry was filled
+            Some(entry) if !entry.is_empty() => *entry,
+            _ => {
+                let entry = pawns::evaluate_pawns(&self.params, pawns);
+                self.pawn_table.insert(key, entry);
+                entry
+            }
+        }
+    }
+}
+
+// values in centipawns, used for move ordering
+#[inline(always)]
+pub(super) const fn get_piece_value(ptype: &PieceType) -> i32 {
+    match ptype {
+        PieceType::Pawn => 100,
+        PieceType::Knight => 320,
+        PieceType::Bishop => 330,
+        PieceType::Rook => 500,
+        PieceType::Queen => 900,
+        PieceType::King => 20000,
+    }
+}
+
+// the default values of the tables below, see EvalParams
+// material of each piece type, the kings always cancel out
+pub(super) const MATERIAL_VALUES: [Score; 6] = [
+    Score::new(100, 120),
+    Score::new(320, 300),
+    Score::new(330, 320),
+    Score::new(500, 530),
+    Score::new(900, 950),
+    Score::new(0, 0),
+];
+
+// all pos values are from whites perspective (a8 = index 0, h1 = index 63)
+#[rustfmt::skip]
+pub(super) const MG_POS_VALUES: [[i32; 64]; 6] = [
+    // pawn
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+         50,  50,  50,  50,  50,  50,  50,  50,
+         10,  10,  20,  30,  30,  20,  10,  10,
+          5,   5,  10,  25,  25,  10,   5,   5,
+          0,   0,   0,  20,  20,   0,   0,   0,
+          5,  -5, -10,   0,   0, -10,  -5,   5,
+          5,  10,  10, -20, -20,  10,  10,   5,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+    // knight
+    [
+        -50, -40, -30, -30, -30, -30, -40, -50,
+        -40, -20,   0,   0,   0,   0, -20, -40,
+        -30,   0,  10,  15,  15,  10,   0, -30,
+        -30,   5,  15,  20,  20,  15,   5, -30,
+        -30,   0,  15,  20,  20,  15,   0, -30,
+        -30,   5,  10,  15,  15,  10,   5, -30,
+        -40, -20,   0,   5,   5,   0, -20, -40,
+        -50, -40, -30, -30, -30, -30, -40, -50,
+    ],
+    // bishop
+    [
+        -20, -10, -10, -10, -10, -10, -10, -20,
+        -10,   0,   0,   0,   0,   0,   0, -10,
+        -10,   0,   5,  10,  10,   5,   0, -10,
+        -10,   5,   5,  10,  10,   5,   5, -10,
+        -10,   0,  10,  10,  10,  10,   0, -10,
+        -10,  10,  10,  10,  10,  10,  10, -10,
+        -10,   5,   0,   0,   0,   0,   5, -10,
+        -20, -10, -10, -10, -10, -10, -10, -20,
+    ],
+    // rook
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+          5,  10,  10,  10,  10,  10,  10,   5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+          0,   0,   0,   5,   5,   0,   0,   0,
+    ],
+    // queen
+    [
+        -20, -10, -10,  -5,  -5, -10, -10, -20,
+        -10,   0,   0,   0,   0,   0,   0, -10,
+        -10,   0,   5,   5,   5,   5,   0, -10,
+         -5,   0,   5,   5,   5,   5,   0,  -5,
+          0,   0,   5,   5,   5,   5,   0,  -5,
+        -10,   5,   5,   5,   5,   5,   0, -10,
+        -10,   0,   5,   0,   0,   0,   0, -10,
+        -20, -10, -10,  -5,  -5, -10, -10, -20,
+    ],
+    // king, sheltered behind its pawns
+    [
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -20, -30, -30, -40, -40, -30, -30, -20,
+        -10, -20, -20, -20, -20, -20, -20, -10,
+         20,  20,   0,   0,   0,   0,  20,  20,
+         20,  30,  10,   0,   0,  10,  30,  20,
+    ],
+];
+
+#[rustfmt::skip]
+pub(super) const EG_POS_VALUES: [[i32; 64]; 6] = [
+    // pawn, worth more the closer it is to promoting
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+         80,  80,  80,  80,  80,  80,  80,  80,
+         50,  50,  50,  50,  50,  50,  50,  50,
+         30,  30,  30,  30,  30,  30,  30,  30,
+         15,  15,  15,  15,  15,  15,  15,  15,
+          5,   5,   5,   5,   5,   5,   5,   5,
+          0,   0,   0,   0,   0,   0,   0,   0,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+    // knight
+    [
+        -40, -30, -20, -20, -20, -20, -30, -40,
+        -30, -15,  -5,   0,   0,  -5, -15, -30,
+        -20,  -5,   5,  10,  10,   5,  -5, -20,
+        -20,   0,  10,  15,  15,  10,   0, -20,
+        -20,   0,  10,  15,  15,  10,   0, -20,
+        -20,  -5,   5,  10,  10,   5,  -5, -20,
+        -30, -15,  -5,   0,   0,  -5, -15, -30,
+        -40, -30, -20, -20, -20, -20, -30, -40,
+    ],
+    // bishop
+    [
+        -15, -10, -10, -10, -10, -10, -10, -15,
+        -10,   0,   0,   0,   0,   0,   0, -10,
+        -10,   0,   5,   5,   5,   5,   0, -10,
+        -10,   0,   5,  10,  10,   5,   0, -10,
+        -10,   0,   5,  10,  10,   5,   0, -10,
+        -10,   0,   5,   5,   5,   5,   0, -10,
+        -10,   0,   0,   0,   0,   0,   0, -10,
+        -15, -10, -10, -10, -10, -10, -10, -15,
+    ],
+    // rook
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+         10,  10,  10,  10,  10,  10,  10,  10,
+          0,   0,   0,   0,   0,   0,   0,   0,
+          0,   0,   0,   0,   0,   0,   0,   0,
+          0,   0,   0,   0,   0,   0,   0,   0,
+          0,   0,   0,   0,   0,   0,   0,   0,
+          0,   0,   0,   0,   0,   0,   0,   0,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+    // queen
+    [
+        -20, -10, -10,  -5,  -5, -10, -10, -20,
+        -10,   0,   5,   5,   5,   5,   0, -10,
+        -10,   5,  10,  10,  10,  10,   5, -10,
+         -5,   5,  10,  15,  15,  10,   5,  -5,
+         -5,   5,  10,  15,  15,  10,   5,  -5,
+        -10,   5,  10,  10,  10,  10,   5, -10,
+        -10,   0,   5,   5,   5,   5,   0, -10,
+        -20, -10, -10,  -5,  -5, -10, -10, -20,
+    ],
+    // king, centralised to support pawns once the attackers are gone
+    [
+        -50, -40, -30, -20, -20, -30, -40, -50,
+        -30, -20, -10,   0,   0, -10, -20, -30,
+        -30, -10,  20,  30,  30,  20, -10, -30,
+        -30, -10,  30,  40,  40,  30, -10, -30,
+        -30, -10,  30,  40,  40,  30, -10, -30,
+        -30, -10,  20,  30,  30,  20, -10, -30,
+        -30, -30,   0,   0,   0,   0, -30, -30,
+        -50, -30, -30, -30, -30, -30, -30, -50,
+    ],
+];
+
+// position value of a piece on square i
+#[inline(always)]
+fn get_pos_score(params: &EvalParams, i: usize, piece: &Piece) -> Score {
+    // black uses the tables flipped to its own side of the board
+    let side_adjusted_idx = match piece.pcolour {
+        PieceColour::White => i,
+        PieceColour::Black => i ^ 56,
+    };
+    params.piece_squares[piece.ptype as usize][side_adjusted_idx]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::BoardState;
+    use crate::fen::FEN;
+
+    fn evaluate_fen(fen: &str) -> i32 {
+        let bs = BoardState::from(fen.parse::<FEN>().unwrap());
+        ClassicalEvaluator::default().evaluate(bs.get_pos64(), bs.side_to_move)
+    }
+
+    #[test]
+    fn test_game_phase() {
+        let bs = BoardState::new_starting();
+        assert_eq!(game_phase(bs.get_pos64()), MAX_PHASE);
+        let bs = BoardState::from(
+            "4k3/pppp4/8/8/8/8/PPPP4/4K3 w - - 0 1"
+                .parse::<FEN>()
+                .unwrap(),
+        );
+        assert_eq!(game_phase(bs.get_pos64()), 0);
+        let bs = BoardState::from("3qk3/8/8/8/8/8/8/1N2K2R w - - 0 1".parse::<FEN>().unwrap());
+        assert_eq!(game_phase(bs.get_pos64()), 7);
+    }
+
+    #[test]
+    fn test_taper() {
+        let score = Score::new(100, -50);
+        assert_eq!(score.taper(MAX_PHASE), 100);
+        assert_eq!(score.taper(0), -50);
+        assert_eq!(score.taper(MAX_PHASE / 2), 25);
+    }
+
+    #[test]
+    fn test_king_placement_tapers() {
+        assert_eq!(
+            evaluate_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            0
+        );
+        // the queens alone are only a third of the way to the middlegame, so the king already belongs in the
+        // centre as it does with no pieces left
+        let sheltered = "3qk3/8/8/8/8/8/8/3Q2K1 w - - 0 1";
+        let centralised = "3qk3/8/8/8/4K3/8/8/3Q4 w - - 0 1";
+        assert!(evaluate_fen(sheltered) < evaluate_fen(centralised));
+        let sheltered = "4k3/8/8/8/8/8/8/6K1 w - - 0 1";
+        let centralised = "4k3/8/8/8/4K3/8/8/8 w - - 0 1";
+        assert!(evaluate_fen(sheltered) < evaluate_fen(centralised));
+        // with all the pieces on, the king belongs on g1
+        let sheltered = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQ1BKR w kq - 0 1";
+        let centralised = "rnbqkbnr/pppppppp/8/8/4K3/8/PPPPPPPP/RNBQ1B1R w kq - 0 1";
+        assert!(evaluate_fen(sheltered) > evaluate_fen(centralised));
+        // black's eval is the same from its own side
+        assert_eq!(
+            evaluate_fen("4k3/8/8/8/4K3/8/8/8 w - - 0 1"),
+            evaluate_fen("8/8/8/4k3/8/8/8/4K3 b - - 0 1")
+        );
+    }
+
+    #[test]
+    fn test_pawn_table() {
+        let bs = BoardState::from(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"
+                .parse::<FEN>()
+                .unwrap(),
+        );
+        let eval =
+            evaluate_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let mut evaluator = ClassicalEvaluator::default();
+        evaluator.reset(bs.get_pos64());
+        assert!(evaluator.pawn_table.is_empty());
+        assert_eq!(evaluator.evaluate(bs.get_pos64(), bs.side_to_move), eval);
+        assert_eq!(evaluator.pawn_table.len(), 1);
+        // the second eval uses the cached pawn structure
+        assert_eq!(evaluator.evaluate(bs.get_pos64(), bs.side_to_move), eval);
+        assert_eq!(evaluator.pawn_table.len(), 1);
+    }
+
+    #[test]
+    fn test_pawn_structure() {
+        // the same material with connected pawns against doubled isolated pawns
+        let connected = "4k3/8/8/8/8/8/3PP3/4K3 w - - 0 1";
+        let doubled = "4k3/8/8/8/8/4P3/4P3/4K3 w - - 0 1";
+        assert!(evaluate_fen(connected) > evaluate_fen(doubled));
+    }
+
+    #[test]
+    fn test_eval_trace() {
+        let bs = BoardState::new_starting();
+        let trace = eval_trace(&bs);
+        assert_eq!(trace.phase, MAX_PHASE);
+        assert_eq!(trace.total(), Score::ZERO);
+        assert_eq!(
+            trace.score(EvalTerm::Material, PieceColour::White),
+            MATERIAL_VALUES[0] * 8
+                + MATERIAL_VALUES[1] * 2
+                + MATERIAL_VALUES[2] * 2
+                + MATERIAL_VALUES[3] * 2
+                + MATERIAL_VALUES[4]
+        );
+        // the trace adds up to the eval of either side
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 0 1",
+        ] {
+            let bs = BoardState::from(fen.parse::<FEN>().unwrap());
+            let trace = eval_trace(&bs);
+            let eval = evaluate_fen(fen);
+            if bs.side_to_move == PieceColour::White {
+                assert_eq!(trace.eval(), eval);
+            } else {
+                assert_eq!(trace.eval(), -eval);
+            }
+            assert!(trace.to_string().ends_with(&format!(
+                "Eval: {:+.2} (white side)",
+                trace.eval() as f64 / 100.0
+            )));
+        }
+        // the trace uses the params of the evaluator it is given, not the defaults
+        let mut params = EvalParams::default();
+        params.material[PieceType::Queen as usize] += Score::new(100, 100);
+        let mut tuned = ClassicalEvaluator::with_params(Arc::new(params));
+        let bs = BoardState::from("4k3/8/8/8/8/8/8/3QK3 w - - 0 1".parse::<FEN>().unwrap());
+        let trace = eval_trace_with(&bs, &tuned);
+        assert_eq!(
+            trace.eval(),
+            tuned.evaluate(bs.get_pos64(), bs.side_to_move)
+        );
+        assert_eq!(trace.eval(), eval_trace(&bs).eval() + 100);
+    }
+}
@@ -0,0 +1,273 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use super::{
+    iterative_deepening, Evaluator, SearchHistory, SearchInfo, SearchLimits, SearchOptions,
+};
+use crate::board::BoardState;
+use crate::transposition::TranspositionTable;
+
+static NEXT_SEARCH_ID: AtomicU64 = AtomicU64::new(0);
+
+// where a handle dropped without being joined puts the transposition table and search history of its search
+pub(crate) type ReturnedTables = Arc<Mutex<Option<(TranspositionTable, SearchHistory)>>>;
+
+// iterative deepening search running on a worker thread. the transposition table and search history are moved
+// into the worker and given back by join(), so they can be reused by the next search.
+// dropping the handle stops the search and waits for the worker to finish
+pub struct SearchHandle {
+    stop: Arc<AtomicBool>,
+    progress: Receiver<SearchInfo>,
+    worker: Option<JoinHandle<(SearchInfo, TranspositionTable, SearchHistory)>>, // None once joined
+    returned_tables: Option<ReturnedTables>,
+    root_hash: u64,
+    id: u64,
+}
+
+impl SearchHandle {
+    pub fn spawn<E: Evaluator + 'static>(
+        bs: BoardState,
+        limits: SearchLimits,
+        options: SearchOptions<E>,
+        tt: TranspositionTable,
+        mut history: SearchHistory,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, progress) = mpsc::channel();
+        let root_hash = bs.board_hash;
+        let worker_stop = stop.clone();
+        let worker = thread::spawn(move || {
+            let info = iterative_deepening(
+                &bs,
+                &limits,
+                &options,
+                &tt,
+                &mut history,
+                Some(&worker_stop),
+                |info| {
+                    // receiver may have been dropped, the search still runs to completion
+                    let _ = sender.send(info.clone());
+                },
+            );
+            (info, tt, history)
+        });
+        log::info!("Search started on worker thread with limits: {:?}", limits);
+        SearchHandle {
+            stop,
+            progress,
+            worker: Some(worker),
+            returned_tables: None,
+            root_hash,
+            id: NEXT_SEARCH_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    // ask the search to stop, it will finish shortly after with the result of the last completed iteration
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    // shared stop flag, so the search can be stopped from another thread without access to the handle
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.worker
+            .as_ref()
+            .is_none_or(|worker| worker.is_finished())
+    }
+
+    // unique id of this search
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    // board hash of the position being searched
+    pub fn root_hash(&self) -> u64 {
+        self.root_hash
+    }
+
+    // blocks until the next iteration completes, returns None once the search has finished
+    pub fn recv_progress(&self) -> Option<SearchInfo> {
+        self.progress.recv().ok()
+    }
+
+    // non blocking version of recv_progress
+    pub fn try_recv_progress(&self) -> Option<SearchInfo> {
+        self.progress.try_recv().ok()
+    }
+
+    // waits for the search to finish, returning the final result, the transposition table and the search history
+    pub fn join(mut self) -> (SearchInfo, TranspositionTable, SearchHistory) {
+        let worker = self.worker.take().expect("search handle joined twice");
+        match worker.join() {
+            Ok(result) => result,
+            // the worker only panics on an engine bug, there is nothing to recover
+            Err(e) => std::panic::resume_unwind(e),
+        }
+    }
+
+    pub fn stop_and_join(self) -> (SearchInfo, TranspositionTable, SearchHistory) {
+        self.stop();
+        self.join()
+    }
+
+    // if the handle is dropped without being joined the tables are put in returned_tables
+    pub(crate) fn return_tables_to(&mut self, returned_tables: ReturnedTables) {
+        self.returned_tables = Some(returned_tables);
+    }
+}
+
+impl Drop for SearchHandle {
+    fn drop(&mut self) {
+        let Some(worker) = self.worker.take() else {
+            return;
+        };
+        self.stop();
+        match worker.join() {
+            Ok((_, tt, history)) => {
+                if let Some(returned_tables) = &self.returned_tables {
+                    if let Ok(mut tables) = returned_tables.lock() {
+                        *tables = Some((tt, history));
+                    }
+                }
+            }
+            // panicking again while dropping could abort, the tables are lost
+            Err(_) => log::error!("Search thread panicked, dropped without being joined"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::{BookMoveSelection, PolyglotBook};
+
+    #[test]
+    fn test_search_handle_stop() {
+        let bs = BoardState::new_starting();
+        let handle = SearchHandle::spawn(
+            bs.clone(),
+            SearchLimits::default(),
+            SearchOptions::default(),
+            TranspositionTable::with_size(1),
+            SearchHistory::new(),
+        );
+        let first = handle.recv_progress().unwrap();
+        assert_eq!(first.depth, 1);
+        let (info, ..) = handle.stop_and_join();
+        assert!(bs.get_legal_moves().unwrap().contains(&info.best_move));
+    }
+
+    #[test]
+    fn test_search_handle_drop() {
+        let returned_tables = ReturnedTables::default();
+        let mut handle = SearchHandle::spawn(
+            BoardState::new_starting(),
+            SearchLimits::default(),
+            SearchOptions::default(),
+            TranspositionTable::with_size(1),
+            SearchHistory::new(),
+        );
+        handle.return_tables_to(returned_tables.clone());
+        // nothing is stored in the table before depth 2, the root and the quiescence search don't use it
+        while handle.recv_progress().unwrap().depth < 2 {}
+        // would block forever if dropping did not stop the search
+        drop(handle);
+        let (tt, _) = returned_tables.lock().unwrap().take().unwrap();
+        assert!(!tt.is_empty());
+    }
+
+    #[test]
+    fn test_search_handle_progress() {
+        let bs = BoardState::new_starting();
+        let handle = SearchHandle::spawn(
+            bs.clone(),
+            SearchLimits::depth(3),
+            SearchOptions::default(),
+            TranspositionTable::with_size(1),
+            SearchHistory::new(),
+        );
+        let depths: Vec<u8> = std::iter::from_fn(|| handle.recv_progress())
+            .map(|i| i.depth)
+            .collect();
+        assert_eq!(depths, vec![1, 2, 3]);
+        let (info, ..) = handle.join();
+        assert_eq!(info.depth, 3);
+        assert_eq!(info.pv.len(), 3);
+        assert_eq!(info.pv[0], info.best_move);
+        assert_eq!(info.pv_notation(&bs).len(), 3);
+    }
+
+    #[test]
+    fn test_search_handle_multipv() {
+        let bs = BoardState::new_starting();
+        let handle = SearchHandle::spawn(
+            bs.clone(),
+            SearchLimits::depth(2),
+            SearchOptions::multipv(3),
+            TranspositionTable::with_size(1),
+            SearchHistory::new(),
+        );
+        let (info, ..) = handle.join();
+        assert_eq!(info.lines.len(), 3);
+        assert_eq!(info.lines[0].mv, info.best_move);
+        assert!(info.lines.windows(2).all(|w| w[0].eval >= w[1].eval));
+        assert_ne!(info.lines[0].mv, info.lines[1].mv);
+        assert_ne!(info.lines[1].mv, info.lines[2].mv);
+
+        // multipv 0 from options built by hand is searched as a single line
+        let handle = SearchHandle::spawn(
+            bs.clone(),
+            SearchLimits::depth(2),
+            SearchOptions {
+                multipv: 0,
+                ..SearchOptions::default()
+            },
+            TranspositionTable::with_size(1),
+            SearchHistory::new(),
+        );
+        let (info, ..) = handle.join();
+        assert_eq!(info.lines.len(), 1);
+        assert!(bs.get_legal_moves().unwrap().contains(&info.best_move));
+    }
+
+    #[test]
+    fn test_search_handle_lazy_smp() {
+        let bs = BoardState::new_starting();
+        let handle = SearchHandle::spawn(
+            bs.clone(),
+            SearchLimits::depth(4),
+            SearchOptions::threads(3),
+            TranspositionTable::with_size(1),
+            SearchHistory::new(),
+        );
+        let (info, tt, _) = handle.join();
+        assert_eq!(info.depth, 4);
+        assert!(bs.get_legal_moves().unwrap().contains(&info.best_move));
+        assert!(!tt.is_empty());
+    }
+
+    #[test]
+    fn test_search_handle_book_move() {
+        let bs = BoardState::new_starting();
+        // single book entry for the starting position: e2e4 with weight 1
+        let mut entry = bs.position_hash.to_be_bytes().to_vec();
+        entry.extend_from_slice(&[0x03, 0x1c, 0, 1, 0, 0, 0, 0]);
+        let book = PolyglotBook::from_bytes(&entry).unwrap();
+        let handle = SearchHandle::spawn(
+            bs.clone(),
+            SearchLimits::depth(4),
+            SearchOptions::book(std::sync::Arc::new(book), BookMoveSelection::Best),
+            TranspositionTable::with_size(1),
+            SearchHistory::new(),
+        );
+        let (info, ..) = handle.join();
+        assert!(info.from_book);
+        assert_eq!(info.best_move.to_uci_string(), "e2e4");
+        assert_eq!(info.nodes, 0);
+    }
+}
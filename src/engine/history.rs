@@ -0,0 +1,194 @@
+// move ordering heuristics learned from beta cutoffs: killer moves, butterfly history, countermoves and
+// continuation history. kept across the iterations of a search and aged between searches
+use crate::movegen::{Move, Piece, ShortMove, NULL_SHORT_MOVE};
+
+// history scores stay between -MAX_HISTORY and MAX_HISTORY, a bonus is smaller the closer a score is to the limit
+const MAX_HISTORY: i32 = 16384;
+const MAX_HISTORY_BONUS: i32 = 1600;
+// ply is a u8, including plies added by check extensions
+const KILLER_PLIES: usize = u8::MAX as usize + 1;
+
+#[derive(Debug, Clone)]
+pub struct SearchHistory {
+    // two quiet moves per ply that caused a beta cutoff, the most recent first
+    killers: Vec<[ShortMove; 2]>,
+    // quiet move scores by side to move, from and to square
+    butterfly: Vec<i16>,
+    // quiet move that refuted the previous move, by the previous move's piece and to square
+    countermoves: Vec<ShortMove>,
+    // quiet move scores after the previous move, by its piece and to square then the move's piece and to square
+    continuation: Vec<i16>,
+}
+
+impl Default for SearchHistory {
+    fn default() -> Self {
+        SearchHistory {
+            killers: vec![[NULL_SHORT_MOVE; 2]; KILLER_PLIES],
+            butterfly: vec![0; 2 * 64 * 64],
+            countermoves: vec![NULL_SHORT_MOVE; 12 * 64],
+            continuation: vec![0; 12 * 64 * 12 * 64],
+        }
+    }
+}
+
+impl SearchHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // forget everything, e.g. for a new game
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    // called before each search. killers are relative to the root so they are cleared, history scores are
+    // halved so the new position's cutoffs soon outweigh them
+    pub fn age(&mut self) {
+        self.killers.fill([NULL_SHORT_MOVE; 2]);
+        for score in self
+            .butterfly
+            .iter_mut()
+            .chain(self.continuation.iter_mut())
+        {
+            *score /= 2;
+        }
+    }
+
+    #[inline(always)]
+    pub(super) fn killers(&self, ply: u8) -> [ShortMove; 2] {
+        self.killers[ply as usize]
+    }
+
+    #[inline(always)]
+    pub(super) fn countermove(&self, prev: Option<&Move>) -> ShortMove {
+        match prev.and_then(piece_square_index) {
+            Some(prev_idx) => self.countermoves[prev_idx],
+            None => NULL_SHORT_MOVE,
+        }
+    }
+
+    // butterfly and continuation history of a quiet move, prev is the move before it
+    #[inline(always)]
+    pub(super) fn quiet_score(&self, mv: &Move, prev: Option<&Move>) -> i32 {
+        let mut score = self.butterfly[butterfly_index(mv)] as i32;
+        if let Some(idx) = continuation_index(mv, prev) {
+            score += self.continuation[idx] as i32;
+        }
+        score
+    }
+
+    // quiet move mv caused a beta cutoff at ply with depth left, the quiet moves tried before it get a malus
+    pub(super) fn update_cutoff(
+        &mut self,
+        ply: u8,
+        depth: u8,
+        mv: &Move,
+        tried: &[Move],
+        prev: Option<&Move>,
+    ) {
+        let killers = &mut self.killers[ply as usize];
+        if *mv != killers[0] {
+            killers[1] = killers[0];
+            killers[0] = mv.short_move();
+        }
+        if let Some(prev_idx) = prev.and_then(piece_square_index) {
+            self.countermoves[prev_idx] = mv.short_move();
+        }
+
+        let bonus = (16 * depth as i32 * depth as i32).min(MAX_HISTORY_BONUS);
+        self.update_score(mv, prev, bonus);
+        for tried_mv in tried {
+            self.update_score(tried_mv, prev, -bonus);
+        }
+    }
+
+    fn update_score(&mut self, mv: &Move, prev: Option<&Move>, bonus: i32) {
+        apply_bonus(&mut self.butterfly[butterfly_index(mv)], bonus);
+        if let Some(idx) = continuation_index(mv, prev) {
+            apply_bonus(&mut self.continuation[idx], bonus);
+        }
+    }
+}
+
+// moves the score towards the bonus, by less the closer it already is to MAX_HISTORY
+#[inline(always)]
+fn apply_bonus(score: &mut i16, bonus: i32) {
+    let current = *score as i32;
+    *score = (current + bonus - current * bonus.abs() / MAX_HISTORY) as i16;
+}
+
+#[inline(always)]
+fn butterfly_index(mv: &Move) -> usize {
+    (mv.piece.pcolour as usize * 64 + mv.from) * 64 + mv.to
+}
+
+#[inline(always)]
+fn piece_index(piece: Piece) -> usize {
+    piece.pcolour as usize * 6 + piece.ptype as usize
+}
+
+// None for a null move
+#[inline(always)]
+fn piece_square_index(mv: &Move) -> Option<usize> {
+    (mv.to < 64).then(|| piece_index(mv.piece) * 64 + mv.to)
+}
+
+#[inline(always)]
+fn continuation_index(mv: &Move, prev: Option<&Move>) -> Option<usize> {
+    let prev_idx = prev.and_then(piece_square_index)?;
+    Some((prev_idx * 12 + piece_index(mv.piece)) * 64 + mv.to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::BoardState;
+
+    fn find_move(bs: &BoardState, uci: &str) -> Move {
+        *bs.get_legal_moves()
+            .unwrap()
+            .iter()
+            .find(|mv| mv.to_uci_string() == uci)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_history_update() {
+        let bs = BoardState::new_starting();
+        let (e4, d4, nf3) = (
+            find_move(&bs, "e2e4"),
+            find_move(&bs, "d2d4"),
+            find_move(&bs, "g1f3"),
+        );
+        let prev = find_move(&bs, "b1c3");
+        let mut history = SearchHistory::new();
+        history.update_cutoff(3, 4, &e4, &[d4], Some(&prev));
+        history.update_cutoff(3, 4, &nf3, &[], Some(&prev));
+        assert_eq!(history.killers(3), [nf3.short_move(), e4.short_move()]);
+        assert_eq!(history.killers(4), [NULL_SHORT_MOVE; 2]);
+        assert_eq!(history.countermove(Some(&prev)), nf3.short_move());
+        // butterfly and continuation history
+        assert_eq!(history.quiet_score(&e4, Some(&prev)), 2 * 256);
+        assert_eq!(history.quiet_score(&e4, None), 256);
+        assert_eq!(history.quiet_score(&d4, Some(&prev)), -2 * 256);
+
+        history.age();
+        assert_eq!(history.killers(3), [NULL_SHORT_MOVE; 2]);
+        assert_eq!(history.quiet_score(&e4, None), 128);
+        assert_eq!(history.countermove(Some(&prev)), nf3.short_move());
+        history.clear();
+        assert_eq!(history.quiet_score(&e4, Some(&prev)), 0);
+    }
+
+    #[test]
+    fn test_history_bounded() {
+        let bs = BoardState::new_starting();
+        let e4 = find_move(&bs, "e2e4");
+        let mut history = SearchHistory::new();
+        for _ in 0..1000 {
+            history.update_cutoff(0, 60, &e4, &[], None);
+        }
+        let score = history.quiet_score(&e4, None);
+        assert!(score > MAX_HISTORY - MAX_HISTORY_BONUS && score <= MAX_HISTORY);
+    }
+}
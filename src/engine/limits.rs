@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use crate::movegen::PieceColour;
+
+pub const MAX_SEARCH_DEPTH: u8 = 64;
+// moves assumed to be left in the game when the clock has no moves to go set
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+// time kept back for communication and move overhead, in ms
+const MOVE_OVERHEAD_MS: u64 = 20;
+
+// limits for an iterative deepening search, any combination can be set and the search stops at the first one reached
+// with no limits set the search only stops at MAX_SEARCH_DEPTH or when it is stopped externally
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub depth: Option<u8>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+    pub wtime: Option<Duration>,
+    pub btime: Option<Duration>,
+    pub winc: Option<Duration>,
+    pub binc: Option<Duration>,
+    pub movestogo: Option<u32>,
+}
+
+impl SearchLimits {
+    pub fn depth(depth: u8) -> Self {
+        SearchLimits {
+            depth: Some(depth),
+            ..Default::default()
+        }
+    }
+
+    pub fn movetime(movetime: Duration) -> Self {
+        SearchLimits {
+            movetime: Some(movetime),
+            ..Default::default()
+        }
+    }
+
+    pub fn nodes(nodes: u64) -> Self {
+        SearchLimits {
+            nodes: Some(nodes),
+            ..Default::default()
+        }
+    }
+
+    pub fn max_depth(&self) -> u8 {
+        self.depth
+            .map_or(MAX_SEARCH_DEPTH, |d| d.clamp(1, MAX_SEARCH_DEPTH))
+    }
+
+    // (soft, hard) time limits for the side to move. the soft limit is checked between iterations,
+    // the hard limit aborts the current iteration
+    pub(crate) fn time_limits(&self, side: PieceColour) -> Option<(Duration, Duration)> {
+        if let Some(movetime) = self.movetime {
+            let hard = movetime.saturating_sub(Duration::from_millis(MOVE_OVERHEAD_MS));
+            return Some((hard, hard));
+        }
+        let (time, inc) = match side {
+            PieceColour::White => (self.wtime?, self.winc.unwrap_or_default()),
+            PieceColour::Black => (self.btime?, self.binc.unwrap_or_default()),
+        };
+        let time = time.saturating_sub(Duration::from_millis(MOVE_OVERHEAD_MS));
+        let moves_to_go = self.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+        // never plan to use more than half of the remaining time on one move
+        let soft = (time / moves_to_go + inc * 3 / 4).min(time / 2);
+        let hard = (soft * 3).min(time / 2);
+        Some((soft, hard))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_movetime_limits() {
+        let limits = SearchLimits::movetime(Duration::from_millis(1000));
+        let (soft, hard) = limits.time_limits(PieceColour::White).unwrap();
+        assert_eq!(soft, hard);
+        assert!(hard < Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_clock_limits() {
+        let limits = SearchLimits {
+            wtime: Some(Duration::from_secs(60)),
+            btime: Some(Duration::from_millis(100)),
+            winc: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let (soft, hard) = limits.time_limits(PieceColour::White).unwrap();
+        assert!(soft <= hard);
+        assert!(hard <= Duration::from_secs(30));
+        let (_, hard) = limits.time_limits(PieceColour::Black).unwrap();
+        assert!(hard <= Duration::from_millis(50));
+        assert_eq!(SearchLimits::depth(3).time_limits(PieceColour::White), None);
+    }
+}
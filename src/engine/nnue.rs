@@ -0,0 +1,353 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::eval::Evaluator;
+use crate::errors::NnueError;
+use crate::log_and_return_error;
+use crate::movegen::*;
+
+// one input for each piece type and colour on each square
+const INPUTS: usize = 768;
+// quantisation of the feature weights and the output weights
+const QA: i64 = 255;
+const QB: i64 = 64;
+// network output to centipawns
+const SCALE: i64 = 400;
+// trainers can pad the end of the file to a multiple of this many bytes
+const MAX_PADDING: usize = 64;
+
+// quantised (768->N)x2->1 network with a squared clipped relu activation, in the layout written by the bullet trainer.
+// little endian i16 values: feature weights [768][N], feature biases [N], output weights [2][N] with the side to move first,
+// then the output bias. N is worked out from the file size
+pub struct NnueNetwork {
+    hidden: usize,
+    feature_weights: Vec<i16>,
+    feature_bias: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i16,
+}
+
+impl fmt::Debug for NnueNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NnueNetwork")
+            .field("hidden", &self.hidden)
+            .finish()
+    }
+}
+
+impl NnueNetwork {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, NnueError> {
+        let path = path.as_ref();
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                let err = NnueError::FileError(format!("{}: {}", path.display(), e));
+                log_and_return_error!(err)
+            }
+        };
+        let network = Self::from_bytes(&bytes)?;
+        log::info!(
+            "NNUE network loaded from {} with {} hidden neurons",
+            path.display(),
+            network.hidden
+        );
+        Ok(network)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, NnueError> {
+        let values: Vec<i16> = bytes
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        // each hidden neuron has a weight per input, a bias and two output weights
+        let hidden = values.len().saturating_sub(1) / (INPUTS + 3);
+        let used = hidden * (INPUTS + 3) + 1;
+        if hidden == 0 || bytes.len() - used * 2 >= MAX_PADDING {
+            let err = NnueError::InvalidFormat(format!(
+                "Network size of {} bytes does not match a (768->N)x2->1 network",
+                bytes.len()
+            ));
+            log_and_return_error!(err)
+        }
+        let (feature_weights, rest) = values.split_at(INPUTS * hidden);
+        let (feature_bias, rest) = rest.split_at(hidden);
+        let (output_weights, rest) = rest.split_at(2 * hidden);
+        Ok(NnueNetwork {
+            hidden,
+            feature_weights: feature_weights.to_vec(),
+            feature_bias: feature_bias.to_vec(),
+            output_weights: output_weights.to_vec(),
+            output_bias: rest[0],
+        })
+    }
+
+    pub fn hidden_size(&self) -> usize {
+        self.hidden
+    }
+
+    #[inline(always)]
+    fn weights(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * self.hidden..(feature + 1) * self.hidden]
+    }
+
+    // accumulators of the side to move and the other side to centipawns
+    fn output(&self, us: &[i16], them: &[i16]) -> i32 {
+        let (us_weights, them_weights) = self.output_weights.split_at(self.hidden);
+        let screlu = |values: &[i16], weights: &[i16]| -> i64 {
+            values
+                .iter()
+                .zip(weights)
+                .map(|(v, w)| {
+                    let v = (*v as i64).clamp(0, QA);
+                    v * v * *w as i64
+                })
+                .sum()
+        };
+        let sum = screlu(us, us_weights) + screlu(them, them_weights);
+        // squaring the activation also squares QA, so one is taken out before adding the bias
+        ((sum / QA + self.output_bias as i64) * SCALE / (QA * QB)) as i32
+    }
+}
+
+// input index of a piece on a square, as seen by one side. each side sees its own pieces first,
+// and the board from its own side with its back rank as rank 1
+#[inline(always)]
+const fn feature_index(perspective: PieceColour, piece: &Piece, i: usize) -> usize {
+    // board index 0 is a8, the network has a1 as 0
+    let (theirs, sq) = match perspective {
+        PieceColour::White => (!matches!(piece.pcolour, PieceColour::White), i ^ 56),
+        PieceColour::Black => (!matches!(piece.pcolour, PieceColour::Black), i),
+    };
+    384 * theirs as usize + 64 * piece.ptype as usize + sq
+}
+
+// evaluates with an NNUE network. the first layer is kept in an accumulator for each side,
+// which is updated with the pieces that moved instead of being recalculated at every node
+#[derive(Clone)]
+pub struct NnueEvaluator {
+    network: Arc<NnueNetwork>,
+    stack: Vec<[Vec<i16>; 2]>, // accumulators for white and black, one entry per ply from the root
+    ply: usize,
+}
+
+impl fmt::Debug for NnueEvaluator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NnueEvaluator")
+            .field("network", &self.network)
+            .field("ply", &self.ply)
+            .finish()
+    }
+}
+
+impl NnueEvaluator {
+    pub fn new(network: Arc<NnueNetwork>) -> Self {
+        let empty = [network.feature_bias.clone(), network.feature_bias.clone()];
+        NnueEvaluator {
+            network,
+            stack: vec![empty],
+            ply: 0,
+        }
+    }
+
+    pub fn network(&self) -> &Arc<NnueNetwork> {
+        &self.network
+    }
+
+    // adds (or removes) a piece to the accumulators of the current ply
+    #[inline(always)]
+    fn update(&mut self, piece: &Piece, i: usize, add: bool) {
+        let accumulators = &mut self.stack[self.ply];
+        for (perspective, accumulator) in [PieceColour::White, PieceColour::Black]
+            .into_iter()
+            .zip(accumulators.iter_mut())
+        {
+            let weights = self.network.weights(feature_index(perspective, piece, i));
+            for (value, weight) in accumulator.iter_mut().zip(weights) {
+                if add {
+                    *value = value.wrapping_add(*weight);
+                } else {
+                    *value = value.wrapping_sub(*weight);
+                }
+            }
+        }
+    }
+}
+
+impl Evaluator for NnueEvaluator {
+    fn reset(&mut self, board: &[Square; 64]) {
+        self.ply = 0;
+        for accumulator in self.stack[0].iter_mut() {
+            accumulator.copy_from_slice(&self.network.feature_bias);
+        }
+        for (i, s) in board.iter().enumerate() {
+            if let Square::Piece(piece) = s {
+                self.update(piece, i, true);
+            }
+        }
+    }
+
+    fn make_move(&mut self, board: &[Square; 64], mv: &Move) {
+        // copy the accumulators to the next ply, reusing the allocation from an earlier move if there is one
+        if self.ply + 1 == self.stack.len() {
+            self.stack.push(self.stack[self.ply].clone());
+        } else {
+            let (below, above) = self.stack.split_at_mut(self.ply + 1);
+            for (next, current) in above[0].iter_mut().zip(below[self.ply].iter()) {
+                next.copy_from_slice(current);
+            }
+        }
+        self.ply += 1;
+
+        let piece = mv.piece;
+        match mv.move_type {
+            MoveType::EnPassant(ep_capture) => {
+                if let Square::Piece(captured) = board[ep_capture] {
+                    self.update(&captured, ep_capture, false);
+                }
+                self.update(&piece, mv.from, false);
+                self.update(&piece, mv.to, true);
+            }
+            MoveType::Castle(castle_mv) => {
+                if let Square::Piece(rook) = board[castle_mv.rook_from] {
+                    self.update(&rook, castle_mv.rook_from, false);
+                    self.update(&rook, castle_mv.rook_to, true);
+                }
+                self.update(&piece, mv.from, false);
+                self.update(&piece, mv.to, true);
+            }
+            _ => {
+                if let Square::Piece(captured) = board[mv.to] {
+                    self.update(&captured, mv.to, false);
+                }
+                let to_piece = match mv.move_type {
+                    MoveType::Promotion(ptype, _) => Piece {
+                        ptype,
+                        pcolour: piece.pcolour,
+                    },
+                    _ => piece,
+                };
+                self.update(&piece, mv.from, false);
+                self.update(&to_piece, mv.to, true);
+            }
+        }
+    }
+
+    fn unmake_move(&mut self) {
+        self.ply -= 1;
+    }
+
+    fn evaluate(&mut self, _board: &[Square; 64], side_to_move: PieceColour) -> i32 {
+        let [white, black] = &self.stack[self.ply];
+        match side_to_move {
+            PieceColour::White => self.network.output(white, black),
+            PieceColour::Black => self.network.output(black, white),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::BoardState;
+    use crate::fen::FEN;
+
+    // network with pseudo random weights, small enough that the accumulators can't overflow
+    fn test_network(hidden: usize) -> Vec<u8> {
+        let mut seed: u32 = 0x9E37_79B9;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            (seed % 61) as i16 - 30
+        };
+        let len = hidden * (INPUTS + 3) + 1;
+        (0..len).flat_map(|_| next().to_le_bytes()).collect()
+    }
+
+    fn check_incremental(evaluator: &mut NnueEvaluator, bs: &BoardState, depth: u8) {
+        let mut refreshed = evaluator.clone();
+        refreshed.reset(&bs.position().pos64);
+        let side = bs.side_to_move;
+        assert_eq!(
+            evaluator.evaluate(&bs.position().pos64, side),
+            refreshed.evaluate(&bs.position().pos64, side),
+            "{}",
+            FEN::from(bs)
+        );
+        if depth == 0 {
+            return;
+        }
+        for mv in bs.get_legal_moves().unwrap() {
+            evaluator.make_move(&bs.position().pos64, mv);
+            check_incremental(evaluator, &bs.next_state(mv).unwrap(), depth - 1);
+            evaluator.unmake_move();
+        }
+    }
+
+    #[test]
+    fn test_incremental_matches_refresh() {
+        let network = Arc::new(NnueNetwork::from_bytes(&test_network(8)).unwrap());
+        assert_eq!(network.hidden_size(), 8);
+        // castling, en passant and promotions, with captures of promoted pieces
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ] {
+            let bs = BoardState::from(fen.parse::<FEN>().unwrap());
+            let mut evaluator = NnueEvaluator::new(network.clone());
+            evaluator.reset(&bs.position().pos64);
+            check_incremental(&mut evaluator, &bs, 2);
+        }
+    }
+
+    #[test]
+    fn test_colour_symmetry() {
+        let network = Arc::new(NnueNetwork::from_bytes(&test_network(4)).unwrap());
+        let mut evaluator = NnueEvaluator::new(network);
+        // the same position with the colours swapped has the same eval for the side to move
+        let white = BoardState::from("4k3/8/8/8/8/8/3PP3/R3K3 w - - 0 1".parse::<FEN>().unwrap());
+        let black = BoardState::from("r3k3/3pp3/8/8/8/8/8/4K3 b - - 0 1".parse::<FEN>().unwrap());
+        evaluator.reset(&white.position().pos64);
+        let white_eval = evaluator.evaluate(&white.position().pos64, PieceColour::White);
+        evaluator.reset(&black.position().pos64);
+        let black_eval = evaluator.evaluate(&black.position().pos64, PieceColour::Black);
+        assert_eq!(white_eval, black_eval);
+    }
+
+    #[test]
+    fn test_search_with_network() {
+        use crate::engine::{iterative_deepening, SearchHistory, SearchLimits, SearchOptions};
+        use crate::transposition::TranspositionTable;
+
+        let network = Arc::new(NnueNetwork::from_bytes(&test_network(8)).unwrap());
+        let options = SearchOptions::threads(2).with_evaluator(NnueEvaluator::new(network));
+        let bs = BoardState::new_starting();
+        let tt = TranspositionTable::with_size(1);
+        let info = iterative_deepening(
+            &bs,
+            &SearchLimits::depth(3),
+            &options,
+            &tt,
+            &mut SearchHistory::new(),
+            None,
+            |_| {},
+        );
+        assert_eq!(info.depth, 3);
+        assert!(bs.get_legal_moves().unwrap().contains(&info.best_move));
+    }
+
+    #[test]
+    fn test_network_size() {
+        let mut bytes = test_network(2);
+        assert!(NnueNetwork::from_bytes(&bytes).is_ok());
+        // padded to 64 bytes
+        bytes.resize(bytes.len().next_multiple_of(64), 0);
+        assert_eq!(NnueNetwork::from_bytes(&bytes).unwrap().hidden_size(), 2);
+        bytes.extend([0; 64]);
+        assert!(NnueNetwork::from_bytes(&bytes).is_err());
+        assert!(NnueNetwork::from_bytes(&[]).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use std::sync::Arc;
+
+use super::eval::{ClassicalEvaluator, Evaluator};
+use crate::book::{BookMoveSelection, PolyglotBook};
+use crate::tablebase::Tablebase;
+
+pub const MAX_MULTIPV: u8 = 64;
+pub const MAX_THREADS: usize = 256;
+
+// options that change how the search behaves, SearchLimits decide when it stops
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchOptions<E = ClassicalEvaluator> {
+    // number of root moves to search with exact evals and their own pv, ranked best first
+    pub multipv: u8,
+    // lazy smp search threads, including the main search thread
+    pub threads: usize,
+    // opening book checked before searching, a book move is played without a search
+    pub book: Option<Arc<PolyglotBook>>,
+    pub book_selection: BookMoveSelection,
+    // endgame tablebase probed at the root and in the search, once few enough pieces are left
+    pub tablebase: Option<Arc<Tablebase>>,
+    // static evaluation, cloned for each search thread
+    pub evaluator: E,
+    // selectivity of the search, each can be turned off on its own to measure it
+    pub features: SearchFeatures,
+}
+
+// pruning, reductions and extensions used by the search, all on by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchFeatures {
+    // a null move searched with a reduced depth failing high prunes the node, not used without pieces
+    // other than pawns where zugzwang is likely
+    pub null_move: bool,
+    // late quiet moves are searched with a reduced depth first, and searched again at full depth if they beat alpha
+    pub late_move_reductions: bool,
+    // principal variation search, moves after the first are searched with a zero window
+    pub pvs: bool,
+    // quiet moves that can't raise the static eval to alpha are skipped near the leaves
+    pub futility: bool,
+    // nodes with a static eval far enough above beta return without searching
+    pub reverse_futility: bool,
+    // positions in check are searched one ply deeper
+    pub check_extensions: bool,
+}
+
+impl Default for SearchFeatures {
+    fn default() -> Self {
+        SearchFeatures {
+            null_move: true,
+            late_move_reductions: true,
+            pvs: true,
+            futility: true,
+            reverse_futility: true,
+            check_extensions: true,
+        }
+    }
+}
+
+impl SearchFeatures {
+    // plain alpha-beta with transposition table cutoffs
+    pub fn none() -> Self {
+        SearchFeatures {
+            null_move: false,
+            late_move_reductions: false,
+            pvs: false,
+            futility: false,
+            reverse_futility: false,
+            check_extensions: false,
+        }
+    }
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            multipv: 1,
+            threads: 1,
+            book: None,
+            book_selection: BookMoveSelection::default(),
+            tablebase: None,
+            evaluator: ClassicalEvaluator::default(),
+            features: SearchFeatures::default(),
+        }
+    }
+}
+
+impl SearchOptions {
+    pub fn multipv(multipv: u8) -> Self {
+        SearchOptions {
+            multipv: multipv.clamp(1, MAX_MULTIPV),
+            ..Default::default()
+        }
+    }
+
+    pub fn threads(threads: usize) -> Self {
+        SearchOptions {
+            threads: threads.clamp(1, MAX_THREADS),
+            ..Default::default()
+        }
+    }
+
+    pub fn book(book: Arc<PolyglotBook>, selection: BookMoveSelection) -> Self {
+        SearchOptions {
+            book: Some(book),
+            book_selection: selection,
+            ..Default::default()
+        }
+    }
+
+    pub fn tablebase(tablebase: Arc<Tablebase>) -> Self {
+        SearchOptions {
+            tablebase: Some(tablebase),
+            ..Default::default()
+        }
+    }
+
+    pub fn features(features: SearchFeatures) -> Self {
+        SearchOptions {
+            features,
+            ..Default::default()
+        }
+    }
+}
+
+impl<E> SearchOptions<E> {
+    // same options with a different evaluator
+    pub fn with_evaluator<F: Evaluator>(self, evaluator: F) -> SearchOptions<F> {
+        SearchOptions {
+            multipv: self.multipv,
+            threads: self.threads,
+            book: self.book,
+            book_selection: self.book_selection,
+            tablebase: self.tablebase,
+            evaluator,
+            features: self.features,
+        }
+    }
+}
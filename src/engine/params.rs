@@ -0,0 +1,281 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::eval::{Score, EG_POS_VALUES, MATERIAL_VALUES, MG_POS_VALUES};
+use super::{pawns, pieces};
+use crate::errors::EvalParamsError;
+use crate::log_and_return_error;
+
+const PIECE_NAMES: [&str; 6] = ["pawn", "knight", "bishop", "rook", "queen", "king"];
+
+// tunable values of the classical evaluation, the defaults are the hand written values next to each term.
+// tables indexed by piece type are indexed by PieceType as usize, piece square tables are from white's side
+// with a8 = index 0, and per rank tables are from the pawn's own side with 0 as its back rank
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalParams {
+    pub material: [Score; 6],
+    pub piece_squares: [[Score; 64]; 6],
+    pub doubled: Score,
+    pub isolated: Score,
+    pub backward: Score,
+    pub island: Score,
+    pub connected: [Score; 8],
+    pub passed_rank: [Score; 8],
+    pub passed_free_path: [Score; 8],
+    // knight, bishop, rook and queen
+    pub mobility: [Score; 4],
+    pub king_attack: [Score; 4],
+    pub bishop_pair: Score,
+    pub rook_open_file: Score,
+    pub rook_semi_open_file: Score,
+    pub knight_outpost: Score,
+    pub pawn_shield: [Score; 8],
+    pub pawn_storm: [Score; 8],
+}
+
+// every parameter group with its name in the file, in file order. $r is & or &mut, with the matching
+// slice flattening and single value methods
+macro_rules! param_groups {
+    ($params:expr, [$($r:tt)+], $flatten:ident, $single:ident) => {
+        [
+            ("material", $($r)+ $params.material[..]),
+            ("piece_squares", $params.piece_squares.$flatten()),
+            ("doubled", core::slice::$single($($r)+ $params.doubled)),
+            ("isolated", core::slice::$single($($r)+ $params.isolated)),
+            ("backward", core::slice::$single($($r)+ $params.backward)),
+            ("island", core::slice::$single($($r)+ $params.island)),
+            ("connected", $($r)+ $params.connected[..]),
+            ("passed_rank", $($r)+ $params.passed_rank[..]),
+            ("passed_free_path", $($r)+ $params.passed_free_path[..]),
+            ("mobility", $($r)+ $params.mobility[..]),
+            ("king_attack", $($r)+ $params.king_attack[..]),
+            ("bishop_pair", core::slice::$single($($r)+ $params.bishop_pair)),
+            ("rook_open_file", core::slice::$single($($r)+ $params.rook_open_file)),
+            ("rook_semi_open_file", core::slice::$single($($r)+ $params.rook_semi_open_file)),
+            ("knight_outpost", core::slice::$single($($r)+ $params.knight_outpost)),
+            ("pawn_shield", $($r)+ $params.pawn_shield[..]),
+            ("pawn_storm", $($r)+ $params.pawn_storm[..]),
+        ]
+    };
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        let mut piece_squares = [[Score::ZERO; 64]; 6];
+        for (ptype, table) in piece_squares.iter_mut().enumerate() {
+            for (i, score) in table.iter_mut().enumerate() {
+                *score = Score::new(MG_POS_VALUES[ptype][i], EG_POS_VALUES[ptype][i]);
+            }
+        }
+        EvalParams {
+            material: MATERIAL_VALUES,
+            piece_squares,
+            doubled: pawns::DOUBLED,
+            isolated: pawns::ISOLATED,
+            backward: pawns::BACKWARD,
+            island: pawns::ISLAND,
+            connected: pawns::CONNECTED,
+            passed_rank: pawns::PASSED_RANK,
+            passed_free_path: pawns::PASSED_FREE_PATH,
+            mobility: pieces::MOBILITY_WEIGHTS,
+            king_attack: pieces::KING_ATTACK_WEIGHTS,
+            bishop_pair: pieces::BISHOP_PAIR,
+            rook_open_file: pieces::ROOK_OPEN_FILE,
+            rook_semi_open_file: pieces::ROOK_SEMI_OPEN_FILE,
+            knight_outpost: pieces::KNIGHT_OUTPOST,
+            pawn_shield: pieces::PAWN_SHIELD,
+            pawn_storm: pieces::PAWN_STORM,
+        }
+    }
+}
+
+impl EvalParams {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, EvalParamsError> {
+        let path = path.as_ref();
+        let s = match fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(e) => {
+                let err = EvalParamsError::FileError(format!("{}: {}", path.display(), e));
+                log_and_return_error!(err)
+            }
+        };
+        let params = s.parse::<Self>()?;
+        log::info!("Evaluation parameters loaded from {}", path.display());
+        Ok(params)
+    }
+
+    // every parameter in file order, used by the tuner
+    pub fn values(&self) -> Vec<Score> {
+        param_groups!(self, [&], as_flattened, from_ref)
+            .iter()
+            .flat_map(|(_, group)| group.iter().copied())
+            .collect()
+    }
+
+    // sets every parameter from a list in the order of values(). panics if the length is different
+    pub fn set_values(&mut self, values: &[Score]) {
+        assert_eq!(values.len(), self.values().len());
+        let mut values = values.iter();
+        for (_, group) in param_groups!(self, [&mut], as_flattened_mut, from_mut) {
+            for score in group.iter_mut() {
+                *score = *values.next().unwrap();
+            }
+        }
+    }
+}
+
+// text format of "name" followed by the middlegame/endgame pairs of that group, e.g. "doubled -10/-25".
+// a # starts a comment, groups that are left out keep their default values
+impl FromStr for EvalParams {
+    type Err = EvalParamsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut params = EvalParams::default();
+        let mut groups = param_groups!(params, [&mut], as_flattened_mut, from_mut);
+        let tokens = s
+            .lines()
+            .map(|line| line.split('#').next().unwrap())
+            .flat_map(|line| line.split_whitespace());
+        // index of the group being read and how many of its values have been set
+        let mut current: Option<(usize, usize)> = None;
+        for token in tokens {
+            if token.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                check_group_len(&groups, current)?;
+                match groups.iter().position(|(name, _)| *name == token) {
+                    Some(idx) => current = Some((idx, 0)),
+                    None => {
+                        let err =
+                            EvalParamsError::InvalidFormat(format!("Unknown parameter: {}", token));
+                        log_and_return_error!(err)
+                    }
+                }
+                continue;
+            }
+            let Some((idx, count)) = current.as_mut() else {
+                let err = EvalParamsError::InvalidFormat(format!(
+                    "Value {} before a parameter name",
+                    token
+                ));
+                log_and_return_error!(err)
+            };
+            let (name, group) = &mut groups[*idx];
+            let Some(score) = group.get_mut(*count) else {
+                let err = EvalParamsError::InvalidFormat(format!(
+                    "Too many values for {}, expected {}",
+                    name,
+                    group.len()
+                ));
+                log_and_return_error!(err)
+            };
+            *score = parse_score(token)?;
+            *count += 1;
+        }
+        check_group_len(&groups, current)?;
+        Ok(params)
+    }
+}
+
+fn check_group_len(
+    groups: &[(&str, &mut [Score])],
+    current: Option<(usize, usize)>,
+) -> Result<(), EvalParamsError> {
+    if let Some((idx, count)) = current {
+        let (name, group) = &groups[idx];
+        if count != group.len() {
+            let err = EvalParamsError::InvalidFormat(format!(
+                "Expected {} values for {}, found {}",
+                group.len(),
+                name,
+                count
+            ));
+            log_and_return_error!(err)
+        }
+    }
+    Ok(())
+}
+
+fn parse_score(token: &str) -> Result<Score, EvalParamsError> {
+    let parsed = token
+        .split_once('/')
+        .and_then(|(mg, eg)| Some(Score::new(mg.parse().ok()?, eg.parse().ok()?)));
+    match parsed {
+        Some(score) => Ok(score),
+        None => {
+            let err = EvalParamsError::InvalidFormat(format!(
+                "Invalid value {}, expected middlegame/endgame",
+                token
+            ));
+            log_and_return_error!(err)
+        }
+    }
+}
+
+impl fmt::Display for EvalParams {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "# chess-oxide evaluation parameters, middlegame/endgame values in centipawns"
+        )?;
+        for (name, group) in param_groups!(self, [&], as_flattened, from_ref) {
+            writeln!(f)?;
+            writeln!(f, "{}", name)?;
+            // piece square tables are written as a board for each piece, rank 8 first
+            let row_len = if name == "piece_squares" {
+                8
+            } else {
+                group.len()
+            };
+            for (i, row) in group.chunks(row_len).enumerate() {
+                if name == "piece_squares" && i % 8 == 0 {
+                    writeln!(f, "# {}", PIECE_NAMES[i / 8])?;
+                }
+                let row: Vec<String> = row
+                    .iter()
+                    .map(|score| format!("{:>9}", format!("{}/{}", score.mg, score.eg)))
+                    .collect();
+                writeln!(f, "{}", row.join(""))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_params_round_trip() {
+        let mut params = EvalParams::default();
+        params.piece_squares[5][63] = Score::new(-7, 12);
+        params.king_attack[3] = Score::new(30, 4);
+        let parsed = params.to_string().parse::<EvalParams>().unwrap();
+        assert_eq!(parsed, params);
+
+        let mut values = params.values();
+        assert_eq!(values.len(), 6 + 6 * 64 + 4 + 3 * 8 + 4 + 4 + 4 + 2 * 8);
+        values[0] = Score::new(1, 2);
+        params.set_values(&values);
+        assert_eq!(params.material[0], Score::new(1, 2));
+        assert_eq!(params.values(), values);
+    }
+
+    #[test]
+    fn test_params_parse() {
+        // left out groups keep their defaults
+        let params = "doubled -1/-2 # comment\nmobility 1/1 2/2\n3/3 4/4"
+            .parse::<EvalParams>()
+            .unwrap();
+        assert_eq!(params.doubled, Score::new(-1, -2));
+        assert_eq!(params.mobility[3], Score::new(4, 4));
+        assert_eq!(params.material, EvalParams::default().material);
+
+        assert!("doubled -1/-2 3/4".parse::<EvalParams>().is_err());
+        assert!("mobility 1/1".parse::<EvalParams>().is_err());
+        assert!("unknown 1/1".parse::<EvalParams>().is_err());
+        assert!("doubled -1".parse::<EvalParams>().is_err());
+        assert!("1/1".parse::<EvalParams>().is_err());
+    }
+}
@@ -0,0 +1,333 @@
+// pawn structure evaluation. apart from the free path of passed pawns, the terms only depend on where the pawns are,
+// so they are cached in a pawn hash table keyed by the pawn only zobrist hash
+use super::eval::Score;
+use super::params::EvalParams;
+use crate::bitboard::{self, Bitboard, FILE_A, FILE_H, PAWN_ATTACKS};
+use crate::movegen::PieceColour;
+use crate::transposition::TTData;
+
+// penalty for each pawn on a file after the first
+pub(super) const DOUBLED: Score = Score::new(-10, -25);
+// no friendly pawns on the adjacent files
+pub(super) const ISOLATED: Score = Score::new(-10, -15);
+// can't be supported by friendly pawns and can't advance without being taken by a pawn
+pub(super) const BACKWARD: Score = Score::new(-8, -10);
+// penalty for each group of pawns on adjacent files after the first
+pub(super) const ISLAND: Score = Score::new(-5, -10);
+
+// the tables below are indexed by rank from the pawn's own side, 0 is its back rank
+// defended by a pawn or side by side with one
+pub(super) const CONNECTED: [Score; 8] = [
+    Score::ZERO,
+    Score::new(4, 2),
+    Score::new(6, 4),
+    Score::new(10, 8),
+    Score::new(18, 14),
+    Score::new(30, 25),
+    Score::new(50, 40),
+    Score::ZERO,
+];
+// no enemy pawns in front of it on its own or the adjacent files
+pub(super) const PASSED_RANK: [Score; 8] = [
+    Score::ZERO,
+    Score::new(5, 10),
+    Score::new(5, 15),
+    Score::new(10, 25),
+    Score::new(20, 45),
+    Score::new(35, 75),
+    Score::new(60, 120),
+    Score::ZERO,
+];
+// extra for a passed pawn with nothing on the squares up to promotion
+pub(super) const PASSED_FREE_PATH: [Score; 8] = [
+    Score::ZERO,
+    Score::new(0, 5),
+    Score::new(0, 5),
+    Score::new(5, 10),
+    Score::new(10, 20),
+    Score::new(15, 35),
+    Score::new(25, 60),
+    Score::ZERO,
+];
+
+pub(super) const FILES: [Bitboard; 8] = files();
+pub(super) const ADJACENT_FILES: [Bitboard; 8] = adjacent_files();
+// squares in front of a pawn on its own file, indexed by colour and square
+const FRONT_SPAN: [[Bitboard; 64]; 2] = [
+    front_spans(PieceColour::White, false),
+    front_spans(PieceColour::Black, false),
+];
+// squares in front of a pawn on its own and the adjacent files, an enemy pawn on any of them stops it being passed
+pub(super) const PASSED_SPAN: [[Bitboard; 64]; 2] = [
+    front_spans(PieceColour::White, true),
+    front_spans(PieceColour::Black, true),
+];
+
+// cached pawn structure of both sides, indexed by PieceColour as usize
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(super) struct PawnEntry {
+    // doubled, isolated, backward and connected pawns and pawn islands
+    pub structure: [Score; 2],
+    // passed pawns by rank, without the free path bonus
+    pub passed_score: [Score; 2],
+    pub passed: [Bitboard; 2],
+    filled: bool,
+}
+
+impl TTData for PawnEntry {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    // every entry is as good as any other
+    fn get_depth(&self) -> u8 {
+        self.filled as u8
+    }
+
+    fn is_empty(&self) -> bool {
+        !self.filled
+    }
+}
+
+// pawn structure from the pawns of each side, indexed by PieceColour as usize
+pub(super) fn evaluate_pawns(params: &EvalParams, pawns: [Bitboard; 2]) -> PawnEntry {
+    let mut entry = PawnEntry {
+        filled: true,
+        ..Default::default()
+    };
+    for pcolour in [PieceColour::White, PieceColour::Black] {
+        let c = pcolour as usize;
+        (entry.structure[c], entry.passed_score[c], entry.passed[c]) =
+            evaluate_side(params, pcolour, pawns);
+    }
+    entry
+}
+
+// bonus for passed pawns that have nothing in front of them, indexed by PieceColour as usize. depends on
+// the other pieces so it is not part of the cached entry
+pub(super) fn passed_free_path(
+    params: &EvalParams,
+    entry: &PawnEntry,
+    occupied: Bitboard,
+) -> [Score; 2] {
+    let mut scores = [Score::ZERO; 2];
+    for pcolour in [PieceColour::White, PieceColour::Black] {
+        let c = pcolour as usize;
+        for sq in bitboard::squares(entry.passed[c]) {
+            if FRONT_SPAN[c][sq] & occupied == 0 {
+                scores[c] += params.passed_free_path[relative_rank(pcolour, sq)];
+            }
+        }
+    }
+    scores
+}
+
+// structure score, passed pawn score and passed pawns of one side
+fn evaluate_side(
+    params: &EvalParams,
+    pcolour: PieceColour,
+    pawns: [Bitboard; 2],
+) -> (Score, Score, Bitboard) {
+    let c = pcolour as usize;
+    let ours = pawns[c];
+    let theirs = pawns[!pcolour as usize];
+    let mut score = Score::ZERO;
+    let mut passed_score = Score::ZERO;
+    let mut passed = 0;
+    let mut files: u8 = 0;
+    for sq in bitboard::squares(ours) {
+        let file = sq % 8;
+        let rank = relative_rank(pcolour, sq);
+        files |= 1 << file;
+        let is_passed = PASSED_SPAN[c][sq] & theirs == 0 && FRONT_SPAN[c][sq] & ours == 0;
+        if is_passed {
+            passed |= bitboard::square_bb(sq);
+            passed_score += params.passed_rank[rank];
+        }
+        if ADJACENT_FILES[file] & ours == 0 {
+            score += params.isolated;
+        } else if PAWN_ATTACKS[!pcolour as usize][sq] & ours != 0
+            || bitboard::adjacent_bb(sq) & ours != 0
+        {
+            score += params.connected[rank];
+        } else if !is_passed
+            // all friendly pawns on the adjacent files are in front of it
+            && ADJACENT_FILES[file] & !PASSED_SPAN[c][sq] & ours == 0
+            && PAWN_ATTACKS[c][stop_square(pcolour, sq)] & theirs != 0
+        {
+            score += params.backward;
+        }
+    }
+    for file in FILES {
+        let count = (ours & file).count_ones() as i32;
+        if count > 1 {
+            score += params.doubled * (count - 1);
+        }
+    }
+    // an island starts on every occupied file that has no pawns on the file to its left
+    let islands = (files & !(files << 1)).count_ones() as i32;
+    if islands > 1 {
+        score += params.island * (islands - 1);
+    }
+    (score, passed_score, passed)
+}
+
+// rank from the side's own point of view, 0 is its back rank
+#[inline(always)]
+pub(super) const fn relative_rank(pcolour: PieceColour, sq: usize) -> usize {
+    match pcolour {
+        PieceColour::White => 7 - sq / 8,
+        PieceColour::Black => sq / 8,
+    }
+}
+
+// square directly in front of a pawn, pawns are never on the last rank
+#[inline(always)]
+const fn stop_square(pcolour: PieceColour, sq: usize) -> usize {
+    match pcolour {
+        PieceColour::White => sq - 8,
+        PieceColour::Black => sq + 8,
+    }
+}
+
+const fn files() -> [Bitboard; 8] {
+    let mut table = [0; 8];
+    let mut file = 0;
+    while file < 8 {
+        table[file] = FILE_A << file;
+        file += 1;
+    }
+    table
+}
+
+const fn adjacent_files() -> [Bitboard; 8] {
+    let mut table = [0; 8];
+    let mut file = 0;
+    while file < 8 {
+        let bb = FILE_A << file;
+        table[file] = ((bb << 1) & !FILE_A) | ((bb >> 1) & !FILE_H);
+        file += 1;
+    }
+    table
+}
+
+const fn front_spans(pcolour: PieceColour, adjacent: bool) -> [Bitboard; 64] {
+    let mut table = [0; 64];
+    let mut sq = 0;
+    while sq < 64 {
+        let (row, col) = ((sq / 8) as i32, (sq % 8) as i32);
+        let step = match pcolour {
+            PieceColour::White => -1,
+            PieceColour::Black => 1,
+        };
+        let mut r = row + step;
+        while r >= 0 && r < 8 {
+            let mut c = col - adjacent as i32;
+            while c <= col + adjacent as i32 {
+                if c >= 0 && c < 8 {
+                    table[sq] |= 1 << (r * 8 + c);
+                }
+                c += 1;
+            }
+            r += step;
+        }
+        sq += 1;
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitboard::Bitboards;
+    use crate::board::BoardState;
+    use crate::fen::FEN;
+    use crate::movegen::PieceType;
+
+    fn pawns_from_fen(fen: &str) -> ([Bitboard; 2], Bitboard) {
+        let bs = BoardState::from(fen.parse::<FEN>().unwrap());
+        let bitboards = Bitboards::from(bs.get_pos64());
+        let pawns = bitboards.ptype(PieceType::Pawn);
+        (
+            [
+                pawns & bitboards.colour(PieceColour::White),
+                pawns & bitboards.colour(PieceColour::Black),
+            ],
+            bitboards.occupied(),
+        )
+    }
+
+    // structure and passed pawn score of the white pawns only
+    fn white_score(fen: &str) -> Score {
+        let (pawns, _) = pawns_from_fen(fen);
+        let (structure, passed_score, _) =
+            evaluate_side(&EvalParams::default(), PieceColour::White, pawns);
+        structure + passed_score
+    }
+
+    #[test]
+    fn test_doubled_and_isolated() {
+        // lone pawn on e4, both an isolated pawn and a single island
+        assert_eq!(
+            white_score("4k3/8/8/8/4P3/8/8/4K3 w - - 0 1"),
+            ISOLATED + PASSED_RANK[3]
+        );
+        // e2 stays behind e4 so only the front pawn is passed
+        assert_eq!(
+            white_score("4k3/8/8/8/4P3/8/4P3/4K3 w - - 0 1"),
+            ISOLATED * 2 + DOUBLED + PASSED_RANK[3]
+        );
+        // a, c and e pawns are three islands
+        let (_, _, passed) = evaluate_side(
+            &EvalParams::default(),
+            PieceColour::White,
+            pawns_from_fen("4k3/p1p1p3/8/8/8/8/P1P1P3/4K3 w - - 0 1").0,
+        );
+        assert_eq!(passed, 0);
+        assert_eq!(
+            white_score("4k3/p1p1p3/8/8/8/8/P1P1P3/4K3 w - - 0 1"),
+            ISOLATED * 3 + ISLAND * 2
+        );
+    }
+
+    #[test]
+    fn test_connected_and_backward() {
+        // d4 is supported by c3, c3 is behind d4 and its stop square is attacked by b5
+        let fen = "4k3/8/8/1p6/3P4/2P5/8/4K3 w - - 0 1";
+        assert_eq!(white_score(fen), CONNECTED[3] + BACKWARD + PASSED_RANK[3]);
+        // side by side pawns are connected
+        let fen = "4k3/pp6/8/8/3PP3/8/8/4K3 w - - 0 1";
+        assert_eq!(white_score(fen), CONNECTED[3] * 2 + PASSED_RANK[3] * 2);
+    }
+
+    #[test]
+    fn test_passed_pawns() {
+        // b6 is passed, f5 is blocked from being passed by the pawn on g7
+        let fen = "4k3/6p1/1P6/5P2/8/8/8/4K3 w - - 0 1";
+        let (pawns, occupied) = pawns_from_fen(fen);
+        let entry = evaluate_pawns(&EvalParams::default(), pawns);
+        assert_eq!(entry.passed[0], bitboard::square_bb(17));
+        assert_eq!(entry.passed[1], 0);
+        assert_eq!(entry.passed_score, [PASSED_RANK[5], Score::ZERO]);
+        assert_eq!(
+            passed_free_path(&EvalParams::default(), &entry, occupied),
+            [PASSED_FREE_PATH[5], Score::ZERO]
+        );
+        // the same pawn with a piece in the way
+        let fen = "1n2k3/6p1/1P6/5P2/8/8/8/4K3 w - - 0 1";
+        let (pawns, occupied) = pawns_from_fen(fen);
+        let entry = evaluate_pawns(&EvalParams::default(), pawns);
+        assert_eq!(
+            passed_free_path(&EvalParams::default(), &entry, occupied),
+            [Score::ZERO; 2]
+        );
+        // the same for black from its own side
+        let (pawns, occupied) = pawns_from_fen("4k3/8/8/8/8/1p6/8/4K3 w - - 0 1");
+        let entry = evaluate_pawns(&EvalParams::default(), pawns);
+        assert_eq!(entry.passed[1], bitboard::square_bb(41));
+        assert_eq!(
+            passed_free_path(&EvalParams::default(), &entry, occupied),
+            [Score::ZERO, PASSED_FREE_PATH[5]]
+        );
+    }
+}
@@ -0,0 +1,237 @@
+// piece activity and king safety: mobility, bishop pair, rooks on open files and knight outposts, the pawns
+// sheltering and storming each king and the pieces attacking the squares around it
+use super::eval::Score;
+use super::params::EvalParams;
+use super::pawns::{self, ADJACENT_FILES, FILES, PASSED_SPAN};
+use crate::bitboard::{self, Bitboard, Bitboards, KING_ATTACKS, PAWN_ATTACKS};
+use crate::movegen::{Piece, PieceColour, PieceType};
+
+// the tables below are indexed by knight, bishop, rook and queen
+// per move, relative to a typical number of moves for the piece
+pub(super) const MOBILITY_WEIGHTS: [Score; 4] = [
+    Score::new(4, 4),
+    Score::new(5, 5),
+    Score::new(2, 4),
+    Score::new(1, 2),
+];
+const MOBILITY_BASE: [i32; 4] = [4, 6, 7, 13];
+// per square next to the enemy king the piece attacks
+pub(super) const KING_ATTACK_WEIGHTS: [Score; 4] = [
+    Score::new(8, 2),
+    Score::new(8, 2),
+    Score::new(12, 3),
+    Score::new(24, 6),
+];
+// percentage of the king attack weight counted, by the number of attacking pieces. one piece can't mate on its own
+const KING_ATTACKERS_SCALE: [i32; 8] = [0, 0, 50, 75, 88, 94, 97, 99];
+
+pub(super) const BISHOP_PAIR: Score = Score::new(30, 50);
+// no pawns on the file
+pub(super) const ROOK_OPEN_FILE: Score = Score::new(25, 10);
+// only enemy pawns on the file
+pub(super) const ROOK_SEMI_OPEN_FILE: Score = Score::new(12, 5);
+// knight on the 4th to 6th rank, defended by a pawn and out of reach of the enemy pawns
+pub(super) const KNIGHT_OUTPOST: Score = Score::new(20, 10);
+
+// the nearest pawns in front of the king on its own and the adjacent files, by how many ranks in front of
+// the king they are. index 0 is no pawn on the file
+pub(super) const PAWN_SHIELD: [Score; 8] = [
+    Score::new(-20, 0),
+    Score::new(15, 0),
+    Score::new(8, 0),
+    Score::new(2, 0),
+    Score::ZERO,
+    Score::ZERO,
+    Score::ZERO,
+    Score::ZERO,
+];
+pub(super) const PAWN_STORM: [Score; 8] = [
+    Score::ZERO,
+    Score::new(-5, 0),
+    Score::new(-25, 0),
+    Score::new(-15, 0),
+    Score::new(-5, 0),
+    Score::ZERO,
+    Score::ZERO,
+    Score::ZERO,
+];
+
+// terms of one side, its king safety includes the attacks on its king by the other side
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(super) struct PieceTerms {
+    pub mobility: Score,
+    pub pieces: Score,
+    pub king_safety: Score,
+}
+
+// terms of each side, indexed by PieceColour as usize
+pub(super) fn evaluate_pieces(params: &EvalParams, bitboards: &Bitboards) -> [PieceTerms; 2] {
+    let mut terms = [PieceTerms::default(); 2];
+    let mut king_attacks = [Score::ZERO; 2];
+    for pcolour in [PieceColour::White, PieceColour::Black] {
+        let (side_terms, king_attack) = evaluate_side(params, bitboards, pcolour);
+        terms[pcolour as usize] = side_terms;
+        king_attacks[!pcolour as usize] = king_attack;
+    }
+    for pcolour in [PieceColour::White, PieceColour::Black] {
+        let c = pcolour as usize;
+        terms[c].king_safety = king_shelter(params, bitboards, pcolour) - king_attacks[c];
+    }
+    terms
+}
+
+// terms of the pieces of one side, and how strongly they attack the enemy king
+fn evaluate_side(
+    params: &EvalParams,
+    bitboards: &Bitboards,
+    pcolour: PieceColour,
+) -> (PieceTerms, Score) {
+    let c = pcolour as usize;
+    let ours = bitboards.colour(pcolour);
+    let occupied = bitboards.occupied();
+    let pawns = bitboards.ptype(PieceType::Pawn);
+    let our_pawns = pawns & ours;
+    let their_pawns = pawns & !ours;
+    let king_zone = match bitboard::squares(bitboards.ptype(PieceType::King) & !ours).next() {
+        Some(sq) => KING_ATTACKS[sq] | bitboard::square_bb(sq),
+        None => 0,
+    };
+
+    let mut terms = PieceTerms::default();
+    let mut attackers = 0;
+    let mut attack_weight = Score::ZERO;
+    for ptype in [
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+    ] {
+        let idx = ptype as usize - 1;
+        for sq in bitboard::squares(bitboards.ptype(ptype) & ours) {
+            let attacks = bitboard::piece_attacks(Piece { pcolour, ptype }, sq, occupied);
+            // the same squares movegen gives as the pseudo legal moves of the piece
+            let moves = (attacks & !ours).count_ones() as i32;
+            terms.mobility += params.mobility[idx] * (moves - MOBILITY_BASE[idx]);
+            let zone_attacks = (attacks & king_zone).count_ones() as i32;
+            if zone_attacks > 0 {
+                attackers += 1;
+                attack_weight += params.king_attack[idx] * zone_attacks;
+            }
+        }
+    }
+
+    if (bitboards.ptype(PieceType::Bishop) & ours).count_ones() >= 2 {
+        terms.pieces += params.bishop_pair;
+    }
+    for sq in bitboard::squares(bitboards.ptype(PieceType::Rook) & ours) {
+        let file = FILES[sq % 8];
+        if file & pawns == 0 {
+            terms.pieces += params.rook_open_file;
+        } else if file & our_pawns == 0 {
+            terms.pieces += params.rook_semi_open_file;
+        }
+    }
+    for sq in bitboard::squares(bitboards.ptype(PieceType::Knight) & ours) {
+        if (3..=5).contains(&pawns::relative_rank(pcolour, sq))
+            && PAWN_ATTACKS[!pcolour as usize][sq] & our_pawns != 0
+            && PASSED_SPAN[c][sq] & ADJACENT_FILES[sq % 8] & their_pawns == 0
+        {
+            terms.pieces += params.knight_outpost;
+        }
+    }
+
+    let scale = KING_ATTACKERS_SCALE[attackers.min(7)];
+    let attack = Score::new(
+        attack_weight.mg * scale / 100,
+        attack_weight.eg * scale / 100,
+    );
+    (terms, attack)
+}
+
+// pawn shield in front of the king and enemy pawns storming it, on the king's file and the files beside it
+fn king_shelter(params: &EvalParams, bitboards: &Bitboards, pcolour: PieceColour) -> Score {
+    let ours = bitboards.colour(pcolour);
+    let Some(king_sq) = bitboard::squares(bitboards.ptype(PieceType::King) & ours).next() else {
+        return Score::ZERO;
+    };
+    let pawns = bitboards.ptype(PieceType::Pawn);
+    let king_file = king_sq % 8;
+    let mut score = Score::ZERO;
+    for file in &FILES[king_file.saturating_sub(1)..=(king_file + 1).min(7)] {
+        let in_front = file & PASSED_SPAN[pcolour as usize][king_sq] & pawns;
+        score += params.pawn_shield[nearest_distance(pcolour, king_sq, in_front & ours)];
+        score += params.pawn_storm[nearest_distance(pcolour, king_sq, in_front & !ours)];
+    }
+    score
+}
+
+// ranks between sq and the nearest of the pawns in front of it, 0 if there are none
+fn nearest_distance(pcolour: PieceColour, sq: usize, in_front: Bitboard) -> usize {
+    if in_front == 0 {
+        return 0;
+    }
+    // white moves towards index 0
+    let nearest = match pcolour {
+        PieceColour::White => 63 - in_front.leading_zeros() as usize,
+        PieceColour::Black => in_front.trailing_zeros() as usize,
+    };
+    (sq / 8).abs_diff(nearest / 8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::BoardState;
+    use crate::fen::FEN;
+
+    fn terms_from_fen(fen: &str) -> [PieceTerms; 2] {
+        let bs = BoardState::from(fen.parse::<FEN>().unwrap());
+        evaluate_pieces(&EvalParams::default(), &Bitboards::from(bs.get_pos64()))
+    }
+
+    #[test]
+    fn test_mobility() {
+        // knight in the corner against one in the centre
+        let terms = terms_from_fen("n3k3/8/8/8/3N4/8/8/4K3 w - - 0 1");
+        assert_eq!(terms[0].mobility, MOBILITY_WEIGHTS[0] * (8 - 4));
+        assert_eq!(terms[1].mobility, MOBILITY_WEIGHTS[0] * (2 - 4));
+        // own pieces block moves, enemy pieces can be captured
+        let terms = terms_from_fen("4k3/8/8/8/8/8/P7/R1n1K3 w - - 0 1");
+        assert_eq!(terms[0].mobility, MOBILITY_WEIGHTS[2] * (2 - 7));
+    }
+
+    #[test]
+    fn test_piece_bonuses() {
+        let terms = terms_from_fen("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1");
+        assert_eq!(terms[0].pieces, BISHOP_PAIR);
+        // a file is open, e file is semi open for white only
+        let terms = terms_from_fen("r3k3/4p3/8/8/8/8/8/R3R1K1 w - - 0 1");
+        assert_eq!(terms[0].pieces, ROOK_OPEN_FILE + ROOK_SEMI_OPEN_FILE);
+        assert_eq!(terms[1].pieces, ROOK_OPEN_FILE);
+        // knights on d5 and f5 are defended by e4, the c pawn can still chase the one on d5 away
+        let terms = terms_from_fen("4k3/2p5/8/3N1N2/4P3/8/8/4K3 w - - 0 1");
+        assert_eq!(terms[0].pieces, KNIGHT_OUTPOST);
+        let terms = terms_from_fen("4k3/6p1/8/3N1N2/4P3/8/8/4K3 w - - 0 1");
+        assert_eq!(terms[0].pieces, KNIGHT_OUTPOST);
+        let terms = terms_from_fen("4k3/4p3/8/3N1N2/4P3/8/8/4K3 w - - 0 1");
+        assert_eq!(terms[0].pieces, Score::ZERO);
+    }
+
+    #[test]
+    fn test_king_safety() {
+        // castled king behind its pawns, then with the pawns pushed and black pawns storming
+        let sheltered = terms_from_fen("4k3/8/8/8/8/8/5PPP/6K1 w - - 0 1");
+        assert_eq!(sheltered[0].king_safety, PAWN_SHIELD[1] * 3);
+        let stormed = terms_from_fen("4k3/8/8/8/5PPP/6pp/8/6K1 w - - 0 1");
+        assert_eq!(
+            stormed[0].king_safety,
+            PAWN_SHIELD[3] * 3 + PAWN_STORM[2] * 2
+        );
+        // a queen and a rook attacking the squares around the king
+        let attacked = terms_from_fen("4k3/8/8/8/8/7q/5PPP/5rK1 w - - 0 1");
+        assert!(attacked[0].king_safety.mg < sheltered[0].king_safety.mg - 20);
+        // a single attacker is not counted
+        let attacked = terms_from_fen("4k3/8/8/8/8/7q/5PPP/6K1 w - - 0 1");
+        assert_eq!(attacked[0].king_safety, PAWN_SHIELD[1] * 3);
+    }
+}
@@ -13,6 +13,7 @@ pub enum BoardStateError {
     InvalidInput(String),
     Detatched(String),
     NotFound(String),
+    EngineSearch(String),
 }
 
 impl fmt::Display for BoardStateError {
@@ -28,46 +29,310 @@ impl fmt::Display for BoardStateError {
             Self::InvalidInput(s) => write!(f, "Invalid input: {}", s),
             Self::Detatched(s) => write!(f, "Detatched from current boardstate: {}", s),
             Self::NotFound(s) => write!(f, "Not found: {}", s),
+            Self::EngineSearch(s) => write!(f, "Engine search: {}", s),
         }
     }
 }
 
 impl error::Error for BoardStateError {}
 
-#[derive(Debug)]
-pub enum FenParseError {
-    InvalidFen(String),
-    VariantIncompatible(String),
+// line and column of the start of a token in the parsed text, both counted from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextPosition {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for TextPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FenParseErrorKind {
+    FieldCount,
+    PiecePlacement,
+    SideToMove,
+    Castling,
+    EnPassant,
+    HalfmoveCount,
+    MoveCount,
+    Square,       // square notation such as e3
+    EpdOperation, // EPD operations after the FEN fields, such as perft depths
+    VariantIncompatible,
+}
+
+impl fmt::Display for FenParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind_str = match self {
+            Self::FieldCount => "Invalid number of fields",
+            Self::PiecePlacement => "Invalid piece placement",
+            Self::SideToMove => "Invalid side to move",
+            Self::Castling => "Invalid castling field",
+            Self::EnPassant => "Invalid en passant field",
+            Self::HalfmoveCount => "Invalid halfmove count",
+            Self::MoveCount => "Invalid move count",
+            Self::Square => "Invalid square",
+            Self::EpdOperation => "Invalid EPD operation",
+            Self::VariantIncompatible => "Variant incompatibility",
+        };
+        write!(f, "{}", kind_str)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FenParseError {
+    kind: FenParseErrorKind,
+    message: String,
+    token: Option<String>, // the field or character that couldn't be parsed
+    column: Option<usize>, // column of the token in the FEN string, counted from 1
+}
+
+impl FenParseError {
+    pub fn new(kind: FenParseErrorKind, message: String) -> Self {
+        Self {
+            kind,
+            message,
+            token: None,
+            column: None,
+        }
+    }
+
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    pub fn at_column(mut self, column: usize) -> Self {
+        self.column = Some(column);
+        self
+    }
+
+    pub fn kind(&self) -> FenParseErrorKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    pub fn column(&self) -> Option<usize> {
+        self.column
+    }
 }
 
 impl fmt::Display for FenParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Self::InvalidFen(s) => write!(f, "Invalid FEN: {}", s),
-            Self::VariantIncompatible(s) => write!(f, "Variant incompatibility: {}", s),
+        write!(f, "Invalid FEN, {}: {}", self.kind, self.message)?;
+        if let Some(column) = self.column {
+            write!(f, " at column {}", column)?;
         }
+        if let Some(token) = &self.token {
+            write!(f, " ({})", token)?;
+        }
+        Ok(())
     }
 }
 
 impl error::Error for FenParseError {}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PGNParseErrorKind {
+    InvalidTag,       // tag pair syntax
+    InvalidNotation,  // SAN that can't be read
+    IllegalMove,      // SAN with no legal move in the position it is played from
+    AmbiguousMove,    // SAN matching more than one legal move
+    InvalidMovetext,  // comments, variations, NAGs or the game termination marker out of place
+    InvalidCharacter, // text outside the character set of the standard
+    InvalidFen,       // FEN tag of a game starting from a position
+    FileError,
+}
+
+impl fmt::Display for PGNParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind_str = match self {
+            Self::InvalidTag => "Invalid tag",
+            Self::InvalidNotation => "Error parsing notation",
+            Self::IllegalMove => "Illegal move",
+            Self::AmbiguousMove => "Ambiguous move",
+            Self::InvalidMovetext => "Invalid movetext",
+            Self::InvalidCharacter => "Invalid character",
+            Self::InvalidFen => "Invalid FEN tag",
+            Self::FileError => "Error reading file",
+        };
+        write!(f, "{}", kind_str)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PGNParseError {
+    kind: PGNParseErrorKind,
+    message: String,
+    token: Option<String>, // the text that couldn't be parsed, such as a move or tag
+    position: Option<TextPosition>, // where the token starts in the game, or the file for a PgnReader
+    game_index: Option<usize>, // index of the game in a database read with a PgnReader, counted from 0
+}
+
+impl PGNParseError {
+    pub fn new(kind: PGNParseErrorKind, message: String) -> Self {
+        Self {
+            kind,
+            message,
+            token: None,
+            position: None,
+            game_index: None,
+        }
+    }
+
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    pub fn at(mut self, position: TextPosition) -> Self {
+        self.position = Some(position);
+        self
+    }
+
+    // marks the error as coming from a game of a database, lines are moved from the start of the game to the
+    // start of the file
+    pub fn in_game(mut self, game_index: usize, first_line: usize) -> Self {
+        self.game_index = Some(game_index);
+        if let Some(position) = &mut self.position {
+            position.line += first_line - 1;
+        }
+        self
+    }
+
+    pub fn kind(&self) -> PGNParseErrorKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    pub fn position(&self) -> Option<TextPosition> {
+        self.position
+    }
+
+    pub fn game_index(&self) -> Option<usize> {
+        self.game_index
+    }
+}
+
+impl fmt::Display for PGNParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.kind, self.message)?;
+        let mut location = Vec::new();
+        if let Some(game_index) = self.game_index {
+            location.push(format!("game index {}", game_index));
+        }
+        if let Some(position) = self.position {
+            location.push(position.to_string());
+        }
+        if let Some(token) = &self.token {
+            location.push(format!("at ({})", token));
+        }
+        if !location.is_empty() {
+            write!(f, " [{}]", location.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for PGNParseError {}
+
 #[derive(Debug)]
-pub enum PGNParseError {
-    InvalidTag(String),
-    NotationParseError(String),
+pub enum BookError {
     FileError(String),
-    MoveNotFound(String),
+    InvalidFormat(String),
 }
 
-impl fmt::Display for PGNParseError {
+impl fmt::Display for BookError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::InvalidTag(s) => write!(f, "Invalid tag: {}", s),
-            Self::NotationParseError(s) => write!(f, "Error parsing notation: {}", s),
-            Self::FileError(s) => write!(f, "Error reading file: {}", s),
-            Self::MoveNotFound(s) => write!(f, "Move not found: {}", s),
+            Self::FileError(s) => write!(f, "Error reading book file: {}", s),
+            Self::InvalidFormat(s) => write!(f, "Invalid book format: {}", s),
         }
     }
 }
 
-impl error::Error for PGNParseError {}
+impl error::Error for BookError {}
+
+#[derive(Debug)]
+pub enum TablebaseError {
+    FileError(String),
+    NoTables(String),
+}
+
+impl fmt::Display for TablebaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::FileError(s) => write!(f, "Error reading tablebase directory: {}", s),
+            Self::NoTables(s) => write!(f, "No tablebases found: {}", s),
+        }
+    }
+}
+
+impl error::Error for TablebaseError {}
+
+#[derive(Debug)]
+pub enum NnueError {
+    FileError(String),
+    InvalidFormat(String),
+}
+
+impl fmt::Display for NnueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::FileError(s) => write!(f, "Error reading network file: {}", s),
+            Self::InvalidFormat(s) => write!(f, "Invalid network format: {}", s),
+        }
+    }
+}
+
+impl error::Error for NnueError {}
+
+#[derive(Debug)]
+pub enum EvalParamsError {
+    FileError(String),
+    InvalidFormat(String),
+}
+
+impl fmt::Display for EvalParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::FileError(s) => write!(f, "Error reading evaluation parameters file: {}", s),
+            Self::InvalidFormat(s) => write!(f, "Invalid evaluation parameters: {}", s),
+        }
+    }
+}
+
+impl error::Error for EvalParamsError {}
+
+#[derive(Debug)]
+pub enum TuningDataError {
+    FileError(String),
+    InvalidFormat(String),
+}
+
+impl fmt::Display for TuningDataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::FileError(s) => write!(f, "Error reading tuning data file: {}", s),
+            Self::InvalidFormat(s) => write!(f, "Invalid tuning data: {}", s),
+        }
+    }
+}
+
+impl error::Error for TuningDataError {}
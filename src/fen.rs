@@ -2,7 +2,7 @@ use std::fmt;
 use std::str::FromStr;
 
 use crate::board::BoardState;
-use crate::errors::FenParseError;
+use crate::errors::{FenParseError, FenParseErrorKind};
 use crate::log_and_return_error;
 use crate::movegen::{MovegenFlags, Piece, PieceColour, PieceType, Square};
 use crate::position::{Pos64, Position};
@@ -29,22 +29,44 @@ impl FromStr for FEN {
         let fen_vec: Vec<&str> = s.split(' ').collect();
         // check if the FEN string has the correct number of fields, accept the last two as optional with default values given in BoardState
         if fen_vec.len() < 4 || fen_vec.len() > 6 {
-            return Err(FenParseError::InvalidFen(format!(
-                "Invalid number of fields in FEN string: {}. Expected at least 4, max 6",
-                fen_vec.len()
-            )));
+            let err = FenParseError::new(
+                FenParseErrorKind::FieldCount,
+                format!(
+                    "Invalid number of fields in FEN string: {}. Expected at least 4, max 6",
+                    fen_vec.len()
+                ),
+            )
+            .with_token(s);
+            log_and_return_error!(err)
+        }
+        // column each field starts at, errors are given the column of their field
+        let mut columns = Vec::new();
+        let mut column = 1;
+        for field in &fen_vec {
+            columns.push(column);
+            column += field.len() + 1;
         }
+        let locate = |i: usize| {
+            let (field, column) = (fen_vec[i], columns[i]);
+            move |err| locate_field_error(err, field, column)
+        };
+
         let mut fen = Self::new();
         // first field of FEN defines the piece positions
-        fen.parse_pos_field(fen_vec[0])?;
+        fen.parse_pos_field(fen_vec[0]).map_err(locate(0))?;
         // second filed of FEN defines which side it is to move, either 'w' or 'b'
-        fen.parse_side_field(fen_vec[1])?;
+        fen.parse_side_field(fen_vec[1]).map_err(locate(1))?;
         // third field of FEN defines castling flags
-        fen.parse_castling_flags(fen_vec[2])?;
+        fen.parse_castling_flags(fen_vec[2]).map_err(locate(2))?;
         // fourth field of FEN defines en passant flag, it gives notation of the square the pawn jumped over
-        fen.parse_en_passant_flag(fen_vec[3])?;
+        fen.parse_en_passant_flag(fen_vec[3]).map_err(locate(3))?;
         // set last two fields if they exist, otherwise default values are 0 and 1 already set in new()
-        fen.parse_halfmove_move_count(fen_vec.get(4).copied(), fen_vec.get(5).copied())?;
+        if let Some(hm) = fen_vec.get(4) {
+            fen.parse_halfmove_count(hm).map_err(locate(4))?;
+        }
+        if let Some(m) = fen_vec.get(5) {
+            fen.parse_move_count(m).map_err(locate(5))?;
+        }
 
         Ok(fen)
     }
@@ -275,6 +297,15 @@ impl FEN {
         // check for multiple kings, should be the only issue in terms of pieces on the board
         let mut wking_num = 0;
         let mut bking_num = 0;
+        let rank_num = field.split('/').count();
+        if rank_num != 8 {
+            let err = FenParseError::new(
+                FenParseErrorKind::PiecePlacement,
+                format!("Invalid number of ranks: {}. Expected 8", rank_num),
+            );
+            log_and_return_error!(err)
+        }
+        let mut rank_column = 1; // column of the rank in the field
         for rank in field.split('/') {
             // check to see if there is 8 squares in a rank.
             let mut square_count = 0;
@@ -287,14 +318,20 @@ impl FEN {
                 }
             }
             if square_count != 8 {
-                return Err(FenParseError::InvalidFen(format!(
-                    "Invalid number of squares in rank: {}. Expected 8, got {}",
-                    rank, square_count
-                )));
+                let err = FenParseError::new(
+                    FenParseErrorKind::PiecePlacement,
+                    format!(
+                        "Invalid number of squares in rank: {}. Expected 8, got {}",
+                        rank, square_count
+                    ),
+                )
+                .with_token(rank)
+                .at_column(rank_column);
+                log_and_return_error!(err)
             }
 
             let mut i = 0;
-            for c in rank.chars() {
+            for (j, c) in rank.char_indices() {
                 let square = match c {
                     'p' => Square::Piece(Piece {
                         pcolour: PieceColour::Black,
@@ -358,10 +395,12 @@ impl FEN {
                         continue; // skip the below square assignment for pieces
                     }
                     other => {
-                        let err = FenParseError::InvalidFen(format!(
-                            "Invalid char in first field: {}",
-                            other
-                        ));
+                        let err = FenParseError::new(
+                            FenParseErrorKind::PiecePlacement,
+                            format!("Invalid char in first field: {}", other),
+                        )
+                        .with_token(&other.to_string())
+                        .at_column(rank_column + j);
                         log_and_return_error!(err)
                     }
                 };
@@ -369,13 +408,17 @@ impl FEN {
                 i += 1;
             }
             rank_start_idx += 8; // next rank
+            rank_column += rank.len() + 1;
         }
 
         if wking_num != 1 || bking_num != 1 {
-            let err = FenParseError::InvalidFen(format!(
-                "Incorrect number of kings (white: {}, black: {}) in FEN field: {}",
-                wking_num, bking_num, field
-            ));
+            let err = FenParseError::new(
+                FenParseErrorKind::PiecePlacement,
+                format!(
+                    "Incorrect number of kings (white: {}, black: {}) in FEN field: {}",
+                    wking_num, bking_num, field
+                ),
+            );
             log_and_return_error!(err)
         }
 
@@ -392,17 +435,24 @@ impl FEN {
                 self.side = PieceColour::Black;
             }
             other => {
-                return Err(FenParseError::InvalidFen(format!(
-                    "Invalid second field: {}. Expected 'w' or 'b'",
-                    other
-                )));
+                let err = FenParseError::new(
+                    FenParseErrorKind::SideToMove,
+                    format!("Invalid second field: {}. Expected 'w' or 'b'", other),
+                );
+                log_and_return_error!(err)
             }
         }
         Ok(())
     }
 
     fn parse_castling_flags(&mut self, field: &str) -> Result<(), FenParseError> {
-        for c in field.chars() {
+        for (j, c) in field.char_indices() {
+            // errors are for the flag character
+            let castling_error = |message: String| {
+                FenParseError::new(FenParseErrorKind::Castling, message)
+                    .with_token(&c.to_string())
+                    .at_column(j + 1)
+            };
             match c {
                 'q' => {
                     self.movegen_flags.black_castle_long = true;
@@ -410,9 +460,9 @@ impl FEN {
                         match self.pos64().queen_side_outer_rook_idx(PieceColour::Black) {
                             Some(idx) => idx,
                             None => {
-                                return Err(FenParseError::InvalidFen(
-                                    "Invalid FEN: Black long castle rook not found".to_string(),
-                                ))
+                                let err =
+                                    castling_error("Black long castle rook not found".to_string());
+                                log_and_return_error!(err)
                             }
                         }
                 }
@@ -422,9 +472,9 @@ impl FEN {
                         match self.pos64().queen_side_outer_rook_idx(PieceColour::White) {
                             Some(idx) => idx,
                             None => {
-                                return Err(FenParseError::InvalidFen(
-                                    "Invalid FEN: White long castle rook not found".to_string(),
-                                ))
+                                let err =
+                                    castling_error("White long castle rook not found".to_string());
+                                log_and_return_error!(err)
                             }
                         }
                 }
@@ -434,9 +484,9 @@ impl FEN {
                         match self.pos64().king_side_outer_rook_idx(PieceColour::Black) {
                             Some(idx) => idx,
                             None => {
-                                return Err(FenParseError::InvalidFen(
-                                    "Invalid FEN: Black short castle rook not found".to_string(),
-                                ))
+                                let err =
+                                    castling_error("Black short castle rook not found".to_string());
+                                log_and_return_error!(err)
                             }
                         }
                 }
@@ -446,9 +496,9 @@ impl FEN {
                         match self.pos64().king_side_outer_rook_idx(PieceColour::White) {
                             Some(idx) => idx,
                             None => {
-                                return Err(FenParseError::InvalidFen(
-                                    "Invalid FEN: White short castle rook not found".to_string(),
-                                ))
+                                let err =
+                                    castling_error("White short castle rook not found".to_string());
+                                log_and_return_error!(err)
                             }
                         }
                 }
@@ -495,16 +545,15 @@ impl FEN {
                         }
                         continue;
                     }
-                    return Err(FenParseError::InvalidFen(format!(
+                    let err = castling_error(format!(
                         "Invalid castling flag: {}. Rook disambiguation is incorrect/rook not found at file",
                         x
-                    )));
+                    ));
+                    log_and_return_error!(err)
                 }
                 other => {
-                    return Err(FenParseError::InvalidFen(format!(
-                        "Invalid char in third field: {}",
-                        other
-                    )));
+                    let err = castling_error(format!("Invalid char in third field: {}", other));
+                    log_and_return_error!(err)
                 }
             }
         }
@@ -513,14 +562,25 @@ impl FEN {
 
     fn parse_en_passant_flag(&mut self, field: &str) -> Result<(), FenParseError> {
         if field != "-" {
-            let ep_mv_idx = notation_to_index(field)?;
+            let ep_mv_idx = match notation_to_index(field) {
+                Ok(idx) => idx,
+                Err(e) => {
+                    let err =
+                        FenParseError::new(FenParseErrorKind::EnPassant, e.message().to_string());
+                    log_and_return_error!(err)
+                }
+            };
 
             // error if index is out of bounds. FEN defines the index behind the pawn that moved, so valid indexes are only 16->47 (excluded top and bottom two ranks)
             if !(16..=47).contains(&ep_mv_idx) {
-                return Err(FenParseError::InvalidFen(format!(
-                    "Invalid en passant square: {}. Index is out of bounds",
-                    field
-                )));
+                let err = FenParseError::new(
+                    FenParseErrorKind::EnPassant,
+                    format!(
+                        "Invalid en passant square: {}. Index is out of bounds",
+                        field
+                    ),
+                );
+                log_and_return_error!(err)
             }
 
             // in our struct however, we store the idx of the pawn to be captured
@@ -539,45 +599,56 @@ impl FEN {
         Ok(())
     }
 
-    fn parse_halfmove_move_count(
-        &mut self,
-        hm_field: Option<&str>,
-        m_field: Option<&str>,
-    ) -> Result<(), FenParseError> {
-        if let Some(hm) = hm_field {
-            self.halfmove_count = if let Ok(halfmove_count) = hm.parse::<u32>() {
-                halfmove_count
-            } else {
-                let err =
-                    FenParseError::InvalidFen(format!("Error parsing halfmove count: {}", hm));
-                log_and_return_error!(err)
-            };
+    fn parse_halfmove_count(&mut self, hm: &str) -> Result<(), FenParseError> {
+        self.halfmove_count = if let Ok(halfmove_count) = hm.parse::<u32>() {
+            halfmove_count
+        } else {
+            let err = FenParseError::new(
+                FenParseErrorKind::HalfmoveCount,
+                format!("Error parsing halfmove count: {}", hm),
+            );
+            log_and_return_error!(err)
         };
+        Ok(())
+    }
 
-        if let Some(m) = m_field {
-            self.move_count = if let Ok(move_count) = m.parse::<u32>() {
-                move_count
-            } else {
-                let err = FenParseError::InvalidFen(format!("Error parsing move count: {}", m));
-                log_and_return_error!(err)
-            };
-        }
+    fn parse_move_count(&mut self, m: &str) -> Result<(), FenParseError> {
+        self.move_count = if let Ok(move_count) = m.parse::<u32>() {
+            move_count
+        } else {
+            let err = FenParseError::new(
+                FenParseErrorKind::MoveCount,
+                format!("Error parsing move count: {}", m),
+            );
+            log_and_return_error!(err)
+        };
         Ok(())
     }
 }
 
+// gives the error the column of the FEN field it was found in, and the field as the token if it has none
+fn locate_field_error(err: FenParseError, field: &str, column: usize) -> FenParseError {
+    let column = column + err.column().unwrap_or(1) - 1;
+    match err.token() {
+        Some(_) => err.at_column(column),
+        None => err.with_token(field).at_column(column),
+    }
+}
+
 #[inline]
-fn notation_to_index(n: &str) -> Result<usize, FenParseError> {
+pub(crate) fn notation_to_index(n: &str) -> Result<usize, FenParseError> {
     if n.len() != 2
         || n.chars().next().unwrap() < 'a'
         || n.chars().next().unwrap() > 'h'
         || n.chars().nth(1).unwrap() < '1'
         || n.chars().nth(1).unwrap() > '8'
     {
-        log_and_return_error!(FenParseError::InvalidFen(format!(
-            "Invalid notation ({}) when converting to index:",
-            n
-        )))
+        let err = FenParseError::new(
+            FenParseErrorKind::Square,
+            format!("Invalid notation ({}) when converting to index", n),
+        )
+        .with_token(n);
+        log_and_return_error!(err)
     }
     let file: char = n.chars().next().unwrap();
     let rank: char = n.chars().nth(1).unwrap();
@@ -598,7 +669,7 @@ fn notation_to_index(n: &str) -> Result<usize, FenParseError> {
 }
 
 #[inline]
-fn index_to_notation(i: usize) -> String {
+pub(crate) fn index_to_notation(i: usize) -> String {
     let file = match i % 8 {
         0 => 'a',
         1 => 'b',
@@ -668,6 +739,57 @@ mod tests {
         assert!(FEN::from_str(fen_str).is_err());
     }
 
+    #[test]
+    fn test_fen_parse_error_location() {
+        let cases = [
+            ("8/8/8 w -", FenParseErrorKind::FieldCount, None, None),
+            (
+                "rnbqkbnr/pppppppp/0/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                FenParseErrorKind::PiecePlacement,
+                Some("0"),
+                Some(19),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR xw KQkq - 0 1",
+                FenParseErrorKind::SideToMove,
+                Some("xw"),
+                Some(45),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KzkQ - 0 1",
+                FenParseErrorKind::Castling,
+                Some("z"),
+                Some(48),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq x2 0 1",
+                FenParseErrorKind::EnPassant,
+                Some("x2"),
+                Some(52),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 x",
+                FenParseErrorKind::MoveCount,
+                Some("x"),
+                Some(56),
+            ),
+            (
+                "8/8/8/8/8/8/8/8/8 w - - 0 1",
+                FenParseErrorKind::PiecePlacement,
+                None,
+                Some(1),
+            ),
+        ];
+        for (fen_str, kind, token, column) in cases {
+            let err = FEN::from_str(fen_str).unwrap_err();
+            assert_eq!(err.kind(), kind, "{}", fen_str);
+            if token.is_some() {
+                assert_eq!(err.token(), token, "{}", fen_str);
+            }
+            assert_eq!(err.column(), column, "{}", fen_str);
+        }
+    }
+
     #[test]
     fn test_fen_to_string() {
         let fen = FEN::new();
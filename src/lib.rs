@@ -1,15 +1,19 @@
+mod bitboard;
 pub mod board;
-mod engine;
+pub mod book;
+pub mod engine;
 mod errors;
 pub mod fen;
 mod macros;
 mod magic;
-mod mailbox;
 mod movegen;
 mod perft;
 pub mod pgn;
 mod position;
+mod search_position;
+pub mod tablebase;
 mod transposition;
+pub mod tune;
 mod util;
 mod zobrist;
 
@@ -20,5 +24,6 @@ pub use {
         NULL_MOVE, NULL_SHORT_MOVE,
     },
     perft::*,
+    transposition::{BoundType, TTData, TableEntry, TranspositionTable, TT},
     util::*,
 };
@@ -1,17 +1,9 @@
 use core::fmt;
 
-use crate::mailbox;
+use crate::bitboard::{self, Bitboards};
+use crate::fen;
 use crate::position;
 
-//pub const MOVE_VEC_SIZE: usize = 27; // max number of squares a queen can possibly move to is 27
-type Offset = [i32; 8];
-
-const PAWN_OFFSET: Offset = [0, 0, 0, 0, 0, 0, 0, 0];
-const KNIGHT_OFFSET: Offset = [-21, -19, -12, -8, 8, 12, 19, 21];
-const BISHOP_OFFSET: Offset = [-11, -9, 9, 11, 0, 0, 0, 0];
-const ROOK_OFFSET: Offset = [-10, -1, 1, 10, 0, 0, 0, 0];
-const QUEEN_KING_OFFSET: Offset = [-11, -10, -9, -1, 1, 9, 10, 11];
-
 // indexes for *standard* starting position
 const STD_LONG_WHITE_ROOK_START: usize = 56;
 const STD_SHORT_WHITE_ROOK_START: usize = 63;
@@ -163,6 +155,28 @@ impl Move {
             },
         }
     }
+
+    // long algebraic notation used by the UCI protocol, e.g. e2e4, e7e8q. castling is written as the king move (e1g1)
+    pub fn to_uci_string(&self) -> String {
+        if *self == NULL_MOVE {
+            return "0000".to_string();
+        }
+        let promotion = match self.move_type {
+            MoveType::Promotion(ptype, _) => match ptype {
+                PieceType::Knight => "n",
+                PieceType::Bishop => "b",
+                PieceType::Rook => "r",
+                _ => "q",
+            },
+            _ => "",
+        };
+        format!(
+            "{}{}{}",
+            fen::index_to_notation(self.from),
+            fen::index_to_notation(self.to),
+            promotion
+        )
+    }
 }
 
 // struct that stores enough information to identify any full sized move
@@ -244,12 +258,19 @@ pub trait MoveMap {
     fn add_move(&mut self, _: &Move);
 }
 
+impl MoveMap for Vec<Move> {
+    #[inline(always)]
+    fn add_move(&mut self, mv: &Move) {
+        self.push(*mv);
+    }
+}
+
 #[inline(always)]
 fn pawn_promotion(
     mv_map: &mut dyn MoveMap,
     i: usize,
     piece: Piece,
-    mv: i32,
+    to: usize,
     capture: Option<PieceType>,
 ) {
     for ptype in PROMOTION_PIECE_TYPES {
@@ -257,58 +278,30 @@ fn pawn_promotion(
             &(Move {
                 piece,
                 from: i,
-                to: mv as usize,
+                to,
                 move_type: MoveType::Promotion(ptype, capture),
             }),
         );
     }
 }
 
+// index of the square in front of a pawn, None when it would be off the board
 #[inline(always)]
-fn is_square_empty(pos: &position::Pos64, i: usize) -> bool {
-    unsafe { return pos.get_unchecked(i) == &Square::Empty }
-}
-
-#[inline(always)]
-const fn mb_get_pawn_push_offset(piece: Piece) -> i32 {
-    match piece.pcolour {
-        PieceColour::White => -10,
-        PieceColour::Black => 10,
-    }
-}
-
-#[inline(always)]
-const fn mb_get_pawn_attack_offset(piece: Piece) -> [i32; 2] {
-    const WHITE_ATTACK_OFFSET: [i32; 2] = [-9, -11];
-    const BLACK_ATTACK_OFFSET: [i32; 2] = [9, 11];
+const fn pawn_push_square(i: usize, piece: Piece) -> Option<usize> {
     match piece.pcolour {
-        PieceColour::White => WHITE_ATTACK_OFFSET,
-        PieceColour::Black => BLACK_ATTACK_OFFSET,
-    }
-}
-
-#[inline(always)]
-const fn mb_get_offset(piece: Piece) -> Offset {
-    match piece.ptype {
-        PieceType::Pawn => PAWN_OFFSET, // not used
-        PieceType::Knight => KNIGHT_OFFSET,
-        PieceType::Bishop => BISHOP_OFFSET,
-        PieceType::Rook => ROOK_OFFSET,
-        PieceType::Queen => QUEEN_KING_OFFSET,
-        PieceType::King => QUEEN_KING_OFFSET,
-    }
-}
-
-#[inline(always)]
-const fn get_slide(piece: Piece) -> bool {
-    match piece.ptype {
-        PieceType::Pawn | PieceType::Knight | PieceType::King => false,
-        PieceType::Bishop | PieceType::Rook | PieceType::Queen => true,
+        PieceColour::White => i.checked_sub(8),
+        PieceColour::Black => {
+            if i + 8 < 64 {
+                Some(i + 8)
+            } else {
+                None
+            }
+        }
     }
 }
 
 #[inline(always)]
-const fn pawn_is_promotion_square(i: i32, piece: Piece) -> bool {
+const fn pawn_is_promotion_square(i: usize, piece: Piece) -> bool {
     match piece.pcolour {
         PieceColour::White => i <= 7,
         PieceColour::Black => i >= 56,
@@ -362,160 +355,106 @@ fn is_castle_possible(
 // doesnt account for discovered king checks after the move
 pub(crate) fn movegen(
     pos: &position::Pos64,
+    bitboards: &Bitboards,
     movegen_flags: &MovegenFlags,
     piece: Piece,
     i: usize,
     mv_map: &mut dyn MoveMap,
 ) {
+    let occupied = bitboards.occupied();
+    let enemy = bitboards.colour(!piece.pcolour);
+
     // Move gen for pawns
     if piece.ptype == PieceType::Pawn {
-        // mailbox offset for moving pawns straight up
-        let push_offset = mb_get_pawn_push_offset(piece);
-
-        // closure that pushes move to mv_map, if move is valid and the mv square is empty
-        // returns true if it pushes successfully
-        let mut push_if_empty = |mv: i32, mvtype: MoveType| -> bool {
-            // check mv is valid
-            if mv >= 0 {
-                // push mv if the square is empty
-                if is_square_empty(pos, mv as usize) {
-                    if pawn_is_promotion_square(mv, piece) {
-                        pawn_promotion(mv_map, i, piece, mv, None);
-                    } else {
-                        mv_map.add_move(
-                            &(Move {
-                                piece,
-                                from: i,
-                                to: mv as usize,
-                                move_type: mvtype,
-                            }),
-                        );
-                    }
-                    true
+        // pushes, only if the square in front is empty
+        if let Some(single) = pawn_push_square(i, piece) {
+            if occupied & bitboard::square_bb(single) == 0 {
+                if pawn_is_promotion_square(single, piece) {
+                    pawn_promotion(mv_map, i, piece, single, None);
                 } else {
-                    false
-                }
-            } else {
-                false // also return false if mv is out of bounds
-            }
-        };
-
-        let mv_single_push = mailbox::next_mailbox_number(i, push_offset);
-        let is_empty = push_if_empty(mv_single_push, MoveType::PawnPush);
-
-        // check if pawn is still on starting rank
-        let is_starting = pawn_is_starting_rank(i, piece);
-
-        // if pawn is on starting square and the first square above it was empty
-        // this is to prevent the pawn from jumping over a piece on it's first move
-        if is_starting && is_empty {
-            let mv_double_push = mailbox::next_mailbox_number(i, push_offset * 2);
-            // again, only pushing if the second square above is empty
-            push_if_empty(mv_double_push, MoveType::DoublePawnPush);
-        }
-
-        // Attacking moves for pawns
-        let attack_offset = mb_get_pawn_attack_offset(piece);
-
-        for j in attack_offset {
-            let mv = mailbox::next_mailbox_number(i, j);
-            if mv >= 0 {
-                let mv_square = &pos[mv as usize];
-                match mv_square {
-                    Square::Piece(mv_square_piece) => {
-                        if piece.pcolour != mv_square_piece.pcolour {
-                            if pawn_is_promotion_square(mv, piece) {
-                                pawn_promotion(mv_map, i, piece, mv, Some(mv_square_piece.ptype));
-                            } else {
+                    mv_map.add_move(
+                        &(Move {
+                            piece,
+                            from: i,
+                            to: single,
+                            move_type: MoveType::PawnPush,
+                        }),
+                    );
+                    // double push from the starting rank, which can never reach the promotion rank
+                    if pawn_is_starting_rank(i, piece) {
+                        if let Some(double) = pawn_push_square(single, piece) {
+                            if occupied & bitboard::square_bb(double) == 0 {
                                 mv_map.add_move(
                                     &(Move {
                                         piece,
                                         from: i,
-                                        to: mv as usize,
-                                        move_type: MoveType::Capture(mv_square_piece.ptype),
+                                        to: double,
+                                        move_type: MoveType::DoublePawnPush,
                                     }),
                                 );
                             }
                         }
                     }
-                    Square::Empty => {}
                 }
             }
         }
-        // en passant captures, checking pawns left and right
-        // also dont check for promotion, as a pawn cannot en passant to the back rank
-        if movegen_flags.en_passant.is_some() {
-            let attack_en_passant_offset = [-1, 1];
-            let en_passant_mv = movegen_flags.en_passant.unwrap();
-            for j in attack_en_passant_offset {
-                let mv = mailbox::next_mailbox_number(i, j);
-                if mv == (en_passant_mv as i32) {
-                    // check if square above this is empty
-                    let mv_above = mailbox::next_mailbox_number(mv as usize, push_offset);
-                    if mv_above >= 0 && is_square_empty(pos, mv_above as usize) {
-                        mv_map.add_move(
-                            &(Move {
-                                piece,
-                                from: i,
-                                to: mv_above as usize,
-                                move_type: MoveType::EnPassant(mv as usize),
-                            }),
-                        );
-                    }
-                } else {
-                    continue;
-                }
+
+        // Attacking moves for pawns
+        for to in bitboard::squares(bitboard::PAWN_ATTACKS[piece.pcolour as usize][i] & enemy) {
+            let Square::Piece(captured) = pos[to] else {
+                unreachable!();
+            };
+            if pawn_is_promotion_square(to, piece) {
+                pawn_promotion(mv_map, i, piece, to, Some(captured.ptype));
+            } else {
+                mv_map.add_move(
+                    &(Move {
+                        piece,
+                        from: i,
+                        to,
+                        move_type: MoveType::Capture(captured.ptype),
+                    }),
+                );
             }
         }
-    } else {
-        // move gen for other pieces
-        for j in mb_get_offset(piece) {
-            // end of offsets
-            if j == 0 {
-                break;
-            }
 
-            let mut mv = mailbox::next_mailbox_number(i, j);
-            let mut slide_idx = j;
-
-            while mv >= 0 {
-                let mv_square = &pos[mv as usize];
-                match mv_square {
-                    Square::Piece(mv_square_piece) => {
-                        if piece.pcolour != mv_square_piece.pcolour {
-                            mv_map.add_move(
-                                &(Move {
-                                    piece,
-                                    from: i,
-                                    to: mv as usize,
-                                    move_type: MoveType::Capture(mv_square_piece.ptype),
-                                }),
-                            );
-                        }
-                        break; // break the slide after encountering a piece
-                    }
-                    Square::Empty => {
+        // en passant captures, the flag is the square of the pawn that can be captured, which must be beside this pawn
+        // also dont check for promotion, as a pawn cannot en passant to the back rank
+        if let Some(en_passant_mv) = movegen_flags.en_passant {
+            if bitboard::adjacent_bb(i) & bitboard::square_bb(en_passant_mv) != 0 {
+                // check if square above this is empty
+                if let Some(to) = pawn_push_square(en_passant_mv, piece) {
+                    if occupied & bitboard::square_bb(to) == 0 {
                         mv_map.add_move(
                             &(Move {
                                 piece,
                                 from: i,
-                                to: mv as usize,
-                                move_type: MoveType::Normal,
+                                to,
+                                move_type: MoveType::EnPassant(en_passant_mv),
                             }),
                         );
                     }
                 }
-                // is piece a sliding type
-                if get_slide(piece) {
-                    slide_idx += j;
-                    mv = mailbox::next_mailbox_number(i, slide_idx);
-
-                    continue;
-                } else {
-                    break;
-                } // continue through rest of offsets
             }
         }
+    } else {
+        // move gen for other pieces, any attacked square not holding a piece of our own colour
+        let attacks =
+            bitboard::piece_attacks(piece, i, occupied) & !bitboards.colour(piece.pcolour);
+        for to in bitboard::squares(attacks) {
+            let move_type = match pos[to] {
+                Square::Piece(captured) => MoveType::Capture(captured.ptype),
+                Square::Empty => MoveType::Normal,
+            };
+            mv_map.add_move(
+                &(Move {
+                    piece,
+                    from: i,
+                    to,
+                    move_type,
+                }),
+            );
+        }
     }
 
     // Castling movegen
@@ -600,60 +539,7 @@ pub(crate) fn movegen(
     }
 }
 
-pub fn movegen_in_check(pos: &position::Pos64, king_idx: usize, king_colour: PieceColour) -> bool {
-    for (i, s) in pos.iter().enumerate() {
-        if let Square::Piece(piece) = s {
-            if piece.pcolour != king_colour {
-                // Move gen for pawns
-                if piece.ptype == PieceType::Pawn {
-                    // Defending moves for pawns
-                    let attack_offset = mb_get_pawn_attack_offset(*piece);
-                    for j in attack_offset {
-                        let mv = mailbox::next_mailbox_number(i, j);
-                        if mv >= 0 {
-                            if (mv as usize) == king_idx {
-                                return true;
-                            }
-                        }
-                    }
-                } else {
-                    // move gen for other pieces
-                    let mb_offset = mb_get_offset(*piece);
-                    let slide = get_slide(*piece);
-                    for j in mb_offset {
-                        // end of offsets
-                        if j == 0 {
-                            break;
-                        }
-
-                        let mut mv = mailbox::next_mailbox_number(i, j);
-                        let mut slide_idx = j;
-
-                        while mv >= 0 {
-                            if matches!(&pos[mv as usize], Square::Piece(_)) {
-                                if mv as usize == king_idx {
-                                    return true;
-                                }
-                                break; // break the slide after encountering a piece
-                            }
-                            // repeating this code here and in the matches! is faster than just putting it on top. Don't know why
-                            if mv as usize == king_idx {
-                                return true;
-                            }
-
-                            // is piece a sliding type
-                            if slide {
-                                slide_idx += j;
-                                mv = mailbox::next_mailbox_number(i, slide_idx);
-                                continue;
-                            } else {
-                                break;
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
-    false
+#[inline(always)]
+pub fn movegen_in_check(bitboards: &Bitboards, king_idx: usize, king_colour: PieceColour) -> bool {
+    bitboards.is_attacked(king_idx, !king_colour)
 }
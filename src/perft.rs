@@ -1,8 +1,13 @@
+use std::ops::AddAssign;
+use std::str::FromStr;
 use std::time::{Duration, Instant};
 
+use crate::errors::{FenParseError, FenParseErrorKind};
+use crate::fen::FEN;
+use crate::search_position::{SearchPosition, MOVE_LIST_START_SIZE};
 use crate::{board, engine, movegen::*, position::Position, transposition, BoardState};
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct PerftNodes {
     pub nodes: u64,
     pub captures: u64,
@@ -11,6 +16,55 @@ pub struct PerftNodes {
     pub promotions: u64,
 }
 
+impl AddAssign for PerftNodes {
+    fn add_assign(&mut self, other: Self) {
+        self.nodes += other.nodes;
+        self.captures += other.captures;
+        self.en_passant += other.en_passant;
+        self.castles += other.castles;
+        self.promotions += other.promotions;
+    }
+}
+
+// a line of an EPD perft suite, a FEN followed by the expected node count at each depth
+// e.g. "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - ;D1 20 ;D2 400 ;D3 8902"
+#[derive(Debug, Clone)]
+pub struct PerftEpd {
+    pub fen: FEN,
+    pub depths: Vec<(u8, u64)>,
+}
+
+impl FromStr for PerftEpd {
+    type Err = FenParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split(';');
+        let fen = fields.next().unwrap_or_default().trim().parse::<FEN>()?;
+        let mut depths = Vec::new();
+        for field in fields {
+            let parsed = field
+                .trim()
+                .strip_prefix('D')
+                .and_then(|f| f.split_once(' '))
+                .and_then(|(d, n)| Some((d.parse::<u8>().ok()?, n.trim().parse::<u64>().ok()?)));
+            match parsed {
+                Some(depth) => depths.push(depth),
+                None => {
+                    return Err(FenParseError::new(
+                        FenParseErrorKind::EpdOperation,
+                        format!(
+                            "Invalid perft depth field: {}. Expected D<depth> <nodes>",
+                            field.trim()
+                        ),
+                    )
+                    .with_token(field.trim()))
+                }
+            }
+        }
+        Ok(Self { fen, depths })
+    }
+}
+
 pub fn perft(pos_iterations: u32, engine_iterations: u32) {
     let pos = Position::new_starting();
     let board = board::Board::new();
@@ -30,9 +84,9 @@ pub fn perft(pos_iterations: u32, engine_iterations: u32) {
 
     let mut total_engine_time = Duration::new(0, 0);
     for i in 0..engine_iterations {
-        let mut tt = transposition::TT::new();
+        let tt = transposition::TranspositionTable::new();
         let start = Instant::now();
-        engine_perft(board.get_current_state(), 7, &mut tt);
+        engine_perft(board.get_current_state(), 7, &tt);
         let duration = start.elapsed();
         total_engine_time += duration;
         println!(
@@ -51,7 +105,12 @@ pub fn pos_perft(pos: &Position, depth: u8) -> PerftNodes {
 
     let start = Instant::now();
 
-    get_all_legal_positions(pos, depth, &mut nodes);
+    get_all_legal_positions(
+        &mut SearchPosition::from(pos.clone()),
+        depth,
+        &mut move_lists(depth),
+        &mut nodes,
+    );
 
     let duration = start.elapsed();
 
@@ -70,10 +129,49 @@ pub fn pos_perft(pos: &Position, depth: u8) -> PerftNodes {
     nodes
 }
 
-pub fn engine_perft(bs: &BoardState, depth: u8, tt: &mut transposition::TranspositionTable) {
+// perft node count without any output, for use by callers that report the results themselves
+pub fn perft_nodes(bs: &BoardState, depth: u8) -> PerftNodes {
+    let mut nodes = PerftNodes::default();
+    get_all_legal_positions(
+        &mut SearchPosition::from(bs),
+        depth,
+        &mut move_lists(depth),
+        &mut nodes,
+    );
+    nodes
+}
+
+// perft split by root move, the sum of all the node counts is the perft result
+pub fn perft_divide(bs: &BoardState, depth: u8) -> Vec<(Move, PerftNodes)> {
+    let mut sp = SearchPosition::from(bs);
+    let mut divide = Vec::new();
+    if depth == 0 {
+        return divide;
+    }
+    let mut move_lists = move_lists(depth);
+    let (moves, child_lists) = move_lists.split_first_mut().unwrap();
+    sp.pseudo_legal_moves(moves);
+    for mv in moves.iter() {
+        if !sp.is_move_legal(mv) {
+            continue;
+        }
+        let mut nodes = PerftNodes::default();
+        if depth == 1 {
+            count_move(mv, &mut nodes);
+        } else {
+            sp.make_move(mv);
+            get_all_legal_positions(&mut sp, depth - 1, child_lists, &mut nodes);
+            sp.unmake_move();
+        }
+        divide.push((*mv, nodes));
+    }
+    divide
+}
+
+pub fn engine_perft(bs: &BoardState, depth: u8, tt: &transposition::TranspositionTable) {
     // let mut tt = transposition::TranspositionTable::new(); // not included in duration
     let start = Instant::now();
-    let (eval, mv) = engine::choose_move(bs, depth, tt);
+    let (eval, mv) = engine::choose_move(bs, depth, tt, &engine::ClassicalEvaluator::default());
     let duration = start.elapsed();
     println!(
         "Engine perft at depth {} (took {:?} to complete):",
@@ -84,38 +182,98 @@ pub fn engine_perft(bs: &BoardState, depth: u8, tt: &mut transposition::Transpos
     println!();
 }
 
+// time-to-depth of the lazy smp search for 1, 2, 4... threads up to max_threads, from the starting position.
+// the tt is cleared before each run so every thread count starts from the same state
+pub fn engine_smp_perft(depth: u8, max_threads: usize) {
+    let bs = BoardState::new_starting();
+    let mut tt = transposition::TranspositionTable::new();
+    let limits = engine::SearchLimits::depth(depth);
+    let mut single_thread_time = None;
+    let mut threads = 1;
+    while threads <= max_threads {
+        tt.clear();
+        let options = engine::SearchOptions::threads(threads);
+        let start = Instant::now();
+        let info = engine::iterative_deepening(
+            &bs,
+            &limits,
+            &options,
+            &tt,
+            &mut engine::SearchHistory::new(),
+            None,
+            |_| {},
+        );
+        let duration = start.elapsed();
+        let single = *single_thread_time.get_or_insert(duration);
+        println!(
+            "Engine time to depth {} with {} thread(s): {:?} (speedup: {:.2}x)",
+            depth,
+            threads,
+            duration,
+            single.as_secs_f64() / duration.as_secs_f64()
+        );
+        println!(" - Nodes: {}", info.nodes);
+        println!(" - Best move: {}", info.best_move.to_uci_string());
+        println!();
+        threads *= 2;
+    }
+}
+
+#[inline(always)]
+fn count_move(mv: &Move, nodes: &mut PerftNodes) {
+    match mv.move_type {
+        MoveType::EnPassant(_) => {
+            nodes.en_passant += 1;
+            nodes.captures += 1;
+        }
+        MoveType::Promotion(_, capture) => {
+            nodes.promotions += 1;
+            if capture.is_some() {
+                nodes.captures += 1;
+            }
+        }
+        MoveType::Castle(_) => {
+            nodes.castles += 1;
+        }
+        MoveType::Capture(_) => {
+            nodes.captures += 1;
+        }
+        _ => {}
+    }
+    nodes.nodes += 1;
+}
+
+// one move list for each ply of a perft to depth, reused by every position at that ply
+fn move_lists(depth: u8) -> Vec<Vec<Move>> {
+    (0..depth)
+        .map(|_| Vec::with_capacity(MOVE_LIST_START_SIZE))
+        .collect()
+}
+
 #[inline]
-fn get_all_legal_positions(pos: &Position, depth: u8, nodes: &mut PerftNodes) {
-    let moves = pos.get_legal_moves();
-    if depth == 0 || moves.is_empty() {
+fn get_all_legal_positions(
+    sp: &mut SearchPosition,
+    depth: u8,
+    move_lists: &mut [Vec<Move>],
+    nodes: &mut PerftNodes,
+) {
+    // only reached for a perft to depth 0, which is the one root position
+    if depth == 0 {
+        nodes.nodes += 1;
         return;
     }
-    for mv in moves {
+    let (moves, child_lists) = move_lists.split_first_mut().unwrap();
+    sp.pseudo_legal_moves(moves);
+    for mv in moves.iter() {
+        if !sp.is_move_legal(mv) {
+            continue;
+        }
         if depth == 1 {
-            match mv.move_type {
-                MoveType::EnPassant(_) => {
-                    nodes.en_passant += 1;
-                    nodes.captures += 1;
-                }
-                MoveType::Promotion(_, capture) => {
-                    nodes.promotions += 1;
-                    if capture.is_some() {
-                        nodes.captures += 1;
-                    }
-                }
-                MoveType::Castle(_) => {
-                    nodes.castles += 1;
-                }
-                MoveType::Capture(_) => {
-                    nodes.captures += 1;
-                }
-                _ => {}
-            }
-            nodes.nodes += 1;
+            count_move(mv, nodes);
         } else {
-            let p = pos.new_position(mv);
-            //let unmake_move = pos.make_move(mv);
-            get_all_legal_positions(&p, depth - 1, nodes);
+            sp.make_move(mv);
+            get_all_legal_positions(sp, depth - 1, child_lists, nodes);
+            sp.unmake_move();
         }
     }
 }
@@ -201,5 +359,72 @@ mod test {
 
         let pos6_nodes = pos_perft(&pos6, 4);
         assert_eq!(pos6_nodes.nodes, 3894594);
+
+        // https://www.chessprogramming.org/Chess960_Perft_Results
+        let chess960_pos1 = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9"
+            .parse::<FEN>()
+            .unwrap()
+            .into();
+        let chess960_pos2 = "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w KQkq - 1 9"
+            .parse::<FEN>()
+            .unwrap()
+            .into();
+
+        assert_eq!(pos_perft(&chess960_pos1, 4).nodes, 326672);
+        assert_eq!(pos_perft(&chess960_pos2, 4).nodes, 667366);
+    }
+
+    #[test]
+    fn test_perft_divide() {
+        let bs = BoardState::new_starting();
+        let divide = perft_divide(&bs, 3);
+        assert_eq!(divide.len(), 20);
+        let mut total = PerftNodes::default();
+        for (_, nodes) in &divide {
+            total += *nodes;
+        }
+        assert_eq!(total, perft_nodes(&bs, 3));
+        assert_eq!(total.nodes, 8902);
+        let e2e4 = divide
+            .iter()
+            .find(|(mv, _)| mv.to_uci_string() == "e2e4")
+            .unwrap();
+        assert_eq!(e2e4.1.nodes, 600);
+    }
+
+    #[test]
+    fn test_perft_depth_zero() {
+        // the root position itself, even with no legal moves
+        let bs = BoardState::new_starting();
+        assert_eq!(
+            perft_nodes(&bs, 0),
+            PerftNodes {
+                nodes: 1,
+                ..Default::default()
+            }
+        );
+        let mated = BoardState::from(
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3"
+                .parse::<FEN>()
+                .unwrap(),
+        );
+        assert_eq!(perft_nodes(&mated, 0).nodes, 1);
+        assert_eq!(perft_nodes(&mated, 1).nodes, 0);
+        assert!(perft_divide(&bs, 0).is_empty());
+    }
+
+    #[test]
+    fn test_perft_epd_suite() {
+        let suite = std::fs::read_to_string("test_data/perftsuite.epd").unwrap();
+        for line in suite.lines().filter(|l| !l.trim().is_empty()) {
+            let epd = line.parse::<PerftEpd>().unwrap();
+            let bs = BoardState::from(epd.fen);
+            // deeper depths are covered by test_perft
+            for (depth, expected) in epd.depths.into_iter().filter(|(d, _)| *d <= 3) {
+                assert_eq!(perft_nodes(&bs, depth).nodes, expected, "{}", line);
+            }
+        }
+        assert!("8/8/8/8/8/8/8/8 w - - ;D1 20".parse::<PerftEpd>().is_err());
+        assert!("8/8/8/8/8/8/8/K6k w - - ;D1".parse::<PerftEpd>().is_err());
     }
 }
@@ -1,5 +1,8 @@
 // Implementing standard from <https://ia902908.us.archive.org/26/items/pgn-standard-1994-03-12/PGN_standard_1994-03-12.txt>
+pub mod command;
+pub mod movetext;
 pub mod notation;
+pub mod reader;
 pub mod tag;
 mod token;
 
@@ -8,11 +11,12 @@ use std::str::FromStr;
 
 use chrono::prelude::*;
 
-use crate::errors::PGNParseError;
+use crate::errors::{PGNParseError, PGNParseErrorKind, TextPosition};
 use crate::fen::FEN;
+use crate::log_and_return_error;
 use crate::movegen::PieceColour;
 use crate::{board, GameOverState};
-use notation::*;
+use movetext::*;
 use tag::*;
 use token::*;
 
@@ -34,25 +38,89 @@ impl fmt::Display for PGNResult {
     }
 }
 
+// how strictly the text of a game is checked. lenient parsing accepts the quirks of real world files, such as UTF-8
+// text, castling written with zeros, figurine notation and e.p. suffixes, keeping a warning for each
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    #[default]
+    Strict,
+    Lenient,
+}
+
+// a quirk that fails strict parsing is kept as a warning when parsing leniently
+fn recover(
+    mode: ParseMode,
+    err: PGNParseError,
+    warnings: &mut Vec<PGNParseError>,
+) -> Result<(), PGNParseError> {
+    match mode {
+        ParseMode::Strict => log_and_return_error!(err),
+        ParseMode::Lenient => {
+            log::warn!("{}", err);
+            warnings.push(err);
+            Ok(())
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PGN {
     tags: Vec<Tag>,
-    moves: Vec<Notation>,
+    movetext: Variation, // main line, with the variations, comments and NAGs of its moves
+    // where the main line moves were read from, to locate errors when the moves are played. empty when made from a Board
+    move_positions: Vec<TextPosition>,
+    game_index: Option<usize>, // index of the game in the database it was read from
+    warnings: Vec<PGNParseError>, // quirks accepted by lenient parsing
+}
+
+// games are equal if they have the same tags and moves, wherever they were read from
+impl PartialEq for PGN {
+    fn eq(&self, other: &Self) -> bool {
+        self.tags == other.tags && self.movetext == other.movetext
+    }
 }
 
+impl Eq for PGN {}
+
 impl FromStr for PGN {
     type Err = PGNParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s, ParseMode::Strict)
+    }
+}
+
+impl PGN {
+    pub fn parse(s: &str, mode: ParseMode) -> Result<Self, PGNParseError> {
+        let mut warnings = Vec::new();
+        // the standard only allows ascii, UTF-8 names and comments are common
+        if let Some(i) = s.find(|c: char| !c.is_ascii()) {
+            let line_start = s[..i].rfind('\n').map_or(0, |n| n + 1);
+            let position = TextPosition {
+                line: s[..i].matches('\n').count() + 1,
+                column: s[line_start..i].chars().count() + 1,
+            };
+            let c = s[i..].chars().next().unwrap();
+            let err = PGNParseError::new(
+                PGNParseErrorKind::InvalidCharacter,
+                format!("Invalid character ({}), PGN must be ascii", c),
+            )
+            .with_token(&c.to_string())
+            .at(position);
+            recover(mode, err, &mut warnings)?;
+        }
+        let tokens = Tokens::from_pgn_str(s);
+        let movetext = tokens.get_movetext(mode)?;
+        warnings.extend(movetext.warnings);
         let mut new = Self {
-            tags: Vec::new(),
-            moves: Vec::new(),
+            tags: tokens.get_tags()?,
+            movetext: movetext.variation,
+            move_positions: movetext.mainline_positions,
+            game_index: None,
+            warnings,
         };
-        let tokens = Tokens::from_pgn_str(s);
-        new.tags = tokens.get_tags()?;
-        new.moves = tokens.get_move_notations()?;
         // set required tags to defaults if they are missing, using game termination marker as the Result tag if it is missing
-        new.set_required_tags_defaults(tokens.get_game_termination());
+        new.set_required_tags_defaults(movetext.termination);
         Ok(new)
     }
 }
@@ -61,7 +129,10 @@ impl From<&board::Board> for PGN {
     fn from(board: &board::Board) -> Self {
         let mut new = Self {
             tags: Vec::new(),
-            moves: Vec::new(),
+            movetext: Variation::default(),
+            move_positions: Vec::new(),
+            game_index: None,
+            warnings: Vec::new(),
         };
 
         new.tags.push(Tag::Event("Chess Oxide".to_string()));
@@ -133,7 +204,10 @@ impl From<&board::Board> for PGN {
 
         new.tags.push(Tag::Termination("UNIMPLEMENTED".to_string()));
         new.tags.push(Tag::Annotator("chess-oxide".to_string()));
-        new.moves = board.move_history_notation();
+        new.movetext = Variation::from(board.move_history_notation());
+        for (node, commands) in new.movetext.moves.iter_mut().zip(board.get_move_commands()) {
+            node.commands = commands.clone();
+        }
 
         new
     }
@@ -149,21 +223,10 @@ impl fmt::Display for PGN {
             pgn.push_str(&format!("{}\n", tag));
         }
         pgn.push('\n');
-        // wrap lines at 80 characters
-        let mut chars_since_newline = 0;
-        for (i, mv) in self.moves.iter().enumerate() {
-            if chars_since_newline >= 80 {
-                pgn.push('\n');
-                chars_since_newline = 0;
-            }
-            if i % 2 == 0 {
-                let str = format!("{}.", i / 2 + 1);
-                pgn.push_str(&str);
-                chars_since_newline += str.len();
-            }
-            let mv_str = mv.to_string();
-            pgn.push_str(&format!("{} ", mv_str));
-            chars_since_newline += mv_str.len() + 1;
+        let movetext = self.movetext.write(self.starting_ply());
+        if !movetext.is_empty() {
+            pgn.push_str(&movetext);
+            pgn.push(' ');
         }
         // unwrap is safe, the Result tag is required and set in all constructors
         let Tag::Result(termination_indicator) = self
@@ -185,8 +248,66 @@ impl PGN {
         &self.tags
     }
 
-    pub fn moves(&self) -> &Vec<Notation> {
-        &self.moves
+    // moves of the main line
+    pub fn moves(&self) -> &[MoveNode] {
+        &self.movetext.moves
+    }
+
+    pub fn movetext(&self) -> &Variation {
+        &self.movetext
+    }
+
+    // quirks accepted by lenient parsing, empty for strict parsing
+    pub fn warnings(&self) -> &[PGNParseError] {
+        &self.warnings
+    }
+
+    // the game was read from a database, its positions are moved from the start of the game to the start of the file
+    pub(crate) fn set_game(&mut self, game_index: usize, first_line: usize) {
+        self.game_index = Some(game_index);
+        for position in &mut self.move_positions {
+            position.line += first_line - 1;
+        }
+        self.warnings = std::mem::take(&mut self.warnings)
+            .into_iter()
+            .map(|warning| warning.in_game(game_index, first_line))
+            .collect();
+    }
+
+    // gives an error found playing the game the game index, and the position and text of the main line move at
+    // move_idx if there is one
+    pub(crate) fn locate_error(
+        &self,
+        err: PGNParseError,
+        move_idx: Option<usize>,
+    ) -> PGNParseError {
+        let mut err = err;
+        if let Some(i) = move_idx {
+            if err.token().is_none() {
+                err = err.with_token(&self.movetext.moves[i].notation.to_string());
+            }
+            if let Some(position) = self.move_positions.get(i) {
+                err = err.at(*position);
+            }
+        }
+        match self.game_index {
+            Some(game_index) => err.in_game(game_index, 1),
+            None => err,
+        }
+    }
+
+    // ply of the first move, 0 unless the game starts from a FEN position
+    fn starting_ply(&self) -> usize {
+        self.tags
+            .iter()
+            .find_map(|tag| match tag {
+                Tag::FEN(fen) => fen.parse::<FEN>().ok(),
+                _ => None,
+            })
+            .map_or(0, |fen| {
+                (fen.move_count().max(1) as usize - 1) * 2
+                    + (fen.side() == PieceColour::Black) as usize
+            })
     }
 
     fn set_required_tags_defaults(&mut self, termination: Option<String>) {
@@ -243,14 +364,21 @@ impl PGN {
 mod tests {
     use super::*;
     use crate::log_and_return_error;
+    use command::{CommandEval, EvalScore, MoveCommands};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
     use std::fs;
     use std::path::Path;
+    use std::time::Duration;
 
     // Moved in testing for now TODO see if this belongs in PGN struct or should user handle file parsing to str
     fn from_file(file_path: &Path) -> Result<PGN, PGNParseError> {
         let pgn = match fs::read_to_string(file_path) {
             Ok(pgn) => pgn,
-            Err(e) => log_and_return_error!(PGNParseError::FileError(e.to_string())),
+            Err(e) => log_and_return_error!(PGNParseError::new(
+                PGNParseErrorKind::FileError,
+                e.to_string()
+            )),
         };
         PGN::from_str(&pgn)
     }
@@ -273,6 +401,187 @@ mod tests {
         );
 
         assert_eq!(pgn.tags.len(), 10);
-        assert_eq!(pgn.moves.len(), 115);
+        assert_eq!(pgn.moves().len(), 115);
+    }
+
+    #[test]
+    fn test_pgn_annotated() {
+        let pgn_str = "[Event \"Annotated\"]\n[Result \"*\"]\n\n\
+            {A [bracketed] comment} 1.e4 e5 2.Nf3 $1 (2.f4 {King's Gambit} exf4 (2...d5)) 2...Nc6 \
+            3.Bb5?! a6 {The Morphy Defence} *";
+        let pgn = pgn_str.parse::<PGN>().unwrap();
+        assert_eq!(pgn.moves().len(), 6);
+        assert_eq!(pgn.movetext().comments, ["A [bracketed] comment"]);
+        assert_eq!(pgn.moves()[2].variations[0].moves[1].variations.len(), 1);
+        assert_eq!(pgn.moves()[4].nags, [6]);
+
+        // round trips through the export format, which sorts the tags
+        let exported = pgn.to_string();
+        let reparsed = exported.parse::<PGN>().unwrap();
+        assert_eq!(reparsed.movetext(), pgn.movetext());
+        assert_eq!(reparsed.to_string(), exported);
+        assert!(exported
+            .replace('\n', " ")
+            .contains("2.Nf3 $1 (2.f4 {King's Gambit} 2...exf4 (2...d5)) 2...Nc6"));
+
+        // the board plays the main line
+        let board = board::Board::try_from(pgn).unwrap();
+        assert_eq!(board.get_state_history().len(), 7);
+        assert_eq!(board.last_move_string_notation(), "a6".to_string());
+    }
+
+    #[test]
+    fn test_pgn_commands() {
+        let pgn_str = "[Event \"Rated Blitz game\"]\n[Result \"*\"]\n\n\
+            1. e4 { [%eval 0.17] [%clk 0:03:00] } 1... c5 { [%eval 0.3] [%clk 0:02:59] } \
+            2. Nf3 { [%clk 0:02:57.4] [%cal Gd2d4] } *";
+        let pgn = pgn_str.parse::<PGN>().unwrap();
+        let clocks: Vec<_> = pgn.moves().iter().map(|node| node.commands.clock).collect();
+        assert_eq!(
+            clocks,
+            [
+                Some(Duration::from_secs(180)),
+                Some(Duration::from_secs(179)),
+                Some(Duration::from_millis(177_400))
+            ]
+        );
+        assert!(pgn.moves().iter().all(|node| node.comments.is_empty()));
+
+        // the board keeps the commands of each move and writes them back out
+        let mut board = board::Board::try_from(pgn.clone()).unwrap();
+        assert_eq!(board.get_move_commands()[1], pgn.moves()[1].commands);
+        let mut commands = board.get_move_commands()[2].clone();
+        commands.eval = Some(CommandEval {
+            score: EvalScore::Mate(4),
+            depth: None,
+        });
+        board.set_move_commands(2, commands).unwrap();
+        assert!(board.set_move_commands(3, MoveCommands::default()).is_err());
+        let exported = PGN::from(&board).to_string().replace('\n', " ");
+        assert!(exported.contains(
+            "1.e4 {[%eval 0.17] [%clk 0:03:00]} 1...c5 {[%eval 0.30] [%clk 0:02:59]} \
+            2.Nf3 {[%eval #4] [%clk 0:02:57.4] [%cal Gd2d4]}"
+        ));
+    }
+
+    #[test]
+    fn test_pgn_lenient() {
+        let pgn_str = "[Event \"Caf\u{e9} open\"]\r\n[White \"M\u{fc}ller\"]\r\n\r\n\
+            1. e4 Nf6 \u{2658}c3 Nc6 3. e5 d5 4. exd6?! e.p. e6 Bc4 Be7 6. \u{2658}f3 0-0 *\r\n";
+        assert!(pgn_str.parse::<PGN>().is_err());
+        assert_eq!(
+            PGN::parse(pgn_str, ParseMode::Strict).unwrap_err().kind(),
+            PGNParseErrorKind::InvalidCharacter
+        );
+
+        let pgn = PGN::parse(pgn_str, ParseMode::Lenient).unwrap();
+        let kinds: Vec<_> = pgn.warnings().iter().map(|w| w.kind()).collect();
+        assert_eq!(
+            kinds,
+            [
+                PGNParseErrorKind::InvalidCharacter,
+                PGNParseErrorKind::InvalidNotation,
+                PGNParseErrorKind::InvalidNotation,
+                PGNParseErrorKind::InvalidNotation,
+                PGNParseErrorKind::InvalidNotation
+            ]
+        );
+        assert_eq!(pgn.warnings()[2].token(), Some("e.p."));
+        assert_eq!(
+            pgn.warnings()[2].position(),
+            Some(TextPosition {
+                line: 4,
+                column: 38
+            })
+        );
+        assert_eq!(pgn.tags()[0], Tag::Event("Caf\u{e9} open".to_string()));
+        assert_eq!(pgn.moves()[6].nags, [6]);
+        let board = board::Board::try_from(pgn).unwrap();
+        assert_eq!(
+            FEN::from(board.get_current_state()).to_string(),
+            "r1bq1rk1/ppp1bppp/2nPpn2/8/2B5/2N2N2/PPPP1PPP/R1BQK2R w KQ - 4 7"
+        );
+
+        // a suffix written onto the move is located after it
+        let pgn = PGN::parse("1. e4 d5 2. e5 f5 3. exf6e.p. *", ParseMode::Lenient).unwrap();
+        assert_eq!(pgn.warnings()[0].token(), Some("e.p."));
+        assert_eq!(
+            pgn.warnings()[0].position(),
+            Some(TextPosition {
+                line: 1,
+                column: 26
+            })
+        );
+        assert_eq!(pgn.moves()[4].notation.to_string(), "exf6");
+
+        // the quirks are still rejected without the UTF-8 text
+        for movetext in [
+            "1. \u{2658}f3 *",
+            "1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4. 0-0 *",
+            "1. e4 d5 2. e5 f5 3. exf6 e.p. *",
+            "1. Pe4 e5 2. Pd4 Pexd4 *",
+        ] {
+            assert!(movetext.parse::<PGN>().is_err());
+            assert!(PGN::parse(movetext, ParseMode::Lenient).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_pgn_malformed_input() {
+        let mut inputs: Vec<String> = [
+            "",
+            "[",
+            "]",
+            "[Event",
+            "[Event \"unclosed]",
+            "[]\n\n1.e4 *",
+            "{",
+            "}",
+            "(",
+            ")",
+            ";",
+            "%",
+            "$",
+            "1.e4 $999",
+            "1.",
+            "1...",
+            "e.p.",
+            "<",
+            "1.e4 (",
+            "1.e4 )",
+            "1.e4 e5 *)",
+            "1.e4 {(} e5 (1...c5 {)} ; )\n) *",
+            "1.e4 {[%clk 99999999999999999999] [%eval #] [%cal Z] [%csl}",
+            "1.Pe4 Kx9 e8=K O-O-O-O exd6e.p. *",
+        ]
+        .map(String::from)
+        .to_vec();
+        inputs.push(format!("1.e4 {}", "(".repeat(100_000)));
+        inputs.push(format!("1.e4 {}", "(1.d4 ".repeat(100_000)));
+        inputs.push(format!(
+            "1.e4 {}e5{} *",
+            "(1.d4 ".repeat(100_000),
+            ")".repeat(100_000)
+        ));
+        // random text made of the characters that mean something in PGN
+        let chars: Vec<char> =
+            "[]{}()<>;%$!?.*-/=+#\"\\ \n\t0123456789abcdefghKQRBNPOx\u{e9}\u{2658}"
+                .chars()
+                .collect();
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..1000 {
+            let len = rng.gen_range(0..100);
+            inputs.push(
+                (0..len)
+                    .map(|_| chars[rng.gen_range(0..chars.len())])
+                    .collect(),
+            );
+        }
+        // errors are fine, panics aren't
+        for input in &inputs {
+            for mode in [ParseMode::Strict, ParseMode::Lenient] {
+                let _ = PGN::parse(input, mode);
+            }
+        }
     }
 }
@@ -0,0 +1,320 @@
+// commands embedded in comments such as {[%clk 0:03:00] [%eval 0.17]}, written by lichess and chess.com exports
+use std::fmt;
+use std::time::Duration;
+
+use crate::fen;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MoveCommands {
+    pub clock: Option<Duration>, // %clk, time left on the clock after the move
+    pub elapsed: Option<Duration>, // %emt, time spent on the move
+    pub eval: Option<CommandEval>, // %eval
+    pub arrows: Vec<Arrow>,      // %cal
+    pub highlights: Vec<Highlight>, // %csl
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvalScore {
+    Centipawns(i32), // from white's point of view
+    Mate(i32),       // moves to mate, negative when black mates
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandEval {
+    pub score: EvalScore,
+    pub depth: Option<u32>, // written after the score as [%eval 0.17,20]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandColour {
+    Red,
+    Green,
+    Blue,
+    Yellow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arrow {
+    pub colour: CommandColour,
+    pub from: usize,
+    pub to: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Highlight {
+    pub colour: CommandColour,
+    pub square: usize,
+}
+
+impl MoveCommands {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // takes the known commands out of a comment, returning the text left. unknown or invalid commands are kept in
+    // the text so they aren't lost
+    pub(super) fn extract(&mut self, comment: &str) -> String {
+        let mut text = String::new();
+        let mut rest = comment;
+        let mut extracted = false;
+        while let Some(start) = rest.find("[%") {
+            let Some(len) = rest[start..].find(']') else {
+                break;
+            };
+            let command = &rest[start + 2..start + len];
+            text.push_str(&rest[..start]);
+            if self.apply(command).is_some() {
+                extracted = true;
+            } else {
+                text.push_str(&rest[start..=start + len]);
+            }
+            rest = &rest[start + len + 1..];
+        }
+        text.push_str(rest);
+        // the spaces around taken out commands are collapsed, other comments are left as they were
+        if extracted {
+            text.split_whitespace().collect::<Vec<_>>().join(" ")
+        } else {
+            text.trim().to_string()
+        }
+    }
+
+    fn apply(&mut self, command: &str) -> Option<()> {
+        let (name, args) = command.split_once(char::is_whitespace)?;
+        let args = args.trim();
+        match name {
+            "clk" => self.clock = Some(parse_duration(args)?),
+            "emt" => self.elapsed = Some(parse_duration(args)?),
+            "eval" => self.eval = Some(parse_eval(args)?),
+            "cal" => {
+                let arrows = split_list(args)
+                    .map(|arg| {
+                        let colour = parse_colour(arg.get(..1)?)?;
+                        let from = parse_square(arg.get(1..3)?)?;
+                        let to = parse_square(arg.get(3..)?)?;
+                        Some(Arrow { colour, from, to })
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                self.arrows.extend(arrows);
+            }
+            "csl" => {
+                let highlights = split_list(args)
+                    .map(|arg| {
+                        let colour = parse_colour(arg.get(..1)?)?;
+                        let square = parse_square(arg.get(1..)?)?;
+                        Some(Highlight { colour, square })
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                self.highlights.extend(highlights);
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+impl fmt::Display for MoveCommands {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut commands = Vec::new();
+        if let Some(eval) = self.eval {
+            commands.push(format!("[%eval {}]", eval));
+        }
+        if let Some(clock) = self.clock {
+            commands.push(format!("[%clk {}]", DurationDisplay(clock)));
+        }
+        if let Some(elapsed) = self.elapsed {
+            commands.push(format!("[%emt {}]", DurationDisplay(elapsed)));
+        }
+        if !self.arrows.is_empty() {
+            let arrows: Vec<_> = self.arrows.iter().map(Arrow::to_string).collect();
+            commands.push(format!("[%cal {}]", arrows.join(",")));
+        }
+        if !self.highlights.is_empty() {
+            let highlights: Vec<_> = self.highlights.iter().map(Highlight::to_string).collect();
+            commands.push(format!("[%csl {}]", highlights.join(",")));
+        }
+        write!(f, "{}", commands.join(" "))
+    }
+}
+
+impl fmt::Display for CommandEval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.score {
+            EvalScore::Centipawns(cp) => {
+                let sign = if cp < 0 { "-" } else { "" };
+                write!(f, "{}{}.{:02}", sign, cp.abs() / 100, cp.abs() % 100)?;
+            }
+            EvalScore::Mate(n) => write!(f, "#{}", n)?,
+        }
+        match self.depth {
+            Some(depth) => write!(f, ",{}", depth),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for CommandColour {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let c = match self {
+            Self::Red => 'R',
+            Self::Green => 'G',
+            Self::Blue => 'B',
+            Self::Yellow => 'Y',
+        };
+        write!(f, "{}", c)
+    }
+}
+
+impl fmt::Display for Arrow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{}{}",
+            self.colour,
+            fen::index_to_notation(self.from),
+            fen::index_to_notation(self.to)
+        )
+    }
+}
+
+impl fmt::Display for Highlight {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.colour, fen::index_to_notation(self.square))
+    }
+}
+
+// h:mm:ss with fractions of a second only when there are any
+struct DurationDisplay(Duration);
+
+impl fmt::Display for DurationDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.0.as_secs();
+        write!(f, "{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)?;
+        let millis = self.0.subsec_millis();
+        if millis > 0 {
+            write!(f, ".{}", format!("{:03}", millis).trim_end_matches('0'))?;
+        }
+        Ok(())
+    }
+}
+
+// h:mm:ss, m:ss or s, with an optional fraction of a second
+fn parse_duration(s: &str) -> Option<Duration> {
+    let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+    let mut secs = 0u64;
+    for part in whole.split(':') {
+        if part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        secs = secs.checked_mul(60)?.checked_add(part.parse().ok()?)?;
+    }
+    if fraction.len() > 3 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let millis = match fraction {
+        "" => 0,
+        _ => format!("{:0<3}", fraction).parse().ok()?,
+    };
+    Some(Duration::from_secs(secs) + Duration::from_millis(millis))
+}
+
+// pawns such as 0.17 or -1.5, or mate in moves such as #-3, with an optional search depth
+fn parse_eval(s: &str) -> Option<CommandEval> {
+    let (score, depth) = match s.split_once(',') {
+        Some((score, depth)) => (score.trim(), Some(depth.trim().parse().ok()?)),
+        None => (s, None),
+    };
+    let score = match score.strip_prefix('#') {
+        Some(n) => EvalScore::Mate(n.parse().ok()?),
+        None => {
+            let pawns: f64 = score.parse().ok()?;
+            if !pawns.is_finite() || pawns.abs() > 1e6 {
+                return None;
+            }
+            EvalScore::Centipawns((pawns * 100.0).round() as i32)
+        }
+    };
+    Some(CommandEval { score, depth })
+}
+
+fn parse_colour(s: &str) -> Option<CommandColour> {
+    match s {
+        "R" => Some(CommandColour::Red),
+        "G" => Some(CommandColour::Green),
+        "B" => Some(CommandColour::Blue),
+        "Y" => Some(CommandColour::Yellow),
+        _ => None,
+    }
+}
+
+fn parse_square(s: &str) -> Option<usize> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('a'..='h'), Some('1'..='8'), None) => fen::notation_to_index(s).ok(),
+        _ => None,
+    }
+}
+
+fn split_list(s: &str) -> impl Iterator<Item = &str> {
+    s.split(',').map(str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_commands() {
+        let mut commands = MoveCommands::default();
+        let text = commands.extract(
+            "[%eval -0.3,18] [%clk 1:02:03.5] Good move [%emt 0:00:07] [%cal Gd1d8,Re2e4] [%csl Yd4] [%foo bar]",
+        );
+        assert_eq!(text, "Good move [%foo bar]");
+        assert_eq!(
+            commands.eval,
+            Some(CommandEval {
+                score: EvalScore::Centipawns(-30),
+                depth: Some(18)
+            })
+        );
+        assert_eq!(commands.clock, Some(Duration::from_millis(3_723_500)));
+        assert_eq!(commands.elapsed, Some(Duration::from_secs(7)));
+        assert_eq!(
+            commands.arrows,
+            [
+                Arrow {
+                    colour: CommandColour::Green,
+                    from: 59,
+                    to: 3
+                },
+                Arrow {
+                    colour: CommandColour::Red,
+                    from: 52,
+                    to: 36
+                }
+            ]
+        );
+        assert_eq!(
+            commands.highlights,
+            [Highlight {
+                colour: CommandColour::Yellow,
+                square: 35
+            }]
+        );
+        assert_eq!(
+            commands.to_string(),
+            "[%eval -0.30,18] [%clk 1:02:03.5] [%emt 0:00:07] [%cal Gd1d8,Re2e4] [%csl Yd4]"
+        );
+
+        let mut mate = MoveCommands::default();
+        assert_eq!(mate.extract("[%eval #-3]"), "");
+        assert_eq!(mate.eval.unwrap().score, EvalScore::Mate(-3));
+        assert_eq!(mate.to_string(), "[%eval #-3]");
+
+        // invalid commands stay in the comment
+        let mut invalid = MoveCommands::default();
+        let text = invalid.extract("[%clk 1:xx] [%cal Gd1d9] [%eval]");
+        assert_eq!(text, "[%clk 1:xx] [%cal Gd1d9] [%eval]");
+        assert!(invalid.is_empty());
+    }
+}
@@ -0,0 +1,551 @@
+// the movetext of a game as a tree: the main line is a Variation, and each move keeps its NAGs, the comments
+// after it and the recursive annotation variations (RAV) that can be played instead of it
+use super::command::MoveCommands;
+use super::notation::Notation;
+use super::token::Token;
+use super::{recover, ParseMode};
+use crate::errors::{PGNParseError, PGNParseErrorKind, TextPosition};
+use crate::log_and_return_error;
+
+// lines longer than this are wrapped in export format
+const MAX_LINE_LEN: usize = 80;
+
+// variations nested deeper than this are rejected, or left out when parsing leniently, so a hostile file
+// can't overflow the stack of the recursive parser
+const MAX_VARIATION_DEPTH: usize = 64;
+
+// suffix annotations and the NAGs they stand for, $1 to $6
+const GLYPHS: [&str; 6] = ["!", "?", "!!", "??", "!?", "?!"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveNode {
+    pub notation: Notation,
+    // numeric annotation glyphs, suffix annotations such as ! are stored as their NAG
+    pub nags: Vec<u8>,
+    pub comments: Vec<String>,
+    // clock, eval and drawing commands taken out of the comments
+    pub commands: MoveCommands,
+    // alternatives to this move, each starting with a move played instead of it
+    pub variations: Vec<Variation>,
+}
+
+impl MoveNode {
+    pub fn new(notation: Notation) -> Self {
+        Self {
+            notation,
+            nags: Vec::new(),
+            comments: Vec::new(),
+            commands: MoveCommands::default(),
+            variations: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Variation {
+    // comments before the first move of the line
+    pub comments: Vec<String>,
+    pub moves: Vec<MoveNode>,
+}
+
+impl From<Vec<Notation>> for Variation {
+    fn from(notations: Vec<Notation>) -> Self {
+        Self {
+            comments: Vec::new(),
+            moves: notations.into_iter().map(MoveNode::new).collect(),
+        }
+    }
+}
+
+// the movetext of a game as read from its tokens
+pub(super) struct ParsedMovetext {
+    pub(super) variation: Variation,
+    pub(super) termination: Option<String>, // game termination marker at the end of the movetext
+    pub(super) mainline_positions: Vec<TextPosition>,
+    pub(super) warnings: Vec<PGNParseError>, // quirks accepted by lenient parsing
+}
+
+impl Variation {
+    // parses the movetext tokens that follow the tag pairs, up to the game termination marker
+    pub(super) fn parse(
+        tokens: &[Token],
+        mode: ParseMode,
+    ) -> Result<ParsedMovetext, PGNParseError> {
+        let mut parser = Parser {
+            tokens,
+            i: 0,
+            depth: 0,
+            mode,
+            mainline_positions: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let (variation, termination) = parser.parse_variation(None)?;
+        Ok(ParsedMovetext {
+            variation,
+            termination,
+            mainline_positions: parser.mainline_positions,
+            warnings: parser.warnings,
+        })
+    }
+
+    // the moves of the line in export format, ply is the ply of the first move with 0 being white's first move
+    pub(super) fn write(&self, ply: usize) -> String {
+        let mut words = Vec::new();
+        self.push_words(ply, &mut words);
+        let mut movetext = String::new();
+        let mut line_len = 0;
+        for word in words {
+            if line_len > 0 && line_len + word.len() + 1 > MAX_LINE_LEN {
+                movetext.push('\n');
+                line_len = 0;
+            } else if line_len > 0 {
+                movetext.push(' ');
+                line_len += 1;
+            }
+            line_len += word.len();
+            movetext.push_str(&word);
+        }
+        movetext
+    }
+
+    fn push_words(&self, ply: usize, words: &mut Vec<String>) {
+        for comment in &self.comments {
+            words.push(format!("{{{}}}", comment));
+        }
+        // black moves need a move number at the start of a line, or after a comment or variation
+        let mut needs_number = true;
+        for (i, node) in self.moves.iter().enumerate() {
+            let ply = ply + i;
+            let number = ply / 2 + 1;
+            words.push(match (ply.is_multiple_of(2), needs_number) {
+                (true, _) => format!("{}.{}", number, node.notation),
+                (false, true) => format!("{}...{}", number, node.notation),
+                (false, false) => node.notation.to_string(),
+            });
+            needs_number = false;
+            for nag in &node.nags {
+                words.push(format!("${}", nag));
+            }
+            // commands are written at the start of the first comment
+            let mut comments = node.comments.clone();
+            if !node.commands.is_empty() {
+                match comments.first_mut() {
+                    Some(comment) => *comment = format!("{} {}", node.commands, comment),
+                    None => comments.push(node.commands.to_string()),
+                }
+            }
+            for comment in comments {
+                words.push(format!("{{{}}}", comment));
+                needs_number = true;
+            }
+            for variation in &node.variations {
+                let mut variation_words = Vec::new();
+                variation.push_words(ply, &mut variation_words);
+                if variation_words.is_empty() {
+                    words.push("()".to_string());
+                } else {
+                    variation_words[0].insert(0, '(');
+                    variation_words.last_mut().unwrap().push(')');
+                    words.append(&mut variation_words);
+                }
+                needs_number = true;
+            }
+        }
+    }
+
+    // the notations of the line without the variations
+    pub fn notations(&self) -> impl Iterator<Item = &Notation> {
+        self.moves.iter().map(|node| &node.notation)
+    }
+
+    // comments before the first move belong to the line, the others to the move before them with their commands
+    // taken out
+    fn add_comment(&mut self, comment: String) {
+        match self.moves.last_mut() {
+            Some(node) => {
+                let text = node.commands.extract(&comment);
+                // a comment holding only commands isn't kept
+                if !text.is_empty() || comment.is_empty() {
+                    node.comments.push(text);
+                }
+            }
+            None => self.comments.push(comment),
+        }
+    }
+
+    fn add_nag(&mut self, token: &Token, nag: Option<u8>) -> Result<(), PGNParseError> {
+        let (Some(node), Some(nag)) = (self.moves.last_mut(), nag) else {
+            let err = token.error(
+                PGNParseErrorKind::InvalidMovetext,
+                format!("Invalid annotation {}, NAGs follow a move", token.value),
+            );
+            log_and_return_error!(err)
+        };
+        node.nags.push(nag);
+        Ok(())
+    }
+}
+
+// NAG of a suffix annotation such as !? or a $ token
+fn parse_nag(s: &str) -> Option<u8> {
+    match s.strip_prefix('$') {
+        Some(n) => n.parse().ok(),
+        None => GLYPHS
+            .iter()
+            .position(|glyph| *glyph == s)
+            .map(|i| i as u8 + 1),
+    }
+}
+
+fn is_game_termination_marker(s: &str) -> bool {
+    matches!(s, "1-0" | "0-1" | "1/2-1/2" | "*")
+}
+
+// SAN written with figurines, castling with zeros or pawn moves with a P, in the letters of the standard.
+// None if there is nothing to change
+fn normalise_san(san: &str) -> Option<String> {
+    let mut normalised: String = san
+        .chars()
+        .filter_map(|c| match c {
+            '\u{2654}' | '\u{265A}' => Some('K'),
+            '\u{2655}' | '\u{265B}' => Some('Q'),
+            '\u{2656}' | '\u{265C}' => Some('R'),
+            '\u{2657}' | '\u{265D}' => Some('B'),
+            '\u{2658}' | '\u{265E}' => Some('N'),
+            '\u{2659}' | '\u{265F}' => None,
+            c => Some(c),
+        })
+        .collect();
+    if matches!(normalised.trim_end_matches(['+', '#']), "0-0" | "0-0-0") {
+        normalised = normalised.replace('0', "O");
+    }
+    // pawn moves written with a P, such as Pe4 or Pexd5
+    if let Some(pawn_move) = normalised.strip_prefix('P') {
+        if pawn_move.starts_with(|c: char| matches!(c, 'a'..='h')) {
+            normalised = pawn_move.to_string();
+        }
+    }
+    (normalised != san).then_some(normalised)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    i: usize,
+    depth: usize, // number of variations the parser is in
+    mode: ParseMode,
+    mainline_positions: Vec<TextPosition>,
+    warnings: Vec<PGNParseError>,
+}
+
+impl<'a> Parser<'a> {
+    // a line of moves up to the closing parenthesis of a variation, or the termination marker or end of the
+    // main line. open is the parenthesis starting the variation, None for the main line
+    fn parse_variation(
+        &mut self,
+        open: Option<&'a Token>,
+    ) -> Result<(Variation, Option<String>), PGNParseError> {
+        let mut variation = Variation::default();
+        while let Some(token) = self.tokens.get(self.i) {
+            self.i += 1;
+            let value = token.value.as_str();
+            match value {
+                _ if value.trim().is_empty() || value == "." => {}
+                "{" => {
+                    let (comment, closed) = self.read_until("}");
+                    if !closed {
+                        let err = token.error(
+                            PGNParseErrorKind::InvalidMovetext,
+                            "Unclosed comment".to_string(),
+                        );
+                        log_and_return_error!(err)
+                    }
+                    variation.add_comment(comment);
+                }
+                // a rest of line comment can also end with the movetext
+                ";" => {
+                    let (comment, _) = self.read_until("\n");
+                    variation.add_comment(comment);
+                }
+                "(" => {
+                    let Some(node) = variation.moves.last_mut() else {
+                        let err = token.error(
+                            PGNParseErrorKind::InvalidMovetext,
+                            "Variation before the first move of a line".to_string(),
+                        );
+                        log_and_return_error!(err)
+                    };
+                    if self.depth == MAX_VARIATION_DEPTH {
+                        let warning = token.error(
+                            PGNParseErrorKind::InvalidMovetext,
+                            format!(
+                                "Variation nested more than {} deep left out",
+                                MAX_VARIATION_DEPTH
+                            ),
+                        );
+                        recover(self.mode, warning, &mut self.warnings)?;
+                        if !self.skip_variation() {
+                            let err = token.error(
+                                PGNParseErrorKind::InvalidMovetext,
+                                "Unclosed variation".to_string(),
+                            );
+                            log_and_return_error!(err)
+                        }
+                        continue;
+                    }
+                    self.depth += 1;
+                    let (rav, _) = self.parse_variation(Some(token))?;
+                    self.depth -= 1;
+                    node.variations.push(rav);
+                }
+                ")" if open.is_some() => return Ok((variation, None)),
+                ")" => {
+                    let err = token.error(
+                        PGNParseErrorKind::InvalidMovetext,
+                        "Closing parenthesis without a variation".to_string(),
+                    );
+                    log_and_return_error!(err)
+                }
+                // reserved for future expansion by the standard
+                "<" => {
+                    self.read_until(">");
+                }
+                _ if is_game_termination_marker(value) => {
+                    if open.is_some() {
+                        let err = token.error(
+                            PGNParseErrorKind::InvalidMovetext,
+                            format!("Game termination marker {} inside a variation", value),
+                        );
+                        log_and_return_error!(err)
+                    }
+                    return Ok((variation, Some(value.to_string())));
+                }
+                // move numbers
+                _ if value.chars().all(|c| c.is_ascii_digit()) => {}
+                _ if value.starts_with('$') => variation.add_nag(token, parse_nag(value))?,
+                _ => {
+                    let value = self.skip_en_passant_suffix(token)?;
+                    // suffix annotations can be written after the move or on their own
+                    let san = value.trim_end_matches(['!', '?']);
+                    let glyph = &value[san.len()..];
+                    if !san.is_empty() {
+                        let notation = match normalise_san(san) {
+                            Some(normalised) if self.mode == ParseMode::Lenient => {
+                                let warning = token.error(
+                                    PGNParseErrorKind::InvalidNotation,
+                                    format!("Move {} read as {}", san, normalised),
+                                );
+                                recover(self.mode, warning, &mut self.warnings)?;
+                                normalised.parse()
+                            }
+                            _ => san.parse(),
+                        }
+                        .map_err(|e| token.locate(e))?;
+                        variation.moves.push(MoveNode::new(notation));
+                        if open.is_none() {
+                            self.mainline_positions.push(token.position);
+                        }
+                    }
+                    if !glyph.is_empty() {
+                        variation.add_nag(token, parse_nag(glyph))?;
+                    }
+                }
+            }
+        }
+        if let Some(open) = open {
+            let err = open.error(
+                PGNParseErrorKind::InvalidMovetext,
+                "Unclosed variation".to_string(),
+            );
+            log_and_return_error!(err)
+        }
+        Ok((variation, None))
+    }
+
+    // the move token without an e.p. suffix, which is split by the tokenizer into "e", ".", "p" and "." tokens.
+    // the suffix can follow the move or be on its own, which leaves nothing of the token
+    fn skip_en_passant_suffix(&mut self, token: &'a Token) -> Result<&'a str, PGNParseError> {
+        let value = token.value.as_str();
+        let next = |n: usize| self.tokens.get(self.i + n).map(|t| t.value.as_str());
+        let Some(move_value) = value.strip_suffix('e') else {
+            return Ok(value);
+        };
+        if next(0) != Some(".") || next(1) != Some("p") {
+            return Ok(value);
+        }
+        // the warning points at the suffix, not the move before it
+        let suffix = if next(2) == Some(".") { "e.p." } else { "e.p" };
+        let position = TextPosition {
+            column: token.position.column + move_value.chars().count(),
+            ..token.position
+        };
+        let warning = Token::at(suffix, position).error(
+            PGNParseErrorKind::InvalidNotation,
+            format!("En passant suffix {} ignored", suffix),
+        );
+        recover(self.mode, warning, &mut self.warnings)?;
+        self.i += suffix.len() - 1;
+        Ok(move_value)
+    }
+
+    // skips the rest of a variation with the ones nested in it, and whether its closing parenthesis was found
+    fn skip_variation(&mut self) -> bool {
+        let mut depth = 1;
+        while let Some(token) = self.tokens.get(self.i) {
+            self.i += 1;
+            match token.value.as_str() {
+                // comments can hold parentheses
+                "{" => {
+                    self.read_until("}");
+                }
+                ";" => {
+                    self.read_until("\n");
+                }
+                "(" => depth += 1,
+                ")" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return true;
+                    }
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    // joins the tokens up to the closing delimiter, which is skipped, and whether it was found
+    fn read_until(&mut self, end: &str) -> (String, bool) {
+        let mut text = String::new();
+        let mut closed = false;
+        while let Some(token) = self.tokens.get(self.i) {
+            self.i += 1;
+            if token.value == end {
+                closed = true;
+                break;
+            }
+            text.push_str(&token.value);
+        }
+        (text.trim().to_string(), closed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgn::command::EvalScore;
+    use crate::pgn::token::Tokens;
+    use std::time::Duration;
+
+    fn parse(movetext: &str) -> Result<(Variation, Option<String>), PGNParseError> {
+        let movetext = Tokens::from_pgn_str(movetext).get_movetext(ParseMode::Strict)?;
+        Ok((movetext.variation, movetext.termination))
+    }
+
+    #[test]
+    fn test_parse_movetext() {
+        let (variation, termination) = parse(
+            "{Opening} 1.e4! e5 $14 (1...c5 {Sicilian} 2.Nf3 (2.c3) d6) 2.Nf3 ; rest of line\nNc6?! *",
+        )
+        .unwrap();
+        assert_eq!(termination.as_deref(), Some("*"));
+        assert_eq!(variation.comments, ["Opening"]);
+        let moves = &variation.moves;
+        assert_eq!(moves.len(), 4);
+        assert_eq!(moves[0].nags, [1]);
+        assert_eq!(moves[1].nags, [14]);
+        assert_eq!(moves[2].comments, ["rest of line"]);
+        assert_eq!(moves[3].nags, [6]);
+
+        // the variation replaces 1...e5, with a nested variation replacing 2.Nf3
+        let rav = &moves[1].variations[0];
+        assert_eq!(rav.moves.len(), 3);
+        assert_eq!(rav.moves[0].comments, ["Sicilian"]);
+        assert_eq!(
+            rav.moves[1].variations[0].moves[0].notation.to_string(),
+            "c3"
+        );
+    }
+
+    #[test]
+    fn test_write_movetext() {
+        let movetext = "{Opening} 1.e4 $1 e5 $14 (1...c5 {Sicilian} 2.Nf3 (2.c3) 2...d6) 2.Nf3 {rest of line} 2...Nc6 $6";
+        let (variation, _) = parse(movetext).unwrap();
+        assert_eq!(variation.write(0).replace('\n', " "), movetext);
+        assert!(variation
+            .write(0)
+            .lines()
+            .all(|line| line.len() <= MAX_LINE_LEN));
+        // a game starting with black to move
+        let (variation, _) = parse("12...Nf6 13.Nc3").unwrap();
+        assert_eq!(variation.write(23), "12...Nf6 13.Nc3");
+    }
+
+    #[test]
+    fn test_movetext_commands() {
+        let movetext = "1.e4 {[%eval 0.17] [%clk 0:03:00]} 1...e5 {[%clk 0:02:58] Solid} {second}";
+        let (variation, _) = parse(movetext).unwrap();
+        let moves = &variation.moves;
+        assert!(moves[0].comments.is_empty());
+        assert_eq!(moves[0].commands.clock, Some(Duration::from_secs(180)));
+        assert_eq!(
+            moves[0].commands.eval.unwrap().score,
+            EvalScore::Centipawns(17)
+        );
+        assert_eq!(moves[1].comments, ["Solid", "second"]);
+        assert_eq!(moves[1].commands.clock, Some(Duration::from_secs(178)));
+        assert_eq!(variation.write(0), movetext);
+    }
+
+    #[test]
+    fn test_parse_movetext_invalid() {
+        assert!(parse("1.e4 (e5").is_err());
+        assert!(parse("1.e4 e5)").is_err());
+        assert!(parse("(1.d4) 1.e4").is_err());
+        assert!(parse("1.e4 {unclosed").is_err());
+        assert!(parse("$1 1.e4").is_err());
+        assert!(parse("1.e4 (1.d4 1-0) e5").is_err());
+        assert!(parse("1.e4 e5!!!").is_err());
+    }
+
+    #[test]
+    fn test_parse_movetext_nesting_limit() {
+        // 1.e4 (1.d4 (1.d4 ... e5)) 1...c5 with the given number of nested variations
+        let nested = |depth: usize| {
+            format!(
+                "1.e4 {}e5{} 1...c5 *",
+                "(1.d4 ".repeat(depth),
+                ")".repeat(depth)
+            )
+        };
+        let nesting = |mut variation: &Variation| {
+            let mut depth = 0;
+            while let Some(rav) = variation
+                .moves
+                .first()
+                .and_then(|node| node.variations.first())
+            {
+                variation = rav;
+                depth += 1;
+            }
+            depth
+        };
+        let (variation, _) = parse(&nested(MAX_VARIATION_DEPTH)).unwrap();
+        assert_eq!(nesting(&variation), MAX_VARIATION_DEPTH);
+        for depth in [MAX_VARIATION_DEPTH + 1, 100_000] {
+            let err = parse(&nested(depth)).unwrap_err();
+            assert_eq!(err.kind(), PGNParseErrorKind::InvalidMovetext);
+
+            // parsed leniently the variations too deep are left out, with the rest of the game
+            let tokens = Tokens::from_pgn_str(&nested(depth));
+            let movetext = tokens.get_movetext(ParseMode::Lenient).unwrap();
+            assert_eq!(movetext.warnings.len(), 1);
+            assert_eq!(nesting(&movetext.variation), MAX_VARIATION_DEPTH);
+            assert_eq!(movetext.variation.moves.len(), 2);
+            assert_eq!(movetext.termination.as_deref(), Some("*"));
+        }
+        // a variation left out still has to be closed
+        let unclosed = format!("1.e4 {}e5", "(1.d4 ".repeat(MAX_VARIATION_DEPTH + 1));
+        let tokens = Tokens::from_pgn_str(&unclosed);
+        assert!(tokens.get_movetext(ParseMode::Lenient).is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use std::fmt;
 use std::str::FromStr;
 
-use crate::errors::PGNParseError;
+use crate::errors::{PGNParseError, PGNParseErrorKind};
 use crate::{board, movegen::*};
 use crate::{hash_to_string, log_and_return_error};
 
@@ -65,6 +65,12 @@ impl FromStr for Notation {
     type Err = PGNParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_str(s).map_err(|e| e.with_token(s))
+    }
+}
+
+impl Notation {
+    fn parse_str(s: &str) -> Result<Self, PGNParseError> {
         // check that str is valid ascii
         Self::validate_ascii(s)?;
 
@@ -124,7 +130,10 @@ impl Notation {
                 _ => {}
             }
         } else {
-            let err = PGNParseError::NotationParseError(format!("Move not legal: {:?}", mv));
+            let err = PGNParseError::new(
+                PGNParseErrorKind::IllegalMove,
+                format!("Move not legal: {:?}", mv),
+            );
             log_and_return_error!(err);
         }
 
@@ -237,10 +246,13 @@ impl Notation {
                     Self::handle_checkmate_char(&mut checkmate, notation_str, i)?;
                 }
                 _ => {
-                    let err = PGNParseError::NotationParseError(format!(
-                        "Invalid character in notation (char: '{}' at index: {})",
-                        c, i
-                    ));
+                    let err = PGNParseError::new(
+                        PGNParseErrorKind::InvalidNotation,
+                        format!(
+                            "Invalid character in notation (char: '{}' at index: {})",
+                            c, i
+                        ),
+                    );
                     log_and_return_error!(err)
                 }
             }
@@ -268,8 +280,10 @@ impl Notation {
             if is_valid_piece(piece) {
                 self.piece = Some(piece);
             } else {
-                let err =
-                    PGNParseError::NotationParseError(format!("Invalid piece char ({})", piece));
+                let err = PGNParseError::new(
+                    PGNParseErrorKind::InvalidNotation,
+                    format!("Invalid piece char ({})", piece),
+                );
                 log_and_return_error!(err)
             }
         }
@@ -281,10 +295,10 @@ impl Notation {
             if is_valid_promotion(promotion) {
                 self.promotion = Some(promotion);
             } else {
-                let err = PGNParseError::NotationParseError(format!(
-                    "Invalid promotion piece char ({})",
-                    promotion
-                ));
+                let err = PGNParseError::new(
+                    PGNParseErrorKind::InvalidNotation,
+                    format!("Invalid promotion piece char ({})", promotion),
+                );
                 log_and_return_error!(err)
             }
         }
@@ -317,22 +331,25 @@ impl Notation {
                 to_rank = rank_file_chars[3];
             }
             _ => {
-                let err = PGNParseError::NotationParseError(format!(
-                    "Invalid move notation char(s) ({:?})",
-                    rank_file_chars
-                ));
+                let err = PGNParseError::new(
+                    PGNParseErrorKind::InvalidNotation,
+                    format!("Invalid move notation char(s) ({:?})", rank_file_chars),
+                );
                 log_and_return_error!(err)
             }
         }
         if !is_valid_file(to_file)
             || !is_valid_rank(to_rank)
-            || dis_file.map_or(false, |c| !is_valid_file(c))
-            || dis_rank.map_or(false, |c| !is_valid_rank(c))
+            || dis_file.is_some_and(|c| !is_valid_file(c))
+            || dis_rank.is_some_and(|c| !is_valid_rank(c))
         {
-            let err = PGNParseError::NotationParseError(format!(
-                "Invalid rank or file char(s) in vec: ({:?})",
-                rank_file_chars
-            ));
+            let err = PGNParseError::new(
+                PGNParseErrorKind::InvalidNotation,
+                format!(
+                    "Invalid rank or file char(s) in vec: ({:?})",
+                    rank_file_chars
+                ),
+            );
             log_and_return_error!(err)
         } else {
             self.to_file = to_file;
@@ -345,10 +362,13 @@ impl Notation {
 
     fn validate_ascii(notation_str: &str) -> Result<(), PGNParseError> {
         if !notation_str.is_ascii() {
-            let err = PGNParseError::NotationParseError(format!(
-                "Invalid notation string: ({}) is not valid ascii",
-                notation_str
-            ));
+            let err = PGNParseError::new(
+                PGNParseErrorKind::InvalidNotation,
+                format!(
+                    "Invalid notation string: ({}) is not valid ascii",
+                    notation_str
+                ),
+            );
             log_and_return_error!(err)
         }
         Ok(())
@@ -357,8 +377,10 @@ impl Notation {
     fn validate_length(notation_str: &str) -> Result<(), PGNParseError> {
         let str_len = notation_str.len();
         if !(2..=8).contains(&str_len) {
-            let err =
-                PGNParseError::NotationParseError(format!("Invalid notation length ({})", str_len));
+            let err = PGNParseError::new(
+                PGNParseErrorKind::InvalidNotation,
+                format!("Invalid notation length ({})", str_len),
+            );
             log_and_return_error!(err)
         }
         Ok(())
@@ -373,10 +395,13 @@ impl Notation {
         if piece_char.is_none() {
             *piece_char = Some(c);
         } else {
-            let err = PGNParseError::NotationParseError(format!(
-                "Invalid notation, multiple uppercase piece chars (char: '{}' at index: {})",
-                notation_str, i
-            ));
+            let err = PGNParseError::new(
+                PGNParseErrorKind::InvalidNotation,
+                format!(
+                    "Invalid notation, multiple uppercase piece chars (char: '{}' at index: {})",
+                    notation_str, i
+                ),
+            );
             log_and_return_error!(err)
         }
         Ok(())
@@ -390,7 +415,7 @@ impl Notation {
         if !*capture {
             // must be at least 2 more chars after 'x' for a valid capture
             if (notation_str.len() - i) < 3 {
-                let err = PGNParseError::NotationParseError(format!(
+                let err = PGNParseError::new(PGNParseErrorKind::InvalidNotation, format!(
                     "Invalid notation, no rank or file after capture char (char: '{}' at index: {})",
                     notation_str, i
                 ));
@@ -399,10 +424,13 @@ impl Notation {
                 *capture = true;
             }
         } else {
-            let err = PGNParseError::NotationParseError(format!(
-                "Invalid notation, multiple capture chars (char: '{}' at index: {})",
-                notation_str, i
-            ));
+            let err = PGNParseError::new(
+                PGNParseErrorKind::InvalidNotation,
+                format!(
+                    "Invalid notation, multiple capture chars (char: '{}' at index: {})",
+                    notation_str, i
+                ),
+            );
             log_and_return_error!(err)
         }
         Ok(())
@@ -418,18 +446,21 @@ impl Notation {
             Some((_, c)) => match c {
                 'Q' | 'R' | 'B' | 'N' => Ok(Some(c)),
                 _ => {
-                    let err = PGNParseError::NotationParseError(format!(
-                        "Invalid promotion piece (char: '{}' at index: {})",
-                        c, i
-                    ));
+                    let err = PGNParseError::new(
+                        PGNParseErrorKind::InvalidNotation,
+                        format!("Invalid promotion piece (char: '{}' at index: {})", c, i),
+                    );
                     log_and_return_error!(err)
                 }
             },
             None => {
-                let err = PGNParseError::NotationParseError(format!(
-                    "Invalid notation, no promotion piece after '=' (char: '{}' at index: {})",
-                    notation_str, i
-                ));
+                let err = PGNParseError::new(
+                    PGNParseErrorKind::InvalidNotation,
+                    format!(
+                        "Invalid notation, no promotion piece after '=' (char: '{}' at index: {})",
+                        notation_str, i
+                    ),
+                );
                 log_and_return_error!(err)
             }
         }
@@ -443,10 +474,13 @@ impl Notation {
         if !*check {
             *check = true;
         } else {
-            let err = PGNParseError::NotationParseError(format!(
-                "Invalid notation, multiple check chars (char: '{}' at index: {})",
-                notation_str, i
-            ));
+            let err = PGNParseError::new(
+                PGNParseErrorKind::InvalidNotation,
+                format!(
+                    "Invalid notation, multiple check chars (char: '{}' at index: {})",
+                    notation_str, i
+                ),
+            );
             log_and_return_error!(err)
         }
         Ok(())
@@ -460,10 +494,13 @@ impl Notation {
         if !*checkmate {
             *checkmate = true;
         } else {
-            let err = PGNParseError::NotationParseError(format!(
-                "Invalid notation, multiple checkmate chars (char: '{}' at index: {})",
-                notation_str, i
-            ));
+            let err = PGNParseError::new(
+                PGNParseErrorKind::InvalidNotation,
+                format!(
+                    "Invalid notation, multiple checkmate chars (char: '{}' at index: {})",
+                    notation_str, i
+                ),
+            );
             log_and_return_error!(err)
         }
         Ok(())
@@ -515,21 +552,26 @@ impl Notation {
                 if possible_dis_moves.len() == 1 {
                     Ok(*possible_dis_moves[0])
                 } else {
-                    let err = PGNParseError::MoveNotFound(format!(
+                    let err = PGNParseError::new(PGNParseErrorKind::AmbiguousMove, format!(
                 "No legal move found for notation ({}) in BoardState (hash: {}) => Could not use notation to disambiguate between multiple possible moves: {:?}",
                 self,
                 hash_to_string(bs_context.board_hash),
                 possible_moves
-                ));
+                ))
+                .with_token(&self.to_string());
                     log_and_return_error!(err)
                 }
             }
             _ => {
-                let err = PGNParseError::MoveNotFound(format!(
-                    "No legal move found for notation ({}) in BoardState (hash: {})",
-                    self,
-                    hash_to_string(bs_context.board_hash)
-                ));
+                let err = PGNParseError::new(
+                    PGNParseErrorKind::IllegalMove,
+                    format!(
+                        "No legal move found for notation ({}) in BoardState (hash: {})",
+                        self,
+                        hash_to_string(bs_context.board_hash)
+                    ),
+                )
+                .with_token(&self.to_string());
                 log_and_return_error!(err)
             }
         }
@@ -624,10 +666,10 @@ fn extract_legal_moves(bs: &board::BoardState) -> Result<&[Move], PGNParseError>
     match bs.get_legal_moves() {
         Ok(moves) => Ok(moves),
         Err(e) => {
-            let err = PGNParseError::NotationParseError(format!(
-                "Error getting legal moves in BoardState: {}",
-                e
-            ));
+            let err = PGNParseError::new(
+                PGNParseErrorKind::IllegalMove,
+                format!("Error getting legal moves in BoardState: {}", e),
+            );
             log_and_return_error!(err)
         }
     }
@@ -672,7 +714,8 @@ fn is_valid_rank(rank: char) -> bool {
 
 #[inline]
 fn is_valid_piece(piece: char) -> bool {
-    let valid_pieces = ['P', 'N', 'B', 'R', 'Q', 'K'];
+    // pawn moves are written without a letter, a P is only read by lenient parsing
+    let valid_pieces = ['N', 'B', 'R', 'Q', 'K'];
     piece.is_ascii_uppercase() && valid_pieces.contains(&piece)
 }
 
@@ -903,6 +946,11 @@ mod test {
         assert_eq!(mv.piece.ptype, PieceType::Pawn);
         assert_eq!(mv.from, 52);
         assert_eq!(mv.to, 36);
+
+        // the pawn letter isn't part of SAN
+        let err = Notation::from_str("Pe4").unwrap_err();
+        assert_eq!(err.kind(), PGNParseErrorKind::InvalidNotation);
+        assert_eq!(err.token(), Some("Pe4"));
     }
 
     #[test]
@@ -0,0 +1,270 @@
+// reads the games of a PGN database one at a time, only the game being parsed is held in memory
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use super::{ParseMode, PGN};
+use crate::errors::{PGNParseError, PGNParseErrorKind};
+use crate::log_and_return_error;
+
+// iterator over the games of a file or any BufRead. a game that fails to parse is returned as an error and
+// the games after it are still read
+pub struct PgnReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+    next_game_start: Option<String>, // first tag line of the next game, read while finding the end of the last
+    line: usize,                     // number of lines read
+    game_index: usize,               // index of the next game
+    mode: ParseMode,
+    done: bool,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: Vec::new(),
+            next_game_start: None,
+            line: 0,
+            game_index: 0,
+            mode: ParseMode::Strict,
+            done: false,
+        }
+    }
+
+    pub fn with_mode(mut self, mode: ParseMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    // text of the next game and the line it starts at, a tag line after the movetext starts the game after it.
+    // None at the end of the input
+    fn read_game(&mut self) -> Result<Option<(String, usize)>, PGNParseError> {
+        let mut game = self.next_game_start.take().unwrap_or_default();
+        // the tag line of the next game has already been counted
+        let first_line = if game.is_empty() {
+            self.line + 1
+        } else {
+            self.line
+        };
+        let mut in_movetext = false;
+        let mut in_comment = false;
+        let mut after_blank_line = false;
+        loop {
+            self.buf.clear();
+            match self.reader.read_until(b'\n', &mut self.buf) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    self.done = true;
+                    let err = PGNParseError::new(PGNParseErrorKind::FileError, e.to_string());
+                    log_and_return_error!(err)
+                }
+            }
+            self.line += 1;
+            // invalid UTF-8 is left for the parser to reject, with the rest of the game
+            let line = String::from_utf8_lossy(&self.buf);
+            let trimmed = line.trim_start();
+            // a tag line after a blank line ends a comment that was never closed, the game is left for the
+            // parser to reject instead of swallowing the games after it
+            if trimmed.starts_with('[') && in_movetext && (!in_comment || after_blank_line) {
+                self.next_game_start = Some(line.into_owned());
+                return Ok(Some((game, first_line)));
+            }
+            after_blank_line = trimmed.is_empty();
+            if !in_comment {
+                // lines starting with % are escaped and ignored by the standard
+                in_movetext |=
+                    !trimmed.is_empty() && !trimmed.starts_with('[') && !trimmed.starts_with('%');
+            }
+            // tag lines can't open a comment, in the movetext a brace comment can run over several lines.
+            // braces after a ; are in a rest of line comment
+            if in_movetext {
+                for c in trimmed.chars() {
+                    match c {
+                        '{' => in_comment = true,
+                        '}' => in_comment = false,
+                        ';' if !in_comment => break,
+                        _ => {}
+                    }
+                }
+            }
+            // escaped lines are left empty to keep the line numbers of the game
+            if trimmed.starts_with('%') {
+                game.push('\n');
+            } else {
+                game.push_str(&line);
+            }
+        }
+        self.done = true;
+        Ok((!game.trim().is_empty()).then_some((game, first_line)))
+    }
+}
+
+impl PgnReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, PGNParseError> {
+        let path = path.as_ref();
+        match File::open(path) {
+            Ok(file) => Ok(Self::new(BufReader::new(file))),
+            Err(e) => {
+                let err = PGNParseError::new(
+                    PGNParseErrorKind::FileError,
+                    format!("{}: {}", path.display(), e),
+                );
+                log_and_return_error!(err)
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<PGN, PGNParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done && self.next_game_start.is_none() {
+            return None;
+        }
+        match self.read_game() {
+            Ok(Some((game, first_line))) => {
+                let game_index = self.game_index;
+                self.game_index += 1;
+                // errors and the moves of the game are located in the file
+                Some(match PGN::parse(&game, self.mode) {
+                    Ok(mut pgn) => {
+                        pgn.set_game(game_index, first_line);
+                        Ok(pgn)
+                    }
+                    Err(e) => Err(e.in_game(game_index, first_line)),
+                })
+            }
+            Ok(None) => None,
+            Err(e) => Some(Err(e.in_game(self.game_index, 1))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+    use crate::errors::TextPosition;
+    use std::fs;
+
+    #[test]
+    fn test_read_games() {
+        let game = fs::read_to_string("test_data/test.pgn").unwrap();
+        let lichess_game = fs::read_to_string("test_data/test_lichess_export.pgn").unwrap();
+        let bad_game = "[Event \"Bad\"]\n\n1.e4 Kx9 *\n\n[Event \"Caf\u{e9}\"]\n\n1.e4 *\n";
+        // a comment running over lines that start with brackets doesn't split the game
+        let comment_game =
+            "[Event \"Comment\"]\n\n1.e4 {a comment\n[over] lines} e5\n%escaped line\n1/2-1/2\n";
+        let database = format!(
+            "{}\n{}\n{}\n{}\n\n",
+            game, bad_game, lichess_game, comment_game
+        );
+
+        let games: Vec<_> = PgnReader::new(database.as_bytes()).collect();
+        assert_eq!(games.len(), 5);
+        assert_eq!(games[0].as_ref().unwrap(), &game.parse::<PGN>().unwrap());
+        assert!(games[1].is_err());
+        assert!(games[2].is_err());
+        assert_eq!(games[3].as_ref().unwrap().moves().len(), 115);
+        let comment_game = games[4].as_ref().unwrap();
+        assert_eq!(
+            comment_game.moves()[0].comments,
+            ["a comment\n[over] lines"]
+        );
+        assert_eq!(comment_game.moves().len(), 2);
+
+        // the UTF-8 game is read with a warning when parsing leniently
+        let games: Vec<_> = PgnReader::new(database.as_bytes())
+            .with_mode(ParseMode::Lenient)
+            .collect();
+        assert!(games[1].is_err());
+        let utf8_game = games[2].as_ref().unwrap();
+        assert_eq!(utf8_game.warnings().len(), 1);
+        assert_eq!(utf8_game.warnings()[0].game_index(), Some(2));
+
+        let games = PgnReader::open("test_data/test_lichess_export.pgn").unwrap();
+        assert_eq!(games.filter(Result::is_ok).count(), 1);
+        assert!(PgnReader::open("test_data/missing.pgn").is_err());
+    }
+
+    #[test]
+    fn test_read_games_error_location() {
+        let database = "[Event \"A\"]\n\n1.e4 e5 *\n\n\
+            [Event \"B\"]\n\n1.e4 Ke7 2.Qh5 Kx9 *\n\n\
+            [Event \"C\"]\n\n1.e4 e5 2.Ke3 *\n\n\
+            [Event \"D\"]\n\n1.e3 a6 2.Ne2 a5 3.Ng3 a4 4.Nc3 h6 5.Ne4 *\n\n\
+            [Event \"E\"]\n[White]\n\n1.e4 *\n";
+        let games: Vec<_> = PgnReader::new(database.as_bytes()).collect();
+        assert_eq!(games.len(), 5);
+
+        let err = games[1].as_ref().unwrap_err();
+        assert_eq!(err.kind(), PGNParseErrorKind::InvalidNotation);
+        assert_eq!(err.game_index(), Some(1));
+        assert_eq!(err.token(), Some("Kx9"));
+        assert_eq!(
+            err.position(),
+            Some(TextPosition {
+                line: 7,
+                column: 16
+            })
+        );
+
+        // illegal and ambiguous moves are found when the game is played, and located in the file
+        let err = Board::try_from(games[2].clone().unwrap()).unwrap_err();
+        assert_eq!(err.kind(), PGNParseErrorKind::IllegalMove);
+        assert_eq!(err.game_index(), Some(2));
+        assert_eq!(err.token(), Some("Ke3"));
+        assert_eq!(
+            err.position(),
+            Some(TextPosition {
+                line: 11,
+                column: 11
+            })
+        );
+        let err = Board::try_from(games[3].clone().unwrap()).unwrap_err();
+        assert_eq!(err.kind(), PGNParseErrorKind::AmbiguousMove);
+        assert_eq!(err.token(), Some("Ne4"));
+        assert_eq!(
+            err.position(),
+            Some(TextPosition {
+                line: 15,
+                column: 38
+            })
+        );
+
+        let err = games[4].as_ref().unwrap_err();
+        assert_eq!(err.kind(), PGNParseErrorKind::InvalidTag);
+        assert_eq!(
+            err.position(),
+            Some(TextPosition {
+                line: 18,
+                column: 1
+            })
+        );
+        assert!(err.to_string().contains("game index 4, line 18, column 1"));
+    }
+
+    #[test]
+    fn test_read_games_unclosed_comment() {
+        // the unclosed brace ends at the tag line after the blank line, and the game after it is still read
+        let database = "[Event \"A\"]\n\n1.e4 {unclosed e5 *\n\n\
+            [Event \"B\"]\n\n1.d4 d5 *\n\n\
+            [Event \"C\"]\n\n1.c4 ; not a {comment\ne5 *\n\n\
+            [Event \"D\"]\n\n1.Nf3 *\n";
+        for mode in [ParseMode::Strict, ParseMode::Lenient] {
+            let games: Vec<_> = PgnReader::new(database.as_bytes())
+                .with_mode(mode)
+                .collect();
+            assert_eq!(games.len(), 4);
+            let err = games[0].as_ref().unwrap_err();
+            assert_eq!(err.kind(), PGNParseErrorKind::InvalidMovetext);
+            assert_eq!(err.game_index(), Some(0));
+            assert_eq!(games[1].as_ref().unwrap().moves().len(), 2);
+            assert_eq!(games[2].as_ref().unwrap().moves().len(), 2);
+            assert_eq!(games[3].as_ref().unwrap().moves().len(), 1);
+        }
+    }
+}
@@ -1,6 +1,9 @@
 use std::{fmt, str::FromStr};
 
-use crate::{errors::PGNParseError, log_and_return_error};
+use crate::{
+    errors::{PGNParseError, PGNParseErrorKind},
+    log_and_return_error,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
 pub struct CustomTag {
@@ -72,14 +75,20 @@ impl FromStr for Tag {
         let name = if let Some(name) = parts.next() {
             name
         } else {
-            let err = PGNParseError::InvalidTag(format!("Tag {} has invalid name", tag));
+            let err = PGNParseError::new(
+                PGNParseErrorKind::InvalidTag,
+                format!("Tag {} has invalid name", tag),
+            );
             log_and_return_error!(err)
         };
 
         let value = if let Some(value) = parts.next() {
             value.trim_matches('"')
         } else {
-            let err = PGNParseError::InvalidTag(format!("Tag {} has invalid value", tag));
+            let err = PGNParseError::new(
+                PGNParseErrorKind::InvalidTag,
+                format!("Tag {} has invalid value", tag),
+            );
             log_and_return_error!(err)
         };
 
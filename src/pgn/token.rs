@@ -1,22 +1,41 @@
 use std::ops::Deref;
-use std::vec;
 
-use super::notation::*;
+use super::movetext::{ParsedMovetext, Variation};
 use super::tag::*;
-use crate::errors::PGNParseError;
+use super::ParseMode;
+use crate::errors::{PGNParseError, PGNParseErrorKind, TextPosition};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Token {
     pub value: String,
+    pub position: TextPosition,
 }
 impl Token {
+    // token at the start of the text, for tests
+    #[cfg(test)]
     pub fn new(value: &str) -> Self {
+        Self::at(value, TextPosition { line: 1, column: 1 })
+    }
+
+    pub fn at(value: &str, position: TextPosition) -> Self {
         Self {
             value: value.to_string(),
+            position,
         }
     }
-    fn is_game_termination_marker(&self) -> bool {
-        self.value == "1-0" || self.value == "0-1" || self.value == "1/2-1/2" || self.value == "*"
+
+    // new error found at this token
+    pub fn error(&self, kind: PGNParseErrorKind, message: String) -> PGNParseError {
+        self.locate(PGNParseError::new(kind, message))
+    }
+
+    // gives an error from parsing this token its text and position
+    pub fn locate(&self, err: PGNParseError) -> PGNParseError {
+        let err = match err.token() {
+            Some(_) => err,
+            None => err.with_token(self.value.trim()),
+        };
+        err.at(self.position)
     }
 }
 
@@ -35,14 +54,20 @@ impl Tokens {
         let mut tags = Vec::new();
         let mut tag_str = String::new();
         let mut in_tag = false;
-        for token in &self.tokens {
+        let mut tag_start = None;
+        for token in &self.tokens[..self.movetext_start()] {
             if token.value == "[" {
                 in_tag = true;
                 tag_str += &token.value;
+                tag_start = Some(token);
             } else if token.value == "]" {
                 in_tag = false;
                 tag_str += &token.value;
-                tags.push(tag_str.parse()?);
+                let tag = tag_str.parse().map_err(|e| match tag_start {
+                    Some(start) => start.locate(e).with_token(&tag_str),
+                    None => e,
+                })?;
+                tags.push(tag);
                 tag_str.clear();
             } else if in_tag {
                 tag_str += &token.value;
@@ -51,51 +76,23 @@ impl Tokens {
         Ok(tags)
     }
 
-    pub fn get_game_termination(&self) -> Option<String> {
-        for token in &self.tokens {
-            if token.is_game_termination_marker() {
-                return Some(token.value.clone());
-            }
-        }
-        None
+    // the move tree of the movetext, with the game termination marker at its end if there is one
+    pub fn get_movetext(&self, mode: ParseMode) -> Result<ParsedMovetext, PGNParseError> {
+        Variation::parse(&self.tokens[self.movetext_start()..], mode)
     }
 
-    pub fn get_move_notations(&self) -> Result<Vec<Notation>, PGNParseError> {
-        // for now trim comments, variations and move numbers from the movetext as we won't use them for now
-        let mut move_tokens = self.tokens.clone();
-        let delimiters = vec![("(", ")"), ("{", "}"), ("[", "]"), ("<", ">")];
-        for delimiter in delimiters {
-            let mut new_tokens = Vec::new();
-            let mut in_delimiter = false;
-            for token in move_tokens {
-                if token.value == delimiter.0 {
-                    in_delimiter = true;
-                } else if token.value == delimiter.1 {
-                    in_delimiter = false;
-                } else if !in_delimiter {
-                    new_tokens.push(token.clone());
-                }
+    // index of the first token after the tag pairs, brackets in comments are not tags
+    fn movetext_start(&self) -> usize {
+        let mut in_tag = false;
+        for (i, token) in self.tokens.iter().enumerate() {
+            match token.value.as_str() {
+                "[" => in_tag = true,
+                "]" => in_tag = false,
+                value if !in_tag && !value.trim().is_empty() => return i,
+                _ => {}
             }
-            move_tokens = new_tokens;
-        }
-        // truncate at game termination marker
-        if let Some(pos) = move_tokens
-            .iter()
-            .position(Token::is_game_termination_marker)
-        {
-            move_tokens.truncate(pos);
         }
-        // remove all single character tokens that are left
-        move_tokens.retain(|token| token.value.len() > 1);
-        // remove move numbers
-        move_tokens.retain(|token| !token.value.chars().all(|c| c.is_ascii_digit()));
-        let mut notations = Vec::new();
-        for token in move_tokens {
-            let notation = token.value.parse()?;
-            notations.push(notation);
-        }
-
-        Ok(notations)
+        self.tokens.len()
     }
 }
 // calling .iter() on Tokens will iterator over the inner Vec
@@ -108,27 +105,35 @@ impl Deref for Tokens {
 }
 
 fn tokenize(pgn: &str) -> Vec<Token> {
-    // input should already be checked at this point, in a public function
-    assert!(pgn.is_ascii(), "PGN must be ASCII");
-
     let mut split_vec = Vec::new();
+    let mut position = TextPosition { line: 1, column: 1 };
+    // tokens cover all of the text, so each starts where the last ended
+    let mut push = |value: &str| {
+        split_vec.push(Token::at(value, position));
+        for c in value.chars() {
+            if c == '\n' {
+                position.line += 1;
+                position.column = 1;
+            } else {
+                position.column += 1;
+            }
+        }
+    };
     let mut last = 0;
-    for (index, matched) in
-        pgn.match_indices(move |c: char| is_pgn_delimiter(pgn.chars().nth(last).unwrap(), c))
-    {
+    for (index, matched) in pgn.match_indices(is_pgn_delimiter) {
         if last != index {
-            split_vec.push(Token::new(&pgn[last..index]));
+            push(&pgn[last..index]);
         }
-        split_vec.push(Token::new(matched));
+        push(matched);
         last = index + matched.len();
     }
     if last < pgn.len() {
-        split_vec.push(Token::new(&pgn[last..]));
+        push(&pgn[last..]);
     }
     split_vec
 }
 
-const fn is_pgn_delimiter(prev_char: char, c: char) -> bool {
+const fn is_pgn_delimiter(c: char) -> bool {
     c.is_ascii_whitespace()
         || c == '.'
         || c == ')'
@@ -141,7 +146,7 @@ const fn is_pgn_delimiter(prev_char: char, c: char) -> bool {
         || c == '<'
         || c == '>'
         || c == '"'
-        || prev_char.is_ascii_digit() && !c.is_ascii_digit()
+        || c == ';'
 }
 
 #[cfg(test)]
@@ -174,10 +179,14 @@ mod test {
             "e6".to_string(),
         ];
 
-        let expected_tokens: Vec<Token> =
-            expected_tokens_str.iter().map(|s| Token::new(s)).collect();
-
-        assert_eq!(tokens, expected_tokens);
+        let values: Vec<_> = tokens.iter().map(|token| token.value.as_str()).collect();
+        assert_eq!(values, expected_tokens_str);
+        // positions of the first token and the last on the second line
+        assert_eq!(tokens[0].position, TextPosition { line: 1, column: 1 });
+        assert_eq!(
+            tokens.last().unwrap().position,
+            TextPosition { line: 2, column: 7 }
+        );
     }
 
     #[test]
@@ -211,10 +220,8 @@ mod test {
             "e5".to_string(),
         ];
 
-        let expected_tokens: Vec<Token> =
-            expected_tokens_str.iter().map(|s| Token::new(s)).collect();
-
-        assert_eq!(tokens, expected_tokens);
+        let values: Vec<_> = tokens.iter().map(|token| token.value.as_str()).collect();
+        assert_eq!(values, expected_tokens_str);
     }
 
     #[test]
@@ -247,10 +254,8 @@ mod test {
             "e5".to_string(),
         ];
 
-        let expected_tokens: Vec<Token> =
-            expected_tokens_str.iter().map(|s| Token::new(s)).collect();
-
-        assert_eq!(tokens, expected_tokens);
+        let values: Vec<_> = tokens.iter().map(|token| token.value.as_str()).collect();
+        assert_eq!(values, expected_tokens_str);
     }
 
     #[test]
@@ -279,7 +284,7 @@ mod test {
     }
 
     #[test]
-    fn test_tokens_get_move_notations() {
+    fn test_tokens_get_movetext() {
         let tokens_vec = vec![
             Token::new("1"),
             Token::new("."),
@@ -292,12 +297,14 @@ mod test {
             Token::new("1-0"),
         ];
         let tokens = Tokens { tokens: tokens_vec };
-        let notations = tokens.get_move_notations().unwrap();
+        let movetext = tokens.get_movetext(ParseMode::Strict).unwrap();
+        let termination = movetext.termination;
+        let notations: Vec<_> = movetext.variation.notations().collect();
 
         assert_eq!(notations.len(), 3);
-        assert_eq!(notations[0], "e4".parse().unwrap());
-        assert_eq!(notations[1], "e5".parse().unwrap());
-        assert_eq!(notations[2], "Q1d7+".parse().unwrap());
-        println!("{:?}", notations[2]);
+        assert_eq!(*notations[0], "e4".parse().unwrap());
+        assert_eq!(*notations[1], "e5".parse().unwrap());
+        assert_eq!(*notations[2], "Q1d7+".parse().unwrap());
+        assert_eq!(termination.as_deref(), Some("1-0"));
     }
 }
@@ -1,3 +1,4 @@
+use std::cmp;
 use std::ops::Deref;
 use std::ops::Index;
 use std::ops::IndexMut;
@@ -5,13 +6,22 @@ use std::vec;
 
 use rand::Rng;
 
+use crate::bitboard::{self, Bitboards};
 use crate::fen::FEN;
-use crate::mailbox;
 use crate::movegen::*;
 use crate::zobrist;
 use crate::zobrist::PositionHash;
 
 const ATTACK_MAP_START_SIZE: usize = 100;
+// least valuable attacker first in static exchange evaluation
+const SEE_ORDER: [PieceType; 6] = [
+    PieceType::Pawn,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Rook,
+    PieceType::Queen,
+    PieceType::King,
+];
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Pos64([Square; 64]);
@@ -52,25 +62,7 @@ impl Pos64 {
             pcolour: pawn_colour,
             ptype: PieceType::Pawn,
         };
-        let left = mailbox::next_mailbox_number(i, -1);
-        // valid mailbox index
-        if left >= 0 {
-            if let Square::Piece(p) = &self[left as usize] {
-                if p == &piece {
-                    return true;
-                }
-            }
-        }
-        let right = mailbox::next_mailbox_number(i, 1);
-        // valid mailbox index
-        if right >= 0 {
-            if let Square::Piece(p) = &self[right as usize] {
-                if p == &piece {
-                    return true;
-                }
-            }
-        }
-        false
+        bitboard::squares(bitboard::adjacent_bb(i)).any(|j| self[j] == Square::Piece(piece))
     }
 
     pub fn back_rank(&self, colour: PieceColour) -> &[Square] {
@@ -122,7 +114,7 @@ impl Pos64 {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct AttackMap(Vec<Move>);
 
 impl AttackMap {
@@ -131,10 +123,6 @@ impl AttackMap {
         Self(Vec::with_capacity(ATTACK_MAP_START_SIZE))
     }
 
-    fn new_no_alloc() -> Self {
-        Self(Vec::with_capacity(0))
-    }
-
     fn clear(&mut self) {
         self.0.clear();
     }
@@ -146,6 +134,14 @@ impl MoveMap for AttackMap {
     }
 }
 
+// state lost by Position::make_move that can't be recovered from the move itself
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PositionUndo {
+    movegen_flags: MovegenFlags,
+    in_check: bool,
+    captured: Square,
+}
+
 #[derive(Debug, Clone)]
 pub struct Position {
     pub pos64: Pos64,
@@ -153,6 +149,7 @@ pub struct Position {
     pub movegen_flags: MovegenFlags,
     in_check: bool,
     attack_map: AttackMap, // map of moves from attacking side
+    bitboards: Bitboards,
     wking_idx: usize,
     bking_idx: usize,
 }
@@ -245,6 +242,7 @@ impl Position {
         });
 
         let mut new = Self {
+            bitboards: Bitboards::from(&pos),
             pos64: pos,
             side: PieceColour::White,
             in_check: false,
@@ -392,6 +390,7 @@ impl Position {
         }
 
         let mut new = Self {
+            bitboards: Bitboards::from(&pos),
             pos64: pos,
             side: PieceColour::White,
             in_check: false,
@@ -410,6 +409,7 @@ impl Position {
         movegen_flags: MovegenFlags,
     ) -> Self {
         let mut new = Self {
+            bitboards: Bitboards::from(&pos64),
             pos64,
             side,
             in_check: false,
@@ -426,54 +426,140 @@ impl Position {
     // Assumes a legal move, no legality checks are done, so no bounds checking is done here
     pub fn new_position(&self, mv: &Move) -> Self {
         let mut new_pos = self.clone();
-        new_pos.set_en_passant_flag(mv);
-        new_pos.set_castle_flags(mv);
-        new_pos.set_king_position(mv);
+        new_pos.make_move(mv);
+        new_pos.gen_maps();
+        new_pos
+    }
+
+    // makes the move in place without regenerating the attack map, so get_pseudo_legal_moves is stale afterwards.
+    // returns the state needed by unmake_move to restore the position
+    pub(crate) fn make_move(&mut self, mv: &Move) -> PositionUndo {
+        let undo = PositionUndo {
+            movegen_flags: self.movegen_flags,
+            in_check: self.in_check,
+            captured: self.pos64[mv.to],
+        };
+        self.set_en_passant_flag(mv);
+        self.set_castle_flags(mv);
+        self.set_king_position(mv, mv.to);
 
         match mv.move_type {
             MoveType::EnPassant(ep_capture) => {
                 // en passant, 'to' square is different from the captured square
-                new_pos.pos64[ep_capture] = Square::Empty;
+                self.set_square(ep_capture, Square::Empty);
+                self.set_square(mv.to, Square::Piece(mv.piece));
+                self.set_square(mv.from, Square::Empty);
             }
             MoveType::Castle(castle_mv) => {
-                if castle_mv.rook_from != castle_mv.rook_to {
-                    new_pos.pos64[castle_mv.rook_to] = Square::Piece(Piece {
+                // clear both starting squares first, in chess960 the king and rook can land on each others squares
+                self.set_square(mv.from, Square::Empty);
+                self.set_square(castle_mv.rook_from, Square::Empty);
+                self.set_square(mv.to, Square::Piece(mv.piece));
+                self.set_square(castle_mv.rook_to, self.castle_rook());
+            }
+            MoveType::Promotion(ptype, _) => {
+                self.set_square(
+                    mv.to,
+                    Square::Piece(Piece {
                         pcolour: self.side,
-                        ptype: PieceType::Rook,
-                    });
-                    new_pos.pos64[castle_mv.rook_from] = Square::Empty;
-                }
+                        ptype,
+                    }),
+                );
+                self.set_square(mv.from, Square::Empty);
+            }
+            _ => {
+                self.set_square(mv.to, Square::Piece(mv.piece));
+                self.set_square(mv.from, Square::Empty);
+            }
+        }
 
-                if mv.from != mv.to {
-                    new_pos.pos64[mv.to] = Square::Piece(Piece {
-                        pcolour: self.side,
-                        ptype: PieceType::King,
-                    });
-                    if castle_mv.rook_to != mv.from {
-                        new_pos.pos64[mv.from] = Square::Empty;
-                    }
-                }
-                new_pos.toggle_side();
-                new_pos.gen_maps();
-                return new_pos;
+        self.toggle_side();
+        self.in_check = movegen_in_check(&self.bitboards, self.get_king_idx(), self.side);
+        undo
+    }
+
+    // reverses make_move, mv must be the last move made
+    pub(crate) fn unmake_move(&mut self, mv: &Move, undo: &PositionUndo) {
+        self.toggle_side();
+        self.movegen_flags = undo.movegen_flags;
+        self.in_check = undo.in_check;
+        self.set_king_position(mv, mv.from);
+
+        match mv.move_type {
+            MoveType::EnPassant(ep_capture) => {
+                self.set_square(mv.to, Square::Empty);
+                self.set_square(
+                    ep_capture,
+                    Square::Piece(Piece {
+                        pcolour: !self.side,
+                        ptype: PieceType::Pawn,
+                    }),
+                );
             }
-            MoveType::Promotion(ptype, _) => match &mut new_pos.pos64[mv.from] {
-                Square::Piece(p) => {
-                    p.ptype = ptype;
-                }
-                Square::Empty => {
-                    unreachable!();
-                }
-            },
-            _ => {}
+            MoveType::Castle(castle_mv) => {
+                self.set_square(mv.to, Square::Empty);
+                self.set_square(castle_mv.rook_to, Square::Empty);
+                self.set_square(castle_mv.rook_from, self.castle_rook());
+                self.set_square(mv.from, Square::Piece(mv.piece));
+                return;
+            }
+            _ => self.set_square(mv.to, undo.captured),
         }
+        self.set_square(mv.from, Square::Piece(mv.piece));
+    }
 
-        new_pos.pos64[mv.to] = new_pos.pos64[mv.from];
-        new_pos.pos64[mv.from] = Square::Empty;
+    // passes the turn without moving a piece, for null move pruning. must not be called when in check
+    pub(crate) fn make_null_move(&mut self) -> PositionUndo {
+        let undo = PositionUndo {
+            movegen_flags: self.movegen_flags,
+            in_check: self.in_check,
+            captured: Square::Empty,
+        };
+        // the pawn that could be taken en passant can't be once the turn is passed
+        self.movegen_flags.en_passant = None;
+        self.movegen_flags.polyglot_en_passant = None;
+        self.toggle_side();
+        // the other side's king can't be attacked, the position before was legal
+        self.in_check = false;
+        undo
+    }
 
-        new_pos.toggle_side();
-        new_pos.gen_maps();
-        new_pos
+    // reverses make_null_move
+    pub(crate) fn unmake_null_move(&mut self, undo: &PositionUndo) {
+        self.toggle_side();
+        self.movegen_flags = undo.movegen_flags;
+        self.in_check = undo.in_check;
+    }
+
+    #[inline(always)]
+    fn castle_rook(&self) -> Square {
+        Square::Piece(Piece {
+            pcolour: self.side,
+            ptype: PieceType::Rook,
+        })
+    }
+
+    // pseudo legal moves generated into a caller owned list, unlike the attack map this works after make_move
+    pub(crate) fn gen_pseudo_legal_moves(&self, mv_map: &mut dyn MoveMap) {
+        for i in bitboard::squares(self.bitboards.colour(self.side)) {
+            if let Square::Piece(p) = self.pos64[i] {
+                movegen(
+                    &self.pos64,
+                    &self.bitboards,
+                    &self.movegen_flags,
+                    p,
+                    i,
+                    mv_map,
+                );
+            }
+        }
+    }
+
+    // keeps the mailbox and bitboards in sync
+    #[inline(always)]
+    fn set_square(&mut self, i: usize, square: Square) {
+        self.pos64[i] = square;
+        self.bitboards.set(i, square);
     }
 
     #[inline(always)]
@@ -498,97 +584,131 @@ impl Position {
 
     // TODO maybe consolidate all movegen flag updates into one place if possible?
     #[inline(always)]
-    fn set_king_position(&mut self, mv: &Move) {
+    fn set_king_position(&mut self, mv: &Move, idx: usize) {
         if mv.piece.ptype == PieceType::King {
             if mv.piece.pcolour == PieceColour::White {
-                self.wking_idx = mv.to;
+                self.wking_idx = idx;
             } else {
-                self.bking_idx = mv.to;
+                self.bking_idx = idx;
             }
         }
     }
-    // clone function for is_move_legal. Avoids expensive clone attack map
-    #[inline(always)]
-    fn test_clone(&self) -> Self {
-        Self {
-            pos64: self.pos64,
-            side: self.side,
-            in_check: self.in_check,
-            movegen_flags: self.movegen_flags,
-            // create new attack map with empty vec, because it's not needed for testing legality.
-            attack_map: AttackMap::new_no_alloc(),
-            wking_idx: self.wking_idx,
-            bking_idx: self.bking_idx,
-        }
-    }
-
     pub fn is_move_legal(&self, mv: &Move) -> bool {
-        if mv.piece.ptype == PieceType::King {
-            if let MoveType::Castle(castle_mv) = mv.move_type {
-                // can't castle out of check
-                if self.in_check {
-                    return false;
-                }
+        // only the bitboards are needed to test for check, so they are copied instead of the whole position
+        let mut test_bb = self.bitboards;
+        let king = Piece {
+            pcolour: self.side,
+            ptype: PieceType::King,
+        };
 
-                let king_square = Square::Piece(Piece {
-                    pcolour: self.side,
-                    ptype: PieceType::King,
-                });
+        if let MoveType::Castle(castle_mv) = mv.move_type {
+            // can't castle out of check
+            if self.in_check {
+                return false;
+            }
 
-                let rook_square = Square::Piece(Piece {
-                    pcolour: self.side,
-                    ptype: PieceType::Rook,
-                });
-
-                // range ignoring starting king square as it is checked above
-                let king_squares = if mv.from < mv.to {
-                    // mv.to +1 for inclusive range while having same type in both branches
-                    mv.from + 1..mv.to + 1
-                } else {
-                    // non inclusive range ignoring mv.from
-                    mv.to..mv.from
-                };
-
-                let mut test_pos = self.test_clone();
-
-                if mv.from != mv.to {
-                    for i in king_squares {
-                        test_pos.pos64[i] = king_square;
-                        let last_king_idx = test_pos.get_king_idx();
-                        test_pos.pos64[last_king_idx] = Square::Empty;
-                        test_pos.set_king_idx(i);
-
-                        if movegen_in_check(&test_pos.pos64, i, self.side) {
-                            return false;
-                        }
-                    }
-                }
+            // range ignoring starting king square as it is checked above
+            let king_squares = if mv.from < mv.to {
+                // mv.to +1 for inclusive range while having same type in both branches
+                mv.from + 1..mv.to + 1
+            } else {
+                // non inclusive range ignoring mv.from
+                mv.to..mv.from
+            };
 
-                // only needed for chess960 positions where moving your rook can open a discovered check
-                // example position: (wKe1, wRb1, bKe8, bRa1) white castles a-side (long)
-                test_pos.pos64[castle_mv.rook_to] = rook_square;
-                if castle_mv.rook_from != mv.to {
-                    test_pos.pos64[castle_mv.rook_from] = Square::Empty;
-                }
-                if movegen_in_check(&test_pos.pos64, test_pos.get_king_idx(), self.side) {
+            test_bb.clear(mv.from);
+            for i in king_squares {
+                if test_bb.is_attacked(i, !self.side) {
                     return false;
                 }
-
-                return true;
             }
-        }
 
-        let mut test_pos = self.test_clone();
-        test_pos.set_king_position(mv);
+            // only needed for chess960 positions where moving your rook can open a discovered check
+            // example position: (wKe1, wRb1, bKe8, bRa1) white castles a-side (long)
+            test_bb.clear(castle_mv.rook_from);
+            test_bb.put(mv.to, king);
+            test_bb.put(
+                castle_mv.rook_to,
+                Piece {
+                    pcolour: self.side,
+                    ptype: PieceType::Rook,
+                },
+            );
+            return !movegen_in_check(&test_bb, mv.to, self.side);
+        }
 
         if let MoveType::EnPassant(ep_capture) = mv.move_type {
-            test_pos.pos64[ep_capture] = Square::Empty;
+            test_bb.clear(ep_capture);
         }
+        test_bb.clear(mv.from);
+        test_bb.set(mv.to, Square::Piece(mv.piece));
 
-        test_pos.pos64[mv.to] = test_pos.pos64[mv.from];
-        test_pos.pos64[mv.from] = Square::Empty;
+        let king_idx = if mv.piece == king {
+            mv.to
+        } else {
+            self.get_king_idx()
+        };
+        !movegen_in_check(&test_bb, king_idx, self.side)
+    }
+
+    // static exchange evaluation, the material mv wins or loses in centipawns if both sides keep recapturing on
+    // mv.to with their least valuable piece, each able to stop when recapturing would lose material.
+    // pins are ignored. a negative value means the piece moved can be won, e.g. for an unsafe capture
+    pub fn see(&self, mv: &Move) -> i32 {
+        let target = mv.to;
+        let (captured, moved) = match mv.move_type {
+            MoveType::Castle(_) | MoveType::None => return 0,
+            MoveType::Capture(ptype) => (see_value(ptype), see_value(mv.piece.ptype)),
+            MoveType::EnPassant(_) => (see_value(PieceType::Pawn), see_value(PieceType::Pawn)),
+            MoveType::Promotion(ptype, capture) => (
+                capture.map_or(0, see_value) + see_value(ptype) - see_value(PieceType::Pawn),
+                see_value(ptype),
+            ),
+            _ => (0, see_value(mv.piece.ptype)),
+        };
 
-        !movegen_in_check(&test_pos.pos64, test_pos.get_king_idx(), self.side)
+        let bitboards = &self.bitboards;
+        let mut occupied = bitboards.occupied() & !bitboard::square_bb(mv.from);
+        if let MoveType::EnPassant(ep_capture) = mv.move_type {
+            occupied &= !bitboard::square_bb(ep_capture);
+        }
+        // gains[i] is the material won by the side making capture i, if the exchange stopped after it
+        let mut gains = [0; 32];
+        gains[0] = captured;
+        let mut on_target = moved;
+        let mut side = !mv.piece.pcolour;
+        let mut depth = 0;
+        while depth + 1 < gains.len() {
+            // x-ray attackers behind the pieces that already captured are found as occupied shrinks
+            let attackers = bitboards.attackers(target, occupied) & occupied;
+            let ours = attackers & bitboards.colour(side);
+            let Some((ptype, sq)) = SEE_ORDER.into_iter().find_map(|ptype| {
+                bitboard::squares(ours & bitboards.ptype(ptype))
+                    .next()
+                    .map(|sq| (ptype, sq))
+            }) else {
+                break;
+            };
+            // the king can't capture onto a defended square
+            if ptype == PieceType::King && attackers & bitboards.colour(!side) != 0 {
+                break;
+            }
+            depth += 1;
+            gains[depth] = on_target - gains[depth - 1];
+            on_target = see_value(ptype);
+            if ptype == PieceType::Pawn && !(8..56).contains(&target) {
+                gains[depth] += see_value(PieceType::Queen) - see_value(PieceType::Pawn);
+                on_target = see_value(PieceType::Queen);
+            }
+            occupied &= !bitboard::square_bb(sq);
+            side = !side;
+        }
+        // each side only makes its capture if that is better than stopping the exchange before it
+        while depth > 0 {
+            gains[depth - 1] = -cmp::max(-gains[depth - 1], gains[depth]);
+            depth -= 1;
+        }
+        gains[0]
     }
 
     #[inline(always)]
@@ -610,17 +730,21 @@ impl Position {
     }
 
     #[inline(always)]
-    fn set_king_idx(&mut self, idx: usize) {
-        if self.side == PieceColour::White {
-            self.wking_idx = idx;
-        } else {
-            self.bking_idx = idx;
-        }
+    pub fn is_in_check(&self) -> bool {
+        self.in_check
     }
 
     #[inline(always)]
-    pub fn is_in_check(&self) -> bool {
-        self.in_check
+    pub(crate) fn bitboards(&self) -> &Bitboards {
+        &self.bitboards
+    }
+
+    pub fn has_castling_rights(&self) -> bool {
+        let flags = &self.movegen_flags;
+        flags.white_castle_short
+            || flags.white_castle_long
+            || flags.black_castle_short
+            || flags.black_castle_long
     }
 
     pub fn get_pseudo_legal_moves(&self) -> &Vec<Move> {
@@ -687,19 +811,24 @@ impl Position {
     }
 
     pub(crate) fn gen_maps(&mut self) {
-        self.attack_map.clear();
+        let mut attack_map = std::mem::take(&mut self.attack_map);
+        attack_map.clear();
+        self.gen_pseudo_legal_moves(&mut attack_map);
+        self.attack_map = attack_map;
+        self.in_check = movegen_in_check(&self.bitboards, self.get_king_idx(), self.side);
+    }
+}
 
-        let pos64 = &self.pos64;
-        let movegen_flags = &self.movegen_flags;
-        for (i, s) in pos64.iter().enumerate() {
-            if let Square::Piece(p) = s {
-                if p.pcolour != self.side {
-                    continue;
-                }
-                movegen(pos64, movegen_flags, *p, i, &mut self.attack_map);
-            }
-        }
-        self.in_check = movegen_in_check(&self.pos64, self.get_king_idx(), self.side);
+// material values used by static exchange evaluation, the same as the engine's piece values
+#[inline(always)]
+const fn see_value(ptype: PieceType) -> i32 {
+    match ptype {
+        PieceType::Pawn => 100,
+        PieceType::Knight => 320,
+        PieceType::Bishop => 330,
+        PieceType::Rook => 500,
+        PieceType::Queen => 900,
+        PieceType::King => 20000,
     }
 }
 
@@ -708,3 +837,51 @@ impl From<FEN> for Position {
         Self::new_from_pub_parts(fen.pos64(), fen.side(), fen.movegen_flags())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::board::BoardState;
+    use crate::fen::FEN;
+
+    fn see(fen: &str, uci: &str) -> i32 {
+        let bs = BoardState::from(fen.parse::<FEN>().unwrap());
+        let mv = bs.move_from_uci(uci).unwrap();
+        bs.see(&mv)
+    }
+
+    #[test]
+    fn test_see() {
+        // undefended pawn
+        assert_eq!(
+            see("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5"),
+            100
+        );
+        // knight takes a defended pawn, the rook and queen behind the knight and the bishop x-ray don't help
+        assert_eq!(
+            see(
+                "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+                "d3e5"
+            ),
+            100 - 320
+        );
+        // rook takes a knight defended by a pawn, black stops after winning the exchange
+        assert_eq!(see("4k3/8/3p4/4n3/8/8/8/4RK2 w - - 0 1", "e1e5"), 320 - 500);
+        // quiet queen move onto a square attacked by a pawn, and a safe one
+        assert_eq!(see("4k3/8/3p4/8/8/8/8/Q3K3 w - - 0 1", "a1e5"), -900);
+        assert_eq!(see("4k3/8/3p4/8/8/8/8/Q3K3 w - - 0 1", "a1d4"), 0);
+        // en passant and a promotion the king can't take back
+        assert_eq!(see("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 100);
+        assert_eq!(see("8/3P4/8/8/8/8/k7/4K3 w - - 0 1", "d7d8q"), 800);
+        // the king can't recapture onto a defended square
+        assert_eq!(see("3rk3/8/8/8/8/8/8/3RK3 w - - 0 1", "d1d8"), 0);
+        assert_eq!(see("3rk3/8/8/8/7B/8/8/3RK3 w - - 0 1", "d1d8"), 500);
+        // promotions the king takes back on the promotion square, with and without a capture
+        assert_eq!(see("3rk3/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7d8q"), 500 - 100);
+        assert_eq!(see("8/2kP4/8/8/8/8/8/4K3 w - - 0 1", "d7d8q"), -100);
+        // a pawn taking back on the back rank promotes as part of the exchange
+        assert_eq!(
+            see("4k3/8/8/8/8/8/2p5/Rn2K3 w - - 0 1", "a1b1"),
+            320 - 500 - 800
+        );
+    }
+}
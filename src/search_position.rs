@@ -0,0 +1,389 @@
+use ahash::AHashMap;
+
+use crate::bitboard::Bitboards;
+use crate::board::{BoardState, GameState};
+use crate::movegen::*;
+use crate::position::{Pos64, Position, PositionUndo};
+use crate::zobrist::{self, PositionHash};
+
+pub(crate) const MOVE_LIST_START_SIZE: usize = 64;
+
+// everything needed to unmake a move, pushed onto the undo stack by make_move
+#[derive(Debug, Clone, Copy)]
+struct Undo {
+    mv: Move,
+    position: PositionUndo,
+    position_hash: PositionHash,
+    board_hash: u64,
+    halfmove_count: u32,
+    occurences: u8,
+}
+
+// position used by the search and perft. moves are made and unmade in place with an undo stack,
+// instead of building a new BoardState for every node. the zobrist hashes are updated incrementally,
+// and the board hash matches the one BoardState would have for the same game, so tt entries are interchangeable
+#[derive(Debug, Clone)]
+pub(crate) struct SearchPosition {
+    position: Position,
+    position_hash: PositionHash,
+    board_hash: u64,
+    halfmove_count: u32,
+    occurences: u8,
+    root_last_move: Option<Move>,
+    game_occurences: AHashMap<PositionHash, u8>, // occurrences of positions played before the root
+    path: Vec<PositionHash>,                     // position hashes of moves made since the root
+    undo_stack: Vec<Undo>,
+}
+
+impl From<&BoardState> for SearchPosition {
+    fn from(bs: &BoardState) -> Self {
+        Self {
+            position: bs.position().clone(),
+            position_hash: bs.position_hash,
+            board_hash: bs.board_hash,
+            halfmove_count: bs.halfmove_count(),
+            occurences: bs.get_occurences_of_current_position(),
+            root_last_move: bs.last_move,
+            game_occurences: bs.position_occurences().clone(),
+            path: Vec::new(),
+            undo_stack: Vec::new(),
+        }
+    }
+}
+
+// position with no game history, as if it was the start of a game
+impl From<Position> for SearchPosition {
+    fn from(position: Position) -> Self {
+        let position_hash = position.pos_hash();
+        let mut game_occurences = AHashMap::default();
+        game_occurences.insert(position_hash, 1);
+        Self {
+            position,
+            position_hash,
+            board_hash: zobrist::board_state_hash(position_hash, 1, 0),
+            halfmove_count: 0,
+            occurences: 1,
+            root_last_move: None,
+            game_occurences,
+            path: Vec::new(),
+            undo_stack: Vec::new(),
+        }
+    }
+}
+
+impl SearchPosition {
+    // same as BoardState::next_state_unchecked, without any of the allocations
+    pub(crate) fn make_move(&mut self, mv: &Move) {
+        let last_flags = self.position.movegen_flags;
+        let position = self.position.make_move(mv);
+        self.undo_stack.push(Undo {
+            mv: *mv,
+            position,
+            position_hash: self.position_hash,
+            board_hash: self.board_hash,
+            halfmove_count: self.halfmove_count,
+            occurences: self.occurences,
+        });
+
+        self.position_hash = zobrist::pos_next_hash(
+            &last_flags,
+            &self.position.movegen_flags,
+            self.position_hash,
+            mv,
+        );
+        let halfmove_reset = matches!(
+            mv.move_type,
+            MoveType::PawnPush | MoveType::DoublePawnPush | MoveType::Capture(_)
+        );
+        self.halfmove_count = if halfmove_reset {
+            0
+        } else {
+            self.halfmove_count + 1
+        };
+        self.occurences = self.count_occurences();
+        self.path.push(self.position_hash);
+        self.board_hash =
+            zobrist::board_state_hash(self.position_hash, self.occurences, self.halfmove_count);
+    }
+
+    // unmakes the last move made, panics if there is none
+    pub(crate) fn unmake_move(&mut self) {
+        let undo = self
+            .undo_stack
+            .pop()
+            .expect("unmake_move called at the root");
+        self.position.unmake_move(&undo.mv, &undo.position);
+        self.position_hash = undo.position_hash;
+        self.board_hash = undo.board_hash;
+        self.halfmove_count = undo.halfmove_count;
+        self.occurences = undo.occurences;
+        self.path.pop();
+    }
+
+    // passes the turn for null move pruning, undone with unmake_null_move. must not be called when in check
+    pub(crate) fn make_null_move(&mut self) {
+        let last_flags = self.position.movegen_flags;
+        let position = self.position.make_null_move();
+        self.undo_stack.push(Undo {
+            mv: NULL_MOVE,
+            position,
+            position_hash: self.position_hash,
+            board_hash: self.board_hash,
+            halfmove_count: self.halfmove_count,
+            occurences: self.occurences,
+        });
+        self.position_hash = zobrist::null_move_hash(&last_flags, self.position_hash);
+        // positions before the null move can't be repeated, as if a pawn had moved
+        self.halfmove_count = 0;
+        self.occurences = 1;
+        self.path.push(self.position_hash);
+        self.board_hash =
+            zobrist::board_state_hash(self.position_hash, self.occurences, self.halfmove_count);
+    }
+
+    // unmakes the null move made last, panics if there is none
+    pub(crate) fn unmake_null_move(&mut self) {
+        let undo = self
+            .undo_stack
+            .pop()
+            .expect("unmake_null_move called at the root");
+        self.position.unmake_null_move(&undo.position);
+        self.position_hash = undo.position_hash;
+        self.board_hash = undo.board_hash;
+        self.halfmove_count = undo.halfmove_count;
+        self.occurences = undo.occurences;
+        self.path.pop();
+    }
+
+    // occurrences of the current position in the game and the search path including this one,
+    // called before the new position is pushed onto the path
+    fn count_occurences(&self) -> u8 {
+        // only positions since the last capture or pawn move can repeat. every second position has the same side to move
+        let window = self.halfmove_count as usize;
+        let in_path = self
+            .path
+            .iter()
+            .rev()
+            .take(window)
+            .skip(1)
+            .step_by(2)
+            .filter(|hash| **hash == self.position_hash)
+            .count() as u8;
+        // the game history is only reachable if the window goes back past the root
+        let in_game = if window > self.path.len() {
+            self.game_occurences
+                .get(&self.position_hash)
+                .copied()
+                .unwrap_or(0)
+        } else {
+            0
+        };
+        in_game + in_path + 1
+    }
+
+    #[inline(always)]
+    pub(crate) fn side_to_move(&self) -> PieceColour {
+        self.position.side
+    }
+
+    #[inline(always)]
+    pub(crate) fn board_hash(&self) -> u64 {
+        self.board_hash
+    }
+
+    #[inline(always)]
+    pub(crate) fn pos64(&self) -> &Pos64 {
+        &self.position.pos64
+    }
+
+    #[inline(always)]
+    pub(crate) fn bitboards(&self) -> &Bitboards {
+        self.position.bitboards()
+    }
+
+    #[inline(always)]
+    pub(crate) fn halfmove_count(&self) -> u32 {
+        self.halfmove_count
+    }
+
+    // third occurrence of the position, a draw
+    #[inline(always)]
+    pub(crate) fn is_repetition(&self) -> bool {
+        self.occurences >= 3
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_in_check(&self) -> bool {
+        self.position.is_in_check()
+    }
+
+    #[inline(always)]
+    pub(crate) fn has_castling_rights(&self) -> bool {
+        self.position.has_castling_rights()
+    }
+
+    // pieces other than pawns and the king, without them null move pruning can miss a zugzwang
+    pub(crate) fn has_non_pawn_material(&self) -> bool {
+        let bitboards = self.position.bitboards();
+        let pawns_and_kings = bitboards.ptype(PieceType::Pawn) | bitboards.ptype(PieceType::King);
+        bitboards.colour(self.position.side) & !pawns_and_kings != 0
+    }
+
+    // NULL_MOVE after a null move
+    pub(crate) fn last_move(&self) -> Option<Move> {
+        self.undo_stack
+            .last()
+            .map(|undo| undo.mv)
+            .or(self.root_last_move)
+    }
+
+    // replaces the contents of moves, callers keep one list per ply so the search doesn't allocate at every node
+    pub(crate) fn pseudo_legal_moves(&self, moves: &mut Vec<Move>) {
+        moves.clear();
+        self.position.gen_pseudo_legal_moves(moves);
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_move_legal(&self, mv: &Move) -> bool {
+        self.position.is_move_legal(mv)
+    }
+
+    #[inline(always)]
+    pub(crate) fn see(&self, mv: &Move) -> i32 {
+        self.position.see(mv)
+    }
+
+    // same checks as BoardState::get_gamestate, pseudo_legal_moves must be the moves of the current position
+    pub(crate) fn get_gamestate(&self, pseudo_legal_moves: &[Move]) -> GameState {
+        let legal_moves_empty = !pseudo_legal_moves
+            .iter()
+            .any(|mv| self.position.is_move_legal(mv));
+        let is_in_check = self.position.is_in_check();
+
+        if is_in_check && legal_moves_empty {
+            GameState::Checkmate
+        } else if !is_in_check && legal_moves_empty {
+            GameState::Stalemate
+        } else if self.halfmove_count >= 100 {
+            GameState::FiftyMove
+        } else if self.occurences >= 3 {
+            GameState::Repetition
+        } else if is_in_check {
+            GameState::Check
+        } else {
+            GameState::Active
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::FEN;
+
+    fn pseudo_legal_moves(sp: &SearchPosition) -> Vec<Move> {
+        let mut moves = Vec::new();
+        sp.pseudo_legal_moves(&mut moves);
+        moves
+    }
+
+    // make and unmake every legal move to the given depth, checking the hashes against a BoardState
+    // built with next_state_unchecked and that unmake restores the position exactly
+    fn check_make_unmake(sp: &mut SearchPosition, bs: &BoardState, depth: u8) {
+        assert_eq!(sp.board_hash(), bs.board_hash);
+        assert_eq!(sp.position_hash, bs.position_hash);
+        assert_eq!(sp.pos64(), bs.get_pos64());
+        if depth == 0 {
+            return;
+        }
+        let before = (
+            sp.board_hash,
+            *sp.pos64(),
+            sp.position.movegen_flags.en_passant,
+        );
+        for mv in pseudo_legal_moves(sp) {
+            if !sp.is_move_legal(&mv) {
+                continue;
+            }
+            sp.make_move(&mv);
+            check_make_unmake(sp, &bs.next_state_unchecked(&mv), depth - 1);
+            sp.unmake_move();
+            assert_eq!(
+                (
+                    sp.board_hash,
+                    *sp.pos64(),
+                    sp.position.movegen_flags.en_passant
+                ),
+                before
+            );
+        }
+    }
+
+    #[test]
+    fn test_make_unmake_matches_board_state() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9",
+        ] {
+            let bs = BoardState::from(fen.parse::<FEN>().unwrap());
+            check_make_unmake(&mut SearchPosition::from(&bs), &bs, 3);
+        }
+    }
+
+    #[test]
+    fn test_null_move() {
+        let bs = BoardState::from(
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3"
+                .parse::<FEN>()
+                .unwrap(),
+        );
+        let mut sp = SearchPosition::from(&bs);
+        let flags = |sp: &SearchPosition| {
+            let flags = sp.position.movegen_flags;
+            (flags.en_passant, flags.polyglot_en_passant)
+        };
+        let before = (sp.board_hash, *sp.pos64(), flags(&sp));
+        sp.make_null_move();
+        assert_eq!(sp.side_to_move(), PieceColour::Black);
+        assert_eq!(sp.last_move(), Some(NULL_MOVE));
+        // same hash as the position with black to move and no en passant
+        let passed = BoardState::from(
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 3"
+                .parse::<FEN>()
+                .unwrap(),
+        );
+        assert_eq!(sp.position_hash, passed.position_hash);
+        assert!(!pseudo_legal_moves(&sp)
+            .iter()
+            .any(|mv| matches!(mv.move_type, MoveType::EnPassant(_))));
+        sp.unmake_null_move();
+        assert_eq!((sp.board_hash, *sp.pos64(), flags(&sp)), before);
+    }
+
+    #[test]
+    fn test_repetition() {
+        let bs = BoardState::new_starting();
+        let mut sp = SearchPosition::from(&bs);
+        let moves = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        for _ in 0..2 {
+            for uci in moves {
+                let mv = *pseudo_legal_moves(&sp)
+                    .iter()
+                    .find(|mv| mv.to_uci_string() == uci)
+                    .unwrap();
+                sp.make_move(&mv);
+            }
+        }
+        // starting position is now on the board for the third time
+        assert_eq!(
+            sp.get_gamestate(&pseudo_legal_moves(&sp)),
+            GameState::Repetition
+        );
+        sp.unmake_move();
+        assert_eq!(
+            sp.get_gamestate(&pseudo_legal_moves(&sp)),
+            GameState::Active
+        );
+    }
+}
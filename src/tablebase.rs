@@ -0,0 +1,601 @@
+mod table;
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use ahash::AHashMap;
+
+use crate::board::BoardState;
+use crate::errors::TablebaseError;
+use crate::log_and_return_error;
+use crate::movegen::*;
+use crate::search_position::SearchPosition;
+use table::{ChangeStm, Material, MaterialKey, Table, TableKind};
+
+// rank given to root moves, higher is better
+const MAX_DTZ: i32 = 1 << 18;
+
+// game theoretical value of a position for the side to move.
+// cursed wins and blessed losses are wins and losses that are drawn by the 50 move rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    const fn from_i32(wdl: i32) -> Self {
+        match wdl {
+            i32::MIN..=-2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+}
+
+// root move chosen with the dtz tables
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TablebaseMove {
+    pub mv: Move,
+    pub wdl: Wdl, // value of the root position
+    pub dtz: i32, // plies to the next capture or pawn move after playing mv, negative when losing
+}
+
+// the wdl and dtz files of one material combination, read into memory when first probed
+struct TableFiles {
+    material: Material,
+    wdl_path: PathBuf,
+    dtz_path: Option<PathBuf>,
+    wdl: OnceLock<Option<Table>>,
+    dtz: OnceLock<Option<Table>>,
+}
+
+impl TableFiles {
+    fn table(&self, kind: TableKind) -> Option<&Table> {
+        let (path, table) = match kind {
+            TableKind::Wdl => (Some(&self.wdl_path), &self.wdl),
+            TableKind::Dtz => (self.dtz_path.as_ref(), &self.dtz),
+        };
+        table
+            .get_or_init(|| {
+                let path = path?;
+                let table = fs::read(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|data| Table::new(kind, &self.material, data));
+                match table {
+                    Ok(table) => {
+                        log::info!("Tablebase file loaded: {}", path.display());
+                        Some(table)
+                    }
+                    Err(e) => {
+                        log::error!("Error loading tablebase file {}: {}", path.display(), e);
+                        None
+                    }
+                }
+            })
+            .as_ref()
+    }
+}
+
+// Syzygy endgame tablebases from a directory of .rtbw (win/draw/loss) and .rtbz (distance to zeroing move) files.
+// only the file names are read when opened, each file is loaded on its first probe.
+// positions with castling rights are never in the tables
+pub struct Tablebase {
+    path: PathBuf,
+    tables: AHashMap<MaterialKey, Arc<TableFiles>>,
+    max_pieces: u8,
+}
+
+impl fmt::Debug for Tablebase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Tablebase")
+            .field("path", &self.path)
+            .field("tables", &self.len())
+            .field("max_pieces", &self.max_pieces)
+            .finish()
+    }
+}
+
+// tablebases opened from the same directory have the same tables
+impl PartialEq for Tablebase {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+    }
+}
+
+impl Eq for Tablebase {}
+
+impl Tablebase {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, TablebaseError> {
+        let path = path.as_ref();
+        let dir = match fs::read_dir(path) {
+            Ok(dir) => dir,
+            Err(e) => {
+                let err = TablebaseError::FileError(format!("{}: {}", path.display(), e));
+                log_and_return_error!(err)
+            }
+        };
+        let mut tables = AHashMap::new();
+        let mut max_pieces = 0;
+        for file in dir.flatten() {
+            let wdl_path = file.path();
+            if wdl_path.extension().is_none_or(|ext| ext != "rtbw") {
+                continue;
+            }
+            let Some(material) = wdl_path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(Material::from_name)
+            else {
+                log::warn!("Skipping unknown tablebase file: {}", wdl_path.display());
+                continue;
+            };
+            let dtz_path = wdl_path.with_extension("rtbz");
+            max_pieces = max_pieces.max(material.piece_count as u8);
+            let (key, key2) = (material.key, material.key2);
+            let files = Arc::new(TableFiles {
+                material,
+                dtz_path: dtz_path.is_file().then_some(dtz_path),
+                wdl_path,
+                wdl: OnceLock::new(),
+                dtz: OnceLock::new(),
+            });
+            tables.insert(key, files.clone());
+            tables.insert(key2, files);
+        }
+        if tables.is_empty() {
+            let err = TablebaseError::NoTables(path.display().to_string());
+            log_and_return_error!(err)
+        }
+        let tablebase = Tablebase {
+            path: path.to_path_buf(),
+            tables,
+            max_pieces,
+        };
+        log::info!(
+            "Tablebase opened from {} with {} tables, up to {} pieces",
+            path.display(),
+            tablebase.len(),
+            max_pieces
+        );
+        Ok(tablebase)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // number of material combinations with a wdl table
+    pub fn len(&self) -> usize {
+        self.tables
+            .iter()
+            .filter(|(key, files)| **key == files.material.key)
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    // largest number of pieces, including kings, of any table
+    pub fn max_pieces(&self) -> u8 {
+        self.max_pieces
+    }
+
+    // win/draw/loss for the side to move, assuming the 50 move counter is 0
+    pub fn probe_wdl(&self, bs: &BoardState) -> Option<Wdl> {
+        let mut sp = SearchPosition::from(bs);
+        self.probe_wdl_search(&mut sp)
+    }
+
+    // plies to the next capture or pawn move with the best play, positive when winning and negative when losing.
+    // values over 100 are cursed wins or blessed losses. assumes the 50 move counter is 0
+    pub fn probe_dtz(&self, bs: &BoardState) -> Option<i32> {
+        let mut sp = SearchPosition::from(bs);
+        if !self.can_probe(&sp) {
+            return None;
+        }
+        self.dtz(&mut sp)
+    }
+
+    // best root move using the dtz tables: the fastest win that doesn't run into the 50 move rule,
+    // a drawing move, or the longest loss. None if the position or one of the tables isn't available
+    pub fn best_move(&self, bs: &BoardState) -> Option<TablebaseMove> {
+        let mut sp = SearchPosition::from(bs);
+        if !self.can_probe(&sp) {
+            return None;
+        }
+        let halfmove_count = bs.halfmove_count() as i32;
+        let repeated = bs.get_occurences_of_current_position() > 1;
+        let mut best: Option<(i32, TablebaseMove)> = None;
+        for mv in legal_moves(&sp) {
+            let zeroing = is_zeroing(&mv);
+            sp.make_move(&mv);
+            // the tables don't know the game history, a move repeating the position a third time is a draw
+            let dtz = if sp.is_repetition() {
+                Some(0)
+            } else {
+                self.root_move_dtz(&mut sp, zeroing)
+            };
+            sp.unmake_move();
+            let dtz = dtz?;
+            // certain wins are ranked equally, losses too unless a 50 move draw is in sight
+            let rank = if dtz > 0 {
+                if dtz + halfmove_count <= 99 && !repeated {
+                    MAX_DTZ
+                } else {
+                    MAX_DTZ - (dtz + halfmove_count)
+                }
+            } else if dtz < 0 {
+                if -dtz * 2 + halfmove_count < 100 {
+                    -MAX_DTZ
+                } else {
+                    -MAX_DTZ + (-dtz + halfmove_count)
+                }
+            } else {
+                0
+            };
+            // the quickest win or slowest loss among equally ranked moves
+            let better = best.is_none_or(|(best_rank, best_mv)| {
+                rank > best_rank || (rank == best_rank && -dtz > -best_mv.dtz)
+            });
+            if better {
+                let wdl = match dtz {
+                    d if d > 0 && d + halfmove_count <= 100 => Wdl::Win,
+                    d if d > 0 => Wdl::CursedWin,
+                    d if d < 0 && -d + halfmove_count <= 100 => Wdl::Loss,
+                    d if d < 0 => Wdl::BlessedLoss,
+                    _ => Wdl::Draw,
+                };
+                best = Some((rank, TablebaseMove { mv, wdl, dtz }));
+            }
+        }
+        best.map(|(_, tb_move)| tb_move)
+    }
+
+    // wdl probe used by the search, sp is left unchanged
+    pub(crate) fn probe_wdl_search(&self, sp: &mut SearchPosition) -> Option<Wdl> {
+        if !self.can_probe(sp) {
+            return None;
+        }
+        self.search(sp, false).map(|(wdl, _)| Wdl::from_i32(wdl))
+    }
+
+    #[inline]
+    pub(crate) fn can_probe(&self, sp: &SearchPosition) -> bool {
+        sp.bitboards().occupied().count_ones() <= self.max_pieces as u32
+            && !sp.has_castling_rights()
+    }
+
+    fn probe_table(
+        &self,
+        sp: &SearchPosition,
+        kind: TableKind,
+        wdl: i32,
+    ) -> Option<Result<i32, ChangeStm>> {
+        let bitboards = sp.bitboards();
+        // king v king is a draw, and has no table
+        if bitboards.occupied().count_ones() == 2 {
+            return Some(Ok(0));
+        }
+        let key = table::material_key(&table::position_counts(bitboards));
+        let table = self.tables.get(&key)?.table(kind)?;
+        Some(table.probe(bitboards, sp.side_to_move(), wdl))
+    }
+
+    // dtz of the root move just made, from the point of view of the side that made it
+    fn root_move_dtz(&self, sp: &mut SearchPosition, zeroing: bool) -> Option<i32> {
+        let mut dtz = if zeroing {
+            // zeroing move, dtz is one of -101, -1, 0, 1, 101
+            dtz_before_zeroing(-self.search(sp, false)?.0)
+        } else {
+            let dtz = -self.dtz(sp)?;
+            dtz + dtz.signum()
+        };
+        // mating move
+        if dtz == 2 && sp.is_in_check() && legal_moves(sp).is_empty() {
+            dtz = 1;
+        }
+        Some(dtz)
+    }
+
+    // wdl of the position, searching captures (and pawn moves if check_zeroing) as the tables store
+    // "don't care" values where a capture is the best move. also returns if the best move is a zeroing move
+    fn search(&self, sp: &mut SearchPosition, check_zeroing: bool) -> Option<(i32, bool)> {
+        let moves = legal_moves(sp);
+        let mut best = -2;
+        let mut move_count = 0;
+        for mv in &moves {
+            let searched = if check_zeroing {
+                is_zeroing(mv)
+            } else {
+                mv.move_type.is_capture()
+            };
+            if !searched {
+                continue;
+            }
+            move_count += 1;
+            sp.make_move(mv);
+            let value = self.search(sp, false);
+            sp.unmake_move();
+            let value = -value?.0;
+            if value > best {
+                best = value;
+                if value >= 2 {
+                    return Some((value, true));
+                }
+            }
+        }
+        // the table can't be trusted if every move has already been searched, e.g. with en passant
+        let no_more_moves = move_count > 0 && move_count == moves.len();
+        let value = if no_more_moves {
+            best
+        } else {
+            self.probe_table(sp, TableKind::Wdl, 0)?.ok()?
+        };
+        if best >= value {
+            return Some((best, best > 0 || no_more_moves));
+        }
+        Some((value, false))
+    }
+
+    fn dtz(&self, sp: &mut SearchPosition) -> Option<i32> {
+        let (wdl, zeroing_best_move) = self.search(sp, true)?;
+        // dtz tables don't store draws
+        if wdl == 0 {
+            return Some(0);
+        }
+        if zeroing_best_move {
+            return Some(dtz_before_zeroing(wdl));
+        }
+        if let Ok(dtz) = self.probe_table(sp, TableKind::Dtz, wdl)? {
+            let cursed = (wdl == 1 || wdl == -1) as i32;
+            return Some((dtz + 100 * cursed) * wdl.signum());
+        }
+
+        // the table is for the other side to move, so take the best dtz of the moves
+        let mut min_dtz = i32::MAX;
+        for mv in legal_moves(sp) {
+            let zeroing = is_zeroing(&mv);
+            sp.make_move(&mv);
+            let dtz = if zeroing {
+                self.search(sp, false)
+                    .map(|(wdl, _)| -dtz_before_zeroing(wdl))
+            } else {
+                self.dtz(sp).map(|dtz| -dtz)
+            };
+            let mates = sp.is_in_check() && legal_moves(sp).is_empty();
+            sp.unmake_move();
+            let mut dtz = dtz?;
+            if dtz == 1 && mates {
+                min_dtz = 1;
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz < min_dtz && dtz.signum() == wdl.signum() {
+                min_dtz = dtz;
+            }
+        }
+        Some(if min_dtz == i32::MAX { -1 } else { min_dtz })
+    }
+}
+
+// dtz tables don't store zeroing moves, their dtz follows from the wdl of the position before them
+const fn dtz_before_zeroing(wdl: i32) -> i32 {
+    match wdl {
+        2 => 1,
+        1 => 101,
+        -1 => -101,
+        -2 => -1,
+        _ => 0,
+    }
+}
+
+// captures and pawn moves reset the 50 move counter
+fn is_zeroing(mv: &Move) -> bool {
+    mv.move_type.is_capture() || mv.piece.ptype == PieceType::Pawn
+}
+
+fn legal_moves(sp: &SearchPosition) -> Vec<Move> {
+    let mut moves = Vec::new();
+    sp.pseudo_legal_moves(&mut moves);
+    moves.retain(|mv| sp.is_move_legal(mv));
+    moves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+    use crate::fen::FEN;
+
+    // writes a table where every position has the same value, one byte per side to move
+    fn write_single_value_table(dir: &Path, name: &str, kind: TableKind, values: &[u8]) {
+        let mut data = match kind {
+            TableKind::Wdl => vec![0x71, 0xE8, 0x23, 0x5D],
+            TableKind::Dtz => vec![0xD7, 0x66, 0x0C, 0xA5],
+        };
+        let material = Material::from_name(name).unwrap();
+        data.push((material.key != material.key2) as u8);
+        // group order, then the piece codes, white 1-6 and black 9-14, for both sides to move
+        data.push(0);
+        for (colour, side) in name.split('v').enumerate() {
+            for c in side.chars() {
+                let code = "PNBRQK".find(c).unwrap() as u8 + 1 + 8 * colour as u8;
+                data.push(code | code << 4);
+            }
+        }
+        if data.len() % 2 == 1 {
+            data.push(0);
+        }
+        for value in values {
+            data.push(0x80);
+            data.push(*value);
+        }
+        let ext = match kind {
+            TableKind::Wdl => "rtbw",
+            TableKind::Dtz => "rtbz",
+        };
+        fs::write(dir.join(format!("{}.{}", name, ext)), data).unwrap();
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chess-oxide-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn board_state(fen: &str) -> BoardState {
+        BoardState::from(fen.parse::<FEN>().unwrap())
+    }
+
+    #[test]
+    fn test_open() {
+        let dir = test_dir("tb-open");
+        assert!(Tablebase::open(&dir).is_err());
+        fs::write(dir.join("KRvK.rtbw"), []).unwrap();
+        fs::write(dir.join("KQvKR.rtbw"), []).unwrap();
+        fs::write(dir.join("notes.txt"), []).unwrap();
+        let tb = Tablebase::open(&dir).unwrap();
+        assert_eq!(tb.len(), 2);
+        assert_eq!(tb.max_pieces(), 4);
+        // invalid files are logged and the probe fails
+        assert_eq!(
+            tb.probe_wdl(&board_state("8/8/8/8/8/3k4/8/R3K3 w - - 0 1")),
+            None
+        );
+        // king v king needs no table
+        assert_eq!(
+            tb.probe_wdl(&board_state("8/8/8/8/8/3k4/8/4K3 w - - 0 1")),
+            Some(Wdl::Draw)
+        );
+        // castling rights
+        assert_eq!(
+            tb.probe_wdl(&board_state("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1")),
+            None
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_single_value_tables() {
+        let dir = test_dir("tb-single");
+        // white to move wins, black to move loses. dtz is only stored for white, 9 moves
+        write_single_value_table(&dir, "KQvK", TableKind::Wdl, &[4, 0]);
+        write_single_value_table(&dir, "KQvK", TableKind::Dtz, &[9]);
+        let tb = Tablebase::open(&dir).unwrap();
+
+        let white = board_state("8/8/8/3k4/8/8/8/4K2Q w - - 0 1");
+        assert_eq!(tb.probe_wdl(&white), Some(Wdl::Win));
+        assert_eq!(tb.probe_dtz(&white), Some(19));
+        // colours swapped, black is the stronger side
+        let black = board_state("4k2q/8/8/8/3K4/8/8/8 b - - 0 1");
+        assert_eq!(tb.probe_wdl(&black), Some(Wdl::Win));
+        // black to move loses, the dtz comes from the moves as only white to move is stored
+        let losing = board_state("8/8/8/3k4/8/8/8/4K2Q b - - 0 1");
+        assert_eq!(tb.probe_wdl(&losing), Some(Wdl::Loss));
+        assert_eq!(tb.probe_dtz(&losing), Some(-20));
+
+        let best = tb.best_move(&white).unwrap();
+        assert_eq!(best.wdl, Wdl::Win);
+        assert!(white.get_legal_moves().unwrap().contains(&best.mv));
+
+        // the queen can be captured, so the stored value isn't used
+        let capture = board_state("8/8/8/8/8/8/6kQ/4K3 b - - 0 1");
+        assert_eq!(tb.probe_wdl(&capture), Some(Wdl::Draw));
+
+        // the losing side draws by going back to a position seen twice before
+        let mut board = Board::from("8/8/8/8/3k4/8/8/4K2Q w - - 0 1".parse::<FEN>().unwrap());
+        for uci in ["e1f1", "d4c5", "f1e1", "c5d4", "e1f1", "d4c5", "f1e1"] {
+            let mv = board.get_current_state().move_from_uci(uci).unwrap();
+            board.make_move(&mv).unwrap();
+        }
+        let best = tb.best_move(board.get_current_state()).unwrap();
+        assert_eq!(best.mv.to_uci_string(), "c5d4");
+        assert_eq!(best.wdl, Wdl::Draw);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // real tables are only probed when SYZYGY_PATH is set to a directory with the 3 piece tables
+    #[test]
+    fn test_syzygy_tables() {
+        let Ok(path) = std::env::var("SYZYGY_PATH") else {
+            return;
+        };
+        let tb = Tablebase::open(path).unwrap();
+        assert!(tb.max_pieces() >= 3);
+
+        // KRvK, a win unless the rook is lost straight away
+        let win = board_state("8/8/8/8/8/3k4/8/R3K3 w - - 0 1");
+        assert_eq!(tb.probe_wdl(&win), Some(Wdl::Win));
+        assert!(tb.probe_dtz(&win).unwrap() > 0);
+        let hanging = board_state("8/8/8/8/8/8/1k6/1R2K3 b - - 0 1");
+        assert_eq!(tb.probe_wdl(&hanging), Some(Wdl::Draw));
+        assert_eq!(tb.probe_dtz(&hanging), Some(0));
+        let mate = tb
+            .best_move(&board_state("k7/8/1K6/8/8/8/8/7R w - - 0 1"))
+            .unwrap();
+        assert_eq!(mate.mv.to_uci_string(), "h1h8");
+        assert_eq!(mate.wdl, Wdl::Win);
+
+        // KPvK, the rook pawn can't be promoted against the king in the corner
+        let rook_pawn = board_state("7k/8/8/8/8/8/7P/7K w - - 0 1");
+        assert_eq!(tb.probe_wdl(&rook_pawn), Some(Wdl::Draw));
+        let outside = board_state("8/8/8/8/8/8/4P3/4K2k w - - 0 1");
+        assert_eq!(tb.probe_wdl(&outside), Some(Wdl::Win));
+        let best = tb.best_move(&outside).unwrap();
+        assert_eq!(best.wdl, Wdl::Win);
+        assert!(outside.get_legal_moves().unwrap().contains(&best.mv));
+    }
+
+    #[test]
+    fn test_search_with_tablebase() {
+        use crate::engine::{self, SearchLimits, SearchOptions};
+        use crate::transposition::TranspositionTable;
+
+        let dir = test_dir("tb-search");
+        write_single_value_table(&dir, "KQvK", TableKind::Wdl, &[4, 0]);
+        write_single_value_table(&dir, "KQvK", TableKind::Dtz, &[9]);
+        let options = SearchOptions::tablebase(Arc::new(Tablebase::open(&dir).unwrap()));
+        let tt = TranspositionTable::with_size(1);
+
+        // in the tablebase at the root, the move is played without a search
+        let bs = board_state("8/8/8/3k4/8/8/8/4K2Q w - - 0 1");
+        let info = engine::iterative_deepening(
+            &bs,
+            &SearchLimits::depth(4),
+            &options,
+            &tt,
+            &mut engine::SearchHistory::new(),
+            None,
+            |_| {},
+        );
+        assert!(info.from_tablebase);
+        assert!(engine::is_eval_tablebase(info.eval) && info.eval > 0);
+
+        // taking the rook reaches a tablebase win in the search
+        let bs = board_state("7r/8/8/1k6/8/8/8/4K2Q w - - 0 1");
+        let info = engine::iterative_deepening(
+            &bs,
+            &SearchLimits::depth(2),
+            &options,
+            &tt,
+            &mut engine::SearchHistory::new(),
+            None,
+            |_| {},
+        );
+        assert!(!info.from_tablebase);
+        assert_eq!(info.best_move.to_uci_string(), "h1h8");
+        assert!(engine::is_eval_tablebase(info.eval) && info.eval > 0);
+        assert!(info.tb_hits > 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,904 @@
+// decoding of a single syzygy .rtbw or .rtbz file, ported from the Stockfish prober.
+// squares in this module are numbered a1 = 0 ... h8 = 63 as in the tablebase format,
+// board squares (a8 = 0) are converted with sq ^ 56
+use std::cmp;
+
+use static_init::dynamic;
+
+use crate::bitboard::{squares, Bitboard, Bitboards};
+use crate::movegen::{PieceColour, PieceType};
+
+pub(super) const TB_PIECES: usize = 7;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+// file header flags
+const SPLIT: u8 = 1;
+const HAS_PAWNS: u8 = 2;
+
+// per table flags
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16;
+const SINGLE_VALUE: u8 = 128;
+
+// piece counts for both colours, 4 bits per colour and piece type
+pub(super) type MaterialKey = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum TableKind {
+    Wdl,
+    Dtz,
+}
+
+// the table stores results for the other side to move, a dtz table only has one side
+pub(super) struct ChangeStm;
+
+#[inline(always)]
+const fn rank_of(sq: usize) -> usize {
+    sq >> 3
+}
+
+#[inline(always)]
+const fn file_of(sq: usize) -> usize {
+    sq & 7
+}
+
+// distance of sq from the a1-h8 diagonal, negative below it
+#[inline(always)]
+const fn off_a1h8(sq: usize) -> i32 {
+    rank_of(sq) as i32 - file_of(sq) as i32
+}
+
+#[inline(always)]
+const fn flip_diagonal(sq: usize) -> usize {
+    ((sq >> 3) | (sq << 3)) & 63
+}
+
+// index tables used to encode a position, built once
+struct Maps {
+    pawns: [u64; 64],    // a2-h7 to 0..47, the leading pawn has the highest value
+    b1h1h7: [u64; 64],   // squares below the a1-h8 diagonal to 0..27
+    a1d1d4: [usize; 64], // a1-d1-d4 triangle to 0..9, diagonal squares last
+    kk: [[u64; 64]; 10], // the 462 legal placements of two kings, first one in the a1-d1-d4 triangle
+    binomial: [[u64; 64]; 7],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+impl Maps {
+    fn new() -> Self {
+        let mut maps = Maps {
+            pawns: [0; 64],
+            b1h1h7: [0; 64],
+            a1d1d4: [0; 64],
+            kk: [[0; 64]; 10],
+            binomial: [[0; 64]; 7],
+            lead_pawn_idx: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+
+        let mut code = 0;
+        for sq in 0..64 {
+            if off_a1h8(sq) < 0 {
+                maps.b1h1h7[sq] = code;
+                code += 1;
+            }
+        }
+
+        let mut code = 0;
+        let mut diagonal = Vec::new();
+        for sq in 0..=27 {
+            if off_a1h8(sq) < 0 && file_of(sq) <= 3 {
+                maps.a1d1d4[sq] = code;
+                code += 1;
+            } else if off_a1h8(sq) == 0 && file_of(sq) <= 3 {
+                diagonal.push(sq);
+            }
+        }
+        for sq in diagonal {
+            maps.a1d1d4[sq] = code;
+            code += 1;
+        }
+
+        let mut code = 0;
+        let mut both_on_diagonal = Vec::new();
+        for idx in 0..10 {
+            // b1 is the only square mapped to 0, the rest of the unmapped squares are 0 too
+            for s1 in (0..=27).filter(|&s1| maps.a1d1d4[s1] == idx && (idx != 0 || s1 == 1)) {
+                for s2 in 0..64 {
+                    let adjacent = file_of(s1).abs_diff(file_of(s2)) <= 1
+                        && rank_of(s1).abs_diff(rank_of(s2)) <= 1;
+                    if adjacent || (off_a1h8(s1) == 0 && off_a1h8(s2) > 0) {
+                        continue;
+                    } else if off_a1h8(s1) == 0 && off_a1h8(s2) == 0 {
+                        both_on_diagonal.push((idx, s2));
+                    } else {
+                        maps.kk[idx][s2] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, s2) in both_on_diagonal {
+            maps.kk[idx][s2] = code;
+            code += 1;
+        }
+
+        // binomial[k][n] ways to choose k of n squares
+        maps.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..cmp::min(7, n + 1) {
+                maps.binomial[k][n] = if k > 0 {
+                    maps.binomial[k - 1][n - 1]
+                } else {
+                    0
+                } + if k < n { maps.binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        let mut available = 48;
+        for lead_pawns in 1..=5 {
+            for file in 0..4 {
+                let mut idx = 0;
+                for rank in 1..=6 {
+                    let sq = rank * 8 + file;
+                    if lead_pawns == 1 {
+                        available -= 1;
+                        maps.pawns[sq] = available;
+                        available -= 1;
+                        maps.pawns[sq ^ 7] = available;
+                    }
+                    maps.lead_pawn_idx[lead_pawns][sq] = idx;
+                    idx += maps.binomial[lead_pawns - 1][maps.pawns[sq] as usize];
+                }
+                maps.lead_pawns_size[lead_pawns][file] = idx;
+            }
+        }
+        maps
+    }
+}
+
+#[dynamic]
+static MAPS: Maps = Maps::new();
+
+pub(super) fn material_key(counts: &[[u8; 6]; 2]) -> MaterialKey {
+    let mut key = 0;
+    for (colour, colour_counts) in counts.iter().enumerate() {
+        for (ptype, count) in colour_counts.iter().enumerate() {
+            key |= (*count as u64) << (4 * (colour * 6 + ptype));
+        }
+    }
+    key
+}
+
+pub(super) fn position_counts(bitboards: &Bitboards) -> [[u8; 6]; 2] {
+    let mut counts = [[0; 6]; 2];
+    for colour in [PieceColour::White, PieceColour::Black] {
+        for ptype in PIECE_TYPES {
+            counts[colour as usize][ptype as usize] =
+                (bitboards.colour(colour) & bitboards.ptype(ptype)).count_ones() as u8;
+        }
+    }
+    counts
+}
+
+const PIECE_TYPES: [PieceType; 6] = [
+    PieceType::Pawn,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Rook,
+    PieceType::Queen,
+    PieceType::King,
+];
+
+// material of a table from its name, e.g. KRPvKR. the first side is white in key and black in key2
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Material {
+    pub(super) name: String,
+    pub(super) key: MaterialKey,
+    pub(super) key2: MaterialKey,
+    pub(super) piece_count: usize,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    pawn_count: [u8; 2], // lead colour, other colour
+}
+
+impl Material {
+    pub(super) fn from_name(name: &str) -> Option<Self> {
+        let (white, black) = name.split_once('v')?;
+        let mut counts = [[0u8; 6]; 2];
+        for (colour, side) in [white, black].iter().enumerate() {
+            if !side.starts_with('K') || side.matches('K').count() != 1 {
+                return None;
+            }
+            for c in side.chars() {
+                let ptype = match c {
+                    'P' => PieceType::Pawn,
+                    'N' => PieceType::Knight,
+                    'B' => PieceType::Bishop,
+                    'R' => PieceType::Rook,
+                    'Q' => PieceType::Queen,
+                    'K' => PieceType::King,
+                    _ => return None,
+                };
+                counts[colour][ptype as usize] += 1;
+            }
+        }
+        let piece_count = white.len() + black.len();
+        if piece_count > TB_PIECES {
+            return None;
+        }
+        let has_unique_pieces = counts.iter().any(|side| side[..5].contains(&1));
+        let (white_pawns, black_pawns) = (counts[0][0], counts[1][0]);
+        // the side with fewer pawns leads, as it compresses better
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        let pawn_count = if white_leads {
+            [white_pawns, black_pawns]
+        } else {
+            [black_pawns, white_pawns]
+        };
+        Some(Material {
+            name: name.to_string(),
+            key: material_key(&counts),
+            key2: material_key(&[counts[1], counts[0]]),
+            piece_count,
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces,
+            pawn_count,
+        })
+    }
+}
+
+// indexing and huffman decoding data for one side to move and leading pawn file of a table
+#[derive(Debug, Clone, Default)]
+struct PairsData {
+    flags: u8,
+    min_sym_len: u8,
+    block_size: usize,
+    span: usize,
+    lowest_sym: usize,   // offset of the lowest symbol of each length
+    btree: usize,        // offset of the symbol pairs, 3 bytes per symbol
+    block_length: usize, // offset of the number of values in each block (minus one)
+    sparse_index: usize, // offset of the entries pointing into the blocks every span values
+    sparse_index_size: usize,
+    num_blocks: u32,
+    block_length_size: u32,
+    data: usize, // offset of the compressed blocks
+    base64: Vec<u64>,
+    symlen: Vec<u8>, // number of values represented by a symbol, minus one
+    pieces: [u8; TB_PIECES],
+    group_idx: [u64; TB_PIECES + 1],
+    group_len: [usize; TB_PIECES + 1],
+    map_idx: [u16; 4], // dtz map offsets for win, loss, cursed win, blessed loss
+}
+
+// out of range reads return 0 rather than panicking on a corrupt file
+#[inline(always)]
+fn read_u8(data: &[u8], pos: usize) -> u8 {
+    data.get(pos).copied().unwrap_or(0)
+}
+
+#[inline(always)]
+fn read_u16_le(data: &[u8], pos: usize) -> u16 {
+    data.get(pos..pos + 2)
+        .map_or(0, |b| u16::from_le_bytes([b[0], b[1]]))
+}
+
+#[inline(always)]
+fn read_u32_le(data: &[u8], pos: usize) -> u32 {
+    data.get(pos..pos + 4)
+        .map_or(0, |b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+#[inline(always)]
+fn read_u32_be(data: &[u8], pos: usize) -> u32 {
+    data.get(pos..pos + 4)
+        .map_or(0, |b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+#[inline(always)]
+fn read_u64_be(data: &[u8], pos: usize) -> u64 {
+    (read_u32_be(data, pos) as u64) << 32 | read_u32_be(data, pos + 4) as u64
+}
+
+// symbol pair stored in 3 bytes, 12 bits each
+#[inline(always)]
+fn btree_left(data: &[u8], d: &PairsData, sym: usize) -> usize {
+    let pos = d.btree + sym * 3;
+    ((read_u8(data, pos + 1) as usize & 0xF) << 8) | read_u8(data, pos) as usize
+}
+
+#[inline(always)]
+fn btree_right(data: &[u8], d: &PairsData, sym: usize) -> usize {
+    let pos = d.btree + sym * 3;
+    ((read_u8(data, pos + 2) as usize) << 4) | (read_u8(data, pos + 1) as usize >> 4)
+}
+
+// contents of a table file, parsed when the table is first probed
+pub(super) struct Table {
+    kind: TableKind,
+    data: Vec<u8>,
+    material: Material,
+    items: Vec<PairsData>, // [side to move][leading pawn file]
+    map: usize,            // offset of the dtz value maps
+}
+
+impl Table {
+    pub(super) fn new(kind: TableKind, material: &Material, data: Vec<u8>) -> Result<Self, String> {
+        let magic = match kind {
+            TableKind::Wdl => WDL_MAGIC,
+            TableKind::Dtz => DTZ_MAGIC,
+        };
+        if data.len() < 5 || data[0..4] != magic {
+            return Err("invalid magic number".to_string());
+        }
+        let header = data[4];
+        if material.has_pawns != (header & HAS_PAWNS != 0)
+            || (material.key != material.key2) != (header & SPLIT != 0)
+        {
+            return Err("header does not match the table name".to_string());
+        }
+        let mut table = Table {
+            kind,
+            data,
+            material: material.clone(),
+            items: vec![PairsData::default(); 8],
+            map: 0,
+        };
+        table.parse()?;
+        Ok(table)
+    }
+
+    fn sides(&self) -> usize {
+        match self.kind {
+            TableKind::Wdl => 2,
+            TableKind::Dtz => 1,
+        }
+    }
+
+    #[inline(always)]
+    fn item_index(&self, stm: usize, file: usize) -> usize {
+        item_index(self.sides(), self.material.has_pawns, stm, file)
+    }
+
+    fn parse(&mut self) -> Result<(), String> {
+        let material = &self.material;
+        let data = &self.data;
+        let items = &mut self.items;
+        let table_sides = match self.kind {
+            TableKind::Wdl => 2,
+            TableKind::Dtz => 1,
+        };
+        let index = |side, file| item_index(table_sides, material.has_pawns, side, file);
+        // symmetric wdl tables only store white to move
+        let sides = if material.key != material.key2 {
+            table_sides
+        } else {
+            1
+        };
+        let max_file = if material.has_pawns { 3 } else { 0 };
+        let pp = material.has_pawns && material.pawn_count[1] > 0;
+        let mut pos = 5;
+
+        for file in 0..=max_file {
+            let order_byte = read_u8(data, pos);
+            let pp_byte = if pp { read_u8(data, pos + 1) } else { 0xFF };
+            let order = [
+                [order_byte & 0xF, pp_byte & 0xF],
+                [order_byte >> 4, pp_byte >> 4],
+            ];
+            pos += 1 + pp as usize;
+            for k in 0..material.piece_count {
+                let byte = read_u8(data, pos);
+                for side in 0..sides {
+                    items[index(side, file)].pieces[k] =
+                        if side == 0 { byte & 0xF } else { byte >> 4 };
+                }
+                pos += 1;
+            }
+            for (side, order) in order.iter().enumerate().take(sides) {
+                set_groups(material, &mut items[index(side, file)], order, file);
+            }
+        }
+        pos += pos & 1;
+
+        for file in 0..=max_file {
+            for side in 0..sides {
+                pos = set_sizes(data, &mut items[index(side, file)], pos)?;
+            }
+        }
+
+        if self.kind == TableKind::Dtz {
+            self.map = pos;
+            for file in 0..=max_file {
+                let d = &mut items[index(0, file)];
+                if d.flags & MAPPED == 0 {
+                    continue;
+                }
+                if d.flags & WIDE != 0 {
+                    pos += pos & 1;
+                    for i in 0..4 {
+                        d.map_idx[i] = ((pos - self.map) / 2 + 1) as u16;
+                        pos += 2 * read_u16_le(data, pos) as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        d.map_idx[i] = (pos - self.map + 1) as u16;
+                        pos += read_u8(data, pos) as usize + 1;
+                    }
+                }
+            }
+            pos += pos & 1;
+        }
+
+        for file in 0..=max_file {
+            for side in 0..sides {
+                let d = &mut items[index(side, file)];
+                d.sparse_index = pos;
+                pos += d.sparse_index_size * 6;
+            }
+        }
+        for file in 0..=max_file {
+            for side in 0..sides {
+                let d = &mut items[index(side, file)];
+                d.block_length = pos;
+                pos += d.block_length_size as usize * 2;
+            }
+        }
+        for file in 0..=max_file {
+            for side in 0..sides {
+                let d = &mut items[index(side, file)];
+                pos = (pos + 0x3F) & !0x3F;
+                d.data = pos;
+                pos += d.num_blocks as usize * d.block_size;
+                // single value tables have no blocks, and the file can end before the alignment
+                if d.num_blocks > 0 && pos > data.len() {
+                    return Err("file is truncated".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // probe the table for a position with no castling rights, that is in this table.
+    // a wdl table returns -2..=2, a dtz table returns the dtz in plies and needs the wdl of the position
+    pub(super) fn probe(
+        &self,
+        bitboards: &Bitboards,
+        side_to_move: PieceColour,
+        wdl: i32,
+    ) -> Result<i32, ChangeStm> {
+        let maps = &*MAPS;
+        let material = &self.material;
+        let mut squares = [0usize; TB_PIECES];
+        let mut pieces = [0u8; TB_PIECES];
+        let mut size = 0;
+        let mut lead_pawns_count = 0;
+        let mut lead_pawns: Bitboard = 0;
+        let mut tb_file = 0;
+
+        // tables are stored with the stronger side as white, and symmetric tables only for white to move.
+        // otherwise the colours are swapped and the board flipped vertically
+        let symmetric_btm = material.key == material.key2 && side_to_move == PieceColour::Black;
+        let black_stronger = material_key(&position_counts(bitboards)) != material.key;
+        let flip = symmetric_btm || black_stronger;
+        let flip_colour = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = flip as usize ^ side_to_move as usize;
+
+        if material.has_pawns {
+            // the leading pawns are the first pieces, and their colour is the reference one
+            let lead = self.items[self.item_index(0, 0)].pieces[0] ^ flip_colour;
+            let lead_colour = if lead & 8 == 0 {
+                PieceColour::White
+            } else {
+                PieceColour::Black
+            };
+            lead_pawns = bitboards.colour(lead_colour) & bitboards.ptype(PieceType::Pawn);
+            for sq in tb_squares(lead_pawns) {
+                squares[size] = sq ^ flip_squares;
+                size += 1;
+            }
+            lead_pawns_count = size;
+            let max = (0..lead_pawns_count)
+                .max_by_key(|&i| maps.pawns[squares[i]])
+                .unwrap_or(0);
+            squares.swap(0, max);
+            let file = file_of(squares[0]);
+            tb_file = cmp::min(file, 7 - file);
+        }
+
+        let d = &self.items[self.item_index(stm, tb_file)];
+        if self.kind == TableKind::Dtz
+            && (d.flags & STM) as usize != stm
+            && (material.key != material.key2 || material.has_pawns)
+        {
+            return Err(ChangeStm);
+        }
+
+        for sq in tb_squares(bitboards.occupied() ^ lead_pawns) {
+            let board_sq = sq ^ 56;
+            let colour = if bitboards.colour(PieceColour::White) & (1 << board_sq) != 0 {
+                0
+            } else {
+                8
+            };
+            let ptype = PIECE_TYPES
+                .iter()
+                .position(|ptype| bitboards.ptype(*ptype) & (1 << board_sq) != 0)
+                .unwrap_or(0) as u8;
+            squares[size] = sq ^ flip_squares;
+            pieces[size] = (ptype + 1 + colour) ^ flip_colour;
+            size += 1;
+        }
+
+        // same piece order as the table
+        for i in lead_pawns_count..size.saturating_sub(1) {
+            for j in i + 1..size {
+                if d.pieces[i] == pieces[j] {
+                    pieces.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        // mirror so the leading piece is on files a-d
+        if file_of(squares[0]) > 3 {
+            for sq in squares.iter_mut().take(size) {
+                *sq ^= 7;
+            }
+        }
+
+        let mut idx;
+        if material.has_pawns {
+            idx = maps.lead_pawn_idx[lead_pawns_count][squares[0]];
+            squares[1..lead_pawns_count].sort_by_key(|sq| maps.pawns[*sq]);
+            for (i, sq) in squares[..lead_pawns_count].iter().enumerate().skip(1) {
+                idx += maps.binomial[i][maps.pawns[*sq] as usize];
+            }
+        } else {
+            // mirror so the leading piece is on ranks 1-4, then below the a1-h8 diagonal
+            if rank_of(squares[0]) > 3 {
+                for sq in squares.iter_mut().take(size) {
+                    *sq ^= 56;
+                }
+            }
+            for i in 0..d.group_len[0] {
+                if off_a1h8(squares[i]) == 0 {
+                    continue;
+                }
+                if off_a1h8(squares[i]) > 0 {
+                    for sq in squares.iter_mut().take(size).skip(i) {
+                        *sq = flip_diagonal(*sq);
+                    }
+                }
+                break;
+            }
+
+            if material.has_unique_pieces {
+                let adjust1 = (squares[1] > squares[0]) as u64;
+                let adjust2 = (squares[2] > squares[0]) as u64 + (squares[2] > squares[1]) as u64;
+                let (s0, s1, s2) = (squares[0], squares[1] as u64, squares[2] as u64);
+                idx = if off_a1h8(s0) != 0 {
+                    (maps.a1d1d4[s0] as u64 * 63 + (s1 - adjust1)) * 62 + s2 - adjust2
+                } else if off_a1h8(squares[1]) != 0 {
+                    (6 * 63 + rank_of(s0) as u64 * 28 + maps.b1h1h7[squares[1]]) * 62 + s2 - adjust2
+                } else if off_a1h8(squares[2]) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + rank_of(s0) as u64 * 7 * 28
+                        + (rank_of(squares[1]) as u64 - adjust1) * 28
+                        + maps.b1h1h7[squares[2]]
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + rank_of(s0) as u64 * 7 * 6
+                        + (rank_of(squares[1]) as u64 - adjust1) * 6
+                        + (rank_of(squares[2]) as u64 - adjust2)
+                };
+            } else {
+                idx = maps.kk[maps.a1d1d4[squares[0]]][squares[1]];
+            }
+        }
+
+        // the remaining groups, each encoded as a combination of the squares left by the earlier groups
+        idx *= d.group_idx[0];
+        let mut group_start = d.group_len[0];
+        let mut remaining_pawns = material.has_pawns && material.pawn_count[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let group_end = group_start + d.group_len[next];
+            squares[group_start..group_end].sort_unstable();
+            let mut n = 0;
+            for i in 0..d.group_len[next] {
+                let sq = squares[group_start + i];
+                let adjust = squares[..group_start].iter().filter(|s| sq > **s).count();
+                n += maps.binomial[i + 1][sq - adjust - 8 * remaining_pawns as usize];
+            }
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            group_start = group_end;
+            next += 1;
+        }
+
+        let value = self.decompress_pairs(d, idx);
+        Ok(match self.kind {
+            TableKind::Wdl => value - 2,
+            TableKind::Dtz => self.map_dtz(tb_file, value, wdl),
+        })
+    }
+
+    // dtz values are stored remapped by frequency, and in moves rather than plies where possible
+    fn map_dtz(&self, file: usize, value: i32, wdl: i32) -> i32 {
+        const WDL_MAP: [usize; 5] = [1, 3, 0, 2, 0];
+        let d = &self.items[self.item_index(0, file)];
+        let mut value = value;
+        if d.flags & MAPPED != 0 {
+            let map_idx = d.map_idx[WDL_MAP[(wdl + 2) as usize]] as usize;
+            value = if d.flags & WIDE != 0 {
+                read_u16_le(&self.data, self.map + 2 * (map_idx + value as usize)) as i32
+            } else {
+                read_u8(&self.data, self.map + map_idx + value as usize) as i32
+            };
+        }
+        if (wdl == 2 && d.flags & WIN_PLIES == 0)
+            || (wdl == -2 && d.flags & LOSS_PLIES == 0)
+            || wdl == 1
+            || wdl == -1
+        {
+            value *= 2;
+        }
+        value + 1
+    }
+
+    // value at idx from the huffman coded blocks, each symbol expanding recursively into pairs of symbols
+    fn decompress_pairs(&self, d: &PairsData, idx: u64) -> i32 {
+        if d.flags & SINGLE_VALUE != 0 {
+            return d.min_sym_len as i32;
+        }
+        let data = &self.data;
+        let span = d.span as u64;
+        let k = (idx / span) as usize;
+        let mut block = read_u32_le(data, d.sparse_index + 6 * k) as usize;
+        let mut offset = read_u16_le(data, d.sparse_index + 6 * k + 4) as i64;
+        offset += (idx % span) as i64 - (span / 2) as i64;
+
+        let block_length = |block: usize| read_u16_le(data, d.block_length + 2 * block) as i64;
+        while offset < 0 {
+            block = block.wrapping_sub(1);
+            offset += block_length(block) + 1;
+        }
+        while offset > block_length(block) {
+            offset -= block_length(block) + 1;
+            block += 1;
+        }
+
+        let mut ptr = d.data + block * d.block_size;
+        let mut buf64 = read_u64_be(data, ptr);
+        ptr += 8;
+        let mut buf64_size = 64;
+        let min_sym_len = d.min_sym_len as usize;
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while len + 1 < d.base64.len() && buf64 < d.base64[len] {
+                len += 1;
+            }
+            sym = ((buf64 - d.base64[len]) >> (64 - len - min_sym_len)) as usize;
+            sym += read_u16_le(data, d.lowest_sym + 2 * len) as usize;
+            let sym_values = *d.symlen.get(sym).unwrap_or(&0) as i64 + 1;
+            if offset < sym_values {
+                break;
+            }
+            offset -= sym_values;
+            len += min_sym_len;
+            buf64 = buf64.checked_shl(len as u32).unwrap_or(0);
+            buf64_size -= len as i32;
+            if buf64_size <= 32 {
+                buf64_size += 32;
+                buf64 |= (read_u32_be(data, ptr) as u64) << (64 - buf64_size);
+                ptr += 4;
+            }
+        }
+
+        while d.symlen.get(sym).is_some_and(|len| *len != 0) {
+            let left = btree_left(data, d, sym);
+            let left_values = *d.symlen.get(left).unwrap_or(&0) as i64 + 1;
+            if offset < left_values {
+                sym = left;
+            } else {
+                offset -= left_values;
+                sym = btree_right(data, d, sym);
+            }
+        }
+        btree_left(data, d, sym) as i32
+    }
+}
+
+// [side to move][leading pawn file], only one side for dtz tables and one file for tables without pawns
+#[inline(always)]
+fn item_index(sides: usize, has_pawns: bool, stm: usize, file: usize) -> usize {
+    (stm % sides) * 4 + if has_pawns { file } else { 0 }
+}
+
+// board squares of a bitboard in tablebase numbering, in ascending tablebase order
+fn tb_squares(bb: Bitboard) -> impl Iterator<Item = usize> {
+    // swapping the bytes flips the ranks, so bit n is tablebase square n
+    squares(bb.swap_bytes())
+}
+
+// pieces are encoded in groups, normally pieces of the same type and colour. the leading group is the
+// leading pawns, or the first 3 pieces, or the 2 kings if there are no unique pieces other than the kings.
+// the order byte gives the order the groups are combined in
+fn set_groups(material: &Material, d: &mut PairsData, order: &[u8; 2], file: usize) {
+    let maps = &*MAPS;
+    let mut n = 0;
+    let mut first_len: i32 = if material.has_pawns {
+        0
+    } else if material.has_unique_pieces {
+        3
+    } else {
+        2
+    };
+    d.group_len[n] = 1;
+    for i in 1..material.piece_count {
+        first_len -= 1;
+        if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+            d.group_len[n] += 1;
+        } else {
+            n += 1;
+            d.group_len[n] = 1;
+        }
+    }
+    n += 1;
+    d.group_len[n] = 0;
+
+    let pp = material.has_pawns && material.pawn_count[1] > 0;
+    let mut next = if pp { 2 } else { 1 };
+    let mut free_squares = 64 - d.group_len[0] - if pp { d.group_len[1] } else { 0 };
+    let mut idx: u64 = 1;
+    let mut k = 0;
+    while next < n || k == order[0] || k == order[1] {
+        if k == order[0] {
+            d.group_idx[0] = idx;
+            idx *= if material.has_pawns {
+                maps.lead_pawns_size[d.group_len[0]][file]
+            } else if material.has_unique_pieces {
+                31332
+            } else {
+                462
+            };
+        } else if k == order[1] {
+            d.group_idx[1] = idx;
+            idx *= maps.binomial[d.group_len[1]][48 - d.group_len[0]];
+        } else {
+            d.group_idx[next] = idx;
+            idx *= maps.binomial[d.group_len[next]][free_squares];
+            free_squares -= d.group_len[next];
+            next += 1;
+        }
+        k += 1;
+    }
+    d.group_idx[n] = idx;
+}
+
+// reads the block and huffman code sizes, returns the position after them
+fn set_sizes(data: &[u8], d: &mut PairsData, mut pos: usize) -> Result<usize, String> {
+    d.flags = read_u8(data, pos);
+    pos += 1;
+    if d.flags & SINGLE_VALUE != 0 {
+        // the single value is stored as the min symbol length
+        d.min_sym_len = read_u8(data, pos);
+        return Ok(pos + 1);
+    }
+
+    let groups = d.group_len.iter().position(|len| *len == 0).unwrap_or(0);
+    let tb_size = d.group_idx[groups];
+    d.block_size = 1 << read_u8(data, pos);
+    d.span = 1 << read_u8(data, pos + 1);
+    d.sparse_index_size = tb_size.div_ceil(d.span as u64) as usize;
+    let padding = read_u8(data, pos + 2);
+    d.num_blocks = read_u32_le(data, pos + 3);
+    d.block_length_size = d.num_blocks + padding as u32;
+    let max_sym_len = read_u8(data, pos + 7);
+    d.min_sym_len = read_u8(data, pos + 8);
+    pos += 9;
+    if max_sym_len < d.min_sym_len || d.min_sym_len == 0 || max_sym_len > 32 {
+        return Err("invalid symbol lengths".to_string());
+    }
+    d.lowest_sym = pos;
+
+    // canonical huffman code, longer symbols have lower values. base64[l] is the lowest symbol of
+    // length l + min_sym_len left aligned in 64 bits
+    let lengths = (max_sym_len - d.min_sym_len + 1) as usize;
+    d.base64 = vec![0; lengths];
+    for i in (0..lengths - 1).rev() {
+        d.base64[i] = d.base64[i + 1]
+            .wrapping_add(read_u16_le(data, d.lowest_sym + 2 * i) as u64)
+            .wrapping_sub(read_u16_le(data, d.lowest_sym + 2 * (i + 1)) as u64)
+            / 2;
+    }
+    for (i, base) in d.base64.iter_mut().enumerate() {
+        *base = base
+            .checked_shl((64 - i - d.min_sym_len as usize) as u32)
+            .unwrap_or(0);
+    }
+    pos += lengths * 2;
+
+    let symbols = read_u16_le(data, pos) as usize;
+    pos += 2;
+    d.btree = pos;
+    d.symlen = vec![0; symbols];
+    let mut visited = vec![false; symbols];
+    for sym in 0..symbols {
+        if !visited[sym] {
+            d.symlen[sym] = set_symlen(data, d, sym, &mut visited);
+        }
+    }
+    Ok(pos + symbols * 3 + (symbols & 1))
+}
+
+// number of values a symbol expands to, minus one
+fn set_symlen(data: &[u8], d: &mut PairsData, sym: usize, visited: &mut [bool]) -> u8 {
+    visited[sym] = true;
+    let right = btree_right(data, d, sym);
+    if right == 0xFFF {
+        return 0;
+    }
+    let left = btree_left(data, d, sym);
+    if left >= visited.len() || right >= visited.len() {
+        return 0;
+    }
+    if !visited[left] {
+        d.symlen[left] = set_symlen(data, d, left, visited);
+    }
+    if !visited[right] {
+        d.symlen[right] = set_symlen(data, d, right, visited);
+    }
+    d.symlen[left].wrapping_add(d.symlen[right]).wrapping_add(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_maps() {
+        let maps = &*MAPS;
+        // 462 placements of two kings, the highest code is for both kings on the diagonal
+        let max_kk = maps.kk.iter().flatten().max().unwrap();
+        assert_eq!(*max_kk, 461);
+        assert_eq!(maps.a1d1d4[1], 0); // b1
+        assert_eq!(maps.a1d1d4[0], 6); // a1
+        assert_eq!(maps.a1d1d4[27], 9); // d4
+        assert_eq!(maps.b1h1h7[55], 27); // h7
+        assert_eq!(maps.pawns[8], 47); // a2
+        assert_eq!(maps.pawns[15], 46); // h2
+        assert_eq!(maps.binomial[2][5], 10);
+        assert_eq!(maps.binomial[5][63], 7028847);
+        // single leading pawn has 6 ranks on each file
+        assert_eq!(maps.lead_pawns_size[1], [6, 6, 6, 6]);
+    }
+
+    #[test]
+    fn test_material_from_name() {
+        let krvk = Material::from_name("KRvK").unwrap();
+        assert_eq!(krvk.piece_count, 3);
+        assert!(krvk.has_unique_pieces);
+        assert_ne!(krvk.key, krvk.key2);
+        assert_eq!(krvk.key2, Material::from_name("KvKR").unwrap().key);
+        let kpvkp = Material::from_name("KPvKP").unwrap();
+        assert_eq!(kpvkp.key, kpvkp.key2);
+        assert_eq!(kpvkp.pawn_count, [1, 1]);
+        assert_eq!(Material::from_name("KPPvKP").unwrap().pawn_count, [1, 2]);
+        assert!(Material::from_name("KRvR").is_none());
+        assert!(Material::from_name("KXvK").is_none());
+        assert!(Material::from_name("KQQQQvKQQQ").is_none());
+    }
+}
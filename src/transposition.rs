@@ -1,13 +1,17 @@
 // https://github.com/mvanthoor/rustic/blob/4.0-beta/src/engine/transposition.rs
-// Based on this author's work, mainly to understand generic types. Only used for type TableEntry currently.
+// Based on this author's work, mainly to understand generic types. TT<T> is the single threaded generic table,
+// the search uses the lockless TranspositionTable at the bottom of this file so it can be shared between threads.
 
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::vec;
 
 use crate::zobrist::PositionHash;
-use crate::{util, ShortMove, NULL_SHORT_MOVE};
+use crate::{util, PieceType, ShortMove, NULL_SHORT_MOVE};
 
 const DEFAULT_TABLE_SIZE_MB: usize = 200; // in MiB
 const NUM_BUCKETS: usize = 3;
+const CLUSTER_SIZE: usize = 4; // slots per cluster in the lockless table, 4 * 16 bytes fills a cache line
 const UNINIT_ENTRY: TableEntry = TableEntry {
     bound_type: BoundType::Invalid,
     depth: 0,
@@ -16,9 +20,6 @@ const UNINIT_ENTRY: TableEntry = TableEntry {
     mv: NULL_SHORT_MOVE,
 };
 
-// TT with generic type T as TableEntry
-pub type TranspositionTable<T = TableEntry> = TT<T>;
-
 // TTData trait must be implemented for any type used in the TT
 pub trait TTData {
     fn new() -> Self;
@@ -191,3 +192,293 @@ impl<T: TTData + Copy + Clone> Entry<T> {
         None
     }
 }
+
+// lockless transposition table used by the search, get and insert only need &self so it can be shared between search threads.
+// each slot stores the hash xor'd with the packed entry, if two threads write to the same slot at once the hash check on
+// read fails instead of returning a corrupt entry
+pub struct TranspositionTable {
+    table: Vec<Cluster>,
+    entry_count: AtomicUsize,
+    size_mb: usize,
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for TranspositionTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TranspositionTable")
+            .field("size_mb", &self.size_mb)
+            .field("entry_count", &self.len())
+            .finish()
+    }
+}
+
+impl Clone for TranspositionTable {
+    fn clone(&self) -> Self {
+        Self {
+            table: self.table.clone(),
+            entry_count: AtomicUsize::new(self.len()),
+            size_mb: self.size_mb,
+        }
+    }
+}
+
+impl TranspositionTable {
+    pub fn new() -> Self {
+        Self::with_size(DEFAULT_TABLE_SIZE_MB)
+    }
+
+    pub fn with_size(size_mb: usize) -> Self {
+        let len = (size_mb * 1024 * 1024) / std::mem::size_of::<Cluster>();
+        Self {
+            table: (0..len).map(|_| Cluster::default()).collect(),
+            entry_count: AtomicUsize::new(0),
+            size_mb,
+        }
+    }
+
+    pub fn get(&self, hash: PositionHash) -> Option<TableEntry> {
+        if self.table.is_empty() {
+            return None;
+        }
+        self.table[self.get_idx(hash)]
+            .slots
+            .iter()
+            .find_map(|slot| slot.get(hash))
+    }
+
+    pub fn insert(&self, hash: PositionHash, data: TableEntry) {
+        if self.table.is_empty() {
+            return;
+        }
+        let cluster = &self.table[self.get_idx(hash)];
+        // replace the entry for the same position, otherwise the lowest depth entry. empty slots are depth 0
+        let slot = cluster
+            .slots
+            .iter()
+            .find(|slot| slot.get(hash).is_some())
+            .unwrap_or_else(|| {
+                cluster
+                    .slots
+                    .iter()
+                    .min_by_key(|slot| slot.depth())
+                    .unwrap() // cluster is never empty
+            });
+        if slot.store(hash, data) {
+            self.entry_count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.table.len() * CLUSTER_SIZE
+    }
+
+    pub fn heap_alloc_size(&self) -> usize {
+        self.table.len() * std::mem::size_of::<Cluster>()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entry_count.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.entry_count.store(0, Ordering::Relaxed);
+        self.table.iter_mut().for_each(|cluster| {
+            *cluster = Cluster::default();
+        });
+    }
+
+    fn get_idx(&self, hash: PositionHash) -> usize {
+        let idx_hash = util::high_bits(hash);
+        (idx_hash as usize) % self.table.len()
+    }
+}
+
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct Cluster {
+    slots: [Slot; CLUSTER_SIZE],
+}
+
+impl Clone for Cluster {
+    fn clone(&self) -> Self {
+        let cluster = Cluster::default();
+        for (new, old) in cluster.slots.iter().zip(self.slots.iter()) {
+            new.key
+                .store(old.key.load(Ordering::Relaxed), Ordering::Relaxed);
+            new.data
+                .store(old.data.load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+        cluster
+    }
+}
+
+#[derive(Debug, Default)]
+struct Slot {
+    key: AtomicU64, // hash ^ data
+    data: AtomicU64,
+}
+
+impl Slot {
+    fn get(&self, hash: PositionHash) -> Option<TableEntry> {
+        let data = self.data.load(Ordering::Relaxed);
+        let key = self.key.load(Ordering::Relaxed);
+        if data != 0 && key ^ data == hash {
+            Some(unpack_entry(data))
+        } else {
+            None
+        }
+    }
+
+    fn depth(&self) -> u8 {
+        unpack_entry(self.data.load(Ordering::Relaxed)).depth
+    }
+
+    // returns true if the slot was empty before data was stored
+    fn store(&self, hash: PositionHash, entry: TableEntry) -> bool {
+        let data = pack_entry(&entry);
+        let was_empty = self.data.swap(data, Ordering::Relaxed) == 0;
+        self.key.store(hash ^ data, Ordering::Relaxed);
+        was_empty
+    }
+}
+
+// entry layout: eval (32 bits) | depth (8) | ply (7) | bound (2) | from (6) | to (6) | promotion (3)
+// bound is never 0 for a stored entry, so a packed entry of 0 is an empty slot.
+// no move is stored with the same from and to square, which no real move has
+fn pack_entry(entry: &TableEntry) -> u64 {
+    let bound = match entry.bound_type {
+        BoundType::Invalid => 0,
+        BoundType::Exact => 1,
+        BoundType::Lower => 2,
+        BoundType::Upper => 3,
+    };
+    let promotion = match entry.mv.promotion_ptype {
+        None => 0,
+        Some(PieceType::Knight) => 1,
+        Some(PieceType::Bishop) => 2,
+        Some(PieceType::Rook) => 3,
+        Some(PieceType::Queen) => 4,
+        Some(PieceType::Pawn) => 5,
+        Some(PieceType::King) => 6,
+    };
+    let (from, to) = if entry.mv == NULL_SHORT_MOVE {
+        (0, 0)
+    } else {
+        (entry.mv.from as u64 & 63, entry.mv.to as u64 & 63)
+    };
+    (entry.eval as u32 as u64)
+        | (entry.depth as u64) << 32
+        | (entry.ply.min(127) as u64) << 40
+        | bound << 47
+        | from << 49
+        | to << 55
+        | promotion << 61
+}
+
+fn unpack_entry(data: u64) -> TableEntry {
+    let bound_type = match (data >> 47) & 3 {
+        1 => BoundType::Exact,
+        2 => BoundType::Lower,
+        3 => BoundType::Upper,
+        _ => BoundType::Invalid,
+    };
+    let promotion_ptype = match data >> 61 {
+        1 => Some(PieceType::Knight),
+        2 => Some(PieceType::Bishop),
+        3 => Some(PieceType::Rook),
+        4 => Some(PieceType::Queen),
+        5 => Some(PieceType::Pawn),
+        6 => Some(PieceType::King),
+        _ => None,
+    };
+    let (from, to) = (((data >> 49) & 63) as u8, ((data >> 55) & 63) as u8);
+    TableEntry {
+        bound_type,
+        depth: (data >> 32) as u8,
+        ply: ((data >> 40) & 127) as u8,
+        eval: data as u32 as i32,
+        mv: if from == to {
+            NULL_SHORT_MOVE
+        } else {
+            ShortMove {
+                from,
+                to,
+                promotion_ptype,
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_entry_round_trip() {
+        let entry = TableEntry {
+            bound_type: BoundType::Lower,
+            depth: 12,
+            ply: 5,
+            eval: -123_456,
+            mv: ShortMove {
+                from: 12,
+                to: 4,
+                promotion_ptype: Some(PieceType::Queen),
+            },
+        };
+        let unpacked = unpack_entry(pack_entry(&entry));
+        assert_eq!(unpacked.bound_type, entry.bound_type);
+        assert_eq!(unpacked.depth, entry.depth);
+        assert_eq!(unpacked.ply, entry.ply);
+        assert_eq!(unpacked.eval, entry.eval);
+        assert_eq!(unpacked.mv, entry.mv);
+
+        // entries without a best move, and a move to or from the last square
+        for mv in [
+            NULL_SHORT_MOVE,
+            ShortMove {
+                from: 63,
+                to: 55,
+                promotion_ptype: None,
+            },
+            ShortMove {
+                from: 0,
+                to: 63,
+                promotion_ptype: None,
+            },
+        ] {
+            let entry = TableEntry { mv, ..entry };
+            assert_eq!(unpack_entry(pack_entry(&entry)).mv, mv);
+        }
+    }
+
+    #[test]
+    fn test_lockless_insert_get() {
+        let tt = TranspositionTable::with_size(1);
+        let entry = TableEntry {
+            bound_type: BoundType::Exact,
+            depth: 3,
+            ply: 1,
+            eval: 42,
+            mv: NULL_SHORT_MOVE,
+        };
+        let hash = 0xdead_beef_1234_5678;
+        assert!(tt.get(hash).is_none());
+        tt.insert(hash, entry);
+        assert_eq!(tt.get(hash).unwrap().eval, 42);
+        assert_eq!(tt.len(), 1);
+        assert!(tt.get(hash ^ 1).is_none());
+        tt.insert(hash, TableEntry { eval: 7, ..entry });
+        assert_eq!(tt.get(hash).unwrap().eval, 7);
+        assert_eq!(tt.len(), 1);
+    }
+}
@@ -0,0 +1,337 @@
+// texel tuning of the classical evaluation, the parameters are fitted so the evaluation of positions taken from
+// games predicts the results of those games -> https://www.chessprogramming.org/Texel%27s_Tuning_Method
+use std::fs;
+use std::io::BufRead;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+
+use crate::board::{Board, BoardState, GameState};
+use crate::engine::{ClassicalEvaluator, EvalParams, Score};
+use crate::errors::TuningDataError;
+use crate::fen::FEN;
+use crate::log_and_return_error;
+use crate::movegen::{MoveType, Square};
+use crate::pgn::reader::PgnReader;
+use crate::pgn::tag::Tag;
+use crate::pgn::ParseMode;
+
+// positions from the opening of a game are mostly book moves and say little about its result
+const PGN_SKIP_PLIES: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TuningPosition {
+    pub board: [Square; 64],
+    // result of the game from white's side, 1 for a win, 0.5 for a draw and 0 for a loss
+    pub result: f64,
+}
+
+// EPD line with the result either as an opcode or in brackets, e.g. <FEN> c9 "1-0"; or <FEN> [0.5]
+impl FromStr for TuningPosition {
+    type Err = TuningDataError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        if fields.len() < 5 {
+            let err = TuningDataError::InvalidFormat(format!("Missing FEN or result: {}", s));
+            log_and_return_error!(err)
+        }
+        let fen = match fields[..4].join(" ").parse::<FEN>() {
+            Ok(fen) => fen,
+            Err(e) => {
+                let err = TuningDataError::InvalidFormat(format!("{}: {}", s, e));
+                log_and_return_error!(err)
+            }
+        };
+        // the move counters of a full FEN can come before the result
+        let result = fields[4..].iter().find_map(|field| {
+            let field = field.trim_end_matches(';');
+            let quoted = field
+                .strip_prefix('[')
+                .and_then(|f| f.strip_suffix(']'))
+                .or_else(|| field.strip_prefix('"').and_then(|f| f.strip_suffix('"')));
+            match quoted {
+                Some(result) => parse_result(result),
+                None if field.contains('-') => parse_result(field),
+                None => None,
+            }
+        });
+        let Some(result) = result else {
+            let err = TuningDataError::InvalidFormat(format!("Missing result: {}", s));
+            log_and_return_error!(err)
+        };
+        Ok(TuningPosition {
+            board: **BoardState::from(fen).get_pos64(),
+            result,
+        })
+    }
+}
+
+fn parse_result(s: &str) -> Option<f64> {
+    match s {
+        "1-0" | "1.0" | "1" => Some(1.0),
+        "0-1" | "0.0" | "0" => Some(0.0),
+        "1/2-1/2" | "0.5" => Some(0.5),
+        _ => None,
+    }
+}
+
+// positions of an EPD file, one per line. empty lines and lines starting with # are skipped
+pub fn read_epd_file(path: impl AsRef<Path>) -> Result<Vec<TuningPosition>, TuningDataError> {
+    positions_from_epd(&read_file(path.as_ref())?)
+}
+
+// quiet positions from every finished game of a PGN file
+pub fn read_pgn_file(path: impl AsRef<Path>) -> Result<Vec<TuningPosition>, TuningDataError> {
+    match PgnReader::open(path) {
+        Ok(games) => Ok(positions_from_pgn(games.with_mode(ParseMode::Lenient))),
+        Err(e) => log_and_return_error!(TuningDataError::FileError(e.to_string())),
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, TuningDataError> {
+    match fs::read_to_string(path) {
+        Ok(s) => Ok(s),
+        Err(e) => {
+            let err = TuningDataError::FileError(format!("{}: {}", path.display(), e));
+            log_and_return_error!(err)
+        }
+    }
+}
+
+fn positions_from_epd(s: &str) -> Result<Vec<TuningPosition>, TuningDataError> {
+    s.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(TuningPosition::from_str)
+        .collect()
+}
+
+// games that can't be parsed or have no result are skipped
+fn positions_from_pgn(games: PgnReader<impl BufRead>) -> Vec<TuningPosition> {
+    let mut positions = Vec::new();
+    for game in games {
+        let pgn = match game {
+            Ok(pgn) => pgn,
+            Err(e) => {
+                log::warn!("Skipping game: {}", e);
+                continue;
+            }
+        };
+        let result = pgn.tags().iter().find_map(|tag| match tag {
+            Tag::Result(result) => parse_result(result),
+            _ => None,
+        });
+        let Some(result) = result else {
+            continue;
+        };
+        let board = match Board::try_from(pgn) {
+            Ok(board) => board,
+            Err(e) => {
+                log::warn!("Skipping game: {}", e);
+                continue;
+            }
+        };
+        for bs in board.get_state_history().iter().skip(PGN_SKIP_PLIES) {
+            if is_quiet(bs) {
+                positions.push(TuningPosition {
+                    board: **bs.get_pos64(),
+                    result,
+                });
+            }
+        }
+    }
+    positions
+}
+
+// the static eval is only meaningful without checks or a recapture to come
+fn is_quiet(bs: &BoardState) -> bool {
+    let noisy_move = bs.last_move.is_some_and(|mv| {
+        mv.move_type.is_capture() || matches!(mv.move_type, MoveType::Promotion(..))
+    });
+    !noisy_move && bs.get_gamestate() == GameState::Active
+}
+
+// probability of white winning predicted from an eval in centipawns, k scales the eval to the results
+fn sigmoid(eval: i32, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval as f64 / 400.0))
+}
+
+pub struct Tuner {
+    positions: Vec<TuningPosition>,
+    params: EvalParams,
+    k: f64,
+    threads: usize,
+}
+
+impl Tuner {
+    pub fn new(positions: Vec<TuningPosition>, params: EvalParams, threads: usize) -> Self {
+        Tuner {
+            positions,
+            params,
+            k: 1.0,
+            threads: threads.max(1),
+        }
+    }
+
+    pub fn params(&self) -> &EvalParams {
+        &self.params
+    }
+
+    pub fn k(&self) -> f64 {
+        self.k
+    }
+
+    pub fn error(&self) -> f64 {
+        mean_squared_error(&self.positions, &self.evals(&self.params), self.k)
+    }
+
+    // the k with the lowest error for the current parameters, searched in finer steps around the best so far
+    pub fn fit_k(&mut self) -> f64 {
+        let evals = self.evals(&self.params);
+        let (mut best_k, mut best_error) = (self.k, f64::MAX);
+        let (mut start, mut end, mut step) = (0.0, 10.0, 1.0);
+        for _ in 0..5 {
+            let mut k = start;
+            while k <= end {
+                let error = mean_squared_error(&self.positions, &evals, k);
+                if error < best_error {
+                    (best_k, best_error) = (k, error);
+                }
+                k += step;
+            }
+            (start, end) = ((best_k - step).max(0.0), best_k + step);
+            step /= 10.0;
+        }
+        self.k = best_k;
+        best_k
+    }
+
+    // one pass of the local search, each middlegame and endgame value is moved by one in whichever direction
+    // lowers the error. returns the error after the pass and how many values changed
+    pub fn pass(&mut self) -> (f64, usize) {
+        let mut values = self.params.values();
+        let mut best_error = self.error();
+        let mut changed = 0;
+        for i in 0..values.len() {
+            for phase in [Score::new(1, 0), Score::new(0, 1)] {
+                for delta in [phase, -phase] {
+                    values[i] += delta;
+                    self.params.set_values(&values);
+                    let error = self.error();
+                    if error < best_error {
+                        best_error = error;
+                        changed += 1;
+                        break;
+                    }
+                    values[i] -= delta;
+                }
+            }
+        }
+        self.params.set_values(&values);
+        (best_error, changed)
+    }
+
+    // white side evals of every position, split between the threads
+    fn evals(&self, params: &EvalParams) -> Vec<i32> {
+        let params = Arc::new(params.clone());
+        let chunk_size = self.positions.len().div_ceil(self.threads).max(1);
+        thread::scope(|s| {
+            let handles: Vec<_> = self
+                .positions
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    let mut evaluator = ClassicalEvaluator::with_params(Arc::clone(&params));
+                    s.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|pos| evaluator.trace(&pos.board).eval())
+                            .collect::<Vec<i32>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        })
+    }
+}
+
+fn mean_squared_error(positions: &[TuningPosition], evals: &[i32], k: f64) -> f64 {
+    let total: f64 = positions
+        .iter()
+        .zip(evals)
+        .map(|(pos, &eval)| (pos.result - sigmoid(eval, k)).powi(2))
+        .sum();
+    total / positions.len().max(1) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_epd() {
+        let pos = "4k3/8/8/8/8/8/4P3/4K3 w - - c9 \"1-0\";"
+            .parse::<TuningPosition>()
+            .unwrap();
+        assert_eq!(pos.result, 1.0);
+        assert_eq!(
+            "4k3/8/8/8/8/8/4P3/4K3 b - - 0 40 [0.5]"
+                .parse::<TuningPosition>()
+                .unwrap()
+                .result,
+            0.5
+        );
+        assert_eq!(
+            "4k3/8/8/8/8/8/4P3/4K3 w - - [0-1]"
+                .parse::<TuningPosition>()
+                .unwrap()
+                .result,
+            0.0
+        );
+        assert!("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"
+            .parse::<TuningPosition>()
+            .is_err());
+        assert!("4k3/8/8/8/8/8/4P3/4K3 w - -"
+            .parse::<TuningPosition>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_positions_from_pgn() {
+        let game = fs::read_to_string("test_data/test.pgn").unwrap();
+        let positions = positions_from_pgn(PgnReader::new(game.as_bytes()));
+        assert!(!positions.is_empty());
+        assert!(positions.iter().all(|pos| pos.result == 1.0));
+        // two games back to back, the second without a result is skipped
+        let games = format!("{}\n{}", game, game.replace("1-0", "*"));
+        assert_eq!(PgnReader::new(games.as_bytes()).count(), 2);
+        assert_eq!(
+            positions_from_pgn(PgnReader::new(games.as_bytes())),
+            positions
+        );
+    }
+
+    #[test]
+    fn test_tuner() {
+        // white is a pawn up and wins each game, a pass moves the values towards those results
+        let positions: Vec<TuningPosition> = [
+            "4k3/8/8/8/8/8/P3P3/4K3 w - - [1-0]",
+            "4k3/8/8/8/8/8/1P1P4/3K4 b - - [1-0]",
+            "3k4/8/8/8/8/8/P1P5/2K5 w - - [1-0]",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+        let mut tuner = Tuner::new(positions, EvalParams::default(), 2);
+        tuner.fit_k();
+        let error = tuner.error();
+        let (tuned_error, changed) = tuner.pass();
+        assert!(changed > 0);
+        assert!(tuned_error < error);
+        assert_eq!(tuned_error, tuner.error());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::engine::{get_checkmate_ply, is_eval_checkmate};
+use crate::engine::{get_checkmate_ply, is_eval_checkmate, is_eval_tablebase};
 use crate::movegen::{PieceColour, PieceType, Square};
 use crate::BoardState;
 
@@ -115,6 +115,12 @@ pub fn eval_to_string(eval: i32) -> String {
             0 => "Checkmate".to_string(),
             x => format!("Mate in {} ply", x),
         }
+    } else if is_eval_tablebase(eval) {
+        if eval > 0 {
+            "Tablebase win".to_string()
+        } else {
+            "Tablebase loss".to_string()
+        }
     } else {
         let eval = eval as f64 / 100.0; // convert centipawns to pawns
         format!("{:+.2}", eval)
@@ -2,6 +2,7 @@ use rand::Rng;
 
 use static_init::dynamic;
 
+use crate::bitboard::{self, Bitboard};
 use crate::magic;
 use crate::movegen::*;
 use crate::position::Position;
@@ -28,6 +29,11 @@ pub fn pos_next_hash(
     ZOBRIST_HASH_TABLE.polyglot_next_hash(last_movegen_flags, new_movegen_flags, last_hash, mv)
 }
 
+// hash after the side to move passes the turn, last_movegen_flags are the flags before the null move
+pub fn null_move_hash(last_movegen_flags: &MovegenFlags, last_hash: PositionHash) -> PositionHash {
+    ZOBRIST_HASH_TABLE.null_move_hash(last_movegen_flags, last_hash)
+}
+
 // add BoardState information into a zobrist Position hash
 pub fn board_state_hash(
     current_hash: PositionHash,
@@ -37,6 +43,11 @@ pub fn board_state_hash(
     ZOBRIST_HASH_TABLE.board_state_hash(current_hash, occurrences, halfmove_count)
 }
 
+// zobrist hash of only the pawns, indexed by PieceColour as usize. keys the pawn hash table in the evaluation
+pub fn pawn_hash(pawns: [Bitboard; 2]) -> PositionHash {
+    ZOBRIST_HASH_TABLE.pawn_hash(pawns)
+}
+
 struct ZobristHashTable {
     pos_table: [[PositionHash; 12]; 64],
     en_passant_table: [PositionHash; 8], // 8 possible files that an en passant move can be made
@@ -162,8 +173,8 @@ impl ZobristHashTable {
         }
 
         // set polyglot en passant index
-        if new_movegen_flags.polyglot_en_passant.is_some() {
-            hash ^= self.en_passant_table[new_movegen_flags.polyglot_en_passant.unwrap() % 8];
+        if let Some(idx) = new_movegen_flags.polyglot_en_passant {
+            hash ^= self.en_passant_table[idx % 8];
         }
 
         if last_movegen_flags.black_castle_long
@@ -213,6 +224,18 @@ impl ZobristHashTable {
         hash
     }
 
+    fn null_move_hash(
+        &self,
+        last_movegen_flags: &MovegenFlags,
+        last_hash: PositionHash,
+    ) -> PositionHash {
+        let mut hash = last_hash ^ self.white_to_move;
+        if let Some(idx) = last_movegen_flags.polyglot_en_passant {
+            hash ^= self.en_passant_table[idx % 8];
+        }
+        hash
+    }
+
     fn polyglot_full_position_hash(&self, pos: &Position) -> PositionHash {
         let mut hash = 0;
         for (i, s) in pos.pos64.iter().enumerate() {
@@ -240,8 +263,8 @@ impl ZobristHashTable {
         if pos.side == PieceColour::White {
             hash ^= self.white_to_move;
         }
-        if pos.movegen_flags.polyglot_en_passant.is_some() {
-            hash ^= self.en_passant_table[pos.movegen_flags.polyglot_en_passant.unwrap() % 8];
+        if let Some(idx) = pos.movegen_flags.polyglot_en_passant {
+            hash ^= self.en_passant_table[idx % 8];
         }
 
         hash
@@ -379,8 +402,8 @@ impl ZobristHashTable {
         if pos.movegen_flags.black_castle_short {
             hash ^= self.black_castle_short;
         }
-        if pos.movegen_flags.en_passant.is_some() {
-            hash ^= self.en_passant_table[pos.movegen_flags.en_passant.unwrap() % 8];
+        if let Some(idx) = pos.movegen_flags.en_passant {
+            hash ^= self.en_passant_table[idx % 8];
         }
         if pos.side == PieceColour::White {
             hash ^= self.white_to_move;
@@ -415,6 +438,20 @@ impl ZobristHashTable {
         }
     }
 
+    fn pawn_hash(&self, pawns: [Bitboard; 2]) -> PositionHash {
+        let mut hash = 0;
+        for pcolour in [PieceColour::White, PieceColour::Black] {
+            let piece = Piece {
+                pcolour,
+                ptype: PieceType::Pawn,
+            };
+            for sq in bitboard::squares(pawns[pcolour as usize]) {
+                hash ^= self.get_piece_hash(piece, sq);
+            }
+        }
+        hash
+    }
+
     #[inline(always)]
     const fn get_piece_hash(&self, piece: Piece, square_idx: usize) -> PositionHash {
         // unsafe {